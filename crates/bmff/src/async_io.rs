@@ -0,0 +1,98 @@
+//! Writing boxes to a tokio [`AsyncWrite`].
+//!
+//! These mirror [`write_to`](crate::write_to) and
+//! [`write_to_full`](crate::write_to_full). Boxes are serialized into a
+//! buffer of their full size first, which is fine for headers and sample
+//! tables, so [`write_to`] refuses `mdat` boxes: use [`write_mdat`] or
+//! [`write_chunks`] to write sample data straight from the shared payload.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use bmff::{async_io, MediaDataBox, MovieFragmentBox};
+//!
+//! async fn append(moof: &MovieFragmentBox, mdat: &MediaDataBox) -> std::io::Result<()> {
+//!     let mut file = tokio::fs::OpenOptions::new()
+//!         .append(true)
+//!         .open("video.mp4")
+//!         .await?;
+//!     async_io::write_to(moof, &mut file).await?;
+//!     async_io::write_mdat(mdat, &mut file).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::*;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Writes a box to `w`. The box is serialized into a buffer first.
+///
+/// # Errors
+///
+/// Returns an error if `bmff_box` is an `mdat`, which should be written with
+/// [`write_mdat`] instead, or if writing fails.
+pub async fn write_to<T: BmffBox>(bmff_box: &T, mut w: impl AsyncWrite + Unpin) -> io::Result<()> {
+    if T::TYPE == MediaDataBox::TYPE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "mdat boxes should be written with write_mdat",
+        ));
+    }
+    let mut buf = buffer::<T>(bmff_box.size())?;
+    crate::write_to(bmff_box, &mut buf)?;
+    w.write_all(&buf).await
+}
+
+/// Writes a full box to `w`. The box is serialized into a buffer first.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub async fn write_to_full<T: FullBox>(
+    bmff_box: &T,
+    mut w: impl AsyncWrite + Unpin,
+) -> io::Result<()> {
+    let mut buf = buffer::<T>(bmff_box.size())?;
+    crate::write_to_full(bmff_box, &mut buf)?;
+    w.write_all(&buf).await
+}
+
+/// Writes a [`WriteTo`] value, such as a [`RawBox`], to `w`.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub async fn write_value(value: &impl WriteTo, mut w: impl AsyncWrite + Unpin) -> io::Result<()> {
+    let mut buf = Vec::new();
+    value.write_to(&mut buf)?;
+    w.write_all(&buf).await
+}
+
+/// Writes an `mdat` box to `w` without copying its payload.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub async fn write_mdat(mdat: &MediaDataBox, w: impl AsyncWrite + Unpin) -> io::Result<()> {
+    let mut chunks = Chunks::new();
+    chunks.push_mdat(mdat)?;
+    write_chunks(&chunks, w).await
+}
+
+/// Writes all of `chunks` to `w`.
+///
+/// # Errors
+///
+/// Returns an error if writing fails.
+pub async fn write_chunks(chunks: &Chunks, mut w: impl AsyncWrite + Unpin) -> io::Result<()> {
+    for chunk in chunks {
+        w.write_all(chunk.as_ref()).await?;
+    }
+    Ok(())
+}
+
+/// Allocates a buffer for a box of `size` bytes.
+fn buffer<T: BmffBox>(size: u64) -> io::Result<Vec<u8>> {
+    let size = usize::try_from(size).map_err(|_| Error::Overflow(T::TYPE, "size"))?;
+    Ok(Vec::with_capacity(size))
+}
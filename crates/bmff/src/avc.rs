@@ -0,0 +1,448 @@
+//! Just enough of H.264 to find where the slice data of a NAL unit starts.
+
+use crate::*;
+use std::collections::HashMap;
+
+/// Reads the bits of a NAL unit payload, skipping emulation prevention bytes.
+struct BitReader<'a> {
+    nal: &'a [u8],
+    /// Index of the next byte of `nal`
+    pos: usize,
+    byte: u8,
+    bits_left: u8,
+    zeros: u8,
+}
+
+impl<'a> BitReader<'a> {
+    /// Starts reading after the 1-byte NAL unit header.
+    fn new(nal: &'a [u8]) -> Self {
+        Self {
+            nal,
+            pos: 1,
+            byte: 0,
+            bits_left: 0,
+            zeros: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> io::Result<u8> {
+        let byte = *self
+            .nal
+            .get(self.pos)
+            .ok_or_else(|| invalid_data("NAL unit ends in the middle of a header"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_bit(&mut self) -> io::Result<bool> {
+        if self.bits_left == 0 {
+            let mut byte = self.next_byte()?;
+            if self.zeros >= 2 && byte == 3 {
+                byte = self.next_byte()?;
+                self.zeros = 0;
+            }
+            self.zeros = if byte == 0 {
+                self.zeros.saturating_add(1)
+            } else {
+                0
+            };
+            self.byte = byte;
+            self.bits_left = 8;
+        }
+        self.bits_left -= 1;
+        Ok(self.byte >> self.bits_left & 1 != 0)
+    }
+
+    fn read_bits(&mut self, n: u8) -> io::Result<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = value << 1 | u32::from(self.read_bit()?);
+        }
+        Ok(value)
+    }
+
+    /// Reads an unsigned Exp-Golomb code, `ue(v)`.
+    fn read_ue(&mut self) -> io::Result<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err(invalid_data("Exp-Golomb code is too long"));
+            }
+        }
+        Ok(((1u64 << leading_zeros) - 1 + u64::from(self.read_bits(leading_zeros)?)) as u32)
+    }
+
+    /// Reads a signed Exp-Golomb code, `se(v)`.
+    fn read_se(&mut self) -> io::Result<i32> {
+        let k = self.read_ue()?;
+        Ok(if k % 2 == 1 {
+            (k / 2 + 1) as i32
+        } else {
+            -((k / 2) as i32)
+        })
+    }
+
+    /// Skips the rest of a partially read byte.
+    fn byte_align(&mut self) {
+        self.bits_left = 0;
+    }
+
+    /// The number of bytes of the NAL unit that have been read, including
+    /// the header and a partially read byte.
+    fn bytes_read(&self) -> usize {
+        self.pos
+    }
+}
+
+/// The fields of a sequence parameter set that the slice header depends on.
+#[derive(Debug, Clone, Copy)]
+struct Sps {
+    chroma_array_type: u32,
+    separate_colour_plane: bool,
+    log2_max_frame_num: u8,
+    pic_order_cnt_type: u32,
+    log2_max_pic_order_cnt_lsb: u8,
+    delta_pic_order_always_zero: bool,
+    frame_mbs_only: bool,
+}
+
+/// The fields of a picture parameter set that the slice header depends on.
+#[derive(Debug, Clone, Copy)]
+struct Pps {
+    seq_parameter_set_id: u32,
+    entropy_coding_mode: bool,
+    bottom_field_pic_order_in_frame_present: bool,
+    num_ref_idx_l0_default_active_minus1: u32,
+    num_ref_idx_l1_default_active_minus1: u32,
+    weighted_pred: bool,
+    weighted_bipred_idc: u32,
+    deblocking_filter_control_present: bool,
+    redundant_pic_cnt_present: bool,
+}
+
+/// The parameter sets of an H.264 stream, by ID.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParameterSets {
+    sps: HashMap<u32, Sps>,
+    pps: HashMap<u32, Pps>,
+}
+
+impl ParameterSets {
+    /// Adds a SPS or PPS NAL unit, replacing any with the same ID. Other NAL
+    /// units are ignored.
+    pub(crate) fn add(&mut self, nal: &[u8]) -> io::Result<()> {
+        match nal.first().map(|x| x & 0x1f) {
+            Some(7) => self.add_sps(nal),
+            Some(8) => self.add_pps(nal),
+            _ => Ok(()),
+        }
+    }
+
+    fn add_sps(&mut self, nal: &[u8]) -> io::Result<()> {
+        let mut r = BitReader::new(nal);
+        let profile_idc = r.read_bits(8)?;
+        r.read_bits(16)?; // constraint_set_flags, level_idc
+        let id = r.read_ue()?;
+        if id > 31 {
+            return Err(invalid_data("seq_parameter_set_id must be at most 31"));
+        }
+        let mut chroma_format_idc = 1;
+        let mut separate_colour_plane = false;
+        if [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135].contains(&profile_idc) {
+            chroma_format_idc = r.read_ue()?;
+            if chroma_format_idc == 3 {
+                separate_colour_plane = r.read_bit()?;
+            }
+            r.read_ue()?; // bit_depth_luma_minus8
+            r.read_ue()?; // bit_depth_chroma_minus8
+            r.read_bit()?; // qpprime_y_zero_transform_bypass_flag
+            if r.read_bit()? {
+                let count = if chroma_format_idc == 3 { 12 } else { 8 };
+                for i in 0..count {
+                    if r.read_bit()? {
+                        skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+        let log2_max_frame_num = r.read_ue()?.saturating_add(4);
+        let pic_order_cnt_type = r.read_ue()?;
+        let mut log2_max_pic_order_cnt_lsb = 0;
+        let mut delta_pic_order_always_zero = false;
+        match pic_order_cnt_type {
+            0 => log2_max_pic_order_cnt_lsb = r.read_ue()?.saturating_add(4),
+            1 => {
+                delta_pic_order_always_zero = r.read_bit()?;
+                r.read_se()?; // offset_for_non_ref_pic
+                r.read_se()?; // offset_for_top_to_bottom_field
+                for _ in 0..r.read_ue()? {
+                    r.read_se()?; // offset_for_ref_frame
+                }
+            }
+            _ => (),
+        }
+        if log2_max_frame_num > 16 || log2_max_pic_order_cnt_lsb > 16 {
+            return Err(invalid_data("SPS has an invalid frame number or POC size"));
+        }
+        r.read_ue()?; // max_num_ref_frames
+        r.read_bit()?; // gaps_in_frame_num_value_allowed_flag
+        r.read_ue()?; // pic_width_in_mbs_minus1
+        r.read_ue()?; // pic_height_in_map_units_minus1
+        let frame_mbs_only = r.read_bit()?;
+
+        self.sps.insert(
+            id,
+            Sps {
+                chroma_array_type: if separate_colour_plane {
+                    0
+                } else {
+                    chroma_format_idc
+                },
+                separate_colour_plane,
+                log2_max_frame_num: log2_max_frame_num as u8,
+                pic_order_cnt_type,
+                log2_max_pic_order_cnt_lsb: log2_max_pic_order_cnt_lsb as u8,
+                delta_pic_order_always_zero,
+                frame_mbs_only,
+            },
+        );
+        Ok(())
+    }
+
+    fn add_pps(&mut self, nal: &[u8]) -> io::Result<()> {
+        let mut r = BitReader::new(nal);
+        let id = r.read_ue()?;
+        if id > 255 {
+            return Err(invalid_data("pic_parameter_set_id must be at most 255"));
+        }
+        let seq_parameter_set_id = r.read_ue()?;
+        let entropy_coding_mode = r.read_bit()?;
+        let bottom_field_pic_order_in_frame_present = r.read_bit()?;
+        if r.read_ue()? > 0 {
+            // only allowed in the Baseline and Extended profiles
+            return Err(invalid_data("slice groups aren't supported"));
+        }
+        let num_ref_idx_l0_default_active_minus1 = r.read_ue()?;
+        let num_ref_idx_l1_default_active_minus1 = r.read_ue()?;
+        let weighted_pred = r.read_bit()?;
+        let weighted_bipred_idc = r.read_bits(2)?;
+        r.read_se()?; // pic_init_qp_minus26
+        r.read_se()?; // pic_init_qs_minus26
+        r.read_se()?; // chroma_qp_index_offset
+        let deblocking_filter_control_present = r.read_bit()?;
+        r.read_bit()?; // constrained_intra_pred_flag
+        let redundant_pic_cnt_present = r.read_bit()?;
+
+        self.pps.insert(
+            id,
+            Pps {
+                seq_parameter_set_id,
+                entropy_coding_mode,
+                bottom_field_pic_order_in_frame_present,
+                num_ref_idx_l0_default_active_minus1,
+                num_ref_idx_l1_default_active_minus1,
+                weighted_pred,
+                weighted_bipred_idc,
+                deblocking_filter_control_present,
+                redundant_pic_cnt_present,
+            },
+        );
+        Ok(())
+    }
+
+    /// Returns the size of the NAL unit header and slice header of a coded
+    /// slice NAL unit, including a partial byte at the end of the slice
+    /// header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the slice header is malformed or refers to a
+    /// parameter set that hasn't been added.
+    pub(crate) fn slice_header_size(&self, nal: &[u8]) -> io::Result<usize> {
+        let nal_ref_idc = nal.first().map_or(0, |x| x >> 5 & 0x3);
+        let idr = nal.first().map_or(false, |x| x & 0x1f == 5);
+        let mut r = BitReader::new(nal);
+        r.read_ue()?; // first_mb_in_slice
+        let slice_type = r.read_ue()? % 5;
+        let (p, b, i, sp, si) = (
+            slice_type == 0,
+            slice_type == 1,
+            slice_type == 2,
+            slice_type == 3,
+            slice_type == 4,
+        );
+        let pps = r
+            .read_ue()
+            .ok()
+            .and_then(|id| self.pps.get(&id))
+            .ok_or_else(|| invalid_data("slice refers to an unknown PPS"))?;
+        let sps = self
+            .sps
+            .get(&pps.seq_parameter_set_id)
+            .ok_or_else(|| invalid_data("PPS refers to an unknown SPS"))?;
+
+        if sps.separate_colour_plane {
+            r.read_bits(2)?; // colour_plane_id
+        }
+        r.read_bits(sps.log2_max_frame_num)?; // frame_num
+        let mut field_pic = false;
+        if !sps.frame_mbs_only {
+            field_pic = r.read_bit()?;
+            if field_pic {
+                r.read_bit()?; // bottom_field_flag
+            }
+        }
+        if idr {
+            r.read_ue()?; // idr_pic_id
+        }
+        let bottom_field_pic_order = pps.bottom_field_pic_order_in_frame_present && !field_pic;
+        if sps.pic_order_cnt_type == 0 {
+            r.read_bits(sps.log2_max_pic_order_cnt_lsb)?; // pic_order_cnt_lsb
+            if bottom_field_pic_order {
+                r.read_se()?; // delta_pic_order_cnt_bottom
+            }
+        }
+        if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero {
+            r.read_se()?; // delta_pic_order_cnt[0]
+            if bottom_field_pic_order {
+                r.read_se()?; // delta_pic_order_cnt[1]
+            }
+        }
+        if pps.redundant_pic_cnt_present {
+            r.read_ue()?; // redundant_pic_cnt
+        }
+        if b {
+            r.read_bit()?; // direct_spatial_mv_pred_flag
+        }
+        let mut num_ref_idx_l0_active_minus1 = pps.num_ref_idx_l0_default_active_minus1;
+        let mut num_ref_idx_l1_active_minus1 = pps.num_ref_idx_l1_default_active_minus1;
+        if (p || sp || b) && r.read_bit()? {
+            num_ref_idx_l0_active_minus1 = r.read_ue()?;
+            if b {
+                num_ref_idx_l1_active_minus1 = r.read_ue()?;
+            }
+        }
+        if num_ref_idx_l0_active_minus1 > 31 || num_ref_idx_l1_active_minus1 > 31 {
+            return Err(invalid_data("slice has more than 32 active references"));
+        }
+
+        // ref_pic_list_modification
+        if !i && !si {
+            skip_ref_pic_list_modification(&mut r)?;
+        }
+        if b {
+            skip_ref_pic_list_modification(&mut r)?;
+        }
+
+        if (pps.weighted_pred && (p || sp)) || (pps.weighted_bipred_idc == 1 && b) {
+            // pred_weight_table
+            r.read_ue()?; // luma_log2_weight_denom
+            if sps.chroma_array_type != 0 {
+                r.read_ue()?; // chroma_log2_weight_denom
+            }
+            skip_weights(&mut r, num_ref_idx_l0_active_minus1, sps.chroma_array_type)?;
+            if b {
+                skip_weights(&mut r, num_ref_idx_l1_active_minus1, sps.chroma_array_type)?;
+            }
+        }
+
+        if nal_ref_idc != 0 {
+            // dec_ref_pic_marking
+            if idr {
+                r.read_bit()?; // no_output_of_prior_pics_flag
+                r.read_bit()?; // long_term_reference_flag
+            } else if r.read_bit()? {
+                loop {
+                    let operation = r.read_ue()?;
+                    if operation == 0 {
+                        break;
+                    }
+                    if operation == 1 || operation == 3 {
+                        r.read_ue()?; // difference_of_pic_nums_minus1
+                    }
+                    if operation == 2 {
+                        r.read_ue()?; // long_term_pic_num
+                    }
+                    if operation == 3 || operation == 6 {
+                        r.read_ue()?; // long_term_frame_idx
+                    }
+                    if operation == 4 {
+                        r.read_ue()?; // max_long_term_frame_idx_plus1
+                    }
+                }
+            }
+        }
+
+        if pps.entropy_coding_mode && !i && !si {
+            r.read_ue()?; // cabac_init_idc
+        }
+        r.read_se()?; // slice_qp_delta
+        if sp || si {
+            if sp {
+                r.read_bit()?; // sp_for_switch_flag
+            }
+            r.read_se()?; // slice_qs_delta
+        }
+        if pps.deblocking_filter_control_present && r.read_ue()? != 1 {
+            r.read_se()?; // slice_alpha_c0_offset_div2
+            r.read_se()?; // slice_beta_offset_div2
+        }
+        if pps.entropy_coding_mode {
+            // cabac_alignment_one_bit
+            r.byte_align();
+        }
+        Ok(r.bytes_read())
+    }
+}
+
+fn skip_scaling_list(r: &mut BitReader<'_>, size: usize) -> io::Result<()> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta_scale = r.read_se()?;
+            next_scale = (last_scale + delta_scale).rem_euclid(256);
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+fn skip_ref_pic_list_modification(r: &mut BitReader<'_>) -> io::Result<()> {
+    if r.read_bit()? {
+        loop {
+            match r.read_ue()? {
+                0..=2 => {
+                    // abs_diff_pic_num_minus1 or long_term_pic_num
+                    r.read_ue()?;
+                }
+                3 => break,
+                _ => return Err(invalid_data("invalid modification_of_pic_nums_idc")),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_weights(
+    r: &mut BitReader<'_>,
+    num_ref_idx_active_minus1: u32,
+    chroma_array_type: u32,
+) -> io::Result<()> {
+    for _ in 0..=num_ref_idx_active_minus1 {
+        if r.read_bit()? {
+            r.read_se()?; // luma_weight
+            r.read_se()?; // luma_offset
+        }
+        if chroma_array_type != 0 && r.read_bit()? {
+            for _ in 0..2 {
+                r.read_se()?; // chroma_weight
+                r.read_se()?; // chroma_offset
+            }
+        }
+    }
+    Ok(())
+}
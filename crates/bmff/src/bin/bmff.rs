@@ -0,0 +1,54 @@
+//! Prints the box tree of MP4 files.
+
+use std::{fs::File, io::BufReader, process::ExitCode};
+
+const USAGE: &str = "usage: bmff [--json] <FILE>...";
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args_os().skip(1) {
+        match arg.to_str() {
+            Some("--json") => json = true,
+            Some("-h" | "--help") => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    let mut files = Vec::new();
+    for path in &paths {
+        let tree = File::open(path).and_then(|file| bmff::dump::read_tree(BufReader::new(file)));
+        match tree {
+            Ok(tree) if json => files.push(serde_json::json!({
+                "path": path.to_string_lossy(),
+                "boxes": tree.iter().map(bmff::dump::BoxNode::to_json).collect::<Vec<_>>(),
+            })),
+            Ok(tree) => {
+                if paths.len() > 1 {
+                    println!("{}:", path.to_string_lossy());
+                }
+                for node in tree {
+                    print!("{node}");
+                }
+            }
+            Err(e) => {
+                eprintln!("bmff: {}: {e}", path.to_string_lossy());
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    if json {
+        #[allow(clippy::unwrap_used)] // a `Value` always serializes
+        let out = serde_json::to_string_pretty(&files).unwrap();
+        println!("{out}");
+    }
+    status
+}
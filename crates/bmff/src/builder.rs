@@ -0,0 +1,378 @@
+use crate::*;
+
+/// Builds a `moov` box from track descriptions.
+///
+/// Track IDs are assigned in the order tracks are added, starting from 1, and
+/// `next_track_id` and the `trex` boxes for fragmented files are derived from
+/// them. Durations are left as zero, since they are filled in by
+/// [`ProgressiveWriter`] or carried by the fragments.
+///
+/// # Example
+///
+/// ```rust
+/// use bmff::*;
+///
+/// # let avc1 = AvcSampleEntry {
+/// #     data_reference_index: 1,
+/// #     width: 640,
+/// #     height: 480,
+/// #     horiz_resolution: U16F16::from_num(72),
+/// #     vert_resolution: U16F16::from_num(72),
+/// #     frame_count: 1,
+/// #     depth: 0x0018,
+/// #     avcc: AvcConfigurationBox {
+/// #         configuration: AvcDecoderConfigurationRecord {
+/// #             profile_idc: 0x64,
+/// #             constraint_set_flag: 0,
+/// #             level_idc: 0x1f,
+/// #             length_size_minus_one: 3,
+/// #             sequence_parameter_sets: vec![vec![0x67, 0x64, 0x00, 0x1f]],
+/// #             picture_parameter_sets: vec![vec![0x68, 0xe9]],
+/// #             extension: vec![],
+/// #         },
+/// #     },
+/// #     colr: None,
+/// #     pasp: None,
+/// #     unknown: vec![],
+/// # };
+/// let moov = MovieBuilder::new()
+///     .fragmented(true)
+///     .track(TrackBuilder::video(avc1, 640, 480, 30))
+///     .build()?;
+/// assert_eq!(moov.trak[0].tkhd.track_id, 1);
+/// assert_eq!(moov.mvhd.next_track_id, 2);
+/// # Ok::<(), bmff::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MovieBuilder {
+    creation_time: DateTime<Utc>,
+    timescale: u32,
+    matrix: [[I16F16; 3]; 3],
+    fragmented: bool,
+    tracks: Vec<TrackBuilder>,
+}
+
+impl MovieBuilder {
+    /// Creates a builder for a non-fragmented movie with a timescale of 1000
+    /// and the current time as its creation time.
+    pub fn new() -> Self {
+        Self {
+            creation_time: Utc::now(),
+            timescale: 1000,
+            matrix: MATRIX_0,
+            fragmented: false,
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Sets the creation and modification times of the movie and all tracks.
+    pub fn creation_time(mut self, creation_time: DateTime<Utc>) -> Self {
+        self.creation_time = creation_time;
+        self
+    }
+
+    /// Sets the timescale of the `mvhd`, which is used for track durations
+    /// and edit lists.
+    pub fn timescale(mut self, timescale: u32) -> Self {
+        self.timescale = timescale;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: [[I16F16; 3]; 3]) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    /// Whether to add an `mvex` box with a `trex` for each track, which is
+    /// needed for fragmented files.
+    pub fn fragmented(mut self, fragmented: bool) -> Self {
+        self.fragmented = fragmented;
+        self
+    }
+
+    pub fn track(mut self, track: TrackBuilder) -> Self {
+        self.tracks.push(track);
+        self
+    }
+
+    /// Builds and validates the `moov`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the width or height of a track doesn't fit in its
+    /// `tkhd`, if a track's language code is invalid, or if [`Validate`]
+    /// fails on the result.
+    pub fn build(self) -> Result<MovieBox, Error> {
+        let time = self.creation_time;
+        let mut trak = Vec::with_capacity(self.tracks.len());
+        let mut trex = Vec::with_capacity(self.tracks.len());
+        for (track_id, track) in (1..).zip(self.tracks) {
+            trex.push(TrackExtendsBox {
+                track_id,
+                default_sample_description_index: 1,
+                default_sample_duration: track.default_sample_duration,
+                default_sample_size: 0,
+                default_sample_flags: track.default_sample_flags,
+            });
+            trak.push(track.build(track_id, self.timescale, time)?);
+        }
+        let next_track_id = trak.len() as u32 + 1;
+
+        let moov = MovieBox {
+            mvhd: MovieHeaderBox {
+                creation_time: time,
+                modification_time: time,
+                timescale: self.timescale,
+                duration: Some(Duration::zero()),
+                rate: I16F16::from_num(1),
+                volume: I8F8::from_num(1),
+                matrix: self.matrix,
+                next_track_id,
+            },
+            trak,
+            mvex: self.fragmented.then_some(MovieExtendsBox {
+                mehd: None,
+                trex,
+                unknown: vec![],
+            }),
+            pssh: vec![],
+            udta: None,
+            unknown: vec![],
+        };
+        moov.validate()?;
+        Ok(moov)
+    }
+}
+
+impl Default for MovieBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TrackKind {
+    Video { width: u32, height: u32 },
+    Audio,
+    Text,
+}
+
+/// A description of a track for [`MovieBuilder`].
+#[derive(Debug)]
+pub struct TrackBuilder {
+    kind: TrackKind,
+    sample_entries: Vec<Box<dyn SampleEntry>>,
+    timescale: u32,
+    language: [u8; 3],
+    name: Option<String>,
+    matrix: [[I16F16; 3]; 3],
+    default_sample_duration: u32,
+    default_sample_flags: SampleFlags,
+}
+
+impl Clone for TrackBuilder {
+    fn clone(&self) -> Self {
+        Self {
+            sample_entries: self.sample_entries.iter().map(|x| x.clone_box()).collect(),
+            name: self.name.clone(),
+            ..*self
+        }
+    }
+}
+
+impl TrackBuilder {
+    fn new(kind: TrackKind, sample_entry: Box<dyn SampleEntry>, timescale: u32) -> Self {
+        Self {
+            kind,
+            sample_entries: vec![sample_entry],
+            timescale,
+            language: *b"und",
+            name: None,
+            matrix: MATRIX_0,
+            default_sample_duration: 0,
+            default_sample_flags: SampleFlags::default(),
+        }
+    }
+
+    /// A video track. `width` and `height` are the display size, which may
+    /// differ from the coded size in the sample entry.
+    pub fn video(
+        sample_entry: impl SampleEntry + 'static,
+        width: u32,
+        height: u32,
+        timescale: u32,
+    ) -> Self {
+        Self::new(
+            TrackKind::Video { width, height },
+            Box::new(sample_entry),
+            timescale,
+        )
+    }
+
+    /// An audio track. The timescale is usually the sample rate.
+    pub fn audio(sample_entry: impl SampleEntry + 'static, timescale: u32) -> Self {
+        Self::new(TrackKind::Audio, Box::new(sample_entry), timescale)
+    }
+
+    /// A timed text track, such as a [`WvttSampleEntry`].
+    pub fn text(sample_entry: impl SampleEntry + 'static, timescale: u32) -> Self {
+        Self::new(TrackKind::Text, Box::new(sample_entry), timescale)
+    }
+
+    /// Adds another sample description, for tracks whose codec configuration
+    /// changes partway through.
+    pub fn sample_entry(mut self, sample_entry: impl SampleEntry + 'static) -> Self {
+        self.sample_entries.push(Box::new(sample_entry));
+        self
+    }
+
+    /// Sets the ISO 639-2/T language code, such as `*b"eng"`. The default is
+    /// `*b"und"`. [`MovieBuilder::build`] returns an error if the code isn't
+    /// three lowercase ASCII letters.
+    pub fn language(mut self, language: [u8; 3]) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Sets the name in the `hdlr` box. The default is based on the kind of
+    /// track, such as `VideoHandler`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the transformation matrix of the track, such as [`MATRIX_90`] for
+    /// video from a rotated camera.
+    pub fn matrix(mut self, matrix: [[I16F16; 3]; 3]) -> Self {
+        self.matrix = matrix;
+        self
+    }
+
+    /// Sets the sample duration in the track's `trex` box.
+    pub fn default_sample_duration(mut self, duration: u32) -> Self {
+        self.default_sample_duration = duration;
+        self
+    }
+
+    /// Sets the sample flags in the track's `trex` box.
+    pub fn default_sample_flags(mut self, flags: SampleFlags) -> Self {
+        self.default_sample_flags = flags;
+        self
+    }
+
+    fn build(
+        self,
+        track_id: u32,
+        movie_timescale: u32,
+        time: DateTime<Utc>,
+    ) -> Result<TrackBox, Error> {
+        let duration = Some(Duration::zero());
+        let (handler_type, name, media_header) = match self.kind {
+            TrackKind::Video { .. } => (
+                HandlerType::Video,
+                "VideoHandler",
+                MediaHeader::Video(VideoMediaHeaderBox {
+                    graphics_mode: GraphicsMode::Copy,
+                    opcolor: [0, 0, 0],
+                }),
+            ),
+            TrackKind::Audio => (
+                HandlerType::Audio,
+                "SoundHandler",
+                MediaHeader::Sound(SoundMediaHeaderBox {
+                    balance: I8F8::ZERO,
+                }),
+            ),
+            TrackKind::Text => (
+                HandlerType::Text,
+                "TextHandler",
+                MediaHeader::Null(NullMediaHeaderBox {
+                    flags: NullMediaHeaderFlags::empty(),
+                }),
+            ),
+        };
+        if !self.language.iter().all(u8::is_ascii_lowercase) {
+            return Err(Error::Invalid(
+                *b"mdhd",
+                "language must be an ISO 639-2/T code in lowercase letters",
+            ));
+        }
+        let (width, height) = match self.kind {
+            TrackKind::Video { width, height } => (width, height),
+            TrackKind::Audio | TrackKind::Text => (0, 0),
+        };
+        let (width, height) = (
+            U16F16::checked_from_num(width).ok_or(Error::Overflow(*b"tkhd", "width"))?,
+            U16F16::checked_from_num(height).ok_or(Error::Overflow(*b"tkhd", "height"))?,
+        );
+        let volume = match self.kind {
+            TrackKind::Audio => I8F8::from_num(1),
+            TrackKind::Video { .. } | TrackKind::Text => I8F8::ZERO,
+        };
+
+        Ok(TrackBox {
+            tkhd: TrackHeaderBox {
+                flags: TrackHeaderFlags::TRACK_ENABLED
+                    | TrackHeaderFlags::TRACK_IN_MOVIE
+                    | TrackHeaderFlags::TRACK_IN_PREVIEW,
+                creation_time: time,
+                modification_time: time,
+                track_id,
+                timescale: movie_timescale,
+                duration,
+                layer: 0,
+                alternate_group: 0,
+                volume,
+                matrix: self.matrix,
+                width,
+                height,
+            },
+            tref: None,
+            edts: None,
+            mdia: MediaBox {
+                mdhd: MediaHeaderBox {
+                    creation_time: time,
+                    modification_time: time,
+                    timescale: self.timescale,
+                    duration,
+                    language: self.language,
+                },
+                hdlr: HandlerBox {
+                    handler_type,
+                    name: self.name.unwrap_or_else(|| name.to_string()),
+                },
+                minf: MediaInformationBox {
+                    media_header,
+                    dinf: DataInformationBox {
+                        dref: DataReferenceBox {
+                            data_entries: vec![DataEntry::Url(DataEntryUrlBox {
+                                flags: DataEntryFlags::SELF_CONTAINED,
+                                location: String::new(),
+                            })],
+                        },
+                    },
+                    stbl: SampleTableBox {
+                        stsd: SampleDescriptionBox {
+                            entries: self.sample_entries,
+                        },
+                        stts: TimeToSampleBox { samples: vec![] },
+                        ctts: None,
+                        stsc: SampleToChunkBox { entries: vec![] },
+                        stsz: SampleSizeBox {
+                            sample_size: SampleSize::Different(vec![]),
+                        },
+                        chunk_offset: ChunkOffset::Compact(ChunkOffsetBox {
+                            chunk_offsets: vec![],
+                        }),
+                        stss: None,
+                        unknown: vec![],
+                    },
+                    unknown: vec![],
+                },
+                unknown: vec![],
+            },
+            udta: None,
+            unknown: vec![],
+        })
+    }
+}
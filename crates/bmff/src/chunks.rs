@@ -0,0 +1,174 @@
+use crate::*;
+use std::io::IoSlice;
+
+/// A part of the output of [`Chunks`].
+#[derive(Debug, Clone)]
+pub enum Chunk {
+    /// Bytes written for this output only, such as box headers
+    Owned(Vec<u8>),
+    /// An `mdat` payload that is shared with other outputs
+    Shared(Arc<Vec<u8>>),
+}
+
+impl AsRef<[u8]> for Chunk {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Owned(x) => x,
+            Self::Shared(x) => x,
+        }
+    }
+}
+
+/// Serialized boxes, split into chunks so that `mdat` payloads are shared
+/// rather than copied.
+///
+/// Boxes are written with the [`Write`] impl, which appends to an owned
+/// chunk, and `mdat` boxes with [`push_mdat`](Self::push_mdat). This is meant
+/// for sending the same media segment to many clients: only the headers are
+/// written for each one.
+///
+/// # Example
+///
+/// ```rust
+/// use bmff::{write_to, Chunks, MediaDataBox};
+/// use std::sync::Arc;
+///
+/// let mdat = MediaDataBox {
+///     headers: None,
+///     data: Arc::new(vec![0; 1024]),
+/// };
+/// let mut chunks = Chunks::new();
+/// chunks.push_mdat(&mdat)?;
+/// assert_eq!(chunks.len(), 8 + 1024);
+///
+/// let mut buf = Vec::new();
+/// chunks.write_all_to(&mut buf)?;
+/// let mut expected = Vec::new();
+/// write_to(&mdat, &mut expected)?;
+/// assert_eq!(buf, expected);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Chunks {
+    chunks: Vec<Chunk>,
+}
+
+impl Chunks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes an `mdat` box. The header and [`headers`](MediaDataBox::headers)
+    /// are copied, but [`data`](MediaDataBox::data) is only referenced.
+    pub fn push_mdat(&mut self, mdat: &MediaDataBox) -> io::Result<()> {
+        let size = mdat.size();
+        if u32::try_from(size).is_ok() {
+            self.write_all(&(size as u32).to_be_bytes())?;
+            self.write_all(&MediaDataBox::TYPE)?;
+        } else {
+            self.write_all(&1u32.to_be_bytes())?;
+            self.write_all(&MediaDataBox::TYPE)?;
+            self.write_all(&size.to_be_bytes())?;
+        }
+        if let Some(headers) = &mdat.headers {
+            self.write_all(headers)?;
+        }
+        if !mdat.data.is_empty() {
+            self.chunks.push(Chunk::Shared(Arc::clone(&mdat.data)));
+        }
+        Ok(())
+    }
+
+    /// The total size of the chunks in bytes.
+    pub fn len(&self) -> u64 {
+        self.chunks.iter().map(|x| x.as_ref().len() as u64).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Chunk> {
+        self.chunks.iter()
+    }
+
+    /// Returns the chunks as slices for [`Write::write_vectored`].
+    pub fn io_slices(&self) -> Vec<IoSlice<'_>> {
+        self.chunks
+            .iter()
+            .map(|x| IoSlice::new(x.as_ref()))
+            .collect()
+    }
+
+    /// Writes all chunks to `w` with [`Write::write_vectored`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails or `w` stops accepting data.
+    pub fn write_all_to(&self, mut w: impl Write) -> io::Result<()> {
+        // index of the first chunk that hasn't been fully written, and how
+        // much of it has been written
+        let mut chunk = 0;
+        let mut offset = 0;
+        while chunk < self.chunks.len() {
+            let first = &self.chunks[chunk].as_ref()[offset..];
+            let slices: Vec<_> = std::iter::once(first)
+                .chain(self.chunks[chunk + 1..].iter().map(AsRef::as_ref))
+                .map(IoSlice::new)
+                .collect();
+            let mut written = w.write_vectored(&slices)?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            while chunk < self.chunks.len() {
+                let remaining = self.chunks[chunk].as_ref().len() - offset;
+                if written < remaining {
+                    offset += written;
+                    break;
+                }
+                written -= remaining;
+                chunk += 1;
+                offset = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for Chunks {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.chunks.last_mut() {
+            Some(Chunk::Owned(last)) => last.extend_from_slice(buf),
+            _ => self.chunks.push(Chunk::Owned(buf.to_vec())),
+        }
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl IntoIterator for Chunks {
+    type Item = Chunk;
+    type IntoIter = std::vec::IntoIter<Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Chunks {
+    type Item = &'a Chunk;
+    type IntoIter = std::slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter()
+    }
+}
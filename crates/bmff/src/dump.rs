@@ -0,0 +1,647 @@
+//! Box tree dumps for debugging.
+
+use crate::*;
+use serde_json::{json, Map, Value};
+use std::fmt;
+
+/// A box with its decoded fields and children, as printed by the `bmff`
+/// binary.
+///
+/// The [`Display`](fmt::Display) impl prints an indented tree, and
+/// [`to_json`](Self::to_json) returns the same information as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxNode {
+    pub header: BoxHeader,
+    /// Decoded fields, in the order they appear in the box. Boxes that aren't
+    /// known have none.
+    pub fields: Vec<(&'static str, Value)>,
+    pub children: Vec<BoxNode>,
+    /// Why decoding the box failed. Fields and children before the error are
+    /// kept.
+    pub error: Option<String>,
+}
+
+/// Reads the box tree of a file. `mdat` payloads are skipped rather than read
+/// into memory.
+///
+/// Malformed boxes are reported in [`BoxNode::error`] so that the rest of the
+/// tree can still be printed.
+///
+/// # Errors
+///
+/// Returns an error if reading from `r` fails or a top-level box header is
+/// invalid or truncated.
+pub fn read_tree(mut r: impl Read + Seek) -> io::Result<Vec<BoxNode>> {
+    let mut pos = r.stream_position()?;
+    let len = r.seek(io::SeekFrom::End(0))?;
+    r.seek(io::SeekFrom::Start(pos))?;
+    let mut nodes = Vec::new();
+    while pos < len {
+        let mut header = read_header(&mut r)?;
+        header.offset = pos;
+        if header.size == 0 {
+            header.size = len - pos;
+        }
+        if pos + header.size > len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        pos += header.size;
+        if header.box_type == *b"mdat" {
+            r.seek(io::SeekFrom::Start(pos))?;
+            nodes.push(BoxNode::new(header));
+        } else {
+            let payload = read_payload(&header, &mut r)?;
+            nodes.push(BoxNode::decode(BoxReader::from_payload(header, &payload)));
+        }
+    }
+    Ok(nodes)
+}
+
+impl BoxNode {
+    fn new(header: BoxHeader) -> Self {
+        Self {
+            header,
+            fields: Vec::new(),
+            children: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn decode(mut r: BoxReader<'_>) -> Self {
+        let mut node = Self::new(*r.header());
+        if let Err(e) = node.decode_payload(&mut r) {
+            node.error = Some(e.to_string());
+        }
+        node
+    }
+
+    fn field(&mut self, name: &'static str, value: impl Into<Value>) {
+        self.fields.push((name, value.into()));
+    }
+
+    /// Returns the first field called `name`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|(x, _)| *x == name).map(|(_, x)| x)
+    }
+
+    /// Returns the first descendant with type `box_type`, searching depth
+    /// first.
+    pub fn find(&self, box_type: &[u8; 4]) -> Option<&BoxNode> {
+        self.children.iter().find_map(|child| {
+            if child.header.box_type == *box_type {
+                Some(child)
+            } else {
+                child.find(box_type)
+            }
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut obj = Map::new();
+        obj.insert("type".into(), fourcc(&self.header.box_type).into());
+        obj.insert("offset".into(), self.header.offset.into());
+        obj.insert("size".into(), self.header.size.into());
+        if !self.fields.is_empty() {
+            let fields = self
+                .fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect();
+            obj.insert("fields".into(), Value::Object(fields));
+        }
+        if !self.children.is_empty() {
+            let children = self.children.iter().map(Self::to_json).collect();
+            obj.insert("children".into(), Value::Array(children));
+        }
+        if let Some(error) = &self.error {
+            obj.insert("error".into(), error.clone().into());
+        }
+        Value::Object(obj)
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let indent = depth * 2;
+        writeln!(
+            f,
+            "{:indent$}[{}] offset={} size={}",
+            "",
+            fourcc(&self.header.box_type),
+            self.header.offset,
+            self.header.size,
+        )?;
+        for (name, value) in &self.fields {
+            match value {
+                Value::Array(items) if items.iter().any(Value::is_object) => {
+                    writeln!(f, "{:indent$}  {name}:", "")?;
+                    for (i, item) in items.iter().enumerate() {
+                        writeln!(f, "{:indent$}    [{i}] {}", "", Inline(item))?;
+                    }
+                }
+                value => writeln!(f, "{:indent$}  {name} = {}", "", Inline(value))?,
+            }
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "{:indent$}  error: {error}", "")?;
+        }
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn decode_payload(&mut self, r: &mut BoxReader<'_>) -> io::Result<()> {
+        match &self.header.box_type {
+            b"moov" | b"trak" | b"edts" | b"mdia" | b"minf" | b"dinf" | b"stbl" | b"mvex"
+            | b"udta" | b"ilst" | b"moof" | b"traf" | b"mfra" | b"vttc" | b"sinf" | b"schi" => (),
+            b"meta" => self.version_flags(r)?,
+            b"stsd" | b"dref" => {
+                self.version_flags(r)?;
+                self.field("entry_count", r.read_u32()?);
+            }
+            b"avc1" | b"avc3" | b"hvc1" | b"hev1" | b"av01" | b"encv" => {
+                self.visual_sample_entry(r)?
+            }
+            b"mp4a" | b"Opus" => self.audio_sample_entry(r)?,
+            b"wvtt" => {
+                r.skip(6)?;
+                self.field("data_reference_index", r.read_u16()?);
+            }
+            b"ftyp" => {
+                let ftyp: FileTypeBox = parse(r)?;
+                self.brands(
+                    ftyp.major_brand,
+                    ftyp.minor_version,
+                    &ftyp.compatible_brands,
+                );
+            }
+            b"styp" => {
+                let styp: SegmentTypeBox = parse(r)?;
+                self.brands(
+                    styp.major_brand,
+                    styp.minor_version,
+                    &styp.compatible_brands,
+                );
+            }
+            b"mvhd" => {
+                let mvhd: MovieHeaderBox = self.parse_full(r)?;
+                self.field("creation_time", mvhd.creation_time.to_rfc3339());
+                self.field("modification_time", mvhd.modification_time.to_rfc3339());
+                self.field("timescale", mvhd.timescale);
+                self.field("duration_ms", milliseconds(mvhd.duration));
+                self.field("rate", mvhd.rate.to_num::<f64>());
+                self.field("volume", mvhd.volume.to_num::<f64>());
+                self.field("next_track_id", mvhd.next_track_id);
+            }
+            b"tkhd" => {
+                let tkhd: TrackHeaderBox = self.parse_full(r)?;
+                self.field("track_id", tkhd.track_id);
+                self.field("duration_ms", milliseconds(tkhd.duration));
+                self.field("layer", tkhd.layer);
+                self.field("alternate_group", tkhd.alternate_group);
+                self.field("volume", tkhd.volume.to_num::<f64>());
+                self.field("width", tkhd.width.to_num::<f64>());
+                self.field("height", tkhd.height.to_num::<f64>());
+            }
+            b"mdhd" => {
+                let mdhd: MediaHeaderBox = self.parse_full(r)?;
+                self.field("timescale", mdhd.timescale);
+                self.field("duration_ms", milliseconds(mdhd.duration));
+                if mdhd.language.iter().all(u8::is_ascii_lowercase) {
+                    self.field("language", String::from_utf8_lossy(&mdhd.language));
+                } else {
+                    let packed = mdhd
+                        .language
+                        .iter()
+                        .fold(0u16, |acc, x| acc << 5 | u16::from(x & 0x1f));
+                    self.field("language", format!("{packed:#06x}"));
+                }
+            }
+            b"hdlr" => {
+                let hdlr: HandlerBox = self.parse_full(r)?;
+                let handler_type = hdlr.handler_type.into();
+                self.field("handler_type", fourcc(&handler_type));
+                self.field("name", hdlr.name);
+            }
+            b"elst" => {
+                let elst: EditListBox = self.parse_full(r)?;
+                let entries = elst.entries.iter().map(|(segment_duration, media_time)| {
+                    json!({ "segment_duration": segment_duration, "media_time": media_time })
+                });
+                self.field("entries", entries.collect::<Vec<_>>());
+            }
+            b"stts" => {
+                let stts: TimeToSampleBox = self.parse_full(r)?;
+                let entries = stts.samples.iter().map(|(sample_count, sample_delta)| {
+                    json!({ "sample_count": sample_count, "sample_delta": sample_delta })
+                });
+                self.field("entries", entries.collect::<Vec<_>>());
+            }
+            b"ctts" => {
+                let ctts: CompositionOffsetBox = self.parse_full(r)?;
+                let entries = ctts.samples.iter().map(|(sample_count, sample_offset)| {
+                    json!({ "sample_count": sample_count, "sample_offset": sample_offset })
+                });
+                self.field("entries", entries.collect::<Vec<_>>());
+            }
+            b"stsc" => {
+                let stsc: SampleToChunkBox = self.parse_full(r)?;
+                let entries = stsc.entries.iter().map(|(first, count, index)| {
+                    json!({
+                        "first_chunk": first,
+                        "samples_per_chunk": count,
+                        "sample_description_index": index,
+                    })
+                });
+                self.field("entries", entries.collect::<Vec<_>>());
+            }
+            b"stsz" => match self.parse_full::<SampleSizeBox>(r)?.sample_size {
+                SampleSize::Same { size, sample_count } => {
+                    self.field("sample_size", size.get());
+                    self.field("sample_count", sample_count);
+                }
+                SampleSize::Different(sizes) => self.field("entry_sizes", sizes),
+            },
+            b"stco" => {
+                let stco: ChunkOffsetBox = self.parse_full(r)?;
+                self.field("chunk_offsets", stco.chunk_offsets);
+            }
+            b"co64" => {
+                let co64: ChunkLargeOffsetBox = self.parse_full(r)?;
+                self.field("chunk_offsets", co64.chunk_offsets);
+            }
+            b"stss" => {
+                let stss: SyncSampleBox = self.parse_full(r)?;
+                self.field("sample_numbers", stss.sample_numbers);
+            }
+            b"avcC" => {
+                let avcc = parse::<AvcConfigurationBox>(r)?.configuration;
+                self.field("profile_idc", avcc.profile_idc);
+                self.field("constraint_set_flag", avcc.constraint_set_flag);
+                self.field("level_idc", avcc.level_idc);
+                self.field("length_size_minus_one", avcc.length_size_minus_one);
+                let hex_all = |sets: &[Vec<u8>]| sets.iter().map(|x| hex(x)).collect::<Vec<_>>();
+                self.field(
+                    "sequence_parameter_sets",
+                    hex_all(&avcc.sequence_parameter_sets),
+                );
+                self.field(
+                    "picture_parameter_sets",
+                    hex_all(&avcc.picture_parameter_sets),
+                );
+            }
+            b"hvcC" => {
+                let hvcc = parse::<HevcConfigurationBox>(r)?.configuration;
+                self.field("general_profile_idc", hvcc.general_profile_idc);
+                self.field("general_tier_flag", hvcc.general_tier_flag);
+                self.field("general_level_idc", hvcc.general_level_idc);
+                self.field("chroma_format_idc", hvcc.chroma_format_idc);
+                self.field("bit_depth_luma_minus8", hvcc.bit_depth_luma_minus8);
+                self.field("length_size_minus_one", hvcc.length_size_minus_one);
+                let arrays = hvcc.arrays.iter().map(|array| {
+                    json!({
+                        "nal_unit_type": array.nal_unit_type,
+                        "nal_units": array.nal_units.iter().map(|x| hex(x)).collect::<Vec<_>>(),
+                    })
+                });
+                self.field("arrays", arrays.collect::<Vec<_>>());
+            }
+            b"av1C" => {
+                let av1c = parse::<Av1CodecConfigurationBox>(r)?.configuration;
+                self.field("seq_profile", av1c.seq_profile);
+                self.field("seq_level_idx_0", av1c.seq_level_idx_0);
+                self.field("seq_tier_0", av1c.seq_tier_0);
+                self.field("high_bitdepth", av1c.high_bitdepth);
+                self.field("twelve_bit", av1c.twelve_bit);
+                self.field("monochrome", av1c.monochrome);
+                self.field("config_obus", hex(&av1c.config_obus));
+            }
+            b"colr" => match parse(r)? {
+                ColourInformationBox::Nclx {
+                    colour_primaries,
+                    transfer_characteristics,
+                    matrix_coefficients,
+                    full_range,
+                } => {
+                    self.field("colour_type", "nclx");
+                    self.field("colour_primaries", colour_primaries);
+                    self.field("transfer_characteristics", transfer_characteristics);
+                    self.field("matrix_coefficients", matrix_coefficients);
+                    self.field("full_range", full_range);
+                }
+                ColourInformationBox::RestrictedIcc(profile) => {
+                    self.field("colour_type", "rICC");
+                    self.field("profile_size", profile.len());
+                }
+                ColourInformationBox::Icc(profile) => {
+                    self.field("colour_type", "prof");
+                    self.field("profile_size", profile.len());
+                }
+                ColourInformationBox::Other { colour_type, .. } => {
+                    self.field("colour_type", fourcc(&colour_type));
+                }
+            },
+            b"pasp" => {
+                let pasp: PixelAspectRatioBox = parse(r)?;
+                self.field("h_spacing", pasp.h_spacing);
+                self.field("v_spacing", pasp.v_spacing);
+            }
+            b"vttC" => {
+                let vttc: WebVttConfigurationBox = parse(r)?;
+                self.field("config", vttc.config);
+            }
+            b"mehd" => {
+                let mehd: MovieExtendsHeaderBox = self.parse_full(r)?;
+                self.field(
+                    "fragment_duration_ms",
+                    mehd.fragment_duration.num_milliseconds(),
+                );
+            }
+            b"trex" => {
+                let trex: TrackExtendsBox = self.parse_full(r)?;
+                self.field("track_id", trex.track_id);
+                self.field(
+                    "default_sample_description_index",
+                    trex.default_sample_description_index,
+                );
+                self.field("default_sample_duration", trex.default_sample_duration);
+                self.field("default_sample_size", trex.default_sample_size);
+                self.field(
+                    "default_sample_flags",
+                    sample_flags(trex.default_sample_flags),
+                );
+            }
+            b"mfhd" => {
+                let mfhd: MovieFragmentHeaderBox = self.parse_full(r)?;
+                self.field("sequence_number", mfhd.sequence_number);
+            }
+            b"tfhd" => {
+                let tfhd: TrackFragmentHeaderBox = self.parse_full(r)?;
+                self.field("track_id", tfhd.track_id);
+                if let Some(x) = tfhd.base_data_offset {
+                    self.field("base_data_offset", x);
+                }
+                if let Some(x) = tfhd.sample_description_index {
+                    self.field("sample_description_index", x);
+                }
+                if let Some(x) = tfhd.default_sample_duration {
+                    self.field("default_sample_duration", x);
+                }
+                if let Some(x) = tfhd.default_sample_size {
+                    self.field("default_sample_size", x);
+                }
+                if let Some(x) = tfhd.default_sample_flags {
+                    self.field("default_sample_flags", sample_flags(x));
+                }
+                self.field("default_base_is_moof", tfhd.default_base_is_moof);
+            }
+            b"tfdt" => {
+                let tfdt: TrackFragmentBaseMediaDecodeTimeBox = self.parse_full(r)?;
+                self.field("base_media_decode_time", tfdt.base_media_decode_time);
+            }
+            b"trun" => {
+                let trun: TrackFragmentRunBox = self.parse_full(r)?;
+                if let Some(x) = trun.data_offset {
+                    self.field("data_offset", x);
+                }
+                if let Some(x) = trun.first_sample_flags {
+                    self.field("first_sample_flags", sample_flags(x));
+                }
+                let samples = (0..trun.len().unwrap_or(0)).map(|i| {
+                    let mut sample = Map::new();
+                    if let Some(x) = &trun.sample_durations {
+                        sample.insert("duration".into(), x[i].into());
+                    }
+                    if let Some(x) = &trun.sample_sizes {
+                        sample.insert("size".into(), x[i].into());
+                    }
+                    if let Some(x) = &trun.sample_flags {
+                        sample.insert("flags".into(), sample_flags(x[i]).into());
+                    }
+                    if let Some(x) = &trun.sample_composition_time_offsets {
+                        sample.insert("composition_time_offset".into(), x[i].into());
+                    }
+                    Value::Object(sample)
+                });
+                self.field("samples", samples.collect::<Vec<_>>());
+            }
+            b"sidx" => {
+                let sidx: SegmentIndexBox = self.parse_full(r)?;
+                self.field("reference_id", sidx.reference_id);
+                self.field("timescale", sidx.timescale);
+                self.field(
+                    "earliest_presentation_time",
+                    sidx.earliest_presentation_time,
+                );
+                self.field("first_offset", sidx.first_offset);
+                let references = sidx.references.iter().map(|x| {
+                    json!({
+                        "reference_type": format!("{:?}", x.reference_type),
+                        "referenced_size": x.referenced_size,
+                        "subsegment_duration": x.subsegment_duration,
+                        "starts_with_sap": x.starts_with_sap,
+                        "sap_type": x.sap_type,
+                        "sap_delta_time": x.sap_delta_time,
+                    })
+                });
+                self.field("references", references.collect::<Vec<_>>());
+            }
+            b"emsg" => {
+                let emsg: EventMessageBox = self.parse_full(r)?;
+                self.field("scheme_id_uri", emsg.scheme_id_uri);
+                self.field("value", emsg.value);
+                self.field("timescale", emsg.timescale);
+                match emsg.presentation_time {
+                    EventTime::Delta(x) => self.field("presentation_time_delta", x),
+                    EventTime::Absolute(x) => self.field("presentation_time", x),
+                }
+                self.field("event_duration", emsg.event_duration);
+                self.field("id", emsg.id);
+                self.field("message_data", hex(&emsg.message_data));
+            }
+            b"prft" => {
+                let prft: ProducerReferenceTimeBox = self.parse_full(r)?;
+                self.field("reference_track_id", prft.reference_track_id);
+                self.field("time_source", format!("{:?}", prft.time_source));
+                self.field("ntp_timestamp", prft.ntp_timestamp.to_rfc3339());
+                self.field("media_time", prft.media_time);
+            }
+            b"pssh" => {
+                let pssh: ProtectionSystemSpecificHeaderBox = self.parse_full(r)?;
+                self.field("system_id", hex(&pssh.system_id));
+                if !pssh.key_ids.is_empty() {
+                    let key_ids = pssh.key_ids.iter().map(|x| hex(x));
+                    self.field("key_ids", key_ids.collect::<Vec<_>>());
+                }
+                self.field("data_size", pssh.data.len());
+            }
+            b"frma" => {
+                let frma: OriginalFormatBox = parse(r)?;
+                self.field("data_format", fourcc(&frma.data_format));
+            }
+            b"schm" => {
+                let schm: SchemeTypeBox = self.parse_full(r)?;
+                self.field("scheme_type", fourcc(&schm.scheme_type));
+                self.field("scheme_version", format!("{:#010x}", schm.scheme_version));
+                if let Some(uri) = schm.scheme_uri {
+                    self.field("scheme_uri", uri);
+                }
+            }
+            b"tenc" => {
+                let tenc: TrackEncryptionBox = self.parse_full(r)?;
+                self.field("default_crypt_byte_block", tenc.default_crypt_byte_block);
+                self.field("default_skip_byte_block", tenc.default_skip_byte_block);
+                self.field("default_is_protected", tenc.default_is_protected);
+                self.field(
+                    "default_per_sample_iv_size",
+                    tenc.default_per_sample_iv_size,
+                );
+                self.field("default_kid", hex(&tenc.default_kid));
+                if let Some(iv) = tenc.default_constant_iv {
+                    self.field("default_constant_iv", hex(&iv));
+                }
+            }
+            b"senc" => {
+                // the IV size is only in the `tenc`, so the entries aren't
+                // decoded
+                self.version_flags(r)?;
+                self.field("sample_count", r.read_u32()?);
+                r.read_to_end();
+            }
+            b"saiz" => {
+                let saiz: SampleAuxiliaryInformationSizesBox = self.parse_full(r)?;
+                if let Some((aux_info_type, parameter)) = saiz.aux_info_type {
+                    self.field("aux_info_type", fourcc(&aux_info_type));
+                    self.field("aux_info_type_parameter", parameter);
+                }
+                match saiz.sample_info_size {
+                    SampleInfoSize::Same { size, sample_count } => {
+                        self.field("default_sample_info_size", size.get());
+                        self.field("sample_count", sample_count);
+                    }
+                    SampleInfoSize::Different(sizes) => {
+                        self.field("sample_info_sizes", sizes);
+                    }
+                }
+            }
+            b"saio" => {
+                let saio: SampleAuxiliaryInformationOffsetsBox = self.parse_full(r)?;
+                if let Some((aux_info_type, parameter)) = saio.aux_info_type {
+                    self.field("aux_info_type", fourcc(&aux_info_type));
+                    self.field("aux_info_type_parameter", parameter);
+                }
+                self.field("offsets", saio.offsets);
+            }
+            // the payload isn't known to hold boxes
+            _ => {
+                r.read_to_end();
+            }
+        }
+        while let Some(child) = r.next_child()? {
+            self.children.push(Self::decode(child));
+        }
+        Ok(())
+    }
+
+    fn version_flags(&mut self, r: &mut BoxReader<'_>) -> io::Result<()> {
+        let version_flags = r.read_u32()?;
+        self.field("version", version_flags >> 24);
+        self.field("flags", format!("{:#08x}", version_flags & 0x00ff_ffff));
+        Ok(())
+    }
+
+    /// Parses a full box, adding its version and flags as fields.
+    fn parse_full<T: ReadBox + FullBox>(&mut self, r: &mut BoxReader<'_>) -> io::Result<T> {
+        self.version_flags(&mut r.clone())?;
+        let parsed = r.clone().parse_full()?;
+        r.read_to_end();
+        Ok(parsed)
+    }
+
+    fn brands(&mut self, major_brand: [u8; 4], minor_version: u32, compatible_brands: &[[u8; 4]]) {
+        self.field("major_brand", fourcc(&major_brand));
+        self.field("minor_version", minor_version);
+        let brands = compatible_brands.iter().map(fourcc);
+        self.field("compatible_brands", brands.collect::<Vec<_>>());
+    }
+
+    fn visual_sample_entry(&mut self, r: &mut BoxReader<'_>) -> io::Result<()> {
+        r.skip(6)?;
+        self.field("data_reference_index", r.read_u16()?);
+        r.skip(16)?;
+        self.field("width", r.read_u16()?);
+        self.field("height", r.read_u16()?);
+        self.field("horiz_resolution", fixed_point(r.read_u32()?));
+        self.field("vert_resolution", fixed_point(r.read_u32()?));
+        r.skip(4)?;
+        self.field("frame_count", r.read_u16()?);
+        let name: [u8; 32] = r.read_array()?;
+        let len = (name[0] as usize).min(31);
+        self.field(
+            "compressor_name",
+            String::from_utf8_lossy(&name[1..1 + len]),
+        );
+        self.field("depth", r.read_u16()?);
+        r.skip(2)?;
+        Ok(())
+    }
+
+    fn audio_sample_entry(&mut self, r: &mut BoxReader<'_>) -> io::Result<()> {
+        r.skip(6)?;
+        self.field("data_reference_index", r.read_u16()?);
+        r.skip(8)?;
+        self.field("channel_count", r.read_u16()?);
+        self.field("sample_size", r.read_u16()?);
+        r.skip(4)?;
+        self.field("sample_rate", r.read_u32()? >> 16);
+        Ok(())
+    }
+}
+
+impl fmt::Display for BoxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Formats a value on one line, with object fields as `name=value`.
+struct Inline<'a>(&'a Value);
+
+impl fmt::Display for Inline<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Value::String(s) => f.write_str(s),
+            Value::Object(obj) => {
+                for (i, (name, value)) in obj.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{name}={}", Inline(value))?;
+                }
+                Ok(())
+            }
+            value => write!(f, "{value}"),
+        }
+    }
+}
+
+fn parse<T: ReadBox>(r: &mut BoxReader<'_>) -> io::Result<T> {
+    let parsed = r.clone().parse()?;
+    r.read_to_end();
+    Ok(parsed)
+}
+
+fn milliseconds(duration: Option<Duration>) -> Value {
+    duration.map(|x| x.num_milliseconds()).into()
+}
+
+fn fixed_point(x: u32) -> f64 {
+    U16F16::from_bits(x).to_num()
+}
+
+fn sample_flags(flags: SampleFlags) -> String {
+    format!("{:#010x}", u32::from(flags))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
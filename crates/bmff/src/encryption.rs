@@ -0,0 +1,387 @@
+//! Common Encryption (ISO 23001-7) of samples.
+//!
+//! An [`Encryptor`] encrypts samples in place with the `cenc` or `cbcs`
+//! scheme and returns the [`SampleEncryptionEntry`] of each one, which goes in
+//! the `senc` box of its track fragment. The sample entry of the track is
+//! wrapped with [`Encryptor::encv`], so players that have the key can find the
+//! scheme and the original codec.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bmff::encryption::Encryptor;
+//! use bmff::AvcDecoderConfigurationRecord;
+//!
+//! let config = AvcDecoderConfigurationRecord {
+//!     profile_idc: 0x64,
+//!     constraint_set_flag: 0,
+//!     level_idc: 0x1f,
+//!     length_size_minus_one: 3,
+//!     sequence_parameter_sets: vec![vec![
+//!         0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x80, 0x50, 0x05, 0xbb, 0x01, 0x6a, 0x02, 0x02,
+//!         0x02, 0x80, 0x00, 0x00, 0x03, 0x00, 0x80, 0x00, 0x00, 0x1e, 0x07, 0x8c, 0x18, 0xcd,
+//!     ]],
+//!     picture_parameter_sets: vec![vec![0x68, 0xe9, 0x7b, 0x2c, 0x8b]],
+//!     extension: vec![],
+//! };
+//! let mut encryptor = Encryptor::cbcs([0x11; 16], [0x22; 16], [0x33; 16]);
+//! // an IDR slice with a 4-byte length prefix and a 4-byte slice header
+//! let mut sample = vec![0, 0, 0, 200, 0x65, 0x88, 0x84, 0x00, 0xff];
+//! sample.resize(204, 0xab);
+//! let original = sample.clone();
+//!
+//! let subsamples = encryptor.avc_subsamples(&config, &sample)?;
+//! let entry = encryptor.encrypt(&mut sample, subsamples)?;
+//! assert_eq!(entry.subsamples[0].clear_bytes, 9);
+//! assert_eq!(sample[..9], original[..9]);
+//!
+//! encryptor.decrypt(&mut sample, &entry)?;
+//! assert_eq!(sample, original);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::*;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, StreamCipher};
+use aes::{Aes128, Block};
+use std::fmt;
+use std::ops::Range;
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+
+const BLOCK_SIZE: usize = 16;
+
+/// The `scheme_version` of Common Encryption.
+const SCHEME_VERSION: u32 = 0x0001_0000;
+
+/// A protection scheme of Common Encryption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    /// AES-CTR of all protected bytes, with an 8-byte IV for each sample
+    Cenc,
+    /// AES-CBC of a pattern of protected blocks, with a constant IV
+    Cbcs,
+}
+
+impl Scheme {
+    /// The `scheme_type` in the `schm` box.
+    pub fn scheme_type(self) -> [u8; 4] {
+        match self {
+            Self::Cenc => *b"cenc",
+            Self::Cbcs => *b"cbcs",
+        }
+    }
+}
+
+/// Encrypts the samples of a track with a single key.
+#[derive(Clone)]
+pub struct Encryptor {
+    scheme: Scheme,
+    key: [u8; 16],
+    kid: [u8; 16],
+    /// For `cenc`, the IV of the next sample is in the first 8 bytes
+    iv: [u8; 16],
+    crypt_byte_block: u8,
+    skip_byte_block: u8,
+}
+
+impl fmt::Debug for Encryptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryptor")
+            .field("scheme", &self.scheme)
+            .field("kid", &self.kid)
+            .field("crypt_byte_block", &self.crypt_byte_block)
+            .field("skip_byte_block", &self.skip_byte_block)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Encryptor {
+    /// Creates an encryptor for the `cenc` scheme. `iv` is used for the first
+    /// sample and incremented for each one after it.
+    pub fn cenc(key: [u8; 16], kid: [u8; 16], iv: [u8; 8]) -> Self {
+        let mut full_iv = [0; 16];
+        full_iv[..8].copy_from_slice(&iv);
+        Self {
+            scheme: Scheme::Cenc,
+            key,
+            kid,
+            iv: full_iv,
+            crypt_byte_block: 0,
+            skip_byte_block: 0,
+        }
+    }
+
+    /// Creates an encryptor for the `cbcs` scheme with the 1:9 pattern used
+    /// for video. `iv` is used for every subsample.
+    pub fn cbcs(key: [u8; 16], kid: [u8; 16], iv: [u8; 16]) -> Self {
+        Self {
+            scheme: Scheme::Cbcs,
+            key,
+            kid,
+            iv,
+            crypt_byte_block: 1,
+            skip_byte_block: 9,
+        }
+    }
+
+    /// Sets the number of encrypted and skipped blocks in the pattern of
+    /// `cbcs`. A pattern of 0:0 encrypts every block, which is used for audio.
+    /// This has no effect on `cenc`.
+    pub fn pattern(mut self, crypt_byte_block: u8, skip_byte_block: u8) -> Self {
+        if self.scheme == Scheme::Cbcs {
+            self.crypt_byte_block = crypt_byte_block;
+            self.skip_byte_block = skip_byte_block;
+        }
+        self
+    }
+
+    #[inline]
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Returns the `tenc` box for the track.
+    pub fn tenc(&self) -> TrackEncryptionBox {
+        let (default_per_sample_iv_size, default_constant_iv) = match self.scheme {
+            Scheme::Cenc => (8, None),
+            Scheme::Cbcs => (0, Some(self.iv.to_vec())),
+        };
+        TrackEncryptionBox {
+            default_crypt_byte_block: self.crypt_byte_block,
+            default_skip_byte_block: self.skip_byte_block,
+            default_is_protected: true,
+            default_per_sample_iv_size,
+            default_kid: self.kid,
+            default_constant_iv,
+        }
+    }
+
+    /// Returns the `sinf` box for a sample entry whose type was `data_format`.
+    pub fn sinf(&self, data_format: [u8; 4]) -> ProtectionSchemeInfoBox {
+        ProtectionSchemeInfoBox {
+            frma: OriginalFormatBox { data_format },
+            schm: Some(SchemeTypeBox {
+                scheme_type: self.scheme.scheme_type(),
+                scheme_version: SCHEME_VERSION,
+                scheme_uri: None,
+            }),
+            schi: Some(SchemeInformationBox {
+                tenc: Some(self.tenc()),
+                unknown: vec![],
+            }),
+            unknown: vec![],
+        }
+    }
+
+    /// Wraps a video sample entry, such as an [`AvcSampleEntry`], in an
+    /// `encv` for this encryptor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry fails validation.
+    pub fn encv(&self, entry: impl SampleEntry + 'static) -> io::Result<EncvSampleEntry> {
+        let mut data_format = [0; 4];
+        data_format.copy_from_slice(&entry.write_to()?[4..8]);
+        Ok(EncvSampleEntry {
+            entry: Box::new(entry),
+            sinf: self.sinf(data_format),
+        })
+    }
+
+    /// Returns the subsamples of an AVC sample. `config` is the record of the
+    /// track, which gives the size of the NAL unit lengths, and parameter sets
+    /// in the sample replace the ones in it.
+    ///
+    /// Only the slice data of coded slice NAL units is encrypted. The length
+    /// prefix, NAL unit header and slice header are left clear, as required
+    /// by ISO 23001-7, and for `cenc`, so are the bytes after the last whole
+    /// block of each slice.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a NAL unit extends past the end of the sample, a
+    /// slice header or parameter set can't be parsed, or a slice uses data
+    /// partitioning or slice groups, which aren't supported.
+    pub fn avc_subsamples(
+        &self,
+        config: &AvcDecoderConfigurationRecord,
+        sample: &[u8],
+    ) -> io::Result<Vec<Subsample>> {
+        let mut parameter_sets = avc::ParameterSets::default();
+        for set in config
+            .sequence_parameter_sets
+            .iter()
+            .chain(&config.picture_parameter_sets)
+        {
+            parameter_sets.add(set)?;
+        }
+
+        let length_size = usize::from(config.length_size_minus_one) + 1;
+        let mut subsamples = Vec::new();
+        let mut clear_bytes = 0;
+        let mut rest = sample;
+        while !rest.is_empty() {
+            let (len, nal) = rest
+                .get(..length_size)
+                .map(|x| x.iter().fold(0, |len, &x| len << 8 | usize::from(x)))
+                .and_then(|len| Some((len, rest.get(length_size..length_size + len)?)))
+                .ok_or_else(|| invalid_data("NAL unit extends past the end of the sample"))?;
+            rest = &rest[length_size + len..];
+
+            let mut protected_bytes = match nal.first().map_or(0, |x| x & 0x1f) {
+                1 | 5 => len - parameter_sets.slice_header_size(nal)?,
+                2..=4 => return Err(invalid_data("data partitioning isn't supported")),
+                7 | 8 => {
+                    parameter_sets.add(nal)?;
+                    0
+                }
+                _ => 0,
+            };
+            if self.scheme == Scheme::Cenc {
+                protected_bytes -= protected_bytes % BLOCK_SIZE;
+            }
+            clear_bytes += length_size + len - protected_bytes;
+            if protected_bytes > 0 {
+                push_subsample(&mut subsamples, clear_bytes, protected_bytes);
+                clear_bytes = 0;
+            }
+        }
+        if clear_bytes > 0 {
+            push_subsample(&mut subsamples, clear_bytes, 0);
+        }
+        Ok(subsamples)
+    }
+
+    /// Encrypts a sample in place and advances to the IV of the next sample.
+    /// If `subsamples` is empty, the whole sample is protected.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subsamples don't add up to the size of the
+    /// sample.
+    pub fn encrypt(
+        &mut self,
+        sample: &mut [u8],
+        subsamples: Vec<Subsample>,
+    ) -> io::Result<SampleEncryptionEntry> {
+        let ranges = protected_ranges(sample.len(), &subsamples)?;
+        let iv = match self.scheme {
+            Scheme::Cenc => {
+                let iv = self.iv[..8].to_vec();
+                self.apply_ctr(sample, &ranges, self.iv);
+                let mut next = [0; 8];
+                next.copy_from_slice(&self.iv[..8]);
+                let next = u64::from_be_bytes(next).wrapping_add(1);
+                self.iv[..8].copy_from_slice(&next.to_be_bytes());
+                iv
+            }
+            Scheme::Cbcs => {
+                for range in ranges {
+                    let mut cipher =
+                        cbc::Encryptor::<Aes128>::new(&self.key.into(), &self.iv.into());
+                    for block in self.pattern_blocks(range) {
+                        cipher.encrypt_block_mut(Block::from_mut_slice(&mut sample[block]));
+                    }
+                }
+                Vec::new()
+            }
+        };
+        Ok(SampleEncryptionEntry { iv, subsamples })
+    }
+
+    /// Decrypts a sample in place. The IV comes from `entry`, or the constant
+    /// IV of the encryptor if the entry doesn't have one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the subsamples don't add up to the size of the
+    /// sample or the IV isn't 8 or 16 bytes.
+    pub fn decrypt(&self, sample: &mut [u8], entry: &SampleEncryptionEntry) -> io::Result<()> {
+        let ranges = protected_ranges(sample.len(), &entry.subsamples)?;
+        let mut iv = [0; 16];
+        match entry.iv.len() {
+            0 => iv = self.iv,
+            8 | 16 => iv[..entry.iv.len()].copy_from_slice(&entry.iv),
+            _ => return Err(invalid_data("IV must be 8 or 16 bytes")),
+        }
+        match self.scheme {
+            Scheme::Cenc => self.apply_ctr(sample, &ranges, iv),
+            Scheme::Cbcs => {
+                for range in ranges {
+                    let mut cipher = cbc::Decryptor::<Aes128>::new(&self.key.into(), &iv.into());
+                    for block in self.pattern_blocks(range) {
+                        cipher.decrypt_block_mut(Block::from_mut_slice(&mut sample[block]));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies AES-CTR to the protected ranges as one continuous stream.
+    fn apply_ctr(&self, sample: &mut [u8], ranges: &[Range<usize>], iv: [u8; 16]) {
+        let mut cipher = Aes128Ctr::new(&self.key.into(), &iv.into());
+        for range in ranges {
+            cipher.apply_keystream(&mut sample[range.clone()]);
+        }
+    }
+
+    /// Returns the encrypted blocks of a protected range for `cbcs`. A partial
+    /// block at the end is left clear.
+    fn pattern_blocks(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let (crypt, skip) = match (self.crypt_byte_block, self.skip_byte_block) {
+            (0, 0) => (1, 0),
+            (crypt, skip) => (crypt as usize, skip as usize),
+        };
+        let mut blocks = Vec::new();
+        let mut pos = range.start;
+        while range.end - pos >= BLOCK_SIZE && crypt > 0 {
+            for _ in 0..crypt {
+                if range.end - pos < BLOCK_SIZE {
+                    break;
+                }
+                blocks.push(pos..pos + BLOCK_SIZE);
+                pos += BLOCK_SIZE;
+            }
+            pos = range.end.min(pos + skip * BLOCK_SIZE);
+        }
+        blocks
+    }
+}
+
+/// Adds a subsample, splitting off clear-only subsamples if `clear_bytes`
+/// doesn't fit in 16 bits.
+fn push_subsample(subsamples: &mut Vec<Subsample>, mut clear_bytes: usize, protected_bytes: usize) {
+    while clear_bytes > u16::MAX as usize {
+        subsamples.push(Subsample {
+            clear_bytes: u16::MAX,
+            protected_bytes: 0,
+        });
+        clear_bytes -= u16::MAX as usize;
+    }
+    subsamples.push(Subsample {
+        clear_bytes: clear_bytes as u16,
+        protected_bytes: protected_bytes as u32,
+    });
+}
+
+fn protected_ranges(len: usize, subsamples: &[Subsample]) -> io::Result<Vec<Range<usize>>> {
+    if subsamples.is_empty() {
+        return Ok(std::iter::once(0..len).collect());
+    }
+    let mut ranges = Vec::with_capacity(subsamples.len());
+    let mut pos = 0;
+    for subsample in subsamples {
+        pos += subsample.clear_bytes as usize;
+        let end = pos + subsample.protected_bytes as usize;
+        ranges.push(pos..end);
+        pos = end;
+    }
+    if pos != len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "subsamples don't add up to the size of the sample",
+        ));
+    }
+    Ok(ranges)
+}
@@ -1,12 +1,45 @@
 //! Low-level definitions for the Base Media File Format (ISO 14496-12).
+//!
+//! # Cargo Features
+//!
+//! - `serde`: Add implementations of [`Serialize`](serde::Serialize) and
+//!   [`Deserialize`](serde::Deserialize) for boxes. Durations are written as
+//!   nanoseconds, and sample entries are tagged with their box type.
+//! - `tokio`: Add the [`async_io`] module, which writes boxes to a tokio
+//!   `AsyncWrite`.
+//! - `dump`: Add the [`dump`] module and a `bmff` binary that prints the box
+//!   tree of a file.
+//! - `encryption`: Add the [`encryption`] module, which encrypts samples with
+//!   the `cenc` or `cbcs` scheme of Common Encryption.
 
 use bitflags::bitflags;
 pub use chrono::{DateTime, Duration, Utc};
 pub use fixed::types::{I16F16, I8F8, U16F16};
+use quick_error::quick_error;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::io::{self, prelude::*};
-use std::num::NonZeroU32;
+use std::num::{NonZeroU32, NonZeroU8};
 use std::sync::Arc;
 
+#[cfg(feature = "tokio")]
+pub mod async_io;
+#[cfg(feature = "encryption")]
+mod avc;
+mod builder;
+mod chunks;
+#[cfg(feature = "dump")]
+pub mod dump;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+mod progressive;
+#[cfg(feature = "serde")]
+mod serde_impls;
+pub mod validate;
+pub use builder::{MovieBuilder, TrackBuilder};
+pub use chunks::{Chunk, Chunks};
+pub use progressive::ProgressiveWriter;
+
 macro_rules! matrix {
     ( $( [ $($val:literal),* $(,)? ] ),* $(,)? ) => {
         [
@@ -45,13 +78,36 @@ pub const MATRIX_270: [[I16F16; 3]; 3] = matrix![
     [0x0000_0000, 0x0000_0000, 0x4000_0000],
 ];
 
+/// Converts a duration to the nearest number of `timescale` ticks. Durations
+/// are kept to the nanosecond, so ticks of any timescale up to 1 GHz survive
+/// being read and written again unchanged.
 fn duration(duration: &Duration, timescale: u32) -> u64 {
-    (duration.num_milliseconds() as f64 / 1000.0 * timescale as f64) as u64
+    let nanos = match duration.num_nanoseconds() {
+        Some(nanos) => i128::from(nanos),
+        None => i128::from(duration.num_milliseconds()) * 1_000_000,
+    };
+    let ticks = (nanos * i128::from(timescale) + 500_000_000).div_euclid(1_000_000_000);
+    ticks.clamp(0, i128::from(u64::MAX)) as u64
+}
+
+/// Converts a number of `timescale` ticks to the nearest nanosecond.
+fn ticks_to_duration(ticks: u64, timescale: u32) -> Duration {
+    let timescale = u64::from(timescale);
+    let secs = i64::try_from(ticks / timescale)
+        .ok()
+        .filter(|&x| x <= i64::MAX / 1000);
+    let Some(secs) = secs else {
+        return Duration::max_value();
+    };
+    let nanos = ((ticks % timescale) * 1_000_000_000 + timescale / 2) / timescale;
+    Duration::seconds(secs) + Duration::nanoseconds(nanos as i64)
 }
 
 pub trait BmffBox {
     const TYPE: [u8; 4];
     const EXTENDED_TYPE: Option<[u8; 16]> = None;
+    /// The size of the box, including the header. Boxes that may not fit in
+    /// 32 bits should pass their size through [`large_size`].
     fn size(&self) -> u64;
     fn write_box(&self, writer: impl Write) -> io::Result<()>;
 }
@@ -68,6 +124,17 @@ pub trait WriteTo {
     fn write_to(&self, writer: impl Write) -> io::Result<()>;
 }
 
+/// Adds the 8-byte `largesize` field to `size` if it doesn't fit in 32 bits.
+/// [`write_to`] uses a `largesize` header for any box whose size doesn't fit.
+#[inline]
+pub fn large_size(size: u64) -> u64 {
+    if size > u32::MAX as u64 {
+        size + 8
+    } else {
+        size
+    }
+}
+
 pub fn write_to<T: BmffBox>(bmff_box: &T, mut w: impl Write) -> io::Result<()> {
     let size = bmff_box.size();
     if u32::try_from(size).is_ok() {
@@ -76,7 +143,7 @@ pub fn write_to<T: BmffBox>(bmff_box: &T, mut w: impl Write) -> io::Result<()> {
     } else {
         w.write_all(&1u32.to_be_bytes())?;
         w.write_all(&T::TYPE)?;
-        w.write_all(&(size + 8).to_be_bytes())?;
+        w.write_all(&size.to_be_bytes())?;
     }
     if let Some(ext_type) = T::EXTENDED_TYPE {
         w.write_all(&ext_type)?;
@@ -93,7 +160,7 @@ pub fn write_to_full<T: FullBox>(bmff_box: &T, mut w: impl Write) -> io::Result<
     } else {
         w.write_all(&1u32.to_be_bytes())?;
         w.write_all(&T::TYPE)?;
-        w.write_all(&(size + 8).to_be_bytes())?;
+        w.write_all(&size.to_be_bytes())?;
     }
     if let Some(ext_type) = T::EXTENDED_TYPE {
         w.write_all(&ext_type)?;
@@ -104,341 +171,1134 @@ pub fn write_to_full<T: FullBox>(bmff_box: &T, mut w: impl Write) -> io::Result<
     Ok(())
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct FileTypeBox {
-    pub major_brand: [u8; 4],
-    pub minor_version: u32,
-    pub compatible_brands: Vec<[u8; 4]>,
+pub trait ReadBox: BmffBox + Sized {
+    /// Reads the box payload. The box header, and the version and flags for
+    /// full boxes, have already been consumed.
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self>;
 }
 
-impl BmffBox for FileTypeBox {
-    const TYPE: [u8; 4] = *b"ftyp";
+quick_error! {
+    /// An error found when validating a box.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum Error {
+        /// Per-sample fields of a box have different numbers of entries.
+        LengthMismatch(box_type: [u8; 4], field: &'static str, expected: usize, found: usize) {
+            display("{} box: expected {} entries in {}, found {}", fourcc(box_type), expected, field, found)
+        }
+        /// A value is too large for the field it is written to.
+        Overflow(box_type: [u8; 4], field: &'static str) {
+            display("{} box: {} is too large", fourcc(box_type), field)
+        }
+        /// A combination of fields or flags that isn't allowed.
+        Invalid(box_type: [u8; 4], reason: &'static str) {
+            display("{} box: {}", fourcc(box_type), reason)
+        }
+    }
+}
 
-    #[inline]
-    fn size(&self) -> u64 {
-        8 + 4 + 4 + self.compatible_brands.len() as u64 * 4
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
     }
+}
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&self.major_brand)?;
-        w.write_all(&self.minor_version.to_be_bytes())?;
-        for i in &self.compatible_brands {
-            w.write_all(i)?;
-        }
+/// Checks that a box can be written without truncating or panicking.
+///
+/// Containers validate their children. Boxes also check their own fields
+/// before writing them, so writing an invalid box returns an
+/// [`InvalidInput`](io::ErrorKind::InvalidInput) error.
+pub trait Validate {
+    fn validate(&self) -> Result<(), Error>;
+}
+
+fn check_bits(box_type: [u8; 4], field: &'static str, value: u64, bits: u32) -> Result<(), Error> {
+    if value >> bits == 0 {
+        Ok(())
+    } else {
+        Err(Error::Overflow(box_type, field))
+    }
+}
+
+/// Times are written as seconds since 1970, so earlier times can't be read
+/// back.
+fn check_times(
+    box_type: [u8; 4],
+    creation_time: &DateTime<Utc>,
+    modification_time: &DateTime<Utc>,
+) -> Result<(), Error> {
+    if creation_time.timestamp() < 0 || modification_time.timestamp() < 0 {
+        Err(Error::Invalid(
+            box_type,
+            "times before 1970 can't be written",
+        ))
+    } else {
         Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct MovieBox {
-    pub mvhd: MovieHeaderBox,
-    pub trak: Vec<TrackBox>,
-    pub mvex: Option<MovieExtendsBox>,
+/// Strings are written null-terminated, so they can't contain a null
+/// character.
+fn check_string(box_type: [u8; 4], string: &str) -> Result<(), Error> {
+    if string.contains('\0') {
+        Err(Error::Invalid(
+            box_type,
+            "strings can't contain a null character",
+        ))
+    } else {
+        Ok(())
+    }
 }
 
-impl BmffBox for MovieBox {
-    const TYPE: [u8; 4] = *b"moov";
+/// Reads a box from `r`, consuming exactly the bytes of the box.
+pub fn read_from<T: ReadBox>(mut r: impl Read) -> io::Result<T> {
+    let header = read_header(&mut r)?;
+    let payload = read_payload(&header, r)?;
+    BoxReader::from_payload(header, &payload).parse()
+}
+
+/// Reads a full box from `r`, consuming exactly the bytes of the box.
+pub fn read_from_full<T: ReadBox + FullBox>(mut r: impl Read) -> io::Result<T> {
+    let header = read_header(&mut r)?;
+    let payload = read_payload(&header, r)?;
+    BoxReader::from_payload(header, &payload).parse_full()
+}
+
+/// Reads a box header from `r`. The offset of the returned header is always 0.
+pub fn read_header(mut r: impl Read) -> io::Result<BoxHeader> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    let mut header_size = 8;
+    let box_type = [buf[4], buf[5], buf[6], buf[7]];
+    let mut size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as u64;
+    if size == 1 {
+        r.read_exact(&mut buf)?;
+        header_size += 8;
+        size = u64::from_be_bytes(buf);
+    }
+    let extended_type = if box_type == *b"uuid" {
+        let mut ext_type = [0u8; 16];
+        r.read_exact(&mut ext_type)?;
+        header_size += 16;
+        Some(ext_type)
+    } else {
+        None
+    };
+    if size != 0 && size < header_size {
+        return Err(invalid_data(format!(
+            "{} box is smaller than its header",
+            fourcc(&box_type)
+        )));
+    }
+    Ok(BoxHeader {
+        box_type,
+        extended_type,
+        offset: 0,
+        size,
+        header_size,
+    })
+}
+
+fn read_payload(header: &BoxHeader, mut r: impl Read) -> io::Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    if header.size == 0 {
+        r.read_to_end(&mut payload)?;
+    } else {
+        r.take(header.payload_size()).read_to_end(&mut payload)?;
+        if (payload.len() as u64) < header.payload_size() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+    Ok(payload)
+}
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn missing_box(box_type: &[u8; 4], parent: &[u8; 4]) -> io::Error {
+    invalid_data(format!(
+        "{} box is missing a {} box",
+        fourcc(parent),
+        fourcc(box_type)
+    ))
+}
 
+fn fourcc(box_type: &[u8; 4]) -> String {
+    String::from_utf8_lossy(box_type).into_owned()
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoxHeader {
+    pub box_type: [u8; 4],
+    pub extended_type: Option<[u8; 16]>,
+    /// Offset of the start of the box from the start of the stream
+    pub offset: u64,
+    /// Size of the box including the header, or 0 if the box extends to the
+    /// end of the stream
+    pub size: u64,
+    pub header_size: u64,
+}
+
+impl BoxHeader {
     #[inline]
-    fn size(&self) -> u64 {
-        8 + self.mvhd.size()
-            + self.trak.iter().map(BmffBox::size).sum::<u64>()
-            + self.mvex.as_ref().map_or(0, BmffBox::size)
+    pub fn payload_size(&self) -> u64 {
+        self.size.saturating_sub(self.header_size)
     }
+}
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.mvhd, &mut w)?;
-        for trak in &self.trak {
-            write_to(trak, &mut w)?;
+/// A cursor over the payload of a single box.
+#[derive(Debug, Clone)]
+pub struct BoxReader<'a> {
+    header: BoxHeader,
+    version: u8,
+    flags: u32,
+    buf: &'a [u8],
+    pos: usize,
+    /// Number of child boxes read so far
+    children: usize,
+}
+
+impl<'a> BoxReader<'a> {
+    /// Creates a reader over the first box in `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the box header is invalid or the box is larger than
+    /// `data`.
+    pub fn new(data: &'a [u8]) -> io::Result<Self> {
+        let mut r = Self::from_payload(
+            BoxHeader {
+                box_type: [0; 4],
+                extended_type: None,
+                offset: 0,
+                size: data.len() as u64,
+                header_size: 0,
+            },
+            data,
+        );
+        r.next_child()?
+            .ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn from_payload(mut header: BoxHeader, payload: &'a [u8]) -> Self {
+        if header.size == 0 {
+            header.size = header.header_size + payload.len() as u64;
         }
-        if let Some(mvex) = &self.mvex {
-            write_to(mvex, &mut w)?;
+        Self {
+            header,
+            version: 0,
+            flags: 0,
+            buf: payload,
+            pos: 0,
+            children: 0,
         }
-        Ok(())
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct MovieHeaderBox {
-    pub creation_time: DateTime<Utc>,
-    pub modification_time: DateTime<Utc>,
-    pub timescale: u32,
-    pub duration: Option<Duration>,
-    pub rate: I16F16,
-    pub volume: I8F8,
-    pub matrix: [[I16F16; 3]; 3],
-    pub next_track_id: u32,
-}
+    #[inline]
+    pub fn header(&self) -> &BoxHeader {
+        &self.header
+    }
 
-impl BmffBox for MovieHeaderBox {
-    const TYPE: [u8; 4] = *b"mvhd";
+    /// The version of a full box. This is 0 for boxes that aren't full boxes.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
 
+    /// The flags of a full box. This is 0 for boxes that aren't full boxes.
     #[inline]
-    fn size(&self) -> u64 {
-        12 + (if self.creation_time.timestamp() as u64 > u32::MAX as u64
-            || self.modification_time.timestamp() as u64 > u32::MAX as u64
-            || self
-                .duration
-                .as_ref()
-                .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
-                > u32::MAX as u64
-        {
-            8 + 8 + 4 + 8
-        } else {
-            4 + 4 + 4 + 4
-        }) + 4
-            + 2
-            + 2
-            + 4 * 2
-            + 4 * 9
-            + 4 * 6
-            + 4
+    pub fn flags(&self) -> u32 {
+        self.flags
     }
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        let creation_timestamp = self.creation_time.timestamp();
-        let modification_timestamp = self.modification_time.timestamp();
-        let duration_secs = self
-            .duration
-            .as_ref()
-            .map_or(u32::MAX as u64, |x| duration(x, self.timescale));
-        if creation_timestamp as u64 > u32::MAX as u64
-            || modification_timestamp as u64 > u32::MAX as u64
-            || duration_secs > u32::MAX as u64
+    /// Number of unread bytes in the payload.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Parses the box as a `T`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the box type doesn't match `T` or the payload is
+    /// invalid.
+    pub fn parse<T: ReadBox>(mut self) -> io::Result<T> {
+        self.check_type::<T>()?;
+        T::read_box(&mut self)
+    }
+
+    /// Parses the box as a full box `T`, reading the version and flags first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the box type doesn't match `T` or the payload is
+    /// invalid.
+    pub fn parse_full<T: ReadBox + FullBox>(mut self) -> io::Result<T> {
+        self.check_type::<T>()?;
+        let version_flags = self.read_u32()?;
+        self.version = (version_flags >> 24) as u8;
+        self.flags = version_flags & 0x00ff_ffff;
+        T::read_box(&mut self)
+    }
+
+    fn check_type<T: BmffBox>(&self) -> io::Result<()> {
+        if self.header.box_type != T::TYPE
+            || (T::EXTENDED_TYPE.is_some() && self.header.extended_type != T::EXTENDED_TYPE)
         {
-            w.write_all(&creation_timestamp.to_be_bytes())?;
-            w.write_all(&modification_timestamp.to_be_bytes())?;
-            w.write_all(&self.timescale.to_be_bytes())?;
-            w.write_all(&duration_secs.to_be_bytes())?;
-        } else {
-            w.write_all(&(creation_timestamp as u32).to_be_bytes())?;
-            w.write_all(&(modification_timestamp as u32).to_be_bytes())?;
-            w.write_all(&self.timescale.to_be_bytes())?;
-            w.write_all(&(duration_secs as u32).to_be_bytes())?;
-        }
-        w.write_all(&self.rate.to_be_bytes())?;
-        w.write_all(&self.volume.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        w.write_all(&[0u32.to_be_bytes(); 2].concat())?;
-        for i in self.matrix {
-            for j in i {
-                w.write_all(&j.to_be_bytes())?;
-            }
+            return Err(invalid_data(format!(
+                "expected {} box, found {}",
+                fourcc(&T::TYPE),
+                fourcc(&self.header.box_type)
+            )));
         }
-        w.write_all(&[0u32.to_be_bytes(); 6].concat())?;
-        w.write_all(&self.next_track_id.to_be_bytes())?;
         Ok(())
     }
-}
 
-impl FullBox for MovieHeaderBox {
+    /// Returns a reader over the next child box and advances past it, or
+    /// `None` if the payload has been consumed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the child's header is invalid or the child extends
+    /// past the end of this box.
+    pub fn next_child(&mut self) -> io::Result<Option<BoxReader<'a>>> {
+        if self.remaining() == 0 {
+            return Ok(None);
+        }
+        let offset = self.header.offset + self.header.header_size + self.pos as u64;
+        let mut header = read_header(&self.buf[self.pos..])?;
+        header.offset = offset;
+        if header.size == 0 {
+            header.size = self.remaining() as u64;
+        }
+        if header.size > self.remaining() as u64 {
+            return Err(invalid_data(format!(
+                "{} box extends past the end of its parent",
+                fourcc(&header.box_type)
+            )));
+        }
+        let start = self.pos + header.header_size as usize;
+        let end = self.pos + header.size as usize;
+        self.pos = end;
+        self.children += 1;
+        Ok(Some(BoxReader::from_payload(header, &self.buf[start..end])))
+    }
+
+    /// Reads `child`, the last child returned by
+    /// [`next_child`](Self::next_child), as an unknown box at its position in
+    /// this box.
+    pub fn unknown(&self, child: BoxReader<'_>) -> UnknownBox {
+        UnknownBox {
+            index: self.children.saturating_sub(1),
+            raw: RawBox::from_reader(child),
+        }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.remaining() < len {
+            return Err(invalid_data(format!(
+                "unexpected end of {} box",
+                fourcc(&self.header.box_type)
+            )));
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Reads the rest of the payload.
+    pub fn read_to_end(&mut self) -> &'a [u8] {
+        let bytes = &self.buf[self.pos..];
+        self.pos = self.buf.len();
+        bytes
+    }
+
     #[inline]
-    fn version(&self) -> u8 {
-        u8::from(
-            self.creation_time.timestamp() as u64 > u32::MAX as u64
-                || self.modification_time.timestamp() as u64 > u32::MAX as u64
-                || self
-                    .duration
-                    .as_ref()
-                    .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
-                    > u32::MAX as u64,
-        )
+    pub fn skip(&mut self, len: usize) -> io::Result<()> {
+        self.read_bytes(len).map(|_| ())
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    #[inline]
+    pub fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    #[inline]
+    pub fn read_u16(&mut self) -> io::Result<u16> {
+        self.read_array().map(u16::from_be_bytes)
+    }
+
+    #[inline]
+    pub fn read_u32(&mut self) -> io::Result<u32> {
+        self.read_array().map(u32::from_be_bytes)
+    }
+
+    #[inline]
+    pub fn read_u64(&mut self) -> io::Result<u64> {
+        self.read_array().map(u64::from_be_bytes)
+    }
+
+    #[inline]
+    pub fn read_i16(&mut self) -> io::Result<i16> {
+        self.read_array().map(i16::from_be_bytes)
+    }
+
+    #[inline]
+    pub fn read_i32(&mut self) -> io::Result<i32> {
+        self.read_array().map(i32::from_be_bytes)
+    }
+
+    #[inline]
+    pub fn read_i64(&mut self) -> io::Result<i64> {
+        self.read_array().map(i64::from_be_bytes)
+    }
+
+    /// Reads a null-terminated UTF-8 string. A missing null terminator at the
+    /// end of the payload is tolerated.
+    pub fn read_string(&mut self) -> io::Result<String> {
+        let rest = &self.buf[self.pos..];
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        let s = std::str::from_utf8(&rest[..len])
+            .map_err(|_| invalid_data("string is not valid UTF-8"))?
+            .to_string();
+        self.pos += (len + 1).min(rest.len());
+        Ok(s)
+    }
+
+    fn read_matrix(&mut self) -> io::Result<[[I16F16; 3]; 3]> {
+        let mut matrix = [[I16F16::ZERO; 3]; 3];
+        for row in &mut matrix {
+            for val in row {
+                *val = I16F16::from_be_bytes(self.read_array()?);
+            }
+        }
+        Ok(matrix)
     }
 }
 
+/// A box at the top level of a file or stream.
+#[allow(clippy::large_enum_variant)] // there's only one `moov` per file
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct TrackBox {
-    pub tkhd: TrackHeaderBox,
-    pub tref: Option<TrackReferenceBox>,
-    pub edts: Option<EditListBox>,
-    pub mdia: MediaBox,
+pub enum TopLevelBox {
+    Ftyp(FileTypeBox),
+    Styp(SegmentTypeBox),
+    Sidx(SegmentIndexBox),
+    Moov(MovieBox),
+    Moof(MovieFragmentBox),
+    Mdat(MediaDataBox),
+    Mfra(MovieFragmentRandomAccessBox),
+    Emsg(EventMessageBox),
+    Prft(ProducerReferenceTimeBox),
+    Unknown(RawBox),
+}
+
+/// An iterator over the top-level boxes of a file or stream.
+///
+/// Iterating reads each box into memory, including `mdat` payloads. To avoid
+/// that, call [`next_header`](Self::next_header) and then either
+/// [`read_box`](Self::read_box) to parse the box or
+/// [`payload`](Self::payload) to read its payload incrementally. Payloads
+/// that aren't read are skipped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use bmff::{Demuxer, TopLevelBox};
+///
+/// let file = std::fs::File::open("video.mp4")?;
+/// for bmff_box in Demuxer::new(std::io::BufReader::new(file))? {
+///     if let TopLevelBox::Moof(moof) = bmff_box? {
+///         println!("fragment {}", moof.mfhd.sequence_number);
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Skipping media data:
+///
+/// ```rust,no_run
+/// use bmff::{Demuxer, TopLevelBox};
+///
+/// let file = std::fs::File::open("video.mp4")?;
+/// let mut demuxer = Demuxer::new(std::io::BufReader::new(file))?;
+/// while let Some(header) = demuxer.next_header() {
+///     if header?.box_type == *b"mdat" {
+///         continue;
+///     }
+///     if let TopLevelBox::Moov(moov) = demuxer.read_box()? {
+///         println!("{} tracks", moov.trak.len());
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Demuxer<R> {
+    reader: R,
+    pos: u64,
+    len: u64,
+    /// The box whose header was read but whose payload hasn't been read
+    current: Option<BoxHeader>,
+    /// Whether the reader may be behind `pos`
+    seek: bool,
+}
+
+impl<R: Read + Seek> Demuxer<R> {
+    /// Creates a demuxer that starts reading at the current position of
+    /// `reader`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if seeking in `reader` fails.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let pos = reader.stream_position()?;
+        let len = reader.seek(io::SeekFrom::End(0))?;
+        reader.seek(io::SeekFrom::Start(pos))?;
+        Ok(Self {
+            reader,
+            pos,
+            len,
+            current: None,
+            seek: false,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the header of the next box, skipping the payload of the
+    /// previous box if it wasn't read. Returns `None` at the end of the
+    /// stream.
+    pub fn next_header(&mut self) -> Option<io::Result<BoxHeader>> {
+        if self.pos >= self.len {
+            return None;
+        }
+        let res = self.read_next_header();
+        if res.is_err() {
+            // don't try to resync after a malformed box
+            self.pos = self.len;
+        }
+        Some(res)
+    }
+
+    fn read_next_header(&mut self) -> io::Result<BoxHeader> {
+        if self.seek {
+            self.reader.seek(io::SeekFrom::Start(self.pos))?;
+        }
+        self.current = None;
+        let mut header = read_header(&mut self.reader)?;
+        header.offset = self.pos;
+        if header.size == 0 {
+            header.size = self.len - self.pos;
+        }
+        self.pos = header.offset.saturating_add(header.size);
+        self.current = Some(header);
+        self.seek = true;
+        Ok(header)
+    }
+
+    /// Returns a reader over the payload of the box whose header was last
+    /// returned by [`next_header`](Self::next_header). The reader is empty
+    /// if there's no such box or its payload was already read.
+    pub fn payload(&mut self) -> io::Take<&mut R> {
+        // whatever isn't read is skipped by seeking in `next_header`
+        let limit = self
+            .current
+            .take()
+            .map_or(0, |header| header.payload_size());
+        (&mut self.reader).take(limit)
+    }
+
+    /// Reads and parses the box whose header was last returned by
+    /// [`next_header`](Self::next_header).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there's no such box, if its payload was already
+    /// read with [`payload`](Self::payload), or if it's malformed.
+    pub fn read_box(&mut self) -> io::Result<TopLevelBox> {
+        let header = self
+            .current
+            .take()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no box header was read"))?;
+        let res = read_payload(&header, &mut self.reader).and_then(|payload| {
+            self.seek = false;
+            let r = BoxReader::from_payload(header, &payload);
+            Ok(match &header.box_type {
+                b"ftyp" => TopLevelBox::Ftyp(r.parse()?),
+                b"styp" => TopLevelBox::Styp(r.parse()?),
+                b"sidx" => TopLevelBox::Sidx(r.parse_full()?),
+                b"moov" => TopLevelBox::Moov(r.parse()?),
+                b"moof" => TopLevelBox::Moof(r.parse()?),
+                b"mdat" => TopLevelBox::Mdat(r.parse()?),
+                b"mfra" => TopLevelBox::Mfra(r.parse()?),
+                b"emsg" => TopLevelBox::Emsg(r.parse_full()?),
+                b"prft" => TopLevelBox::Prft(r.parse_full()?),
+                _ => TopLevelBox::Unknown(RawBox::from_reader(r)),
+            })
+        });
+        if res.is_err() {
+            self.pos = self.len;
+        }
+        res
+    }
 }
 
-impl BmffBox for TrackBox {
-    const TYPE: [u8; 4] = *b"trak";
+impl<R: Read + Seek> Iterator for Demuxer<R> {
+    type Item = io::Result<TopLevelBox>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match self.next_header()? {
+            Ok(_) => self.read_box(),
+            Err(e) => Err(e),
+        })
+    }
+}
+
+fn read_time(timestamp: u64) -> io::Result<DateTime<Utc>> {
+    i64::try_from(timestamp)
+        .ok()
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+        .ok_or_else(|| invalid_data("timestamp out of range"))
+}
+
+fn read_duration(duration: u64, timescale: u32) -> Option<Duration> {
+    // `u32::MAX` is written for an unknown duration in both versions
+    if duration == u32::MAX as u64 || duration == u64::MAX || timescale == 0 {
+        None
+    } else {
+        Some(ticks_to_duration(duration, timescale))
+    }
+}
+
+/// A box that isn't modeled by this crate.
+///
+/// Unknown boxes are kept as an opaque payload so they can be written back
+/// unchanged.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawBox {
+    pub box_type: [u8; 4],
+    /// Only used for `uuid` boxes
+    pub extended_type: Option<[u8; 16]>,
+    pub payload: Vec<u8>,
+}
+
+impl RawBox {
+    /// Reads the rest of a box as a `RawBox`.
+    pub fn from_reader(mut r: BoxReader<'_>) -> Self {
+        Self {
+            box_type: r.header().box_type,
+            extended_type: r.header().extended_type,
+            payload: r.read_to_end().to_vec(),
+        }
+    }
 
     #[inline]
-    fn size(&self) -> u64 {
-        8 + self.tkhd.size()
-            + self.tref.as_ref().map_or(0, BmffBox::size)
-            + self.edts.as_ref().map_or(0, BmffBox::size)
-            + self.mdia.size()
+    pub fn size(&self) -> u64 {
+        large_size(8 + self.extended_type.map_or(0, |_| 16) + self.payload.len() as u64)
     }
+}
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.tkhd, &mut w)?;
-        if let Some(tref) = &self.tref {
-            write_to(tref, &mut w)?;
+impl WriteTo for RawBox {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        let size = self.size();
+        if u32::try_from(size).is_ok() {
+            w.write_all(&(size as u32).to_be_bytes())?;
+            w.write_all(&self.box_type)?;
+        } else {
+            w.write_all(&1u32.to_be_bytes())?;
+            w.write_all(&self.box_type)?;
+            w.write_all(&size.to_be_bytes())?;
         }
-        if let Some(edts) = &self.edts {
-            write_to_full(edts, &mut w)?;
+        if let Some(ext_type) = self.extended_type {
+            w.write_all(&ext_type)?;
         }
-        write_to(&self.mdia, &mut w)?;
+        w.write_all(&self.payload)?;
         Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TrackHeaderBox {
-    pub flags: TrackHeaderFlags,
-    pub creation_time: DateTime<Utc>,
-    pub modification_time: DateTime<Utc>,
-    pub track_id: u32,
-    /// must be the same as in mvhd
-    pub timescale: u32,
-    pub duration: Option<Duration>,
-    pub layer: i16,
-    pub alternate_group: i16,
-    pub volume: I8F8,
-    pub matrix: [[I16F16; 3]; 3],
-    pub width: U16F16,
-    pub height: U16F16,
+/// An unknown child of a container and its position among the children.
+///
+/// Containers write each unknown child back at the index it was read from, so
+/// parsing and writing a box doesn't reorder its children. Unknown children
+/// should be sorted by index.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownBox {
+    /// Index of the box among all children of its parent. Boxes with an index
+    /// past the last modeled child are written at the end.
+    pub index: usize,
+    pub raw: RawBox,
 }
 
-bitflags! {
-    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
-    pub struct TrackHeaderFlags: u32 {
-        const TRACK_ENABLED = 0x00_0001;
-        const TRACK_IN_MOVIE = 0x00_0002;
-        const TRACK_IN_PREVIEW = 0x00_0004;
+impl UnknownBox {
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.raw.size()
     }
 }
 
-impl BmffBox for TrackHeaderBox {
-    const TYPE: [u8; 4] = *b"tkhd";
+/// Creates an unknown child that is written after all the modeled children.
+impl From<RawBox> for UnknownBox {
+    fn from(raw: RawBox) -> Self {
+        Self {
+            index: usize::MAX,
+            raw,
+        }
+    }
+}
+
+/// Writes the children of a container, putting its unknown children back
+/// between the modeled ones.
+struct Children<'a> {
+    unknown: std::iter::Peekable<std::slice::Iter<'a, UnknownBox>>,
+    /// Number of children written so far
+    index: usize,
+}
+
+impl<'a> Children<'a> {
+    fn new(unknown: &'a [UnknownBox]) -> Self {
+        Self {
+            unknown: unknown.iter().peekable(),
+            index: 0,
+        }
+    }
+
+    /// Returns the unknown children that come before the next modeled child.
+    fn before_next(&mut self) -> impl Iterator<Item = &'a UnknownBox> + '_ {
+        std::iter::from_fn(move || {
+            let unknown = self.unknown.next_if(|x| x.index <= self.index)?;
+            self.index += 1;
+            Some(unknown)
+        })
+    }
+
+    /// Writes the unknown children that come before the next modeled child.
+    fn next(&mut self, mut w: impl Write) -> io::Result<()> {
+        for unknown in self.before_next() {
+            WriteTo::write_to(&unknown.raw, &mut w)?;
+        }
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Moves past the next modeled child without writing anything, and
+    /// returns the size of the unknown children that come before it.
+    fn skip(&mut self) -> u64 {
+        let size = self.before_next().map(UnknownBox::size).sum();
+        self.index += 1;
+        size
+    }
+
+    fn write<T: BmffBox>(&mut self, child: &T, mut w: impl Write) -> io::Result<()> {
+        self.next(&mut w)?;
+        write_to(child, w)
+    }
+
+    fn write_full<T: FullBox>(&mut self, child: &T, mut w: impl Write) -> io::Result<()> {
+        self.next(&mut w)?;
+        write_to_full(child, w)
+    }
+
+    /// Writes the unknown children after the last modeled child.
+    fn finish(self, mut w: impl Write) -> io::Result<()> {
+        for unknown in self.unknown {
+            WriteTo::write_to(&unknown.raw, &mut w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for RawBox {
+    fn validate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
 
+impl SampleEntry for RawBox {
     #[inline]
     fn size(&self) -> u64 {
-        12 + (if self.creation_time.timestamp() as u64 > u32::MAX as u64
-            || self.modification_time.timestamp() as u64 > u32::MAX as u64
-            || self
-                .duration
-                .as_ref()
-                .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
-                > u32::MAX as u64
-        {
-            8 + 8 + 4 + 4 + 8
-        } else {
-            4 + 4 + 4 + 4 + 4
-        }) + 4 * 2
-            + 2
-            + 2
-            + 2
-            + 2
-            + 4 * 9
-            + 4
-            + 4
+        RawBox::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(RawBox::size(self) as usize);
+        WriteTo::write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default)]
+pub struct FileTypeBox {
+    pub major_brand: [u8; 4],
+    pub minor_version: u32,
+    pub compatible_brands: Vec<[u8; 4]>,
+}
+
+impl BmffBox for FileTypeBox {
+    const TYPE: [u8; 4] = *b"ftyp";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + 4 + 4 + self.compatible_brands.len() as u64 * 4
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        let creation_timestamp = self.creation_time.timestamp();
-        let modification_timestamp = self.modification_time.timestamp();
-        let duration_secs = self
-            .duration
-            .as_ref()
-            .map_or(u32::MAX as u64, |x| duration(x, self.timescale));
-        if creation_timestamp as u64 > u32::MAX as u64
-            || modification_timestamp as u64 > u32::MAX as u64
-            || duration_secs > u32::MAX as u64
-        {
-            w.write_all(&creation_timestamp.to_be_bytes())?;
-            w.write_all(&modification_timestamp.to_be_bytes())?;
-            w.write_all(&self.track_id.to_be_bytes())?;
-            w.write_all(&0u32.to_be_bytes())?;
-            w.write_all(&duration_secs.to_be_bytes())?;
-        } else {
-            w.write_all(&(creation_timestamp as u32).to_be_bytes())?;
-            w.write_all(&(modification_timestamp as u32).to_be_bytes())?;
-            w.write_all(&self.track_id.to_be_bytes())?;
-            w.write_all(&0u32.to_be_bytes())?;
-            w.write_all(&(duration_secs as u32).to_be_bytes())?;
-        }
-        w.write_all(&[0u32.to_be_bytes(); 2].concat())?;
-        w.write_all(&self.layer.to_be_bytes())?;
-        w.write_all(&self.alternate_group.to_be_bytes())?;
-        w.write_all(&self.volume.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        for i in self.matrix {
-            for j in i {
-                w.write_all(&j.to_be_bytes())?;
-            }
+        w.write_all(&self.major_brand)?;
+        w.write_all(&self.minor_version.to_be_bytes())?;
+        for i in &self.compatible_brands {
+            w.write_all(i)?;
         }
-        w.write_all(&self.width.to_be_bytes())?;
-        w.write_all(&self.height.to_be_bytes())?;
         Ok(())
     }
 }
 
-impl FullBox for TrackHeaderBox {
+impl ReadBox for FileTypeBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let major_brand = r.read_array()?;
+        let minor_version = r.read_u32()?;
+        let mut compatible_brands = Vec::with_capacity(r.remaining() / 4);
+        while r.remaining() >= 4 {
+            compatible_brands.push(r.read_array()?);
+        }
+        Ok(Self {
+            major_brand,
+            minor_version,
+            compatible_brands,
+        })
+    }
+}
+
+/// Identifies a media segment. This has the same layout as [`FileTypeBox`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SegmentTypeBox {
+    pub major_brand: [u8; 4],
+    pub minor_version: u32,
+    pub compatible_brands: Vec<[u8; 4]>,
+}
+
+impl BmffBox for SegmentTypeBox {
+    const TYPE: [u8; 4] = *b"styp";
+
     #[inline]
-    fn version(&self) -> u8 {
-        u8::from(
-            self.creation_time.timestamp() as u64 > u32::MAX as u64
-                || self.modification_time.timestamp() as u64 > u32::MAX as u64
-                || self
-                    .duration
-                    .as_ref()
-                    .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
-                    > u32::MAX as u64,
-        )
+    fn size(&self) -> u64 {
+        8 + 4 + 4 + self.compatible_brands.len() as u64 * 4
     }
 
-    fn flags(&self) -> [u8; 3] {
-        let flags = self.flags.bits().to_be_bytes();
-        [flags[1], flags[2], flags[3]]
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&self.major_brand)?;
+        w.write_all(&self.minor_version.to_be_bytes())?;
+        for i in &self.compatible_brands {
+            w.write_all(i)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TrackReferenceBox;
+impl ReadBox for SegmentTypeBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let major_brand = r.read_array()?;
+        let minor_version = r.read_u32()?;
+        let mut compatible_brands = Vec::with_capacity(r.remaining() / 4);
+        while r.remaining() >= 4 {
+            compatible_brands.push(r.read_array()?);
+        }
+        Ok(Self {
+            major_brand,
+            minor_version,
+            compatible_brands,
+        })
+    }
+}
 
-impl BmffBox for TrackReferenceBox {
-    const TYPE: [u8; 4] = *b"tref";
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SegmentIndexBox {
+    pub reference_id: u32,
+    pub timescale: u32,
+    pub earliest_presentation_time: u64,
+    /// Distance in bytes from the end of this box to the first referenced byte
+    pub first_offset: u64,
+    pub references: Vec<SegmentReference>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentReference {
+    pub reference_type: SegmentReferenceType,
+    /// 31 bits
+    pub referenced_size: u32,
+    pub subsegment_duration: u32,
+    pub starts_with_sap: bool,
+    /// 3 bits
+    pub sap_type: u8,
+    /// 28 bits
+    pub sap_delta_time: u32,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentReferenceType {
+    /// The reference points to media content, i.e. a `moof` and `mdat`.
+    Media,
+    /// The reference points to another `sidx` box.
+    Index,
+}
+
+impl BmffBox for SegmentIndexBox {
+    const TYPE: [u8; 4] = *b"sidx";
 
     #[inline]
     fn size(&self) -> u64 {
-        8
+        12 + 4
+            + 4
+            + if self.version() == 1 { 8 + 8 } else { 4 + 4 }
+            + 2
+            + 2
+            + self.references.len() as u64 * (4 + 4 + 4)
     }
 
-    fn write_box(&self, _w: impl Write) -> io::Result<()> {
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&self.reference_id.to_be_bytes())?;
+        w.write_all(&self.timescale.to_be_bytes())?;
+        if self.version() == 1 {
+            w.write_all(&self.earliest_presentation_time.to_be_bytes())?;
+            w.write_all(&self.first_offset.to_be_bytes())?;
+        } else {
+            w.write_all(&(self.earliest_presentation_time as u32).to_be_bytes())?;
+            w.write_all(&(self.first_offset as u32).to_be_bytes())?;
+        }
+        w.write_all(&0u16.to_be_bytes())?;
+        w.write_all(&(self.references.len() as u16).to_be_bytes())?;
+        for reference in &self.references {
+            let reference_type = match reference.reference_type {
+                SegmentReferenceType::Media => 0,
+                SegmentReferenceType::Index => 1 << 31,
+            };
+            w.write_all(&(reference_type | reference.referenced_size & 0x7fff_ffff).to_be_bytes())?;
+            w.write_all(&reference.subsegment_duration.to_be_bytes())?;
+            w.write_all(
+                &(u32::from(reference.starts_with_sap) << 31
+                    | (reference.sap_type as u32 & 0x07) << 28
+                    | reference.sap_delta_time & 0x0fff_ffff)
+                    .to_be_bytes(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for SegmentIndexBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let reference_id = r.read_u32()?;
+        let timescale = r.read_u32()?;
+        let (earliest_presentation_time, first_offset) = if r.version() == 1 {
+            (r.read_u64()?, r.read_u64()?)
+        } else {
+            (r.read_u32()? as u64, r.read_u32()? as u64)
+        };
+        r.skip(2)?;
+        let reference_count = r.read_u16()?;
+        let mut references = Vec::with_capacity(reference_count as usize);
+        for _ in 0..reference_count {
+            let size = r.read_u32()?;
+            let subsegment_duration = r.read_u32()?;
+            let sap = r.read_u32()?;
+            references.push(SegmentReference {
+                reference_type: if size >> 31 == 0 {
+                    SegmentReferenceType::Media
+                } else {
+                    SegmentReferenceType::Index
+                },
+                referenced_size: size & 0x7fff_ffff,
+                subsegment_duration,
+                starts_with_sap: sap >> 31 != 0,
+                sap_type: (sap >> 28 & 0x07) as u8,
+                sap_delta_time: sap & 0x0fff_ffff,
+            });
+        }
+        Ok(Self {
+            reference_id,
+            timescale,
+            earliest_presentation_time,
+            first_offset,
+            references,
+        })
+    }
+}
+
+impl Validate for SegmentIndexBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(Self::TYPE, "references", self.references.len() as u64, 16)?;
+        for reference in &self.references {
+            check_bits(
+                Self::TYPE,
+                "referenced_size",
+                reference.referenced_size as u64,
+                31,
+            )?;
+            check_bits(Self::TYPE, "sap_type", reference.sap_type as u64, 3)?;
+            check_bits(
+                Self::TYPE,
+                "sap_delta_time",
+                reference.sap_delta_time as u64,
+                28,
+            )?;
+        }
         Ok(())
     }
 }
 
+impl FullBox for SegmentIndexBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        u8::from(
+            self.earliest_presentation_time > u32::MAX as u64
+                || self.first_offset > u32::MAX as u64,
+        )
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct MediaBox {
-    pub mdhd: MediaHeaderBox,
-    pub hdlr: HandlerBox,
-    pub minf: MediaInformationBox,
+pub struct MovieBox {
+    pub mvhd: MovieHeaderBox,
+    pub trak: Vec<TrackBox>,
+    pub mvex: Option<MovieExtendsBox>,
+    pub pssh: Vec<ProtectionSystemSpecificHeaderBox>,
+    pub udta: Option<UserDataBox>,
+    pub unknown: Vec<UnknownBox>,
 }
 
-impl BmffBox for MediaBox {
-    const TYPE: [u8; 4] = *b"mdia";
+impl BmffBox for MovieBox {
+    const TYPE: [u8; 4] = *b"moov";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + self.mdhd.size() + self.hdlr.size() + self.minf.size()
+        8 + self.mvhd.size()
+            + self.trak.iter().map(BmffBox::size).sum::<u64>()
+            + self.mvex.as_ref().map_or(0, BmffBox::size)
+            + self.pssh.iter().map(BmffBox::size).sum::<u64>()
+            + self.udta.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.mdhd, &mut w)?;
-        write_to_full(&self.hdlr, &mut w)?;
-        write_to(&self.minf, &mut w)?;
+        let mut children = Children::new(&self.unknown);
+        children.write_full(&self.mvhd, &mut w)?;
+        for trak in &self.trak {
+            children.write(trak, &mut w)?;
+        }
+        if let Some(mvex) = &self.mvex {
+            children.write(mvex, &mut w)?;
+        }
+        for pssh in &self.pssh {
+            children.write_full(pssh, &mut w)?;
+        }
+        if let Some(udta) = &self.udta {
+            children.write(udta, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for MovieBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut mvhd = None;
+        let mut trak = Vec::new();
+        let mut mvex = None;
+        let mut pssh = Vec::new();
+        let mut udta = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"mvhd" => mvhd = Some(child.parse_full::<MovieHeaderBox>()?),
+                b"trak" => trak.push(child.parse::<TrackBox>()?),
+                b"mvex" => mvex = Some(child.parse()?),
+                b"pssh" => pssh.push(child.parse_full()?),
+                b"udta" => udta = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        let mvhd = mvhd.ok_or_else(|| missing_box(b"mvhd", b"moov"))?;
+        // tkhd doesn't carry a timescale, so track durations were read in
+        // milliseconds and have to be rescaled with the movie timescale
+        for trak in &mut trak {
+            let tkhd = &mut trak.tkhd;
+            tkhd.timescale = mvhd.timescale;
+            tkhd.duration = tkhd
+                .duration
+                .and_then(|d| read_duration(d.num_milliseconds() as u64, mvhd.timescale));
+        }
+        Ok(Self {
+            mvhd,
+            trak,
+            mvex,
+            pssh,
+            udta,
+            unknown,
+        })
+    }
+}
+
+impl Validate for MovieBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.mvhd.validate()?;
+        for trak in &self.trak {
+            trak.validate()?;
+        }
+        if let Some(mvex) = &self.mvex {
+            mvex.validate()?;
+        }
+        for pssh in &self.pssh {
+            pssh.validate()?;
+        }
+        if let Some(udta) = &self.udta {
+            udta.validate()?;
+        }
         Ok(())
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct MediaHeaderBox {
+pub struct MovieHeaderBox {
     pub creation_time: DateTime<Utc>,
     pub modification_time: DateTime<Utc>,
     pub timescale: u32,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::option_duration"))]
     pub duration: Option<Duration>,
-    pub language: [u8; 3],
+    pub rate: I16F16,
+    pub volume: I8F8,
+    pub matrix: [[I16F16; 3]; 3],
+    pub next_track_id: u32,
 }
 
-impl BmffBox for MediaHeaderBox {
-    const TYPE: [u8; 4] = *b"mdhd";
+impl BmffBox for MovieHeaderBox {
+    const TYPE: [u8; 4] = *b"mvhd";
 
     #[inline]
     fn size(&self) -> u64 {
@@ -452,12 +1312,18 @@ impl BmffBox for MediaHeaderBox {
         {
             8 + 8 + 4 + 8
         } else {
-            4 * 4
-        }) + 2
+            4 + 4 + 4 + 4
+        }) + 4
+            + 2
             + 2
+            + 4 * 2
+            + 4 * 9
+            + 4 * 6
+            + 4
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
         let creation_timestamp = self.creation_time.timestamp();
         let modification_timestamp = self.modification_time.timestamp();
         let duration_secs = self
@@ -478,23 +1344,55 @@ impl BmffBox for MediaHeaderBox {
             w.write_all(&self.timescale.to_be_bytes())?;
             w.write_all(&(duration_secs as u32).to_be_bytes())?;
         }
-        // 000aaaaa 000bbbbb 000ccccc
-        //    |||||   //  \\\   |||||
-        //    |||||  //    \\\  |||||
-        //  0 xxxxx xx      xxx xxxxx
-        let language = [
-            (self.language[0] - 0x60) << 2 | (self.language[1] - 0x60) >> 3,
-            (self.language[1] - 0x60) << 5 | (self.language[2] - 0x60),
-        ];
-        w.write_all(&language)?;
+        w.write_all(&self.rate.to_be_bytes())?;
+        w.write_all(&self.volume.to_be_bytes())?;
         w.write_all(&0u16.to_be_bytes())?;
+        w.write_all(&[0u32.to_be_bytes(); 2].concat())?;
+        for i in self.matrix {
+            for j in i {
+                w.write_all(&j.to_be_bytes())?;
+            }
+        }
+        w.write_all(&[0u32.to_be_bytes(); 6].concat())?;
+        w.write_all(&self.next_track_id.to_be_bytes())?;
         Ok(())
     }
 }
 
-impl FullBox for MediaHeaderBox {
-    #[inline]
-    fn version(&self) -> u8 {
+impl ReadBox for MovieHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let (creation_time, modification_time, timescale, duration) = if r.version() == 1 {
+            (r.read_u64()?, r.read_u64()?, r.read_u32()?, r.read_u64()?)
+        } else {
+            (
+                r.read_u32()? as u64,
+                r.read_u32()? as u64,
+                r.read_u32()?,
+                r.read_u32()? as u64,
+            )
+        };
+        let rate = I16F16::from_be_bytes(r.read_array()?);
+        let volume = I8F8::from_be_bytes(r.read_array()?);
+        r.skip(2 + 4 * 2)?;
+        let matrix = r.read_matrix()?;
+        r.skip(4 * 6)?;
+        let next_track_id = r.read_u32()?;
+        Ok(Self {
+            creation_time: read_time(creation_time)?,
+            modification_time: read_time(modification_time)?,
+            timescale,
+            duration: read_duration(duration, timescale),
+            rate,
+            volume,
+            matrix,
+            next_track_id,
+        })
+    }
+}
+
+impl FullBox for MovieHeaderBox {
+    #[inline]
+    fn version(&self) -> u8 {
         u8::from(
             self.creation_time.timestamp() as u64 > u32::MAX as u64
                 || self.modification_time.timestamp() as u64 > u32::MAX as u64
@@ -507,349 +1405,730 @@ impl FullBox for MediaHeaderBox {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct HandlerBox {
-    pub handler_type: HandlerType,
-    // spec says a null-terminated UTF-8 string, so not a `CString`
-    pub name: String,
+impl Validate for MovieHeaderBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_times(Self::TYPE, &self.creation_time, &self.modification_time)?;
+        if self.next_track_id == 0 {
+            return Err(Error::Invalid(Self::TYPE, "next_track_id can't be 0"));
+        }
+        Ok(())
+    }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
-#[repr(u32)]
-pub enum HandlerType {
-    Video = u32::from_be_bytes(*b"vide"),
-    Audio = u32::from_be_bytes(*b"soun"),
-    Hint = u32::from_be_bytes(*b"hint"),
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TrackBox {
+    pub tkhd: TrackHeaderBox,
+    pub tref: Option<TrackReferenceBox>,
+    pub edts: Option<EditBox>,
+    pub mdia: MediaBox,
+    pub udta: Option<UserDataBox>,
+    pub unknown: Vec<UnknownBox>,
 }
 
-impl BmffBox for HandlerBox {
-    const TYPE: [u8; 4] = *b"hdlr";
+impl BmffBox for TrackBox {
+    const TYPE: [u8; 4] = *b"trak";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + 4 + 4 * 3 + self.name.len() as u64 + 1
+        8 + self.tkhd.size()
+            + self.tref.as_ref().map_or(0, BmffBox::size)
+            + self.edts.as_ref().map_or(0, BmffBox::size)
+            + self.mdia.size()
+            + self.udta.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&0u32.to_be_bytes())?;
-        w.write_all(&(self.handler_type as u32).to_be_bytes())?;
-        w.write_all(&[0u32.to_be_bytes(); 3].concat())?;
-        w.write_all(self.name.as_bytes())?;
-        w.write_all(&[0u8])?; // Null terminator
+        let mut children = Children::new(&self.unknown);
+        children.write_full(&self.tkhd, &mut w)?;
+        if let Some(tref) = &self.tref {
+            children.write(tref, &mut w)?;
+        }
+        if let Some(edts) = &self.edts {
+            children.write(edts, &mut w)?;
+        }
+        children.write(&self.mdia, &mut w)?;
+        if let Some(udta) = &self.udta {
+            children.write(udta, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for TrackBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut tkhd = None;
+        let mut tref = None;
+        let mut edts = None;
+        let mut mdia = None;
+        let mut udta = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"tkhd" => tkhd = Some(child.parse_full()?),
+                b"tref" => tref = Some(child.parse()?),
+                b"edts" => edts = Some(child.parse()?),
+                b"mdia" => mdia = Some(child.parse()?),
+                b"udta" => udta = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            tkhd: tkhd.ok_or_else(|| missing_box(b"tkhd", b"trak"))?,
+            tref,
+            edts,
+            mdia: mdia.ok_or_else(|| missing_box(b"mdia", b"trak"))?,
+            udta,
+            unknown,
+        })
+    }
+}
+
+impl Validate for TrackBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.tkhd.validate()?;
+        self.mdia.validate()?;
+        if let Some(udta) = &self.udta {
+            udta.validate()?;
+        }
         Ok(())
     }
 }
 
-impl FullBox for HandlerBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
-    }
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TrackHeaderBox {
+    pub flags: TrackHeaderFlags,
+    pub creation_time: DateTime<Utc>,
+    pub modification_time: DateTime<Utc>,
+    pub track_id: u32,
+    /// must be the same as in mvhd
+    pub timescale: u32,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::option_duration"))]
+    pub duration: Option<Duration>,
+    pub layer: i16,
+    pub alternate_group: i16,
+    pub volume: I8F8,
+    pub matrix: [[I16F16; 3]; 3],
+    pub width: U16F16,
+    pub height: U16F16,
 }
 
-#[derive(Debug, Clone)]
-pub struct MediaInformationBox {
-    pub media_header: MediaHeader,
-    pub dinf: DataInformationBox,
-    pub stbl: SampleTableBox,
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    pub struct TrackHeaderFlags: u32 {
+        const TRACK_ENABLED = 0x00_0001;
+        const TRACK_IN_MOVIE = 0x00_0002;
+        const TRACK_IN_PREVIEW = 0x00_0004;
+    }
 }
 
-impl BmffBox for MediaInformationBox {
-    const TYPE: [u8; 4] = *b"minf";
+impl BmffBox for TrackHeaderBox {
+    const TYPE: [u8; 4] = *b"tkhd";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + self.media_header.size() + self.dinf.size() + self.stbl.size()
+        12 + (if self.creation_time.timestamp() as u64 > u32::MAX as u64
+            || self.modification_time.timestamp() as u64 > u32::MAX as u64
+            || self
+                .duration
+                .as_ref()
+                .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
+                > u32::MAX as u64
+        {
+            8 + 8 + 4 + 4 + 8
+        } else {
+            4 + 4 + 4 + 4 + 4
+        }) + 4 * 2
+            + 2
+            + 2
+            + 2
+            + 2
+            + 4 * 9
+            + 4
+            + 4
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        self.media_header.write_to(&mut w)?;
-        write_to(&self.dinf, &mut w)?;
-        write_to(&self.stbl, &mut w)?;
+        self.validate()?;
+        let creation_timestamp = self.creation_time.timestamp();
+        let modification_timestamp = self.modification_time.timestamp();
+        let duration_secs = self
+            .duration
+            .as_ref()
+            .map_or(u32::MAX as u64, |x| duration(x, self.timescale));
+        if creation_timestamp as u64 > u32::MAX as u64
+            || modification_timestamp as u64 > u32::MAX as u64
+            || duration_secs > u32::MAX as u64
+        {
+            w.write_all(&creation_timestamp.to_be_bytes())?;
+            w.write_all(&modification_timestamp.to_be_bytes())?;
+            w.write_all(&self.track_id.to_be_bytes())?;
+            w.write_all(&0u32.to_be_bytes())?;
+            w.write_all(&duration_secs.to_be_bytes())?;
+        } else {
+            w.write_all(&(creation_timestamp as u32).to_be_bytes())?;
+            w.write_all(&(modification_timestamp as u32).to_be_bytes())?;
+            w.write_all(&self.track_id.to_be_bytes())?;
+            w.write_all(&0u32.to_be_bytes())?;
+            w.write_all(&(duration_secs as u32).to_be_bytes())?;
+        }
+        w.write_all(&[0u32.to_be_bytes(); 2].concat())?;
+        w.write_all(&self.layer.to_be_bytes())?;
+        w.write_all(&self.alternate_group.to_be_bytes())?;
+        w.write_all(&self.volume.to_be_bytes())?;
+        w.write_all(&0u16.to_be_bytes())?;
+        for i in self.matrix {
+            for j in i {
+                w.write_all(&j.to_be_bytes())?;
+            }
+        }
+        w.write_all(&self.width.to_be_bytes())?;
+        w.write_all(&self.height.to_be_bytes())?;
         Ok(())
     }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub enum MediaHeader {
-    Video(VideoMediaHeaderBox),
-    Sound(SoundMediaHeaderBox),
-    Hint(HintMediaHeaderBox),
-    Null(NullMediaHeaderBox),
+impl ReadBox for TrackHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let (creation_time, modification_time, track_id, duration) = if r.version() == 1 {
+            let times = (r.read_u64()?, r.read_u64()?, r.read_u32()?);
+            r.skip(4)?;
+            (times.0, times.1, times.2, r.read_u64()?)
+        } else {
+            let times = (r.read_u32()? as u64, r.read_u32()? as u64, r.read_u32()?);
+            r.skip(4)?;
+            (times.0, times.1, times.2, r.read_u32()? as u64)
+        };
+        r.skip(4 * 2)?;
+        let layer = r.read_i16()?;
+        let alternate_group = r.read_i16()?;
+        let volume = I8F8::from_be_bytes(r.read_array()?);
+        r.skip(2)?;
+        let matrix = r.read_matrix()?;
+        let width = U16F16::from_be_bytes(r.read_array()?);
+        let height = U16F16::from_be_bytes(r.read_array()?);
+        Ok(Self {
+            flags: TrackHeaderFlags::from_bits_retain(r.flags()),
+            creation_time: read_time(creation_time)?,
+            modification_time: read_time(modification_time)?,
+            track_id,
+            // replaced with the mvhd timescale by `MovieBox`
+            timescale: 1000,
+            duration: read_duration(duration, 1000),
+            layer,
+            alternate_group,
+            volume,
+            matrix,
+            width,
+            height,
+        })
+    }
 }
 
-impl MediaHeader {
+impl FullBox for TrackHeaderBox {
     #[inline]
-    pub fn size(&self) -> u64 {
-        match self {
-            Self::Video(vmhd) => vmhd.size(),
-            Self::Sound(smhd) => smhd.size(),
-            Self::Hint(hmhd) => hmhd.size(),
-            Self::Null(nmhd) => nmhd.size(),
-        }
+    fn version(&self) -> u8 {
+        u8::from(
+            self.creation_time.timestamp() as u64 > u32::MAX as u64
+                || self.modification_time.timestamp() as u64 > u32::MAX as u64
+                || self
+                    .duration
+                    .as_ref()
+                    .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
+                    > u32::MAX as u64,
+        )
+    }
+
+    fn flags(&self) -> [u8; 3] {
+        let flags = self.flags.bits().to_be_bytes();
+        [flags[1], flags[2], flags[3]]
     }
 }
 
-impl WriteTo for MediaHeader {
-    fn write_to(&self, w: impl Write) -> io::Result<()> {
-        match self {
-            Self::Video(vmhd) => write_to_full(vmhd, w),
-            Self::Sound(smhd) => write_to_full(smhd, w),
-            Self::Hint(hmhd) => write_to_full(hmhd, w),
-            Self::Null(nmhd) => write_to_full(nmhd, w),
+impl Validate for TrackHeaderBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_times(Self::TYPE, &self.creation_time, &self.modification_time)?;
+        if self.track_id == 0 {
+            return Err(Error::Invalid(Self::TYPE, "track_id can't be 0"));
         }
+        Ok(())
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct VideoMediaHeaderBox {
-    pub graphics_mode: GraphicsMode,
-    pub opcolor: [u16; 3],
-}
-
-#[derive(Debug, Clone, Copy)]
-#[repr(u16)]
-pub enum GraphicsMode {
-    Copy = 0,
-}
+pub struct TrackReferenceBox;
 
-impl BmffBox for VideoMediaHeaderBox {
-    const TYPE: [u8; 4] = *b"vmhd";
+impl BmffBox for TrackReferenceBox {
+    const TYPE: [u8; 4] = *b"tref";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 2 + 2 * 3
+        8
     }
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&(self.graphics_mode as u16).to_be_bytes())?;
-        for i in self.opcolor {
-            w.write_all(&i.to_be_bytes())?;
-        }
+    fn write_box(&self, _w: impl Write) -> io::Result<()> {
         Ok(())
     }
 }
 
-impl FullBox for VideoMediaHeaderBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
-    }
-
-    #[inline]
-    fn flags(&self) -> [u8; 3] {
-        [0, 0, 1]
+impl ReadBox for TrackReferenceBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.read_to_end();
+        Ok(Self)
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct SoundMediaHeaderBox {
-    pub balance: I8F8,
+pub struct MediaBox {
+    pub mdhd: MediaHeaderBox,
+    pub hdlr: HandlerBox,
+    pub minf: MediaInformationBox,
+    pub unknown: Vec<UnknownBox>,
 }
 
-impl BmffBox for SoundMediaHeaderBox {
-    const TYPE: [u8; 4] = *b"smhd";
+impl BmffBox for MediaBox {
+    const TYPE: [u8; 4] = *b"mdia";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 2 + 2
+        8 + self.mdhd.size()
+            + self.hdlr.size()
+            + self.minf.size()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&self.balance.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        Ok(())
+        let mut children = Children::new(&self.unknown);
+        children.write_full(&self.mdhd, &mut w)?;
+        children.write_full(&self.hdlr, &mut w)?;
+        children.write(&self.minf, &mut w)?;
+        children.finish(w)
+    }
+}
+
+impl ReadBox for MediaBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut mdhd = None;
+        let mut hdlr = None;
+        let mut minf = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"mdhd" => mdhd = Some(child.parse_full()?),
+                b"hdlr" => hdlr = Some(child.parse_full()?),
+                b"minf" => minf = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            mdhd: mdhd.ok_or_else(|| missing_box(b"mdhd", b"mdia"))?,
+            hdlr: hdlr.ok_or_else(|| missing_box(b"hdlr", b"mdia"))?,
+            minf: minf.ok_or_else(|| missing_box(b"minf", b"mdia"))?,
+            unknown,
+        })
     }
 }
 
-impl FullBox for SoundMediaHeaderBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
+impl Validate for MediaBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.mdhd.validate()?;
+        self.hdlr.validate()?;
+        self.minf.validate()
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct HintMediaHeaderBox {
-    pub max_pdu_size: u16,
-    pub avg_pdu_size: u16,
-    pub max_bitrate: u32,
-    pub avg_bitrate: u32,
+pub struct MediaHeaderBox {
+    pub creation_time: DateTime<Utc>,
+    pub modification_time: DateTime<Utc>,
+    pub timescale: u32,
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::option_duration"))]
+    pub duration: Option<Duration>,
+    /// The ISO 639-2/T language code, such as `*b"eng"`. Each letter is
+    /// packed into 5 bits as its offset from 0x60, so codes that aren't
+    /// letters, such as 0 or a Macintosh language code, read as bytes from
+    /// 0x60 to 0x7f and are written back unchanged.
+    pub language: [u8; 3],
 }
 
-impl BmffBox for HintMediaHeaderBox {
-    const TYPE: [u8; 4] = *b"hmhd";
+impl BmffBox for MediaHeaderBox {
+    const TYPE: [u8; 4] = *b"mdhd";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 2 + 2 + 4 + 4 + 4 // reserved u32
+        12 + (if self.creation_time.timestamp() as u64 > u32::MAX as u64
+            || self.modification_time.timestamp() as u64 > u32::MAX as u64
+            || self
+                .duration
+                .as_ref()
+                .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
+                > u32::MAX as u64
+        {
+            8 + 8 + 4 + 8
+        } else {
+            4 * 4
+        }) + 2
+            + 2
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&self.max_pdu_size.to_be_bytes())?;
-        w.write_all(&self.avg_pdu_size.to_be_bytes())?;
-        w.write_all(&self.max_bitrate.to_be_bytes())?;
-        w.write_all(&self.avg_bitrate.to_be_bytes())?;
-        w.write_all(&0u32.to_be_bytes())?;
+        self.validate()?;
+        let creation_timestamp = self.creation_time.timestamp();
+        let modification_timestamp = self.modification_time.timestamp();
+        let duration_secs = self
+            .duration
+            .as_ref()
+            .map_or(u32::MAX as u64, |x| duration(x, self.timescale));
+        if creation_timestamp as u64 > u32::MAX as u64
+            || modification_timestamp as u64 > u32::MAX as u64
+            || duration_secs > u32::MAX as u64
+        {
+            w.write_all(&creation_timestamp.to_be_bytes())?;
+            w.write_all(&modification_timestamp.to_be_bytes())?;
+            w.write_all(&self.timescale.to_be_bytes())?;
+            w.write_all(&duration_secs.to_be_bytes())?;
+        } else {
+            w.write_all(&(creation_timestamp as u32).to_be_bytes())?;
+            w.write_all(&(modification_timestamp as u32).to_be_bytes())?;
+            w.write_all(&self.timescale.to_be_bytes())?;
+            w.write_all(&(duration_secs as u32).to_be_bytes())?;
+        }
+        // 000aaaaa 000bbbbb 000ccccc
+        //    |||||   //  \\\   |||||
+        //    |||||  //    \\\  |||||
+        //  0 xxxxx xx      xxx xxxxx
+        let language = [
+            (self.language[0] - 0x60) << 2 | (self.language[1] - 0x60) >> 3,
+            (self.language[1] - 0x60) << 5 | (self.language[2] - 0x60),
+        ];
+        w.write_all(&language)?;
+        w.write_all(&0u16.to_be_bytes())?;
         Ok(())
     }
 }
 
-impl FullBox for HintMediaHeaderBox {
+impl ReadBox for MediaHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let (creation_time, modification_time, timescale, duration) = if r.version() == 1 {
+            (r.read_u64()?, r.read_u64()?, r.read_u32()?, r.read_u64()?)
+        } else {
+            (
+                r.read_u32()? as u64,
+                r.read_u32()? as u64,
+                r.read_u32()?,
+                r.read_u32()? as u64,
+            )
+        };
+        let language = r.read_u16()?;
+        r.skip(2)?;
+        Ok(Self {
+            creation_time: read_time(creation_time)?,
+            modification_time: read_time(modification_time)?,
+            timescale,
+            duration: read_duration(duration, timescale),
+            language: [
+                (language >> 10 & 0x1f) as u8 + 0x60,
+                (language >> 5 & 0x1f) as u8 + 0x60,
+                (language & 0x1f) as u8 + 0x60,
+            ],
+        })
+    }
+}
+
+impl FullBox for MediaHeaderBox {
     #[inline]
     fn version(&self) -> u8 {
-        0
+        u8::from(
+            self.creation_time.timestamp() as u64 > u32::MAX as u64
+                || self.modification_time.timestamp() as u64 > u32::MAX as u64
+                || self
+                    .duration
+                    .as_ref()
+                    .map_or(u32::MAX as u64, |x| duration(x, self.timescale))
+                    > u32::MAX as u64,
+        )
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct NullMediaHeaderBox {
-    pub flags: NullMediaHeaderFlags,
+impl Validate for MediaHeaderBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_times(Self::TYPE, &self.creation_time, &self.modification_time)?;
+        if !self.language.iter().all(|x| (0x60..=0x7f).contains(x)) {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "language must be packed as three bytes from 0x60 to 0x7f",
+            ));
+        }
+        Ok(())
+    }
 }
 
-bitflags! {
-    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
-    pub struct NullMediaHeaderFlags: u32 {}
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HandlerBox {
+    pub handler_type: HandlerType,
+    // spec says a null-terminated UTF-8 string, so not a `CString`
+    pub name: String,
 }
 
-impl BmffBox for NullMediaHeaderBox {
-    const TYPE: [u8; 4] = *b"nmhd";
-
-    #[inline]
-    fn size(&self) -> u64 {
-        12
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandlerType {
+    Video,
+    Audio,
+    Hint,
+    /// iTunes-style metadata, in a `meta` box
+    Metadata,
+    /// Metadata with a `keys` box, in a `meta` box
+    MetadataKeys,
+    /// Timed text, with a `nmhd` media header
+    Text,
+    /// Subtitles, with a `sthd` media header
+    Subtitle,
+    /// Any other handler, such as `tmcd` for timecode tracks
+    Other([u8; 4]),
+}
+
+impl From<[u8; 4]> for HandlerType {
+    fn from(handler_type: [u8; 4]) -> Self {
+        match &handler_type {
+            b"vide" => Self::Video,
+            b"soun" => Self::Audio,
+            b"hint" => Self::Hint,
+            b"mdir" => Self::Metadata,
+            b"mdta" => Self::MetadataKeys,
+            b"text" => Self::Text,
+            b"subt" => Self::Subtitle,
+            _ => Self::Other(handler_type),
+        }
     }
+}
 
-    fn write_box(&self, _w: impl Write) -> io::Result<()> {
-        Ok(())
+impl From<HandlerType> for [u8; 4] {
+    fn from(handler_type: HandlerType) -> Self {
+        match handler_type {
+            HandlerType::Video => *b"vide",
+            HandlerType::Audio => *b"soun",
+            HandlerType::Hint => *b"hint",
+            HandlerType::Metadata => *b"mdir",
+            HandlerType::MetadataKeys => *b"mdta",
+            HandlerType::Text => *b"text",
+            HandlerType::Subtitle => *b"subt",
+            HandlerType::Other(handler_type) => handler_type,
+        }
     }
 }
 
-impl FullBox for NullMediaHeaderBox {
+impl BmffBox for HandlerBox {
+    const TYPE: [u8; 4] = *b"hdlr";
+
     #[inline]
-    fn version(&self) -> u8 {
-        0
+    fn size(&self) -> u64 {
+        12 + 4 + 4 + 4 * 3 + self.name.len() as u64 + 1
     }
 
-    #[inline]
-    fn flags(&self) -> [u8; 3] {
-        let flags = self.flags.bits().to_be_bytes();
-        [flags[1], flags[2], flags[3]]
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&0u32.to_be_bytes())?;
+        w.write_all(&<[u8; 4]>::from(self.handler_type))?;
+        w.write_all(&[0u32.to_be_bytes(); 3].concat())?;
+        w.write_all(self.name.as_bytes())?;
+        w.write_all(&[0u8])?; // Null terminator
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DataInformationBox {
-    pub dref: DataReferenceBox,
+impl ReadBox for HandlerBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.skip(4)?;
+        let handler_type = HandlerType::from(r.read_array()?);
+        r.skip(4 * 3)?;
+        let name = r.read_string()?;
+        Ok(Self { handler_type, name })
+    }
 }
 
-impl BmffBox for DataInformationBox {
-    const TYPE: [u8; 4] = *b"dinf";
-
+impl FullBox for HandlerBox {
     #[inline]
-    fn size(&self) -> u64 {
-        8 + self.dref.size()
+    fn version(&self) -> u8 {
+        0
     }
+}
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.dref, &mut w)?;
-        Ok(())
+impl Validate for HandlerBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_string(Self::TYPE, &self.name)
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct DataReferenceBox {
-    pub data_entries: Vec<DataEntry>,
+pub struct MediaInformationBox {
+    pub media_header: MediaHeader,
+    pub dinf: DataInformationBox,
+    pub stbl: SampleTableBox,
+    pub unknown: Vec<UnknownBox>,
 }
 
-impl BmffBox for DataReferenceBox {
-    const TYPE: [u8; 4] = *b"dref";
+impl BmffBox for MediaInformationBox {
+    const TYPE: [u8; 4] = *b"minf";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + self.data_entries.iter().map(DataEntry::size).sum::<u64>()
+        8 + self.media_header.size()
+            + self.dinf.size()
+            + self.stbl.size()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&(self.data_entries.len() as u32).to_be_bytes())?;
-        for entry in &self.data_entries {
-            entry.write_to(&mut w)?;
+        let mut children = Children::new(&self.unknown);
+        children.next(&mut w)?;
+        self.media_header.write_to(&mut w)?;
+        children.write(&self.dinf, &mut w)?;
+        children.write(&self.stbl, &mut w)?;
+        children.finish(w)
+    }
+}
+
+impl ReadBox for MediaInformationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut media_header = None;
+        let mut dinf = None;
+        let mut stbl = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"vmhd" => media_header = Some(MediaHeader::Video(child.parse_full()?)),
+                b"smhd" => media_header = Some(MediaHeader::Sound(child.parse_full()?)),
+                b"hmhd" => media_header = Some(MediaHeader::Hint(child.parse_full()?)),
+                b"nmhd" => media_header = Some(MediaHeader::Null(child.parse_full()?)),
+                b"sthd" => media_header = Some(MediaHeader::Subtitle(child.parse_full()?)),
+                b"dinf" => dinf = Some(child.parse()?),
+                b"stbl" => stbl = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
         }
-        Ok(())
+        Ok(Self {
+            media_header: media_header.ok_or_else(|| missing_box(b"vmhd", b"minf"))?,
+            dinf: dinf.ok_or_else(|| missing_box(b"dinf", b"minf"))?,
+            stbl: stbl.ok_or_else(|| missing_box(b"stbl", b"minf"))?,
+            unknown,
+        })
     }
 }
 
-impl FullBox for DataReferenceBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
+impl Validate for MediaInformationBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.dinf.dref.validate()?;
+        self.stbl.validate()
     }
 }
 
 #[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub enum DataEntry {
-    Url(DataEntryUrlBox),
-    Urn(DataEntryUrnBox),
+pub enum MediaHeader {
+    Video(VideoMediaHeaderBox),
+    Sound(SoundMediaHeaderBox),
+    Hint(HintMediaHeaderBox),
+    Null(NullMediaHeaderBox),
+    Subtitle(SubtitleMediaHeaderBox),
 }
 
-impl DataEntry {
+impl MediaHeader {
     #[inline]
     pub fn size(&self) -> u64 {
         match self {
-            Self::Url(url) => url.size(),
-            Self::Urn(urn) => urn.size(),
+            Self::Video(vmhd) => vmhd.size(),
+            Self::Sound(smhd) => smhd.size(),
+            Self::Hint(hmhd) => hmhd.size(),
+            Self::Null(nmhd) => nmhd.size(),
+            Self::Subtitle(sthd) => sthd.size(),
         }
     }
 }
 
-impl WriteTo for DataEntry {
+impl WriteTo for MediaHeader {
     fn write_to(&self, w: impl Write) -> io::Result<()> {
         match self {
-            Self::Url(url) => write_to_full(url, w),
-            Self::Urn(urn) => write_to_full(urn, w),
+            Self::Video(vmhd) => write_to_full(vmhd, w),
+            Self::Sound(smhd) => write_to_full(smhd, w),
+            Self::Hint(hmhd) => write_to_full(hmhd, w),
+            Self::Null(nmhd) => write_to_full(nmhd, w),
+            Self::Subtitle(sthd) => write_to_full(sthd, w),
         }
     }
 }
 
-bitflags! {
-    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
-    pub struct DataEntryFlags: u32 {
-        /// Indicates that the media data is in the same file as the containing MovieBox.
-        const SELF_CONTAINED = 0x00_0001;
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct VideoMediaHeaderBox {
+    pub graphics_mode: GraphicsMode,
+    pub opcolor: [u16; 3],
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsMode {
+    Copy,
+    /// Any other mode, such as the QuickTime blending modes
+    Other(u16),
+}
+
+impl From<u16> for GraphicsMode {
+    fn from(graphics_mode: u16) -> Self {
+        match graphics_mode {
+            0 => Self::Copy,
+            _ => Self::Other(graphics_mode),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DataEntryUrlBox {
-    pub flags: DataEntryFlags,
-    pub location: String,
+impl From<GraphicsMode> for u16 {
+    fn from(graphics_mode: GraphicsMode) -> Self {
+        match graphics_mode {
+            GraphicsMode::Copy => 0,
+            GraphicsMode::Other(graphics_mode) => graphics_mode,
+        }
+    }
 }
 
-impl BmffBox for DataEntryUrlBox {
-    const TYPE: [u8; 4] = *b"url ";
+impl BmffBox for VideoMediaHeaderBox {
+    const TYPE: [u8; 4] = *b"vmhd";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + if self.flags.contains(DataEntryFlags::SELF_CONTAINED) {
-            0
-        } else {
-            self.location.len() as u64 + 1
-        }
+        12 + 2 + 2 * 3
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        if !self.flags.contains(DataEntryFlags::SELF_CONTAINED) {
-            w.write_all(self.location.as_bytes())?;
-            w.write_all(&[0u8])?; // Null terminator
+        w.write_all(&u16::from(self.graphics_mode).to_be_bytes())?;
+        for i in self.opcolor {
+            w.write_all(&i.to_be_bytes())?;
         }
         Ok(())
     }
 }
 
-impl FullBox for DataEntryUrlBox {
+impl ReadBox for VideoMediaHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let graphics_mode = r.read_u16()?.into();
+        let opcolor = [r.read_u16()?, r.read_u16()?, r.read_u16()?];
+        Ok(Self {
+            graphics_mode,
+            opcolor,
+        })
+    }
+}
+
+impl FullBox for VideoMediaHeaderBox {
     #[inline]
     fn version(&self) -> u8 {
         0
@@ -857,1035 +2136,6332 @@ impl FullBox for DataEntryUrlBox {
 
     #[inline]
     fn flags(&self) -> [u8; 3] {
-        let flags = self.flags.bits().to_be_bytes();
-        [flags[1], flags[2], flags[3]]
+        [0, 0, 1]
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct DataEntryUrnBox {
-    pub flags: DataEntryFlags,
-    pub name: String,
-    pub location: Option<String>,
+pub struct SoundMediaHeaderBox {
+    pub balance: I8F8,
 }
 
-impl BmffBox for DataEntryUrnBox {
-    const TYPE: [u8; 4] = *b"urn ";
+impl BmffBox for SoundMediaHeaderBox {
+    const TYPE: [u8; 4] = *b"smhd";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + self.name.len() as u64 + 1 + self.location.as_ref().map_or(0, |x| x.len() as u64 + 1)
+        12 + 2 + 2
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(self.name.as_bytes())?;
-        w.write_all(&[0u8])?; // Null terminator
-        if let Some(location) = &self.location {
-            w.write_all(location.as_bytes())?;
-            w.write_all(&[0u8])?; // Null terminator
-        }
+        w.write_all(&self.balance.to_be_bytes())?;
+        w.write_all(&0u16.to_be_bytes())?;
         Ok(())
     }
 }
 
-impl FullBox for DataEntryUrnBox {
+impl ReadBox for SoundMediaHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let balance = I8F8::from_be_bytes(r.read_array()?);
+        r.skip(2)?;
+        Ok(Self { balance })
+    }
+}
+
+impl FullBox for SoundMediaHeaderBox {
     #[inline]
     fn version(&self) -> u8 {
         0
     }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HintMediaHeaderBox {
+    pub max_pdu_size: u16,
+    pub avg_pdu_size: u16,
+    pub max_bitrate: u32,
+    pub avg_bitrate: u32,
+}
+
+impl BmffBox for HintMediaHeaderBox {
+    const TYPE: [u8; 4] = *b"hmhd";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 2 + 2 + 4 + 4 + 4 // reserved u32
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&self.max_pdu_size.to_be_bytes())?;
+        w.write_all(&self.avg_pdu_size.to_be_bytes())?;
+        w.write_all(&self.max_bitrate.to_be_bytes())?;
+        w.write_all(&self.avg_bitrate.to_be_bytes())?;
+        w.write_all(&0u32.to_be_bytes())?;
+        Ok(())
+    }
+}
+
+impl ReadBox for HintMediaHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let max_pdu_size = r.read_u16()?;
+        let avg_pdu_size = r.read_u16()?;
+        let max_bitrate = r.read_u32()?;
+        let avg_bitrate = r.read_u32()?;
+        r.skip(4)?;
+        Ok(Self {
+            max_pdu_size,
+            avg_pdu_size,
+            max_bitrate,
+            avg_bitrate,
+        })
+    }
+}
+
+impl FullBox for HintMediaHeaderBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct NullMediaHeaderBox {
+    pub flags: NullMediaHeaderFlags,
+}
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    pub struct NullMediaHeaderFlags: u32 {}
+}
+
+impl BmffBox for NullMediaHeaderBox {
+    const TYPE: [u8; 4] = *b"nmhd";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12
+    }
+
+    fn write_box(&self, _w: impl Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ReadBox for NullMediaHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            flags: NullMediaHeaderFlags::from_bits_retain(r.flags()),
+        })
+    }
+}
+
+impl FullBox for NullMediaHeaderBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        let flags = self.flags.bits().to_be_bytes();
+        [flags[1], flags[2], flags[3]]
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SubtitleMediaHeaderBox;
+
+impl BmffBox for SubtitleMediaHeaderBox {
+    const TYPE: [u8; 4] = *b"sthd";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12
+    }
+
+    fn write_box(&self, _w: impl Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ReadBox for SubtitleMediaHeaderBox {
+    fn read_box(_r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self)
+    }
+}
+
+impl FullBox for SubtitleMediaHeaderBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct DataInformationBox {
+    pub dref: DataReferenceBox,
+}
+
+impl BmffBox for DataInformationBox {
+    const TYPE: [u8; 4] = *b"dinf";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.dref.size()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        write_to_full(&self.dref, &mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for DataInformationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut dref = None;
+        while let Some(child) = r.next_child()? {
+            if child.header().box_type == DataReferenceBox::TYPE {
+                dref = Some(child.parse_full()?);
+            }
+        }
+        Ok(Self {
+            dref: dref.ok_or_else(|| missing_box(b"dref", b"dinf"))?,
+        })
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct DataReferenceBox {
+    pub data_entries: Vec<DataEntry>,
+}
+
+impl BmffBox for DataReferenceBox {
+    const TYPE: [u8; 4] = *b"dref";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.data_entries.iter().map(DataEntry::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&(self.data_entries.len() as u32).to_be_bytes())?;
+        for entry in &self.data_entries {
+            entry.write_to(&mut w)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for DataReferenceBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut data_entries = Vec::new();
+        for _ in 0..entry_count {
+            let child = r
+                .next_child()?
+                .ok_or_else(|| invalid_data("dref box has too few entries"))?;
+            data_entries.push(match &child.header().box_type {
+                b"url " => DataEntry::Url(child.parse_full()?),
+                b"urn " => DataEntry::Urn(child.parse_full()?),
+                other => {
+                    return Err(invalid_data(format!(
+                        "unsupported data entry {}",
+                        fourcc(other)
+                    )))
+                }
+            });
+        }
+        Ok(Self { data_entries })
+    }
+}
+
+impl FullBox for DataReferenceBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+impl Validate for DataReferenceBox {
+    fn validate(&self) -> Result<(), Error> {
+        if self.data_entries.is_empty() {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "there must be at least one entry",
+            ));
+        }
+        check_bits(
+            Self::TYPE,
+            "entry_count",
+            self.data_entries.len() as u64,
+            32,
+        )?;
+        for entry in &self.data_entries {
+            match entry {
+                DataEntry::Url(url) => check_string(DataEntryUrlBox::TYPE, &url.location)?,
+                DataEntry::Urn(urn) => {
+                    check_string(DataEntryUrnBox::TYPE, &urn.name)?;
+                    if let Some(location) = &urn.location {
+                        check_string(DataEntryUrnBox::TYPE, location)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum DataEntry {
+    Url(DataEntryUrlBox),
+    Urn(DataEntryUrnBox),
+}
+
+impl DataEntry {
+    #[inline]
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Url(url) => url.size(),
+            Self::Urn(urn) => urn.size(),
+        }
+    }
+}
+
+impl WriteTo for DataEntry {
+    fn write_to(&self, w: impl Write) -> io::Result<()> {
+        match self {
+            Self::Url(url) => write_to_full(url, w),
+            Self::Urn(urn) => write_to_full(urn, w),
+        }
+    }
+}
+
+bitflags! {
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    pub struct DataEntryFlags: u32 {
+        /// Indicates that the media data is in the same file as the containing MovieBox.
+        const SELF_CONTAINED = 0x00_0001;
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct DataEntryUrlBox {
+    pub flags: DataEntryFlags,
+    pub location: String,
+}
+
+impl BmffBox for DataEntryUrlBox {
+    const TYPE: [u8; 4] = *b"url ";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + if self.flags.contains(DataEntryFlags::SELF_CONTAINED) {
+            0
+        } else {
+            self.location.len() as u64 + 1
+        }
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        if !self.flags.contains(DataEntryFlags::SELF_CONTAINED) {
+            w.write_all(self.location.as_bytes())?;
+            w.write_all(&[0u8])?; // Null terminator
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for DataEntryUrlBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let flags = DataEntryFlags::from_bits_retain(r.flags());
+        let location = if flags.contains(DataEntryFlags::SELF_CONTAINED) {
+            String::new()
+        } else {
+            r.read_string()?
+        };
+        Ok(Self { flags, location })
+    }
+}
+
+impl FullBox for DataEntryUrlBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        let flags = self.flags.bits().to_be_bytes();
+        [flags[1], flags[2], flags[3]]
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct DataEntryUrnBox {
+    pub flags: DataEntryFlags,
+    pub name: String,
+    pub location: Option<String>,
+}
+
+impl BmffBox for DataEntryUrnBox {
+    const TYPE: [u8; 4] = *b"urn ";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + self.name.len() as u64 + 1 + self.location.as_ref().map_or(0, |x| x.len() as u64 + 1)
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(self.name.as_bytes())?;
+        w.write_all(&[0u8])?; // Null terminator
+        if let Some(location) = &self.location {
+            w.write_all(location.as_bytes())?;
+            w.write_all(&[0u8])?; // Null terminator
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for DataEntryUrnBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let name = r.read_string()?;
+        let location = if r.remaining() > 0 {
+            Some(r.read_string()?)
+        } else {
+            None
+        };
+        Ok(Self {
+            flags: DataEntryFlags::from_bits_retain(r.flags()),
+            name,
+            location,
+        })
+    }
+}
+
+impl FullBox for DataEntryUrnBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        let flags = self.flags.bits().to_be_bytes();
+        [flags[1], flags[2], flags[3]]
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SampleTableBox {
+    pub stsd: SampleDescriptionBox,
+    pub stts: TimeToSampleBox,
+    pub ctts: Option<CompositionOffsetBox>,
+    pub stsc: SampleToChunkBox,
+    pub stsz: SampleSizeBox,
+    pub chunk_offset: ChunkOffset,
+    /// If this is `None`, every sample is a sync sample.
+    pub stss: Option<SyncSampleBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl BmffBox for SampleTableBox {
+    const TYPE: [u8; 4] = *b"stbl";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.stsd.size()
+            + self.stts.size()
+            + self.ctts.as_ref().map_or(0, BmffBox::size)
+            + self.stsc.size()
+            + self.stsz.size()
+            + self.chunk_offset.size()
+            + self.stss.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        children.write_full(&self.stsd, &mut w)?;
+        children.write_full(&self.stts, &mut w)?;
+        if let Some(ctts) = &self.ctts {
+            children.write_full(ctts, &mut w)?;
+        }
+        children.write_full(&self.stsc, &mut w)?;
+        children.write_full(&self.stsz, &mut w)?;
+        children.next(&mut w)?;
+        self.chunk_offset.write_to(&mut w)?;
+        if let Some(stss) = &self.stss {
+            children.write_full(stss, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for SampleTableBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut stsd = None;
+        let mut stts = None;
+        let mut ctts = None;
+        let mut stsc = None;
+        let mut stsz = None;
+        let mut chunk_offset = None;
+        let mut stss = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"stsd" => stsd = Some(child.parse_full()?),
+                b"stts" => stts = Some(child.parse_full()?),
+                b"ctts" => ctts = Some(child.parse_full()?),
+                b"stsc" => stsc = Some(child.parse_full()?),
+                b"stsz" => stsz = Some(child.parse_full()?),
+                b"stco" => chunk_offset = Some(ChunkOffset::Compact(child.parse_full()?)),
+                b"co64" => chunk_offset = Some(ChunkOffset::Large(child.parse_full()?)),
+                b"stss" => stss = Some(child.parse_full()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            stsd: stsd.ok_or_else(|| missing_box(b"stsd", b"stbl"))?,
+            stts: stts.ok_or_else(|| missing_box(b"stts", b"stbl"))?,
+            ctts,
+            stsc: stsc.ok_or_else(|| missing_box(b"stsc", b"stbl"))?,
+            stsz: stsz.ok_or_else(|| missing_box(b"stsz", b"stbl"))?,
+            chunk_offset: chunk_offset.ok_or_else(|| missing_box(b"stco", b"stbl"))?,
+            stss,
+            unknown,
+        })
+    }
+}
+
+impl Validate for SampleTableBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.stsd.validate()?;
+        self.stts.validate()?;
+        if let Some(ctts) = &self.ctts {
+            ctts.validate()?;
+        }
+        self.stsc.validate()?;
+        self.chunk_offset.validate()
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TimeToSampleBox {
+    /// `(sample_count, sample_delta)`
+    pub samples: Vec<(u32, u32)>,
+}
+
+impl BmffBox for TimeToSampleBox {
+    const TYPE: [u8; 4] = *b"stts";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.samples.len() as u64 * 8
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&(self.samples.len() as u32).to_be_bytes())?;
+        for (sample_count, sample_delta) in &self.samples {
+            w.write_all(&sample_count.to_be_bytes())?;
+            w.write_all(&sample_delta.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for TimeToSampleBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut samples = Vec::new();
+        for _ in 0..entry_count {
+            samples.push((r.read_u32()?, r.read_u32()?));
+        }
+        Ok(Self { samples })
+    }
+}
+
+impl FullBox for TimeToSampleBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+impl Validate for TimeToSampleBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(Self::TYPE, "entry_count", self.samples.len() as u64, 32)
+    }
+}
+
+/// Returns the lowest version of `ctts` or `trun` that can hold `offsets`.
+///
+/// Composition offsets are written as unsigned integers in version 0 and as
+/// signed integers in version 1, so this is 1 if any offset is negative.
+pub fn composition_offsets_version(offsets: impl IntoIterator<Item = i64>) -> u8 {
+    u8::from(offsets.into_iter().any(|x| x < 0))
+}
+
+fn check_composition_offsets(
+    box_type: [u8; 4],
+    field: &'static str,
+    version: u8,
+    offsets: &[i64],
+) -> Result<(), Error> {
+    let fits = match version {
+        0 => offsets.iter().all(|&x| u32::try_from(x).is_ok()),
+        1 => offsets.iter().all(|&x| i32::try_from(x).is_ok()),
+        _ => return Err(Error::Invalid(box_type, "version must be 0 or 1")),
+    };
+    if fits {
+        Ok(())
+    } else {
+        Err(Error::Overflow(box_type, field))
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct CompositionOffsetBox {
+    /// 1 if the offsets are signed. Negative offsets need version 1, and
+    /// offsets above `i32::MAX` need version 0.
+    pub version: u8,
+    /// `(sample_count, sample_offset)`
+    pub samples: Vec<(u32, i64)>,
+}
+
+impl BmffBox for CompositionOffsetBox {
+    const TYPE: [u8; 4] = *b"ctts";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.samples.len() as u64 * 8
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&(self.samples.len() as u32).to_be_bytes())?;
+        for (sample_count, sample_offset) in &self.samples {
+            w.write_all(&sample_count.to_be_bytes())?;
+            if self.version() == 0 {
+                w.write_all(&(*sample_offset as u32).to_be_bytes())?;
+            } else {
+                w.write_all(&(*sample_offset as i32).to_be_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for CompositionOffsetBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut samples = Vec::new();
+        for _ in 0..entry_count {
+            let sample_count = r.read_u32()?;
+            let sample_offset = if r.version() == 0 {
+                i64::from(r.read_u32()?)
+            } else {
+                i64::from(r.read_i32()?)
+            };
+            samples.push((sample_count, sample_offset));
+        }
+        Ok(Self {
+            version: r.version(),
+            samples,
+        })
+    }
+}
+
+impl Validate for CompositionOffsetBox {
+    fn validate(&self) -> Result<(), Error> {
+        let offsets = self.samples.iter().map(|&(_, x)| x).collect::<Vec<_>>();
+        check_composition_offsets(Self::TYPE, "sample_offset", self.version, &offsets)
+    }
+}
+
+impl FullBox for CompositionOffsetBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+pub trait SampleEntry: Validate + std::fmt::Debug + Send + Sync {
+    fn size(&self) -> u64;
+    /// Writes the entry, including its header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry fails validation.
+    fn write_to(&self) -> io::Result<Vec<u8>>;
+    fn clone_box(&self) -> Box<dyn SampleEntry>;
+    /// Allows downcasting a parsed sample entry to its concrete type.
+    fn as_any(&self) -> &dyn std::any::Any;
+}
+
+fn read_sample_entry(r: BoxReader<'_>) -> io::Result<Box<dyn SampleEntry>> {
+    Ok(match &r.header().box_type {
+        b"avc1" => Box::new(r.parse::<AvcSampleEntry>()?),
+        b"hvc1" => Box::new(r.parse::<HevcSampleEntry>()?),
+        b"hev1" => Box::new(r.parse::<Hev1SampleEntry>()?),
+        b"av01" => Box::new(r.parse::<Av1SampleEntry>()?),
+        b"mp4a" => Box::new(r.parse::<Mp4aSampleEntry>()?),
+        b"Opus" => Box::new(r.parse::<OpusSampleEntry>()?),
+        b"wvtt" => Box::new(r.parse::<WvttSampleEntry>()?),
+        b"encv" => Box::new(r.parse::<EncvSampleEntry>()?),
+        b"vide" => Box::new(r.parse::<VisualSampleEntry>()?),
+        b"soun" => Box::new(r.parse::<AudioSampleEntry>()?),
+        b"hint" => Box::new(r.parse::<HintSampleEntry>()?),
+        _ => Box::new(RawBox::from_reader(r)),
+    })
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug)]
+pub struct SampleDescriptionBox {
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::sample_entries"))]
+    pub entries: Vec<Box<dyn SampleEntry>>,
+}
+
+impl Clone for SampleDescriptionBox {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.iter().map(|x| x.clone_box()).collect(),
+        }
+    }
+}
+
+impl BmffBox for SampleDescriptionBox {
+    const TYPE: [u8; 4] = *b"stsd";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.entries.iter().map(|x| x.size()).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&(self.entries.len() as u32).to_be_bytes())?;
+        for entry in &self.entries {
+            w.write_all(&entry.write_to()?)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for SampleDescriptionBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut entries = Vec::new();
+        for _ in 0..entry_count {
+            let child = r
+                .next_child()?
+                .ok_or_else(|| invalid_data("stsd box has too few entries"))?;
+            entries.push(read_sample_entry(child)?);
+        }
+        Ok(Self { entries })
+    }
+}
+
+impl Validate for SampleDescriptionBox {
+    fn validate(&self) -> Result<(), Error> {
+        for entry in &self.entries {
+            entry.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl FullBox for SampleDescriptionBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SampleSizeBox {
+    pub sample_size: SampleSize,
+}
+
+impl BmffBox for SampleSizeBox {
+    const TYPE: [u8; 4] = *b"stsz";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + self.sample_size.size()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.sample_size.write_to(&mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for SampleSizeBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let sample_size = r.read_u32()?;
+        let sample_count = r.read_u32()?;
+        let sample_size = match NonZeroU32::new(sample_size) {
+            Some(size) => SampleSize::Same { size, sample_count },
+            None => {
+                let mut entry_sizes = Vec::new();
+                for _ in 0..sample_count {
+                    entry_sizes.push(r.read_u32()?);
+                }
+                SampleSize::Different(entry_sizes)
+            }
+        };
+        Ok(Self { sample_size })
+    }
+}
+
+impl FullBox for SampleSizeBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[allow(dead_code)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleSize {
+    Same { size: NonZeroU32, sample_count: u32 },
+    Different(Vec<u32>),
+}
+
+impl SampleSize {
+    fn size(&self) -> u64 {
+        match self {
+            Self::Same { .. } => 8,
+            Self::Different(x) => 8 + x.len() as u64 * 4,
+        }
+    }
+}
+
+impl WriteTo for SampleSize {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        match self {
+            Self::Same { size, sample_count } => {
+                w.write_all(&size.get().to_be_bytes())?;
+                w.write_all(&sample_count.to_be_bytes())?;
+            }
+            Self::Different(entry_sizes) => {
+                w.write_all(&0u32.to_be_bytes())?;
+                w.write_all(&(entry_sizes.len() as u32).to_be_bytes())?;
+                for entry_size in entry_sizes.iter() {
+                    w.write_all(&entry_size.to_be_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fields at the start of every visual sample entry, before its child
+/// boxes.
+struct VisualSampleEntryHeader {
+    data_reference_index: u16,
+    width: u16,
+    height: u16,
+    horiz_resolution: U16F16,
+    vert_resolution: U16F16,
+    frame_count: u16,
+    /// A length byte followed by the name, padded with zeros
+    compressor_name: [u8; 32],
+    depth: u16,
+}
+
+impl VisualSampleEntryHeader {
+    /// The size of the fields, not including the box header
+    const SIZE: u64 = 6 + 2 + 2 + 2 + 4 * 3 + 2 + 2 + 4 + 4 + 4 + 2 + 32 + 2 + 2;
+
+    fn write(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&[0u8; 6])?;
+        w.write_all(&self.data_reference_index.to_be_bytes())?;
+        w.write_all(&0u16.to_be_bytes())?;
+        w.write_all(&0u16.to_be_bytes())?;
+        w.write_all(&[0u32.to_be_bytes(); 3].concat())?;
+        w.write_all(&self.width.to_be_bytes())?;
+        w.write_all(&self.height.to_be_bytes())?;
+        w.write_all(&self.horiz_resolution.to_be_bytes())?;
+        w.write_all(&self.vert_resolution.to_be_bytes())?;
+        w.write_all(&0u32.to_be_bytes())?;
+        w.write_all(&self.frame_count.to_be_bytes())?;
+        w.write_all(&self.compressor_name)?;
+        w.write_all(&self.depth.to_be_bytes())?;
+        w.write_all(&(-1i16).to_be_bytes())?; // pre_defined
+        Ok(())
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.skip(6)?;
+        let data_reference_index = r.read_u16()?;
+        r.skip(2 + 2 + 4 * 3)?;
+        let width = r.read_u16()?;
+        let height = r.read_u16()?;
+        let horiz_resolution = U16F16::from_be_bytes(r.read_array()?);
+        let vert_resolution = U16F16::from_be_bytes(r.read_array()?);
+        r.skip(4)?;
+        let frame_count = r.read_u16()?;
+        let compressor_name = r.read_array()?;
+        let depth = r.read_u16()?;
+        r.skip(2)?;
+        Ok(Self {
+            data_reference_index,
+            width,
+            height,
+            horiz_resolution,
+            vert_resolution,
+            frame_count,
+            compressor_name,
+            depth,
+        })
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct VisualSampleEntry {
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    /// DPI
+    pub horiz_resolution: U16F16,
+    /// DPI
+    pub vert_resolution: U16F16,
+    pub frame_count: u16,
+    // must be less than 32 bytes
+    pub compressor_name: String,
+    pub depth: u16,
+}
+
+impl BmffBox for VisualSampleEntry {
+    const TYPE: [u8; 4] = *b"vide";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + VisualSampleEntryHeader::SIZE
+    }
+
+    fn write_box(&self, w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        let mut compressor_name = [0u8; 32];
+        compressor_name[0] = self.compressor_name.len() as u8;
+        compressor_name[1..1 + self.compressor_name.len()]
+            .copy_from_slice(self.compressor_name.as_bytes());
+        VisualSampleEntryHeader {
+            data_reference_index: self.data_reference_index,
+            width: self.width,
+            height: self.height,
+            horiz_resolution: self.horiz_resolution,
+            vert_resolution: self.vert_resolution,
+            frame_count: self.frame_count,
+            compressor_name,
+            depth: self.depth,
+        }
+        .write(w)
+    }
+}
+
+impl ReadBox for VisualSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let header = VisualSampleEntryHeader::read(r)?;
+        let len = (header.compressor_name[0] as usize).min(31);
+        let compressor_name = std::str::from_utf8(&header.compressor_name[1..1 + len])
+            .map_err(|_| invalid_data("compressor name is not valid UTF-8"))?
+            .to_string();
+        Ok(Self {
+            data_reference_index: header.data_reference_index,
+            width: header.width,
+            height: header.height,
+            horiz_resolution: header.horiz_resolution,
+            vert_resolution: header.vert_resolution,
+            frame_count: header.frame_count,
+            compressor_name,
+            depth: header.depth,
+        })
+    }
+}
+
+impl Validate for VisualSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        if self.compressor_name.len() >= 32 {
+            return Err(Error::Overflow(Self::TYPE, "compressor_name"));
+        }
+        Ok(())
+    }
+}
+
+impl SampleEntry for VisualSampleEntry {
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// The fields at the start of every audio sample entry, before its child
+/// boxes.
+struct AudioSampleEntryHeader {
+    data_reference_index: u16,
+    channel_count: u16,
+    sample_size: u16,
+    sample_rate: U16F16,
+}
+
+impl AudioSampleEntryHeader {
+    /// The size of the fields, not including the box header
+    const SIZE: u64 = 6 + 2 + 4 * 2 + 2 + 2 + 2 + 2 + 4;
+
+    fn write(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&[0u8; 6])?;
+        w.write_all(&self.data_reference_index.to_be_bytes())?;
+        w.write_all(&[0u32.to_be_bytes(); 2].concat())?;
+        w.write_all(&self.channel_count.to_be_bytes())?;
+        w.write_all(&self.sample_size.to_be_bytes())?;
+        w.write_all(&0u16.to_be_bytes())?;
+        w.write_all(&0u16.to_be_bytes())?;
+        w.write_all(&self.sample_rate.to_be_bytes())?;
+        Ok(())
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.skip(6)?;
+        let data_reference_index = r.read_u16()?;
+        r.skip(4 * 2)?;
+        let channel_count = r.read_u16()?;
+        let sample_size = r.read_u16()?;
+        r.skip(2 + 2)?;
+        let sample_rate = U16F16::from_be_bytes(r.read_array()?);
+        Ok(Self {
+            data_reference_index,
+            channel_count,
+            sample_size,
+            sample_rate,
+        })
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct AudioSampleEntry {
+    pub data_reference_index: u16,
+    pub channel_count: u16,
+    /// bits
+    pub sample_size: u16,
+    pub sample_rate: u32,
+}
+
+impl BmffBox for AudioSampleEntry {
+    const TYPE: [u8; 4] = *b"soun";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + AudioSampleEntryHeader::SIZE
+    }
+
+    fn write_box(&self, w: impl Write) -> io::Result<()> {
+        AudioSampleEntryHeader {
+            data_reference_index: self.data_reference_index,
+            channel_count: self.channel_count,
+            sample_size: self.sample_size,
+            sample_rate: U16F16::from_bits(self.sample_rate),
+        }
+        .write(w)
+    }
+}
+
+impl ReadBox for AudioSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let header = AudioSampleEntryHeader::read(r)?;
+        Ok(Self {
+            data_reference_index: header.data_reference_index,
+            channel_count: header.channel_count,
+            sample_size: header.sample_size,
+            sample_rate: header.sample_rate.to_bits(),
+        })
+    }
+}
+
+impl Validate for AudioSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl SampleEntry for AudioSampleEntry {
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// An `mp4a` sample entry, used for AAC and other MPEG-4 audio.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Mp4aSampleEntry {
+    pub data_reference_index: u16,
+    pub channel_count: u16,
+    /// bits
+    pub sample_size: u16,
+    pub sample_rate: U16F16,
+    pub esds: ElementaryStreamDescriptorBox,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl Mp4aSampleEntry {
+    fn header(&self) -> AudioSampleEntryHeader {
+        AudioSampleEntryHeader {
+            data_reference_index: self.data_reference_index,
+            channel_count: self.channel_count,
+            sample_size: self.sample_size,
+            sample_rate: self.sample_rate,
+        }
+    }
+}
+
+impl BmffBox for Mp4aSampleEntry {
+    const TYPE: [u8; 4] = *b"mp4a";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + AudioSampleEntryHeader::SIZE
+            + self.esds.size()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        self.header().write(&mut w)?;
+        children.write_full(&self.esds, &mut w)?;
+        children.finish(w)
+    }
+}
+
+impl ReadBox for Mp4aSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let header = AudioSampleEntryHeader::read(r)?;
+        let mut esds = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            if child.header().box_type == ElementaryStreamDescriptorBox::TYPE {
+                esds = Some(child.parse_full()?);
+            } else {
+                unknown.push(r.unknown(child));
+            }
+        }
+        Ok(Self {
+            data_reference_index: header.data_reference_index,
+            channel_count: header.channel_count,
+            sample_size: header.sample_size,
+            sample_rate: header.sample_rate,
+            esds: esds.ok_or_else(|| missing_box(b"esds", b"mp4a"))?,
+            unknown,
+        })
+    }
+}
+
+impl Validate for Mp4aSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        self.esds.validate()
+    }
+}
+
+impl SampleEntry for Mp4aSampleEntry {
+    #[inline]
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ElementaryStreamDescriptorBox {
+    pub es_descriptor: EsDescriptor,
+}
+
+impl BmffBox for ElementaryStreamDescriptorBox {
+    const TYPE: [u8; 4] = *b"esds";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + self.es_descriptor.size()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        self.es_descriptor.write_to(&mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for ElementaryStreamDescriptorBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let (tag, mut payload) = read_descriptor(r)?;
+        if tag != EsDescriptor::TAG {
+            return Err(invalid_data(format!(
+                "expected ES_Descriptor in esds box, got tag {tag}"
+            )));
+        }
+        Ok(Self {
+            es_descriptor: EsDescriptor::read(&mut payload)?,
+        })
+    }
+}
+
+impl Validate for ElementaryStreamDescriptorBox {
+    fn validate(&self) -> Result<(), Error> {
+        let es = &self.es_descriptor;
+        let config = &es.decoder_config;
+        check_bits(Self::TYPE, "stream_priority", es.stream_priority as u64, 5)?;
+        check_bits(Self::TYPE, "stream_type", config.stream_type as u64, 6)?;
+        check_bits(
+            Self::TYPE,
+            "buffer_size_db",
+            config.buffer_size_db as u64,
+            24,
+        )?;
+        // descriptor sizes are at most four bytes of seven bits each
+        check_bits(Self::TYPE, "es_descriptor", es.len(), 28)?;
+        Ok(())
+    }
+}
+
+impl FullBox for ElementaryStreamDescriptorBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+/// Reads an MPEG-4 descriptor tag and size, returning the tag and a reader
+/// over the descriptor body.
+fn read_descriptor<'a>(r: &mut BoxReader<'a>) -> io::Result<(u8, BoxReader<'a>)> {
+    let tag = r.read_u8()?;
+    let mut len = 0usize;
+    for _ in 0..4 {
+        let byte = r.read_u8()?;
+        len = len << 7 | (byte & 0x7f) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let payload = r.read_bytes(len)?;
+    Ok((tag, BoxReader::from_payload(*r.header(), payload)))
+}
+
+fn descriptor_size(len: u64) -> u64 {
+    let mut size_bytes = 1;
+    while len >> (7 * size_bytes) != 0 && size_bytes < 4 {
+        size_bytes += 1;
+    }
+    1 + size_bytes + len
+}
+
+fn write_descriptor_header(tag: u8, len: u64, mut w: impl Write) -> io::Result<()> {
+    w.write_all(&[tag])?;
+    let size_bytes = descriptor_size(len) - 1 - len;
+    for i in (0..size_bytes).rev() {
+        let more = if i == 0 { 0 } else { 0x80 };
+        w.write_all(&[more | (len >> (7 * i)) as u8 & 0x7f])?;
+    }
+    Ok(())
+}
+
+/// An `ES_Descriptor`, as defined in ISO/IEC 14496-1.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct EsDescriptor {
+    pub es_id: u16,
+    /// 5 bits
+    pub stream_priority: u8,
+    pub decoder_config: DecoderConfigDescriptor,
+}
+
+impl EsDescriptor {
+    const TAG: u8 = 0x03;
+    const SL_CONFIG_TAG: u8 = 0x06;
+
+    fn len(&self) -> u64 {
+        2 + 1 + self.decoder_config.size() + descriptor_size(1)
+    }
+
+    fn size(&self) -> u64 {
+        descriptor_size(self.len())
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let es_id = r.read_u16()?;
+        let flags = r.read_u8()?;
+        if flags & 0x80 != 0 {
+            r.skip(2)?; // depends_on_es_id
+        }
+        if flags & 0x40 != 0 {
+            let len = r.read_u8()? as usize;
+            r.skip(len)?; // url
+        }
+        if flags & 0x20 != 0 {
+            r.skip(2)?; // ocr_es_id
+        }
+        let mut decoder_config = None;
+        while r.remaining() > 0 {
+            let (tag, mut payload) = read_descriptor(r)?;
+            if tag == DecoderConfigDescriptor::TAG {
+                decoder_config = Some(DecoderConfigDescriptor::read(&mut payload)?);
+            }
+        }
+        Ok(Self {
+            es_id,
+            stream_priority: flags & 0x1f,
+            decoder_config: decoder_config.ok_or_else(|| {
+                invalid_data("ES_Descriptor is missing a DecoderConfigDescriptor")
+            })?,
+        })
+    }
+}
+
+impl WriteTo for EsDescriptor {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        write_descriptor_header(Self::TAG, self.len(), &mut w)?;
+        w.write_all(&self.es_id.to_be_bytes())?;
+        w.write_all(&[self.stream_priority & 0x1f])?;
+        self.decoder_config.write_to(&mut w)?;
+        write_descriptor_header(Self::SL_CONFIG_TAG, 1, &mut w)?;
+        w.write_all(&[0x02])?; // predefined: reserved for use in MP4 files
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct DecoderConfigDescriptor {
+    /// `0x40` for MPEG-4 audio
+    pub object_type_indication: u8,
+    /// 6 bits, `0x05` for audio
+    pub stream_type: u8,
+    /// 24 bits
+    pub buffer_size_db: u32,
+    pub max_bitrate: u32,
+    pub avg_bitrate: u32,
+    /// The `DecoderSpecificInfo`. For AAC, this is the `AudioSpecificConfig`.
+    pub decoder_specific_info: Option<Vec<u8>>,
+}
+
+impl DecoderConfigDescriptor {
+    const TAG: u8 = 0x04;
+    const DECODER_SPECIFIC_INFO_TAG: u8 = 0x05;
+
+    fn len(&self) -> u64 {
+        1 + 1
+            + 3
+            + 4
+            + 4
+            + self
+                .decoder_specific_info
+                .as_ref()
+                .map_or(0, |x| descriptor_size(x.len() as u64))
+    }
+
+    fn size(&self) -> u64 {
+        descriptor_size(self.len())
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let object_type_indication = r.read_u8()?;
+        let stream_type = r.read_u8()? >> 2;
+        let buffer_size_db = u32::from_be_bytes([0, r.read_u8()?, r.read_u8()?, r.read_u8()?]);
+        let max_bitrate = r.read_u32()?;
+        let avg_bitrate = r.read_u32()?;
+        let mut decoder_specific_info = None;
+        while r.remaining() > 0 {
+            let (tag, mut payload) = read_descriptor(r)?;
+            if tag == Self::DECODER_SPECIFIC_INFO_TAG {
+                decoder_specific_info = Some(payload.read_to_end().to_vec());
+            }
+        }
+        Ok(Self {
+            object_type_indication,
+            stream_type,
+            buffer_size_db,
+            max_bitrate,
+            avg_bitrate,
+            decoder_specific_info,
+        })
+    }
+}
+
+impl WriteTo for DecoderConfigDescriptor {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        write_descriptor_header(Self::TAG, self.len(), &mut w)?;
+        w.write_all(&[self.object_type_indication])?;
+        w.write_all(&[self.stream_type << 2 | 0x01])?;
+        w.write_all(&self.buffer_size_db.to_be_bytes()[1..])?;
+        w.write_all(&self.max_bitrate.to_be_bytes())?;
+        w.write_all(&self.avg_bitrate.to_be_bytes())?;
+        if let Some(info) = &self.decoder_specific_info {
+            write_descriptor_header(Self::DECODER_SPECIFIC_INFO_TAG, info.len() as u64, &mut w)?;
+            w.write_all(info)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct OpusSampleEntry {
+    pub data_reference_index: u16,
+    pub channel_count: u16,
+    /// bits
+    pub sample_size: u16,
+    pub sample_rate: U16F16,
+    pub dops: OpusSpecificBox,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl OpusSampleEntry {
+    fn header(&self) -> AudioSampleEntryHeader {
+        AudioSampleEntryHeader {
+            data_reference_index: self.data_reference_index,
+            channel_count: self.channel_count,
+            sample_size: self.sample_size,
+            sample_rate: self.sample_rate,
+        }
+    }
+}
+
+impl BmffBox for OpusSampleEntry {
+    const TYPE: [u8; 4] = *b"Opus";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + AudioSampleEntryHeader::SIZE
+            + self.dops.size()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        self.header().write(&mut w)?;
+        children.write(&self.dops, &mut w)?;
+        children.finish(w)
+    }
+}
+
+impl ReadBox for OpusSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let header = AudioSampleEntryHeader::read(r)?;
+        let mut dops = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            if child.header().box_type == OpusSpecificBox::TYPE {
+                dops = Some(child.parse()?);
+            } else {
+                unknown.push(r.unknown(child));
+            }
+        }
+        Ok(Self {
+            data_reference_index: header.data_reference_index,
+            channel_count: header.channel_count,
+            sample_size: header.sample_size,
+            sample_rate: header.sample_rate,
+            dops: dops.ok_or_else(|| missing_box(b"dOps", b"Opus"))?,
+            unknown,
+        })
+    }
+}
+
+impl Validate for OpusSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        self.dops.validate()
+    }
+}
+
+impl SampleEntry for OpusSampleEntry {
+    #[inline]
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct OpusSpecificBox {
+    pub output_channel_count: u8,
+    pub pre_skip: u16,
+    pub input_sample_rate: u32,
+    /// dB
+    pub output_gain: I8F8,
+    pub channel_mapping_family: u8,
+    /// Must be present if `channel_mapping_family` is not 0
+    pub channel_mapping_table: Option<ChannelMappingTable>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMappingTable {
+    pub stream_count: u8,
+    pub coupled_count: u8,
+    /// One entry per output channel
+    pub channel_mapping: Vec<u8>,
+}
+
+impl BmffBox for OpusSpecificBox {
+    const TYPE: [u8; 4] = *b"dOps";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + 1
+            + 1
+            + 2
+            + 4
+            + 2
+            + 1
+            + self
+                .channel_mapping_table
+                .as_ref()
+                .map_or(0, |x| 2 + x.channel_mapping.len() as u64)
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&[0])?; // version
+        w.write_all(&[self.output_channel_count])?;
+        w.write_all(&self.pre_skip.to_be_bytes())?;
+        w.write_all(&self.input_sample_rate.to_be_bytes())?;
+        w.write_all(&self.output_gain.to_be_bytes())?;
+        w.write_all(&[self.channel_mapping_family])?;
+        if let Some(table) = &self.channel_mapping_table {
+            w.write_all(&[table.stream_count, table.coupled_count])?;
+            w.write_all(&table.channel_mapping)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for OpusSpecificBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let version = r.read_u8()?;
+        if version != 0 {
+            return Err(invalid_data(format!("unsupported dOps version {version}")));
+        }
+        let output_channel_count = r.read_u8()?;
+        let pre_skip = r.read_u16()?;
+        let input_sample_rate = r.read_u32()?;
+        let output_gain = I8F8::from_be_bytes(r.read_array()?);
+        let channel_mapping_family = r.read_u8()?;
+        let channel_mapping_table = if channel_mapping_family == 0 {
+            None
+        } else {
+            Some(ChannelMappingTable {
+                stream_count: r.read_u8()?,
+                coupled_count: r.read_u8()?,
+                channel_mapping: r.read_bytes(output_channel_count as usize)?.to_vec(),
+            })
+        };
+        Ok(Self {
+            output_channel_count,
+            pre_skip,
+            input_sample_rate,
+            output_gain,
+            channel_mapping_family,
+            channel_mapping_table,
+        })
+    }
+}
+
+impl Validate for OpusSpecificBox {
+    fn validate(&self) -> Result<(), Error> {
+        match &self.channel_mapping_table {
+            None if self.channel_mapping_family != 0 => Err(Error::Invalid(
+                Self::TYPE,
+                "channel_mapping_table is required when channel_mapping_family is not 0",
+            )),
+            Some(_) if self.channel_mapping_family == 0 => Err(Error::Invalid(
+                Self::TYPE,
+                "channel_mapping_table is not allowed when channel_mapping_family is 0",
+            )),
+            Some(table) if table.channel_mapping.len() != self.output_channel_count as usize => {
+                Err(Error::LengthMismatch(
+                    Self::TYPE,
+                    "channel_mapping",
+                    self.output_channel_count as usize,
+                    table.channel_mapping.len(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HintSampleEntry {
+    pub data_reference_index: u16,
+    pub data: Vec<u8>,
+}
+
+impl BmffBox for HintSampleEntry {
+    const TYPE: [u8; 4] = *b"hint";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + 6 + 2 + self.data.len() as u64
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&[0u8; 6])?;
+        w.write_all(&self.data_reference_index.to_be_bytes())?;
+        w.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for HintSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.skip(6)?;
+        let data_reference_index = r.read_u16()?;
+        let data = r.read_to_end().to_vec();
+        Ok(Self {
+            data_reference_index,
+            data,
+        })
+    }
+}
+
+impl Validate for HintSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl SampleEntry for HintSampleEntry {
+    #[inline]
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct AvcSampleEntry {
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub horiz_resolution: U16F16,
+    pub vert_resolution: U16F16,
+    pub frame_count: u16,
+    pub depth: u16,
+    pub avcc: AvcConfigurationBox,
+    pub colr: Option<ColourInformationBox>,
+    pub pasp: Option<PixelAspectRatioBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl AvcSampleEntry {
+    fn header(&self) -> VisualSampleEntryHeader {
+        VisualSampleEntryHeader {
+            data_reference_index: self.data_reference_index,
+            width: self.width,
+            height: self.height,
+            horiz_resolution: self.horiz_resolution,
+            vert_resolution: self.vert_resolution,
+            frame_count: self.frame_count,
+            compressor_name: [0; 32],
+            depth: self.depth,
+        }
+    }
+}
+
+impl BmffBox for AvcSampleEntry {
+    const TYPE: [u8; 4] = *b"avc1";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + VisualSampleEntryHeader::SIZE
+            + self.avcc.size()
+            + self.colr.as_ref().map_or(0, BmffBox::size)
+            + self.pasp.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        self.header().write(&mut w)?;
+        children.write(&self.avcc, &mut w)?;
+        if let Some(colr) = &self.colr {
+            children.write(colr, &mut w)?;
+        }
+        if let Some(pasp) = &self.pasp {
+            children.write(pasp, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for AvcSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let header = VisualSampleEntryHeader::read(r)?;
+        let mut avcc = None;
+        let mut colr = None;
+        let mut pasp = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"avcC" => avcc = Some(child.parse()?),
+                b"colr" => colr = Some(child.parse()?),
+                b"pasp" => pasp = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            data_reference_index: header.data_reference_index,
+            width: header.width,
+            height: header.height,
+            horiz_resolution: header.horiz_resolution,
+            vert_resolution: header.vert_resolution,
+            frame_count: header.frame_count,
+            depth: header.depth,
+            avcc: avcc.ok_or_else(|| missing_box(b"avcC", b"avc1"))?,
+            colr,
+            pasp,
+            unknown,
+        })
+    }
+}
+
+impl Validate for AvcSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        self.avcc.validate()
+    }
+}
+
+impl SampleEntry for AvcSampleEntry {
+    #[inline]
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct AvcConfigurationBox {
+    pub configuration: AvcDecoderConfigurationRecord,
+}
+
+impl BmffBox for AvcConfigurationBox {
+    const TYPE: [u8; 4] = *b"avcC";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.configuration.size()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        self.configuration.write_to(&mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for AvcConfigurationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            configuration: AvcDecoderConfigurationRecord::read(r)?,
+        })
+    }
+}
+
+impl Validate for AvcConfigurationBox {
+    fn validate(&self) -> Result<(), Error> {
+        let c = &self.configuration;
+        check_bits(
+            Self::TYPE,
+            "length_size_minus_one",
+            c.length_size_minus_one as u64,
+            2,
+        )?;
+        for (field, sets, bits) in [
+            ("sequence_parameter_sets", &c.sequence_parameter_sets, 5),
+            ("picture_parameter_sets", &c.picture_parameter_sets, 8),
+        ] {
+            check_bits(Self::TYPE, field, sets.len() as u64, bits)?;
+            for set in sets {
+                check_bits(Self::TYPE, field, set.len() as u64, 16)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct AvcDecoderConfigurationRecord {
+    pub profile_idc: u8,
+    pub constraint_set_flag: u8,
+    pub level_idc: u8,
+    /// The size of the NAL unit lengths in samples, minus one. 2 bits
+    pub length_size_minus_one: u8,
+    /// At most 31 entries
+    pub sequence_parameter_sets: Vec<Vec<u8>>,
+    /// At most 255 entries
+    pub picture_parameter_sets: Vec<Vec<u8>>,
+    /// The fields after the parameter sets, such as the chroma format and
+    /// bit depths of the High profiles, kept as they were read
+    pub extension: Vec<u8>,
+}
+
+impl AvcDecoderConfigurationRecord {
+    fn size(&self) -> u64 {
+        let sets = self
+            .sequence_parameter_sets
+            .iter()
+            .chain(&self.picture_parameter_sets)
+            .map(|x| 2 + x.len() as u64)
+            .sum::<u64>();
+        1 + 1 + 1 + 1 + 1 + 1 + 1 + sets + self.extension.len() as u64
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let configuration_version = r.read_u8()?;
+        if configuration_version != 1 {
+            return Err(invalid_data(format!(
+                "unsupported avcC version {configuration_version}"
+            )));
+        }
+        let profile_idc = r.read_u8()?;
+        let constraint_set_flag = r.read_u8()?;
+        let level_idc = r.read_u8()?;
+        let length_size_minus_one = r.read_u8()? & 0x03;
+        let read_sets = |r: &mut BoxReader<'_>, count: u8| {
+            (0..count)
+                .map(|_| {
+                    let len = r.read_u16()? as usize;
+                    Ok(r.read_bytes(len)?.to_vec())
+                })
+                .collect::<io::Result<Vec<_>>>()
+        };
+        let count = r.read_u8()? & 0x1f;
+        let sequence_parameter_sets = read_sets(r, count)?;
+        let count = r.read_u8()?;
+        let picture_parameter_sets = read_sets(r, count)?;
+        Ok(Self {
+            profile_idc,
+            constraint_set_flag,
+            level_idc,
+            length_size_minus_one,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+            extension: r.read_to_end().to_vec(),
+        })
+    }
+}
+
+impl WriteTo for AvcDecoderConfigurationRecord {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&1u8.to_be_bytes())?;
+        w.write_all(&self.profile_idc.to_be_bytes())?;
+        w.write_all(&self.constraint_set_flag.to_be_bytes())?;
+        w.write_all(&self.level_idc.to_be_bytes())?;
+        w.write_all(&(0xfc | self.length_size_minus_one).to_be_bytes())?;
+        w.write_all(&(0xe0 | self.sequence_parameter_sets.len() as u8).to_be_bytes())?;
+        for set in &self.sequence_parameter_sets {
+            w.write_all(&(set.len() as u16).to_be_bytes())?;
+            w.write_all(set)?;
+        }
+        w.write_all(&(self.picture_parameter_sets.len() as u8).to_be_bytes())?;
+        for set in &self.picture_parameter_sets {
+            w.write_all(&(set.len() as u16).to_be_bytes())?;
+            w.write_all(set)?;
+        }
+        w.write_all(&self.extension)?;
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HevcSampleEntry {
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub horiz_resolution: U16F16,
+    pub vert_resolution: U16F16,
+    pub frame_count: u16,
+    pub depth: u16,
+    pub hvcc: HevcConfigurationBox,
+    pub colr: Option<ColourInformationBox>,
+    pub pasp: Option<PixelAspectRatioBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl HevcSampleEntry {
+    fn header(&self) -> VisualSampleEntryHeader {
+        VisualSampleEntryHeader {
+            data_reference_index: self.data_reference_index,
+            width: self.width,
+            height: self.height,
+            horiz_resolution: self.horiz_resolution,
+            vert_resolution: self.vert_resolution,
+            frame_count: self.frame_count,
+            compressor_name: [0; 32],
+            depth: self.depth,
+        }
+    }
+}
+
+impl BmffBox for HevcSampleEntry {
+    const TYPE: [u8; 4] = *b"hvc1";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + VisualSampleEntryHeader::SIZE
+            + self.hvcc.size()
+            + self.colr.as_ref().map_or(0, BmffBox::size)
+            + self.pasp.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        self.header().write(&mut w)?;
+        children.write(&self.hvcc, &mut w)?;
+        if let Some(colr) = &self.colr {
+            children.write(colr, &mut w)?;
+        }
+        if let Some(pasp) = &self.pasp {
+            children.write(pasp, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for HevcSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let header = VisualSampleEntryHeader::read(r)?;
+        let mut hvcc = None;
+        let mut colr = None;
+        let mut pasp = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"hvcC" => hvcc = Some(child.parse()?),
+                b"colr" => colr = Some(child.parse()?),
+                b"pasp" => pasp = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            data_reference_index: header.data_reference_index,
+            width: header.width,
+            height: header.height,
+            horiz_resolution: header.horiz_resolution,
+            vert_resolution: header.vert_resolution,
+            frame_count: header.frame_count,
+            depth: header.depth,
+            hvcc: hvcc.ok_or_else(|| missing_box(b"hvcC", &r.header().box_type))?,
+            colr,
+            pasp,
+            unknown,
+        })
+    }
+}
+
+impl Validate for HevcSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        self.hvcc.validate()
+    }
+}
+
+impl SampleEntry for HevcSampleEntry {
+    #[inline]
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// An `hev1` sample entry. This is identical to [`HevcSampleEntry`], except
+/// that parameter sets may also be sent in-band in the samples.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Hev1SampleEntry(pub HevcSampleEntry);
+
+impl BmffBox for Hev1SampleEntry {
+    const TYPE: [u8; 4] = *b"hev1";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        BmffBox::size(&self.0)
+    }
+
+    fn write_box(&self, w: impl Write) -> io::Result<()> {
+        self.0.write_box(w)
+    }
+}
+
+impl ReadBox for Hev1SampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self(HevcSampleEntry::read_box(r)?))
+    }
+}
+
+impl Validate for Hev1SampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        self.0.validate()
+    }
+}
+
+impl SampleEntry for Hev1SampleEntry {
+    #[inline]
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HevcConfigurationBox {
+    pub configuration: HevcDecoderConfigurationRecord,
+}
+
+impl BmffBox for HevcConfigurationBox {
+    const TYPE: [u8; 4] = *b"hvcC";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.configuration.size()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        self.configuration.write_to(&mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for HevcConfigurationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            configuration: HevcDecoderConfigurationRecord::read(r)?,
+        })
+    }
+}
+
+impl Validate for HevcConfigurationBox {
+    fn validate(&self) -> Result<(), Error> {
+        let c = &self.configuration;
+        for (field, value, bits) in [
+            ("general_profile_space", c.general_profile_space as u64, 2),
+            ("general_profile_idc", c.general_profile_idc as u64, 5),
+            (
+                "general_constraint_indicator_flags",
+                c.general_constraint_indicator_flags,
+                48,
+            ),
+            (
+                "min_spatial_segmentation_idc",
+                c.min_spatial_segmentation_idc as u64,
+                12,
+            ),
+            ("parallelism_type", c.parallelism_type as u64, 2),
+            ("chroma_format_idc", c.chroma_format_idc as u64, 2),
+            ("bit_depth_luma_minus8", c.bit_depth_luma_minus8 as u64, 3),
+            (
+                "bit_depth_chroma_minus8",
+                c.bit_depth_chroma_minus8 as u64,
+                3,
+            ),
+            ("constant_frame_rate", c.constant_frame_rate as u64, 2),
+            ("num_temporal_layers", c.num_temporal_layers as u64, 3),
+            ("length_size_minus_one", c.length_size_minus_one as u64, 2),
+            ("arrays", c.arrays.len() as u64, 8),
+        ] {
+            check_bits(Self::TYPE, field, value, bits)?;
+        }
+        for array in &c.arrays {
+            check_bits(Self::TYPE, "nal_unit_type", array.nal_unit_type as u64, 6)?;
+            check_bits(Self::TYPE, "nal_units", array.nal_units.len() as u64, 16)?;
+            for nal_unit in &array.nal_units {
+                check_bits(Self::TYPE, "nal_units", nal_unit.len() as u64, 16)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct HevcDecoderConfigurationRecord {
+    /// 2 bits
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    /// 5 bits
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    /// 48 bits
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    /// 12 bits
+    pub min_spatial_segmentation_idc: u16,
+    /// 2 bits
+    pub parallelism_type: u8,
+    /// 2 bits
+    pub chroma_format_idc: u8,
+    /// 3 bits
+    pub bit_depth_luma_minus8: u8,
+    /// 3 bits
+    pub bit_depth_chroma_minus8: u8,
+    /// Frames per 256 seconds, or 0 if unspecified
+    pub avg_frame_rate: u16,
+    /// 2 bits
+    pub constant_frame_rate: u8,
+    /// 3 bits
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+    /// 2 bits
+    pub length_size_minus_one: u8,
+    pub arrays: Vec<HevcNalArray>,
+}
+
+impl HevcDecoderConfigurationRecord {
+    fn size(&self) -> u64 {
+        1 + 1
+            + 4
+            + 6
+            + 1
+            + 2
+            + 1
+            + 1
+            + 1
+            + 1
+            + 2
+            + 1
+            + 1
+            + self.arrays.iter().map(HevcNalArray::size).sum::<u64>()
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let configuration_version = r.read_u8()?;
+        if configuration_version != 1 {
+            return Err(invalid_data(format!(
+                "unsupported hvcC version {configuration_version}"
+            )));
+        }
+        let profile = r.read_u8()?;
+        let general_profile_compatibility_flags = r.read_u32()?;
+        let mut general_constraint_indicator_flags = [0u8; 8];
+        general_constraint_indicator_flags[2..].copy_from_slice(r.read_bytes(6)?);
+        let general_level_idc = r.read_u8()?;
+        let min_spatial_segmentation_idc = r.read_u16()? & 0x0fff;
+        let parallelism_type = r.read_u8()? & 0x03;
+        let chroma_format_idc = r.read_u8()? & 0x03;
+        let bit_depth_luma_minus8 = r.read_u8()? & 0x07;
+        let bit_depth_chroma_minus8 = r.read_u8()? & 0x07;
+        let avg_frame_rate = r.read_u16()?;
+        let temporal = r.read_u8()?;
+        let num_of_arrays = r.read_u8()?;
+        let mut arrays = Vec::new();
+        for _ in 0..num_of_arrays {
+            arrays.push(HevcNalArray::read(r)?);
+        }
+        Ok(Self {
+            general_profile_space: profile >> 6,
+            general_tier_flag: profile & 0x20 != 0,
+            general_profile_idc: profile & 0x1f,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags: u64::from_be_bytes(
+                general_constraint_indicator_flags,
+            ),
+            general_level_idc,
+            min_spatial_segmentation_idc,
+            parallelism_type,
+            chroma_format_idc,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            avg_frame_rate,
+            constant_frame_rate: temporal >> 6,
+            num_temporal_layers: temporal >> 3 & 0x07,
+            temporal_id_nested: temporal & 0x04 != 0,
+            length_size_minus_one: temporal & 0x03,
+            arrays,
+        })
+    }
+}
+
+impl WriteTo for HevcDecoderConfigurationRecord {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&1u8.to_be_bytes())?;
+        w.write_all(&[self.general_profile_space << 6
+            | u8::from(self.general_tier_flag) << 5
+            | self.general_profile_idc & 0x1f])?;
+        w.write_all(&self.general_profile_compatibility_flags.to_be_bytes())?;
+        w.write_all(&self.general_constraint_indicator_flags.to_be_bytes()[2..])?;
+        w.write_all(&self.general_level_idc.to_be_bytes())?;
+        w.write_all(&(0xf000 | self.min_spatial_segmentation_idc).to_be_bytes())?;
+        w.write_all(&[0xfc | self.parallelism_type])?;
+        w.write_all(&[0xfc | self.chroma_format_idc])?;
+        w.write_all(&[0xf8 | self.bit_depth_luma_minus8])?;
+        w.write_all(&[0xf8 | self.bit_depth_chroma_minus8])?;
+        w.write_all(&self.avg_frame_rate.to_be_bytes())?;
+        w.write_all(&[self.constant_frame_rate << 6
+            | (self.num_temporal_layers & 0x07) << 3
+            | u8::from(self.temporal_id_nested) << 2
+            | self.length_size_minus_one & 0x03])?;
+        w.write_all(&(self.arrays.len() as u8).to_be_bytes())?;
+        for array in &self.arrays {
+            array.write_to(&mut w)?;
+        }
+        Ok(())
+    }
+}
+
+/// The NAL units of a single type in an [`HevcDecoderConfigurationRecord`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HevcNalArray {
+    /// Whether all NAL units of this type are in the array, rather than also
+    /// in the stream
+    pub array_completeness: bool,
+    /// 6 bits
+    pub nal_unit_type: u8,
+    pub nal_units: Vec<Vec<u8>>,
+}
+
+impl HevcNalArray {
+    pub const VPS: u8 = 32;
+    pub const SPS: u8 = 33;
+    pub const PPS: u8 = 34;
+
+    fn size(&self) -> u64 {
+        1 + 2
+            + self
+                .nal_units
+                .iter()
+                .map(|x| 2 + x.len() as u64)
+                .sum::<u64>()
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let nal_unit_type = r.read_u8()?;
+        let num_nalus = r.read_u16()?;
+        let mut nal_units = Vec::new();
+        for _ in 0..num_nalus {
+            let len = r.read_u16()? as usize;
+            nal_units.push(r.read_bytes(len)?.to_vec());
+        }
+        Ok(Self {
+            array_completeness: nal_unit_type & 0x80 != 0,
+            nal_unit_type: nal_unit_type & 0x3f,
+            nal_units,
+        })
+    }
+}
+
+impl WriteTo for HevcNalArray {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&[u8::from(self.array_completeness) << 7 | self.nal_unit_type & 0x3f])?;
+        w.write_all(&(self.nal_units.len() as u16).to_be_bytes())?;
+        for nal_unit in &self.nal_units {
+            w.write_all(&(nal_unit.len() as u16).to_be_bytes())?;
+            w.write_all(nal_unit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Av1SampleEntry {
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+    pub horiz_resolution: U16F16,
+    pub vert_resolution: U16F16,
+    pub frame_count: u16,
+    pub depth: u16,
+    pub av1c: Av1CodecConfigurationBox,
+    pub colr: Option<ColourInformationBox>,
+    pub pasp: Option<PixelAspectRatioBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl Av1SampleEntry {
+    fn header(&self) -> VisualSampleEntryHeader {
+        VisualSampleEntryHeader {
+            data_reference_index: self.data_reference_index,
+            width: self.width,
+            height: self.height,
+            horiz_resolution: self.horiz_resolution,
+            vert_resolution: self.vert_resolution,
+            frame_count: self.frame_count,
+            compressor_name: [0; 32],
+            depth: self.depth,
+        }
+    }
+}
+
+impl BmffBox for Av1SampleEntry {
+    const TYPE: [u8; 4] = *b"av01";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + VisualSampleEntryHeader::SIZE
+            + self.av1c.size()
+            + self.colr.as_ref().map_or(0, BmffBox::size)
+            + self.pasp.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        self.header().write(&mut w)?;
+        children.write(&self.av1c, &mut w)?;
+        if let Some(colr) = &self.colr {
+            children.write(colr, &mut w)?;
+        }
+        if let Some(pasp) = &self.pasp {
+            children.write(pasp, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for Av1SampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let header = VisualSampleEntryHeader::read(r)?;
+        let mut av1c = None;
+        let mut colr = None;
+        let mut pasp = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"av1C" => av1c = Some(child.parse()?),
+                b"colr" => colr = Some(child.parse()?),
+                b"pasp" => pasp = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            data_reference_index: header.data_reference_index,
+            width: header.width,
+            height: header.height,
+            horiz_resolution: header.horiz_resolution,
+            vert_resolution: header.vert_resolution,
+            frame_count: header.frame_count,
+            depth: header.depth,
+            av1c: av1c.ok_or_else(|| missing_box(b"av1C", b"av01"))?,
+            colr,
+            pasp,
+            unknown,
+        })
+    }
+}
+
+impl Validate for Av1SampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        self.av1c.validate()
+    }
+}
+
+impl SampleEntry for Av1SampleEntry {
+    #[inline]
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Av1CodecConfigurationBox {
+    pub configuration: Av1CodecConfigurationRecord,
+}
+
+impl BmffBox for Av1CodecConfigurationBox {
+    const TYPE: [u8; 4] = *b"av1C";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.configuration.size()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        self.configuration.write_to(&mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for Av1CodecConfigurationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            configuration: Av1CodecConfigurationRecord::read(r)?,
+        })
+    }
+}
+
+impl Validate for Av1CodecConfigurationBox {
+    fn validate(&self) -> Result<(), Error> {
+        let c = &self.configuration;
+        check_bits(Self::TYPE, "seq_profile", c.seq_profile as u64, 3)?;
+        check_bits(Self::TYPE, "seq_level_idx_0", c.seq_level_idx_0 as u64, 5)?;
+        check_bits(
+            Self::TYPE,
+            "chroma_sample_position",
+            c.chroma_sample_position as u64,
+            2,
+        )?;
+        if let Some(delay) = c.initial_presentation_delay_minus_one {
+            check_bits(
+                Self::TYPE,
+                "initial_presentation_delay_minus_one",
+                delay as u64,
+                4,
+            )?;
+        }
+        if c.twelve_bit && !c.high_bitdepth {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "twelve_bit requires high_bitdepth",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Av1CodecConfigurationRecord {
+    /// 3 bits
+    pub seq_profile: u8,
+    /// 5 bits
+    pub seq_level_idx_0: u8,
+    pub seq_tier_0: bool,
+    pub high_bitdepth: bool,
+    pub twelve_bit: bool,
+    pub monochrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    /// 2 bits
+    pub chroma_sample_position: u8,
+    /// 4 bits
+    pub initial_presentation_delay_minus_one: Option<u8>,
+    /// Sequence header and metadata OBUs, in low overhead bitstream format
+    pub config_obus: Vec<u8>,
+}
+
+impl Av1CodecConfigurationRecord {
+    fn size(&self) -> u64 {
+        1 + 1 + 1 + 1 + self.config_obus.len() as u64
+    }
+
+    fn read(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let version = r.read_u8()?;
+        if version != 0x81 {
+            return Err(invalid_data(format!(
+                "unsupported av1C version {}",
+                version & 0x7f
+            )));
+        }
+        let seq = r.read_u8()?;
+        let color = r.read_u8()?;
+        let delay = r.read_u8()?;
+        Ok(Self {
+            seq_profile: seq >> 5,
+            seq_level_idx_0: seq & 0x1f,
+            seq_tier_0: color & 0x80 != 0,
+            high_bitdepth: color & 0x40 != 0,
+            twelve_bit: color & 0x20 != 0,
+            monochrome: color & 0x10 != 0,
+            chroma_subsampling_x: color & 0x08 != 0,
+            chroma_subsampling_y: color & 0x04 != 0,
+            chroma_sample_position: color & 0x03,
+            initial_presentation_delay_minus_one: (delay & 0x10 != 0).then_some(delay & 0x0f),
+            config_obus: r.read_to_end().to_vec(),
+        })
+    }
+}
+
+impl WriteTo for Av1CodecConfigurationRecord {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&[0x81])?; // marker and version
+        w.write_all(&[self.seq_profile << 5 | self.seq_level_idx_0 & 0x1f])?;
+        w.write_all(&[u8::from(self.seq_tier_0) << 7
+            | u8::from(self.high_bitdepth) << 6
+            | u8::from(self.twelve_bit) << 5
+            | u8::from(self.monochrome) << 4
+            | u8::from(self.chroma_subsampling_x) << 3
+            | u8::from(self.chroma_subsampling_y) << 2
+            | self.chroma_sample_position & 0x03])?;
+        w.write_all(&[self
+            .initial_presentation_delay_minus_one
+            .map_or(0, |x| 0x10 | x & 0x0f)])?;
+        w.write_all(&self.config_obus)?;
+        Ok(())
+    }
+}
+
+/// Colour information for a visual sample entry. Without it, players have to
+/// guess the colour space and range from the codec configuration.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColourInformationBox {
+    /// Code points from ISO/IEC 23091-2, the same as in H.264 and H.265 VUI.
+    /// For example, BT.709 is 1 for all three.
+    Nclx {
+        colour_primaries: u16,
+        transfer_characteristics: u16,
+        matrix_coefficients: u16,
+        full_range: bool,
+    },
+    /// A restricted ICC profile
+    RestrictedIcc(Vec<u8>),
+    /// An unrestricted ICC profile
+    Icc(Vec<u8>),
+    Other {
+        colour_type: [u8; 4],
+        data: Vec<u8>,
+    },
+}
+
+impl BmffBox for ColourInformationBox {
+    const TYPE: [u8; 4] = *b"colr";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + 4
+            + match self {
+                Self::Nclx { .. } => 2 + 2 + 2 + 1,
+                Self::RestrictedIcc(data) | Self::Icc(data) | Self::Other { data, .. } => {
+                    data.len() as u64
+                }
+            }
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        match self {
+            Self::Nclx {
+                colour_primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+                full_range,
+            } => {
+                w.write_all(b"nclx")?;
+                w.write_all(&colour_primaries.to_be_bytes())?;
+                w.write_all(&transfer_characteristics.to_be_bytes())?;
+                w.write_all(&matrix_coefficients.to_be_bytes())?;
+                w.write_all(&[u8::from(*full_range) << 7])?;
+            }
+            Self::RestrictedIcc(data) => {
+                w.write_all(b"rICC")?;
+                w.write_all(data)?;
+            }
+            Self::Icc(data) => {
+                w.write_all(b"prof")?;
+                w.write_all(data)?;
+            }
+            Self::Other { colour_type, data } => {
+                w.write_all(colour_type)?;
+                w.write_all(data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for ColourInformationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(match &r.read_array()? {
+            b"nclx" => Self::Nclx {
+                colour_primaries: r.read_u16()?,
+                transfer_characteristics: r.read_u16()?,
+                matrix_coefficients: r.read_u16()?,
+                full_range: r.read_u8()? & 0x80 != 0,
+            },
+            b"rICC" => Self::RestrictedIcc(r.read_to_end().to_vec()),
+            b"prof" => Self::Icc(r.read_to_end().to_vec()),
+            colour_type => Self::Other {
+                colour_type: *colour_type,
+                data: r.read_to_end().to_vec(),
+            },
+        })
+    }
+}
+
+/// The relative width and height of a pixel.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelAspectRatioBox {
+    pub h_spacing: u32,
+    pub v_spacing: u32,
+}
+
+impl BmffBox for PixelAspectRatioBox {
+    const TYPE: [u8; 4] = *b"pasp";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + 4 + 4
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&self.h_spacing.to_be_bytes())?;
+        w.write_all(&self.v_spacing.to_be_bytes())?;
+        Ok(())
+    }
+}
+
+impl ReadBox for PixelAspectRatioBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            h_spacing: r.read_u32()?,
+            v_spacing: r.read_u32()?,
+        })
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SampleToChunkBox {
+    /// `(first_chunk, samples_per_chunk, sample_description_index)`
+    pub entries: Vec<(u32, u32, u32)>,
+}
+
+impl BmffBox for SampleToChunkBox {
+    const TYPE: [u8; 4] = *b"stsc";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.entries.len() as u64 * 4 * 3
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&(self.entries.len() as u32).to_be_bytes())?;
+        for (first_chunk, samples_per_chunk, sample_description_index) in &self.entries {
+            w.write_all(&first_chunk.to_be_bytes())?;
+            w.write_all(&samples_per_chunk.to_be_bytes())?;
+            w.write_all(&sample_description_index.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for SampleToChunkBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut entries = Vec::new();
+        for _ in 0..entry_count {
+            entries.push((r.read_u32()?, r.read_u32()?, r.read_u32()?));
+        }
+        Ok(Self { entries })
+    }
+}
+
+impl FullBox for SampleToChunkBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+impl Validate for SampleToChunkBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(Self::TYPE, "entry_count", self.entries.len() as u64, 32)?;
+        let mut previous = 0;
+        for &(first_chunk, _, sample_description_index) in &self.entries {
+            if first_chunk <= previous {
+                return Err(Error::Invalid(
+                    Self::TYPE,
+                    "first_chunk must start at 1 and increase",
+                ));
+            }
+            if sample_description_index == 0 {
+                return Err(Error::Invalid(
+                    Self::TYPE,
+                    "sample_description_index can't be 0",
+                ));
+            }
+            previous = first_chunk;
+        }
+        Ok(())
+    }
+}
+
+/// Chunk offsets are stored in an `stco` box if they all fit in 32 bits, and a
+/// `co64` box otherwise.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub enum ChunkOffset {
+    Compact(ChunkOffsetBox),
+    Large(ChunkLargeOffsetBox),
+}
+
+impl ChunkOffset {
+    /// Picks the smallest box that can hold `chunk_offsets`.
+    pub fn from_offsets(chunk_offsets: Vec<u64>) -> Self {
+        if chunk_offsets.iter().all(|&x| x <= u32::MAX as u64) {
+            Self::Compact(ChunkOffsetBox {
+                chunk_offsets: chunk_offsets.into_iter().map(|x| x as u32).collect(),
+            })
+        } else {
+            Self::Large(ChunkLargeOffsetBox { chunk_offsets })
+        }
+    }
+
+    /// The offset of each chunk from the start of the file.
+    pub fn chunk_offsets(&self) -> Vec<u64> {
+        match self {
+            Self::Compact(stco) => stco.chunk_offsets.iter().map(|&x| x as u64).collect(),
+            Self::Large(co64) => co64.chunk_offsets.clone(),
+        }
+    }
+
+    #[inline]
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Compact(stco) => stco.size(),
+            Self::Large(co64) => co64.size(),
+        }
+    }
+}
+
+impl Validate for ChunkOffset {
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::Compact(stco) => stco.validate(),
+            Self::Large(co64) => co64.validate(),
+        }
+    }
+}
+
+impl WriteTo for ChunkOffset {
+    fn write_to(&self, w: impl Write) -> io::Result<()> {
+        match self {
+            Self::Compact(stco) => write_to_full(stco, w),
+            Self::Large(co64) => write_to_full(co64, w),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ChunkOffsetBox {
+    pub chunk_offsets: Vec<u32>,
+}
+
+impl BmffBox for ChunkOffsetBox {
+    const TYPE: [u8; 4] = *b"stco";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.chunk_offsets.len() as u64 * 4
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&(self.chunk_offsets.len() as u32).to_be_bytes())?;
+        for chunk_offset in &self.chunk_offsets {
+            w.write_all(&chunk_offset.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for ChunkOffsetBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut chunk_offsets = Vec::new();
+        for _ in 0..entry_count {
+            chunk_offsets.push(r.read_u32()?);
+        }
+        Ok(Self { chunk_offsets })
+    }
+}
+
+impl FullBox for ChunkOffsetBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+impl Validate for ChunkOffsetBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(
+            Self::TYPE,
+            "entry_count",
+            self.chunk_offsets.len() as u64,
+            32,
+        )
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ChunkLargeOffsetBox {
+    pub chunk_offsets: Vec<u64>,
+}
+
+impl BmffBox for ChunkLargeOffsetBox {
+    const TYPE: [u8; 4] = *b"co64";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.chunk_offsets.len() as u64 * 8
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&(self.chunk_offsets.len() as u32).to_be_bytes())?;
+        for chunk_offset in &self.chunk_offsets {
+            w.write_all(&chunk_offset.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for ChunkLargeOffsetBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut chunk_offsets = Vec::new();
+        for _ in 0..entry_count {
+            chunk_offsets.push(r.read_u64()?);
+        }
+        Ok(Self { chunk_offsets })
+    }
+}
+
+impl FullBox for ChunkLargeOffsetBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+impl Validate for ChunkLargeOffsetBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(
+            Self::TYPE,
+            "entry_count",
+            self.chunk_offsets.len() as u64,
+            32,
+        )
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SyncSampleBox {
+    /// 1-based, in increasing order
+    pub sample_numbers: Vec<u32>,
+}
+
+impl BmffBox for SyncSampleBox {
+    const TYPE: [u8; 4] = *b"stss";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.sample_numbers.len() as u64 * 4
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&(self.sample_numbers.len() as u32).to_be_bytes())?;
+        for sample_number in &self.sample_numbers {
+            w.write_all(&sample_number.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for SyncSampleBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut sample_numbers = Vec::new();
+        for _ in 0..entry_count {
+            sample_numbers.push(r.read_u32()?);
+        }
+        Ok(Self { sample_numbers })
+    }
+}
+
+impl FullBox for SyncSampleBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct EditBox {
+    pub elst: EditListBox,
+}
+
+impl BmffBox for EditBox {
+    const TYPE: [u8; 4] = *b"edts";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.elst.size()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        write_to_full(&self.elst, &mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for EditBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut elst = None;
+        while let Some(child) = r.next_child()? {
+            if child.header().box_type == EditListBox::TYPE {
+                elst = Some(child.parse_full()?);
+            }
+        }
+        Ok(Self {
+            elst: elst.ok_or_else(|| missing_box(b"elst", b"edts"))?,
+        })
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct EditListBox {
+    /// `(segment_duration, media_time)`
+    pub entries: Vec<(u64, i64)>,
+    /// Media rate for all entries
+    pub media_rate_integer: i16,
+    pub media_rate_fraction: i16,
+}
+
+impl BmffBox for EditListBox {
+    const TYPE: [u8; 4] = *b"elst";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + self.entries.len() as u64 * (8 * 2 + 2 + 2)
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&(self.entries.len() as u32).to_be_bytes())?;
+        for (segment_duration, media_time) in &self.entries {
+            w.write_all(&segment_duration.to_be_bytes())?;
+            w.write_all(&media_time.to_be_bytes())?;
+            w.write_all(&self.media_rate_integer.to_be_bytes())?;
+            w.write_all(&self.media_rate_fraction.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for EditListBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut entries = Vec::new();
+        let mut media_rate = (1, 0);
+        for _ in 0..entry_count {
+            entries.push(if r.version() == 1 {
+                (r.read_u64()?, r.read_i64()?)
+            } else {
+                (r.read_u32()? as u64, r.read_i32()? as i64)
+            });
+            media_rate = (r.read_i16()?, r.read_i16()?);
+        }
+        Ok(Self {
+            entries,
+            media_rate_integer: media_rate.0,
+            media_rate_fraction: media_rate.1,
+        })
+    }
+}
+
+impl FullBox for EditListBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        1
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct MovieExtendsBox {
+    pub mehd: Option<MovieExtendsHeaderBox>,
+    pub trex: Vec<TrackExtendsBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl BmffBox for MovieExtendsBox {
+    const TYPE: [u8; 4] = *b"mvex";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.mehd.as_ref().map_or(0, BmffBox::size)
+            + self.trex.iter().map(BmffBox::size).sum::<u64>()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        if let Some(mehd) = &self.mehd {
+            children.write_full(mehd, &mut w)?;
+        }
+        for trex in &self.trex {
+            children.write_full(trex, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for MovieExtendsBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut mehd = None;
+        let mut trex = Vec::new();
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"mehd" => mehd = Some(child.parse_full()?),
+                b"trex" => trex.push(child.parse_full()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            mehd,
+            trex,
+            unknown,
+        })
+    }
+}
+
+impl Validate for MovieExtendsBox {
+    fn validate(&self) -> Result<(), Error> {
+        for trex in &self.trex {
+            trex.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct MovieExtendsHeaderBox {
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::duration"))]
+    pub fragment_duration: Duration,
+}
+
+impl BmffBox for MovieExtendsHeaderBox {
+    const TYPE: [u8; 4] = *b"mehd";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + if self.fragment_duration.num_seconds() as u64 > u32::MAX as u64 {
+            8
+        } else {
+            4
+        }
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let fragment_duration = self.fragment_duration.num_seconds();
+        if fragment_duration as u64 > u32::MAX as u64 {
+            w.write_all(&fragment_duration.to_be_bytes())?;
+        } else {
+            w.write_all(&(fragment_duration as u32).to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for MovieExtendsHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let fragment_duration = if r.version() == 1 {
+            r.read_u64()?
+        } else {
+            r.read_u32()? as u64
+        };
+        Ok(Self {
+            fragment_duration: Duration::seconds(fragment_duration as i64),
+        })
+    }
+}
+
+impl FullBox for MovieExtendsHeaderBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        (self.fragment_duration.num_seconds() as u64 > u32::MAX as u64) as u8
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TrackExtendsBox {
+    pub track_id: u32,
+    pub default_sample_description_index: u32,
+    pub default_sample_duration: u32,
+    pub default_sample_size: u32,
+    pub default_sample_flags: SampleFlags,
+}
+
+/// Per-sample flags, as used in `trex`, `tfhd` and `trun`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SampleFlags {
+    /// 2 bits. 0 is unknown, 1 is a leading sample that depends on a sample
+    /// before the preceding I-picture, 2 is not a leading sample, and 3 is a
+    /// leading sample without that dependency.
+    pub is_leading: u8,
+    /// Whether this sample depends on others. `No` means it is an I-picture.
+    pub depends_on: SampleDependency,
+    /// Whether other samples depend on this one. `No` means it is disposable.
+    pub is_depended_on: SampleDependency,
+    pub has_redundancy: SampleDependency,
+    /// 3 bits
+    pub padding_value: u8,
+    pub is_non_sync_sample: bool,
+    pub degradation_priority: u16,
+}
+
+/// The value of a 2-bit sample dependency field.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SampleDependency {
+    #[default]
+    Unknown = 0,
+    Yes = 1,
+    No = 2,
+    Reserved = 3,
+}
+
+impl SampleDependency {
+    fn from_bits(bits: u32) -> Self {
+        match bits & 0x03 {
+            0 => Self::Unknown,
+            1 => Self::Yes,
+            2 => Self::No,
+            _ => Self::Reserved,
+        }
+    }
+}
+
+impl SampleFlags {
+    fn check(&self, box_type: [u8; 4]) -> Result<(), Error> {
+        check_bits(box_type, "is_leading", self.is_leading as u64, 2)?;
+        check_bits(box_type, "padding_value", self.padding_value as u64, 3)?;
+        Ok(())
+    }
+}
+
+impl From<u32> for SampleFlags {
+    fn from(bits: u32) -> Self {
+        Self {
+            is_leading: (bits >> 26 & 0x03) as u8,
+            depends_on: SampleDependency::from_bits(bits >> 24),
+            is_depended_on: SampleDependency::from_bits(bits >> 22),
+            has_redundancy: SampleDependency::from_bits(bits >> 20),
+            padding_value: (bits >> 17 & 0x07) as u8,
+            is_non_sync_sample: bits >> 16 & 0x01 != 0,
+            degradation_priority: bits as u16,
+        }
+    }
+}
+
+impl From<SampleFlags> for u32 {
+    fn from(flags: SampleFlags) -> Self {
+        (flags.is_leading as u32 & 0x03) << 26
+            | (flags.depends_on as u32) << 24
+            | (flags.is_depended_on as u32) << 22
+            | (flags.has_redundancy as u32) << 20
+            | (flags.padding_value as u32 & 0x07) << 17
+            | u32::from(flags.is_non_sync_sample) << 16
+            | flags.degradation_priority as u32
+    }
+}
+
+impl WriteTo for SampleFlags {
+    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&u32::from(*self).to_be_bytes())
+    }
+}
+
+impl BmffBox for TrackExtendsBox {
+    const TYPE: [u8; 4] = *b"trex";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4 + 4 + 4 + 4 + 4
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&self.track_id.to_be_bytes())?;
+        w.write_all(&self.default_sample_description_index.to_be_bytes())?;
+        w.write_all(&self.default_sample_duration.to_be_bytes())?;
+        w.write_all(&self.default_sample_size.to_be_bytes())?;
+        self.default_sample_flags.write_to(&mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for TrackExtendsBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            track_id: r.read_u32()?,
+            default_sample_description_index: r.read_u32()?,
+            default_sample_duration: r.read_u32()?,
+            default_sample_size: r.read_u32()?,
+            default_sample_flags: SampleFlags::from(r.read_u32()?),
+        })
+    }
+}
+
+impl Validate for TrackExtendsBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.default_sample_flags.check(Self::TYPE)
+    }
+}
+
+impl FullBox for TrackExtendsBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+/// User data, attached to a `moov` or `trak` box.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct UserDataBox {
+    pub meta: Option<MetaBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl BmffBox for UserDataBox {
+    const TYPE: [u8; 4] = *b"udta";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.meta.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        if let Some(meta) = &self.meta {
+            children.write_full(meta, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for UserDataBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut meta = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"meta" => meta = Some(child.parse_full()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self { meta, unknown })
+    }
+}
+
+impl Validate for UserDataBox {
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(meta) = &self.meta {
+            meta.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Metadata items. With a [`HandlerType::Metadata`] handler, items are keyed
+/// by iTunes-style four-character codes. With a [`HandlerType::MetadataKeys`]
+/// handler, items are keyed by their 1-based index in `keys`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct MetaBox {
+    pub hdlr: HandlerBox,
+    pub keys: Option<ItemKeysBox>,
+    pub ilst: Option<ItemListBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl MetaBox {
+    /// Creates an iTunes-style metadata box.
+    pub fn from_items(items: Vec<MetadataItem>) -> Self {
+        Self {
+            hdlr: HandlerBox {
+                handler_type: HandlerType::Metadata,
+                name: String::new(),
+            },
+            keys: None,
+            ilst: Some(ItemListBox { items }),
+            unknown: Vec::new(),
+        }
+    }
+
+    /// Creates a metadata box with reverse-DNS keys, such as
+    /// `com.apple.quicktime.title`.
+    pub fn from_tags(tags: impl IntoIterator<Item = (String, MetadataValue)>) -> Self {
+        let (keys, items) = tags
+            .into_iter()
+            .enumerate()
+            .map(|(i, (key, value))| {
+                let key = MetadataKey {
+                    namespace: *b"mdta",
+                    value: key,
+                };
+                let item = MetadataItem::new((i as u32 + 1).to_be_bytes(), value);
+                (key, item)
+            })
+            .unzip();
+        Self {
+            hdlr: HandlerBox {
+                handler_type: HandlerType::MetadataKeys,
+                name: String::new(),
+            },
+            keys: Some(ItemKeysBox { keys }),
+            ilst: Some(ItemListBox { items }),
+            unknown: Vec::new(),
+        }
+    }
+
+    /// Looks up the first value of an item by its four-character code.
+    pub fn item(&self, key: [u8; 4]) -> Option<&MetadataValue> {
+        let ilst = self.ilst.as_ref()?;
+        ilst.items
+            .iter()
+            .find(|item| item.key == key)
+            .and_then(|item| item.values.first())
+    }
+
+    /// Looks up the first value of a freeform (`----`) item by its mean and
+    /// name, such as `com.apple.iTunes` and `iTunSMPB`.
+    pub fn freeform(&self, mean: &str, name: &str) -> Option<&MetadataValue> {
+        let ilst = self.ilst.as_ref()?;
+        ilst.items
+            .iter()
+            .find(|item| {
+                item.freeform
+                    .as_ref()
+                    .map_or(false, |x| x.mean == mean && x.name == name)
+            })
+            .and_then(|item| item.values.first())
+    }
+
+    /// Looks up an item by its entry in `keys`.
+    pub fn tag(&self, key: &str) -> Option<&MetadataValue> {
+        let index = self
+            .keys
+            .as_ref()?
+            .keys
+            .iter()
+            .position(|k| k.value == key)?;
+        self.item((index as u32 + 1).to_be_bytes())
+    }
+}
+
+impl BmffBox for MetaBox {
+    const TYPE: [u8; 4] = *b"meta";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + self.hdlr.size()
+            + self.keys.as_ref().map_or(0, BmffBox::size)
+            + self.ilst.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        let mut children = Children::new(&self.unknown);
+        children.write_full(&self.hdlr, &mut w)?;
+        if let Some(keys) = &self.keys {
+            children.write_full(keys, &mut w)?;
+        }
+        if let Some(ilst) = &self.ilst {
+            children.write(ilst, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for MetaBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut hdlr = None;
+        let mut keys = None;
+        let mut ilst = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"hdlr" => hdlr = Some(child.parse_full()?),
+                b"keys" => keys = Some(child.parse_full()?),
+                b"ilst" => ilst = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            hdlr: hdlr.ok_or_else(|| missing_box(b"hdlr", b"meta"))?,
+            keys,
+            ilst,
+            unknown,
+        })
+    }
+}
+
+impl Validate for MetaBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.hdlr.validate()?;
+        if let Some(ilst) = &self.ilst {
+            ilst.validate()?;
+        }
+        let (Some(keys), Some(ilst)) = (&self.keys, &self.ilst) else {
+            return Ok(());
+        };
+        for item in &ilst.items {
+            let index = u32::from_be_bytes(item.key) as usize;
+            if index == 0 || index > keys.keys.len() {
+                return Err(Error::Invalid(
+                    *b"ilst",
+                    "item key index is not an entry in keys",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FullBox for MetaBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ItemKeysBox {
+    pub keys: Vec<MetadataKey>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataKey {
+    /// Usually `mdta`, for reverse-DNS keys
+    pub namespace: [u8; 4],
+    pub value: String,
+}
+
+impl BmffBox for ItemKeysBox {
+    const TYPE: [u8; 4] = *b"keys";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4
+            + self
+                .keys
+                .iter()
+                .map(|k| 4 + 4 + k.value.len() as u64)
+                .sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&(self.keys.len() as u32).to_be_bytes())?;
+        for key in &self.keys {
+            w.write_all(&(4 + 4 + key.value.len() as u32).to_be_bytes())?;
+            w.write_all(&key.namespace)?;
+            w.write_all(key.value.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for ItemKeysBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let entry_count = r.read_u32()?;
+        let mut keys = Vec::new();
+        for _ in 0..entry_count {
+            let key_size = r.read_u32()? as usize;
+            let namespace = r.read_array()?;
+            let len = key_size
+                .checked_sub(8)
+                .ok_or_else(|| invalid_data("key size is too small"))?;
+            let value = std::str::from_utf8(r.read_bytes(len)?)
+                .map_err(|_| invalid_data("key is not valid UTF-8"))?
+                .to_string();
+            keys.push(MetadataKey { namespace, value });
+        }
+        Ok(Self { keys })
+    }
+}
+
+impl FullBox for ItemKeysBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ItemListBox {
+    pub items: Vec<MetadataItem>,
+}
+
+/// An entry in an `ilst` box.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataItem {
+    /// A four-character code, or a big-endian index into `keys`
+    pub key: [u8; 4],
+    /// The `mean` and `name` of a freeform (`----`) item
+    pub freeform: Option<FreeformName>,
+    /// The `data` boxes of the item. Most items have one value, but some,
+    /// such as cover art, can have several.
+    pub values: Vec<MetadataValue>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+/// Identifies a freeform item with a reverse-DNS `mean`, such as
+/// `com.apple.iTunes`, and a `name` within it.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FreeformName {
+    pub mean: String,
+    pub name: String,
+}
+
+impl MetadataItem {
+    pub const TITLE: [u8; 4] = *b"\xa9nam";
+    pub const ARTIST: [u8; 4] = *b"\xa9ART";
+    pub const COMMENT: [u8; 4] = *b"\xa9cmt";
+    pub const DATE: [u8; 4] = *b"\xa9day";
+    pub const ENCODER: [u8; 4] = *b"\xa9too";
+    pub const TEMPO: [u8; 4] = *b"tmpo";
+    pub const COMPILATION: [u8; 4] = *b"cpil";
+    pub const FREEFORM: [u8; 4] = *b"----";
+
+    /// Creates an item with a single value.
+    pub fn new(key: [u8; 4], value: MetadataValue) -> Self {
+        Self {
+            key,
+            freeform: None,
+            values: vec![value],
+            unknown: Vec::new(),
+        }
+    }
+
+    /// Creates a freeform (`----`) item with a single value.
+    pub fn freeform(mean: String, name: String, value: MetadataValue) -> Self {
+        Self {
+            key: Self::FREEFORM,
+            freeform: Some(FreeformName { mean, name }),
+            values: vec![value],
+            unknown: Vec::new(),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        8 + self
+            .freeform
+            .as_ref()
+            .map_or(0, |x| 12 + x.mean.len() as u64 + 12 + x.name.len() as u64)
+            + self.values.iter().map(BmffBox::size).sum::<u64>()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write(&self, mut w: impl Write) -> io::Result<()> {
+        let size = u32::try_from(self.size())
+            .map_err(|_| io::Error::from(Error::Overflow(*b"ilst", "item size")))?;
+        w.write_all(&size.to_be_bytes())?;
+        w.write_all(&self.key)?;
+        let mut children = Children::new(&self.unknown);
+        if let Some(freeform) = &self.freeform {
+            for (box_type, value) in [(b"mean", &freeform.mean), (b"name", &freeform.name)] {
+                children.next(&mut w)?;
+                w.write_all(&(12 + value.len() as u32).to_be_bytes())?;
+                w.write_all(box_type)?;
+                // version and flags
+                w.write_all(&[0; 4])?;
+                w.write_all(value.as_bytes())?;
+            }
+        }
+        for value in &self.values {
+            children.write_full(value, &mut w)?;
+        }
+        children.finish(w)
+    }
+
+    fn read(mut r: BoxReader<'_>) -> io::Result<Self> {
+        let key = r.header().box_type;
+        let mut mean = None;
+        let mut name = None;
+        let mut values = Vec::new();
+        let mut unknown = Vec::new();
+        while let Some(mut child) = r.next_child()? {
+            match &child.header().box_type {
+                b"mean" | b"name" if key == Self::FREEFORM => {
+                    child.skip(4)?;
+                    let value = std::str::from_utf8(child.read_to_end())
+                        .map_err(|_| invalid_data("freeform item name is not valid UTF-8"))?
+                        .to_string();
+                    if &child.header().box_type == b"mean" {
+                        mean = Some(value);
+                    } else {
+                        name = Some(value);
+                    }
+                }
+                b"data" => values.push(child.parse_full()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        let freeform = match (mean, name) {
+            (Some(mean), Some(name)) => Some(FreeformName { mean, name }),
+            (None, None) => None,
+            _ => return Err(invalid_data("freeform item needs both a mean and a name")),
+        };
+        Ok(Self {
+            key,
+            freeform,
+            values,
+            unknown,
+        })
+    }
+}
+
+impl BmffBox for ItemListBox {
+    const TYPE: [u8; 4] = *b"ilst";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.items.iter().map(MetadataItem::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        for item in &self.items {
+            item.write(&mut w)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for ItemListBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut items = Vec::new();
+        while let Some(item) = r.next_child()? {
+            items.push(MetadataItem::read(item)?);
+        }
+        Ok(Self { items })
+    }
+}
+
+impl Validate for ItemListBox {
+    fn validate(&self) -> Result<(), Error> {
+        for item in &self.items {
+            if (item.key == MetadataItem::FREEFORM) != item.freeform.is_some() {
+                return Err(Error::Invalid(
+                    *b"ilst",
+                    "only freeform items have a mean and name",
+                ));
+            }
+            for value in &item.values {
+                value.validate()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The value of a metadata item, written as a `data` box.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataValue {
+    Utf8(String),
+    /// A big-endian signed integer. Some items need a particular size, such
+    /// as 2 bytes for `tmpo` and 1 byte for `cpil`.
+    Integer {
+        value: i64,
+        /// 1, 2, 3, 4 or 8 bytes
+        size: u8,
+    },
+    /// Any other well-known type, such as JPEG (13) or PNG (14)
+    Other {
+        type_indicator: u32,
+        data: Vec<u8>,
+    },
+}
+
+impl MetadataValue {
+    const UTF8: u32 = 1;
+    const INTEGER: u32 = 21;
+
+    /// Creates a 4-byte integer, or an 8-byte one if `value` doesn't fit.
+    pub fn integer(value: i64) -> Self {
+        let size = if i32::try_from(value).is_ok() { 4 } else { 8 };
+        Self::Integer { value, size }
+    }
+
+    fn type_indicator(&self) -> u32 {
+        match self {
+            Self::Utf8(_) => Self::UTF8,
+            Self::Integer { .. } => Self::INTEGER,
+            Self::Other { type_indicator, .. } => *type_indicator,
+        }
+    }
+}
+
+impl BmffBox for MetadataValue {
+    const TYPE: [u8; 4] = *b"data";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4
+            + match self {
+                Self::Utf8(s) => s.len() as u64,
+                Self::Integer { size, .. } => u64::from(*size),
+                Self::Other { data, .. } => data.len() as u64,
+            }
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        // locale
+        w.write_all(&0u32.to_be_bytes())?;
+        match self {
+            Self::Utf8(s) => w.write_all(s.as_bytes())?,
+            Self::Integer { value, size } => {
+                w.write_all(&value.to_be_bytes()[8 - usize::from(*size)..])?;
+            }
+            Self::Other { data, .. } => w.write_all(data)?,
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for MetadataValue {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.skip(4)?;
+        let data = r.read_to_end();
+        Ok(match r.flags() {
+            Self::UTF8 => Self::Utf8(
+                std::str::from_utf8(data)
+                    .map_err(|_| invalid_data("metadata value is not valid UTF-8"))?
+                    .to_string(),
+            ),
+            Self::INTEGER => {
+                if !matches!(data.len(), 1..=4 | 8) {
+                    return Err(invalid_data("invalid metadata integer size"));
+                }
+                // sign-extend from the first byte
+                let fill = if data[0] & 0x80 == 0 { 0 } else { 0xff };
+                let mut bytes = [fill; 8];
+                bytes[8 - data.len()..].copy_from_slice(data);
+                Self::Integer {
+                    value: i64::from_be_bytes(bytes),
+                    size: data.len() as u8,
+                }
+            }
+            type_indicator => Self::Other {
+                type_indicator,
+                data: data.to_vec(),
+            },
+        })
+    }
+}
+
+impl Validate for MetadataValue {
+    fn validate(&self) -> Result<(), Error> {
+        if let Self::Integer { value, size } = *self {
+            if !matches!(size, 1..=4 | 8) {
+                return Err(Error::Invalid(
+                    *b"data",
+                    "integer size must be 1, 2, 3, 4 or 8 bytes",
+                ));
+            }
+            let bits = u32::from(size) * 8;
+            if bits < 64 && !(-(1 << (bits - 1))..1 << (bits - 1)).contains(&value) {
+                return Err(Error::Overflow(*b"data", "value"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FullBox for MetadataValue {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+
+    /// The type indicator, which is only 24 bits for well-known types.
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        let flags = self.type_indicator().to_be_bytes();
+        [flags[1], flags[2], flags[3]]
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct MediaDataBox {
+    pub headers: Option<Vec<u8>>,
+    pub data: Arc<Vec<u8>>,
+}
+
+impl BmffBox for MediaDataBox {
+    const TYPE: [u8; 4] = *b"mdat";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        large_size(8 + self.headers.as_ref().map_or(0, Vec::len) as u64 + self.data.len() as u64)
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        if let Some(headers) = &self.headers {
+            w.write_all(headers)?;
+        }
+        w.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for MediaDataBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            headers: None,
+            data: Arc::new(r.read_to_end().to_vec()),
+        })
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct MovieFragmentBox {
+    pub mfhd: MovieFragmentHeaderBox,
+    pub pssh: Vec<ProtectionSystemSpecificHeaderBox>,
+    pub traf: Vec<TrackFragmentBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl MovieFragmentBox {
+    /// Replaces the `saiz` and `saio` boxes of each track fragment that has a
+    /// `senc` box with ones that describe the `senc`.
+    ///
+    /// The offsets in the `saio` are relative to the start of the `moof`, as
+    /// with [`default_base_is_moof`](TrackFragmentHeaderBox::default_base_is_moof).
+    /// This changes the size of the `moof`, so it should be called before
+    /// setting the `data_offset` of each `trun`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data of a sample doesn't fit in a `saiz`.
+    pub fn set_sample_aux_info(&mut self) -> Result<(), Error> {
+        // the children are counted in the order `write_box` writes them,
+        // with the unknown ones in between
+        let mut children = Children::new(&self.unknown);
+        let mut offset = 8 + children.skip() + self.mfhd.size();
+        for pssh in &self.pssh {
+            offset += children.skip() + pssh.size();
+        }
+        for traf in &mut self.traf {
+            offset += children.skip();
+            if let Some(senc) = &traf.senc {
+                traf.saiz = vec![senc.saiz()?];
+                traf.saio = vec![SampleAuxiliaryInformationOffsetsBox {
+                    aux_info_type: None,
+                    offsets: vec![0],
+                }];
+                traf.saio[0].offsets[0] = offset + traf.senc_data_offset();
+            }
+            offset += traf.size();
+        }
+        Ok(())
+    }
+}
+
+impl BmffBox for MovieFragmentBox {
+    const TYPE: [u8; 4] = *b"moof";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.mfhd.size()
+            + self.pssh.iter().map(BmffBox::size).sum::<u64>()
+            + self.traf.iter().map(BmffBox::size).sum::<u64>()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        children.write_full(&self.mfhd, &mut w)?;
+        for pssh in &self.pssh {
+            children.write_full(pssh, &mut w)?;
+        }
+        for traf in &self.traf {
+            children.write(traf, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for MovieFragmentBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut mfhd = None;
+        let mut pssh = Vec::new();
+        let mut traf = Vec::new();
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"mfhd" => mfhd = Some(child.parse_full()?),
+                b"pssh" => pssh.push(child.parse_full()?),
+                b"traf" => traf.push(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            mfhd: mfhd.ok_or_else(|| missing_box(b"mfhd", b"moof"))?,
+            pssh,
+            traf,
+            unknown,
+        })
+    }
+}
+
+impl Validate for MovieFragmentBox {
+    fn validate(&self) -> Result<(), Error> {
+        for pssh in &self.pssh {
+            pssh.validate()?;
+        }
+        for traf in &self.traf {
+            traf.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct MovieFragmentHeaderBox {
+    pub sequence_number: u32,
+}
+
+impl BmffBox for MovieFragmentHeaderBox {
+    const TYPE: [u8; 4] = *b"mfhd";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&self.sequence_number.to_be_bytes())?;
+        Ok(())
+    }
+}
+
+impl ReadBox for MovieFragmentHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            sequence_number: r.read_u32()?,
+        })
+    }
+}
+
+impl FullBox for MovieFragmentHeaderBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TrackFragmentBox {
+    pub tfhd: TrackFragmentHeaderBox,
+    pub tfdt: Option<TrackFragmentBaseMediaDecodeTimeBox>,
+    pub trun: Vec<TrackFragmentRunBox>,
+    pub saiz: Vec<SampleAuxiliaryInformationSizesBox>,
+    pub saio: Vec<SampleAuxiliaryInformationOffsetsBox>,
+    /// The `senc` can't be parsed without the IV size from the `tenc` of the
+    /// track, so it's kept in `unknown` when reading until
+    /// [`read_senc`](Self::read_senc) is called.
+    pub senc: Option<SampleEncryptionBox>,
+    pub unknown: Vec<UnknownBox>,
+    // pub sdtp: (),
+    // pub sbgp: (),
+    // pub subs: (),
+}
+
+impl TrackFragmentBox {
+    /// Parses the `senc` box that was kept in `unknown` when reading, using
+    /// the IV size of the track's `tenc`. Does nothing if there's no `senc`
+    /// or it was already parsed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `senc` doesn't match the IV size.
+    pub fn read_senc(&mut self, tenc: &TrackEncryptionBox) -> io::Result<()> {
+        let Some(i) = self.unknown.iter().position(|x| x.raw.box_type == *b"senc") else {
+            return Ok(());
+        };
+        let raw = &self.unknown[i].raw;
+        let header = BoxHeader {
+            box_type: raw.box_type,
+            extended_type: None,
+            offset: 0,
+            size: raw.size(),
+            header_size: raw.size() - raw.payload.len() as u64,
+        };
+        let per_sample_iv_size = if tenc.default_is_protected {
+            tenc.default_per_sample_iv_size
+        } else {
+            0
+        };
+        let senc = SampleEncryptionBox::read_with_iv_size(
+            BoxReader::from_payload(header, &raw.payload),
+            per_sample_iv_size,
+        )?;
+        self.unknown.remove(i);
+        self.senc = Some(senc);
+        Ok(())
+    }
+
+    /// Returns the offset of the sample data in the `senc` from the start of
+    /// the `traf`, following the order of `write_box`.
+    fn senc_data_offset(&self) -> u64 {
+        let mut children = Children::new(&self.unknown);
+        let mut offset = 8 + children.skip() + self.tfhd.size();
+        let sizes = self
+            .tfdt
+            .iter()
+            .map(BmffBox::size)
+            .chain(self.trun.iter().map(BmffBox::size))
+            .chain(self.saiz.iter().map(BmffBox::size))
+            .chain(self.saio.iter().map(BmffBox::size));
+        for size in sizes {
+            offset += children.skip() + size;
+        }
+        // the sample data starts after the header and sample count of the `senc`
+        offset + children.skip() + 12 + 4
+    }
+}
+
+impl BmffBox for TrackFragmentBox {
+    const TYPE: [u8; 4] = *b"traf";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        8 + self.tfhd.size()
+            + self.tfdt.as_ref().map_or(0, BmffBox::size)
+            + self.trun.iter().map(BmffBox::size).sum::<u64>()
+            + self.saiz.iter().map(BmffBox::size).sum::<u64>()
+            + self.saio.iter().map(BmffBox::size).sum::<u64>()
+            + self.senc.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
+        children.write_full(&self.tfhd, &mut w)?;
+        if let Some(tfdt) = &self.tfdt {
+            children.write_full(tfdt, &mut w)?;
+        }
+        for trun in &self.trun {
+            children.write_full(trun, &mut w)?;
+        }
+        for saiz in &self.saiz {
+            children.write_full(saiz, &mut w)?;
+        }
+        for saio in &self.saio {
+            children.write_full(saio, &mut w)?;
+        }
+        if let Some(senc) = &self.senc {
+            children.write_full(senc, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for TrackFragmentBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut tfhd = None;
+        let mut tfdt = None;
+        let mut trun = Vec::new();
+        let mut saiz = Vec::new();
+        let mut saio = Vec::new();
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"tfhd" => tfhd = Some(child.parse_full()?),
+                b"tfdt" => tfdt = Some(child.parse_full()?),
+                b"trun" => trun.push(child.parse_full()?),
+                b"saiz" => saiz.push(child.parse_full()?),
+                b"saio" => saio.push(child.parse_full()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            tfhd: tfhd.ok_or_else(|| missing_box(b"tfhd", b"traf"))?,
+            tfdt,
+            trun,
+            saiz,
+            saio,
+            senc: None,
+            unknown,
+        })
+    }
+}
+
+impl Validate for TrackFragmentBox {
+    fn validate(&self) -> Result<(), Error> {
+        self.tfhd.validate()?;
+        for trun in &self.trun {
+            trun.validate()?;
+        }
+        for saiz in &self.saiz {
+            saiz.validate()?;
+        }
+        for saio in &self.saio {
+            saio.validate()?;
+        }
+        if let Some(senc) = &self.senc {
+            senc.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TrackFragmentHeaderBox {
+    pub track_id: u32,
+    pub base_data_offset: Option<u64>,
+    pub sample_description_index: Option<u32>,
+    pub default_sample_duration: Option<u32>,
+    pub default_sample_size: Option<u32>,
+    pub default_sample_flags: Option<SampleFlags>,
+    pub default_base_is_moof: bool,
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    struct TrackFragmentHeaderFlags: u32 {
+        const BASE_DATA_OFFSET_PRESENT = 0x00_0001;
+        const SAMPLE_DESCRIPTION_INDEX_PRESENT = 0x00_0002;
+        const DEFAULT_SAMPLE_DURATION_PRESENT = 0x00_0008;
+        const DEFAULT_SAMPLE_SIZE_PRESENT = 0x00_0010;
+        const DEFAULT_SAMPLE_FLAGS_PRESENT = 0x00_0020;
+        const DURATION_IS_EMPTY = 0x01_0000;
+        const DEFAULT_BASE_IS_MOOF = 0x02_0000;
+    }
+}
+
+impl BmffBox for TrackFragmentHeaderBox {
+    const TYPE: [u8; 4] = *b"tfhd";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4
+            + self.base_data_offset.as_ref().map_or(0, |_| 8)
+            + self.sample_description_index.as_ref().map_or(0, |_| 4)
+            + self.default_sample_duration.as_ref().map_or(0, |_| 4)
+            + self.default_sample_size.as_ref().map_or(0, |_| 4)
+            + self.default_sample_flags.as_ref().map_or(0, |_| 4)
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        w.write_all(&self.track_id.to_be_bytes())?;
+        if let Some(base_data_offset) = self.base_data_offset {
+            w.write_all(&base_data_offset.to_be_bytes())?;
+        }
+        if let Some(sample_description_index) = self.sample_description_index {
+            w.write_all(&sample_description_index.to_be_bytes())?;
+        }
+        if let Some(default_sample_duration) = self.default_sample_duration {
+            w.write_all(&default_sample_duration.to_be_bytes())?;
+        }
+        if let Some(default_sample_size) = self.default_sample_size {
+            w.write_all(&default_sample_size.to_be_bytes())?;
+        }
+        if let Some(default_sample_flags) = self.default_sample_flags {
+            default_sample_flags.write_to(&mut w)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for TrackFragmentHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let flags = TrackFragmentHeaderFlags::from_bits_retain(r.flags());
+        let track_id = r.read_u32()?;
+        let base_data_offset = flags
+            .contains(TrackFragmentHeaderFlags::BASE_DATA_OFFSET_PRESENT)
+            .then(|| r.read_u64())
+            .transpose()?;
+        let sample_description_index = flags
+            .contains(TrackFragmentHeaderFlags::SAMPLE_DESCRIPTION_INDEX_PRESENT)
+            .then(|| r.read_u32())
+            .transpose()?;
+        let default_sample_duration = flags
+            .contains(TrackFragmentHeaderFlags::DEFAULT_SAMPLE_DURATION_PRESENT)
+            .then(|| r.read_u32())
+            .transpose()?;
+        let default_sample_size = flags
+            .contains(TrackFragmentHeaderFlags::DEFAULT_SAMPLE_SIZE_PRESENT)
+            .then(|| r.read_u32())
+            .transpose()?;
+        let default_sample_flags = flags
+            .contains(TrackFragmentHeaderFlags::DEFAULT_SAMPLE_FLAGS_PRESENT)
+            .then(|| r.read_u32().map(SampleFlags::from))
+            .transpose()?;
+        Ok(Self {
+            track_id,
+            base_data_offset,
+            sample_description_index,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags,
+            default_base_is_moof: flags.contains(TrackFragmentHeaderFlags::DEFAULT_BASE_IS_MOOF),
+        })
+    }
+}
+
+impl Validate for TrackFragmentHeaderBox {
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(default_sample_flags) = self.default_sample_flags {
+            default_sample_flags.check(Self::TYPE)?;
+        }
+        Ok(())
+    }
+}
+
+impl FullBox for TrackFragmentHeaderBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        let mut flags = TrackFragmentHeaderFlags::empty();
+        if self.base_data_offset.is_some() {
+            flags |= TrackFragmentHeaderFlags::BASE_DATA_OFFSET_PRESENT;
+        }
+        if self.sample_description_index.is_some() {
+            flags |= TrackFragmentHeaderFlags::SAMPLE_DESCRIPTION_INDEX_PRESENT;
+        }
+        if self.default_sample_duration.is_some() {
+            flags |= TrackFragmentHeaderFlags::DEFAULT_SAMPLE_DURATION_PRESENT;
+        }
+        if self.default_sample_size.is_some() {
+            flags |= TrackFragmentHeaderFlags::DEFAULT_SAMPLE_SIZE_PRESENT;
+        }
+        if self.default_sample_flags.is_some() {
+            flags |= TrackFragmentHeaderFlags::DEFAULT_SAMPLE_FLAGS_PRESENT;
+        }
+        if self.default_base_is_moof {
+            flags |= TrackFragmentHeaderFlags::DEFAULT_BASE_IS_MOOF;
+        }
+        let flags = flags.bits().to_be_bytes();
+        [flags[1], flags[2], flags[3]]
+    }
+}
+
+/// The decode time of the first sample in a track fragment, in the track's
+/// timescale.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TrackFragmentBaseMediaDecodeTimeBox {
+    pub base_media_decode_time: u64,
+}
+
+impl BmffBox for TrackFragmentBaseMediaDecodeTimeBox {
+    const TYPE: [u8; 4] = *b"tfdt";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + if self.version() == 1 { 8 } else { 4 }
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        if self.version() == 1 {
+            w.write_all(&self.base_media_decode_time.to_be_bytes())?;
+        } else {
+            w.write_all(&(self.base_media_decode_time as u32).to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for TrackFragmentBaseMediaDecodeTimeBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let base_media_decode_time = if r.version() == 1 {
+            r.read_u64()?
+        } else {
+            r.read_u32()? as u64
+        };
+        Ok(Self {
+            base_media_decode_time,
+        })
+    }
+}
+
+impl FullBox for TrackFragmentBaseMediaDecodeTimeBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        u8::from(self.base_media_decode_time > u32::MAX as u64)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TrackFragmentRunBox {
+    /// 1 if the composition time offsets are signed. Negative offsets need
+    /// version 1, and offsets above `i32::MAX` need version 0.
+    pub version: u8,
+    pub data_offset: Option<i32>,
+    pub first_sample_flags: Option<SampleFlags>,
+    pub sample_durations: Option<Vec<u32>>,
+    pub sample_sizes: Option<Vec<u32>>,
+    pub sample_flags: Option<Vec<SampleFlags>>,
+    pub sample_composition_time_offsets: Option<Vec<i64>>,
+}
+
+bitflags! {
+    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+    struct TrackFragmentRunFlags: u32 {
+        const DATA_OFFSET_PRESENT = 0x00_0001;
+        const FIRST_SAMPLE_FLAGS_PRESENT = 0x00_0004;
+        const SAMPLE_DURATION_PRESENT = 0x00_0100;
+        const SAMPLE_SIZE_PRESENT = 0x00_0200;
+        const SAMPLE_FLAGS_PRESENT = 0x00_0400;
+        const SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT = 0x00_0800;
+    }
+}
+
+impl TrackFragmentRunBox {
+    /// The sample count, taken from the first per-sample field that is
+    /// present. [`Validate`] checks that the other fields agree.
+    fn len(&self) -> Option<usize> {
+        self.sample_durations
+            .as_ref()
+            .map(Vec::len)
+            .or_else(|| self.sample_sizes.as_ref().map(Vec::len))
+            .or_else(|| self.sample_flags.as_ref().map(Vec::len))
+            .or_else(|| self.sample_composition_time_offsets.as_ref().map(Vec::len))
+    }
+}
+
+impl BmffBox for TrackFragmentRunBox {
+    const TYPE: [u8; 4] = *b"trun";
+
+    #[inline]
+    fn size(&self) -> u64 {
+        12 + 4
+            + self.data_offset.as_ref().map_or(0, |_| 4)
+            + self.first_sample_flags.as_ref().map_or(0, |_| 4)
+            + self.len().unwrap_or(0) as u64
+                * 4
+                * (self.sample_durations.is_some() as u64
+                    + self.sample_sizes.is_some() as u64
+                    + self.sample_flags.is_some() as u64
+                    + self.sample_composition_time_offsets.is_some() as u64)
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        self.validate()?;
+        let len = self.len().unwrap_or(0);
+        w.write_all(&(len as u32).to_be_bytes())?;
+        if let Some(data_offset) = self.data_offset {
+            w.write_all(&data_offset.to_be_bytes())?;
+        }
+        if let Some(first_sample_flags) = self.first_sample_flags {
+            first_sample_flags.write_to(&mut w)?;
+        }
+        for i in 0..len {
+            if let Some(sample_durations) = &self.sample_durations {
+                w.write_all(&sample_durations[i].to_be_bytes())?;
+            }
+            if let Some(sample_sizes) = &self.sample_sizes {
+                w.write_all(&sample_sizes[i].to_be_bytes())?;
+            }
+            if let Some(sample_flags) = &self.sample_flags {
+                sample_flags[i].write_to(&mut w)?;
+            }
+            if let Some(sample_composition_time_offsets) = &self.sample_composition_time_offsets {
+                let offset = sample_composition_time_offsets[i];
+                if self.version() == 0 {
+                    w.write_all(&(offset as u32).to_be_bytes())?;
+                } else {
+                    w.write_all(&(offset as i32).to_be_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReadBox for TrackFragmentRunBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let flags = TrackFragmentRunFlags::from_bits_retain(r.flags());
+        let sample_count = r.read_u32()? as usize;
+        let data_offset = flags
+            .contains(TrackFragmentRunFlags::DATA_OFFSET_PRESENT)
+            .then(|| r.read_i32())
+            .transpose()?;
+        let first_sample_flags = flags
+            .contains(TrackFragmentRunFlags::FIRST_SAMPLE_FLAGS_PRESENT)
+            .then(|| r.read_u32().map(SampleFlags::from))
+            .transpose()?;
+        let present = |flag| flags.contains(flag).then(Vec::new);
+        let mut sample_durations = present(TrackFragmentRunFlags::SAMPLE_DURATION_PRESENT);
+        let mut sample_sizes = present(TrackFragmentRunFlags::SAMPLE_SIZE_PRESENT);
+        let mut sample_flags = flags
+            .contains(TrackFragmentRunFlags::SAMPLE_FLAGS_PRESENT)
+            .then(Vec::new);
+        let mut sample_composition_time_offsets = flags
+            .contains(TrackFragmentRunFlags::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT)
+            .then(Vec::new);
+        for _ in 0..sample_count {
+            for field in [&mut sample_durations, &mut sample_sizes]
+                .into_iter()
+                .flatten()
+            {
+                field.push(r.read_u32()?);
+            }
+            if let Some(sample_flags) = &mut sample_flags {
+                sample_flags.push(SampleFlags::from(r.read_u32()?));
+            }
+            if let Some(offsets) = &mut sample_composition_time_offsets {
+                offsets.push(if r.version() == 0 {
+                    i64::from(r.read_u32()?)
+                } else {
+                    i64::from(r.read_i32()?)
+                });
+            }
+        }
+        Ok(Self {
+            version: r.version(),
+            data_offset,
+            first_sample_flags,
+            sample_durations,
+            sample_sizes,
+            sample_flags,
+            sample_composition_time_offsets,
+        })
+    }
+}
+
+impl Validate for TrackFragmentRunBox {
+    fn validate(&self) -> Result<(), Error> {
+        let len = self.len().unwrap_or(0);
+        for (field, l) in [
+            (
+                "sample_durations",
+                self.sample_durations.as_ref().map(Vec::len),
+            ),
+            ("sample_sizes", self.sample_sizes.as_ref().map(Vec::len)),
+            ("sample_flags", self.sample_flags.as_ref().map(Vec::len)),
+            (
+                "sample_composition_time_offsets",
+                self.sample_composition_time_offsets.as_ref().map(Vec::len),
+            ),
+        ] {
+            match l {
+                Some(l) if l != len => {
+                    return Err(Error::LengthMismatch(Self::TYPE, field, len, l));
+                }
+                _ => (),
+            }
+        }
+        check_bits(Self::TYPE, "sample_count", len as u64, 32)?;
+        if let Some(offsets) = &self.sample_composition_time_offsets {
+            check_composition_offsets(
+                Self::TYPE,
+                "sample_composition_time_offsets",
+                self.version,
+                offsets,
+            )?;
+        }
+        if self.first_sample_flags.is_some() && self.sample_flags.is_some() {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "first_sample_flags and sample_flags can't both be present",
+            ));
+        }
+        for flags in self
+            .first_sample_flags
+            .iter()
+            .chain(self.sample_flags.iter().flatten())
+        {
+            flags.check(Self::TYPE)?;
+        }
+        Ok(())
+    }
+}
+
+impl FullBox for TrackFragmentRunBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        self.version
+    }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        let mut flags = TrackFragmentRunFlags::empty();
+
+        flags.set(
+            TrackFragmentRunFlags::DATA_OFFSET_PRESENT,
+            self.data_offset.is_some(),
+        );
+        flags.set(
+            TrackFragmentRunFlags::FIRST_SAMPLE_FLAGS_PRESENT,
+            self.first_sample_flags.is_some(),
+        );
+        flags.set(
+            TrackFragmentRunFlags::SAMPLE_DURATION_PRESENT,
+            self.sample_durations.is_some(),
+        );
+        flags.set(
+            TrackFragmentRunFlags::SAMPLE_SIZE_PRESENT,
+            self.sample_sizes.is_some(),
+        );
+        flags.set(
+            TrackFragmentRunFlags::SAMPLE_FLAGS_PRESENT,
+            self.sample_flags.is_some(),
+        );
+        flags.set(
+            TrackFragmentRunFlags::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT,
+            self.sample_composition_time_offsets.is_some(),
+        );
 
-    #[inline]
-    fn flags(&self) -> [u8; 3] {
-        let flags = self.flags.bits().to_be_bytes();
+        let flags = flags.bits().to_be_bytes();
         [flags[1], flags[2], flags[3]]
     }
 }
 
+/// An index of sync samples in a fragmented file, placed at the end of the
+/// file. The trailing `mfro` box is written automatically.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct SampleTableBox {
-    pub stsd: SampleDescriptionBox,
-    pub stts: TimeToSampleBox,
-    pub stsc: SampleToChunkBox,
-    pub stsz: SampleSizeBox,
-    pub stco: ChunkOffsetBox,
+pub struct MovieFragmentRandomAccessBox {
+    pub tfra: Vec<TrackFragmentRandomAccessBox>,
+    pub unknown: Vec<UnknownBox>,
 }
 
-impl BmffBox for SampleTableBox {
-    const TYPE: [u8; 4] = *b"stbl";
-
-    #[inline]
-    fn size(&self) -> u64 {
-        8 + self.stsd.size()
-            + self.stts.size()
-            + self.stsc.size()
-            + self.stsz.size()
-            + self.stco.size()
+impl MovieFragmentRandomAccessBox {
+    /// Creates an index for a single track from `(time, moof_offset,
+    /// sample_number)` entries, where `sample_number` is 1-based within the
+    /// first run of the first track fragment.
+    pub fn new(track_id: u32, entries: impl IntoIterator<Item = (u64, u64, u32)>) -> Self {
+        Self {
+            tfra: vec![TrackFragmentRandomAccessBox {
+                track_id,
+                entries: entries
+                    .into_iter()
+                    .map(|(time, moof_offset, sample_number)| RandomAccessEntry {
+                        time,
+                        moof_offset,
+                        traf_number: 1,
+                        trun_number: 1,
+                        sample_number,
+                    })
+                    .collect(),
+            }],
+            unknown: Vec::new(),
+        }
     }
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.stsd, &mut w)?;
-        write_to_full(&self.stts, &mut w)?;
-        write_to_full(&self.stsc, &mut w)?;
-        write_to_full(&self.stsz, &mut w)?;
-        write_to_full(&self.stco, &mut w)?;
-        Ok(())
+    fn mfro(&self) -> MovieFragmentRandomAccessOffsetBox {
+        MovieFragmentRandomAccessOffsetBox {
+            mfra_size: self.size() as u32,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TimeToSampleBox {
-    /// `(sample_count, sample_delta)`
-    pub samples: Vec<(u32, u32)>,
-}
-
-impl BmffBox for TimeToSampleBox {
-    const TYPE: [u8; 4] = *b"stts";
+impl BmffBox for MovieFragmentRandomAccessBox {
+    const TYPE: [u8; 4] = *b"mfra";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + self.samples.len() as u64 * 8
+        8 + self.tfra.iter().map(BmffBox::size).sum::<u64>()
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
+            + 16
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&(self.samples.len() as u32).to_be_bytes())?;
-        for (sample_count, sample_delta) in &self.samples {
-            w.write_all(&sample_count.to_be_bytes())?;
-            w.write_all(&sample_delta.to_be_bytes())?;
+        let mut children = Children::new(&self.unknown);
+        for tfra in &self.tfra {
+            children.write_full(tfra, &mut w)?;
         }
-        Ok(())
-    }
-}
-
-impl FullBox for TimeToSampleBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
+        // mfro is always the last child
+        children.finish(&mut w)?;
+        write_to_full(&self.mfro(), &mut w)
+    }
+}
+
+impl ReadBox for MovieFragmentRandomAccessBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut tfra = Vec::new();
+        let mut mfro = false;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"tfra" => tfra.push(child.parse_full()?),
+                b"mfro" => {
+                    child.parse_full::<MovieFragmentRandomAccessOffsetBox>()?;
+                    mfro = true;
+                }
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        if !mfro {
+            return Err(missing_box(b"mfro", b"mfra"));
+        }
+        Ok(Self { tfra, unknown })
     }
 }
 
-pub trait SampleEntry: std::fmt::Debug + Send + Sync {
-    fn size(&self) -> u64;
-    fn write_to(&self) -> Vec<u8>;
-    fn clone_box(&self) -> Box<dyn SampleEntry>;
-}
-
-#[derive(Debug)]
-pub struct SampleDescriptionBox {
-    pub entries: Vec<Box<dyn SampleEntry>>,
-}
-
-impl Clone for SampleDescriptionBox {
-    fn clone(&self) -> Self {
-        Self {
-            entries: self.entries.iter().map(|x| x.clone_box()).collect(),
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct TrackFragmentRandomAccessBox {
+    pub track_id: u32,
+    pub entries: Vec<RandomAccessEntry>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RandomAccessEntry {
+    /// Presentation time of the sync sample, in the track's timescale
+    pub time: u64,
+    /// Offset of the `moof` from the start of the file
+    pub moof_offset: u64,
+    /// 1-based
+    pub traf_number: u32,
+    /// 1-based
+    pub trun_number: u32,
+    /// 1-based
+    pub sample_number: u32,
+}
+
+impl TrackFragmentRandomAccessBox {
+    /// The number of bytes used for each of `traf_number`, `trun_number` and
+    /// `sample_number`.
+    fn length_sizes(&self) -> [u64; 3] {
+        fn length_size(x: u32) -> u64 {
+            (4 - x.leading_zeros() as u64 / 8).max(1)
         }
+        self.entries.iter().fold([1; 3], |acc, entry| {
+            [
+                acc[0].max(length_size(entry.traf_number)),
+                acc[1].max(length_size(entry.trun_number)),
+                acc[2].max(length_size(entry.sample_number)),
+            ]
+        })
     }
 }
 
-impl BmffBox for SampleDescriptionBox {
-    const TYPE: [u8; 4] = *b"stsd";
+impl BmffBox for TrackFragmentRandomAccessBox {
+    const TYPE: [u8; 4] = *b"tfra";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + self.entries.iter().map(|x| x.size()).sum::<u64>()
+        let entry_size = if self.version() == 1 { 8 + 8 } else { 4 + 4 }
+            + self.length_sizes().iter().sum::<u64>();
+        12 + 4 + 4 + 4 + self.entries.len() as u64 * entry_size
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let length_sizes = self.length_sizes();
+        w.write_all(&self.track_id.to_be_bytes())?;
+        let lengths =
+            (length_sizes[0] - 1) << 4 | (length_sizes[1] - 1) << 2 | (length_sizes[2] - 1);
+        w.write_all(&(lengths as u32).to_be_bytes())?;
         w.write_all(&(self.entries.len() as u32).to_be_bytes())?;
         for entry in &self.entries {
-            w.write_all(&entry.write_to())?;
+            if self.version() == 1 {
+                w.write_all(&entry.time.to_be_bytes())?;
+                w.write_all(&entry.moof_offset.to_be_bytes())?;
+            } else {
+                w.write_all(&(entry.time as u32).to_be_bytes())?;
+                w.write_all(&(entry.moof_offset as u32).to_be_bytes())?;
+            }
+            for (x, len) in [entry.traf_number, entry.trun_number, entry.sample_number]
+                .into_iter()
+                .zip(length_sizes)
+            {
+                w.write_all(&x.to_be_bytes()[4 - len as usize..])?;
+            }
         }
         Ok(())
     }
 }
 
-impl FullBox for SampleDescriptionBox {
+impl ReadBox for TrackFragmentRandomAccessBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        fn read_number(r: &mut BoxReader<'_>, len: usize) -> io::Result<u32> {
+            let mut buf = [0u8; 4];
+            buf[4 - len..].copy_from_slice(r.read_bytes(len)?);
+            Ok(u32::from_be_bytes(buf))
+        }
+
+        let track_id = r.read_u32()?;
+        let lengths = r.read_u32()?;
+        let traf_len = (lengths >> 4 & 0x03) as usize + 1;
+        let trun_len = (lengths >> 2 & 0x03) as usize + 1;
+        let sample_len = (lengths & 0x03) as usize + 1;
+        let number_of_entry = r.read_u32()?;
+        let mut entries = Vec::new();
+        for _ in 0..number_of_entry {
+            let (time, moof_offset) = if r.version() == 1 {
+                (r.read_u64()?, r.read_u64()?)
+            } else {
+                (r.read_u32()? as u64, r.read_u32()? as u64)
+            };
+            entries.push(RandomAccessEntry {
+                time,
+                moof_offset,
+                traf_number: read_number(r, traf_len)?,
+                trun_number: read_number(r, trun_len)?,
+                sample_number: read_number(r, sample_len)?,
+            });
+        }
+        Ok(Self { track_id, entries })
+    }
+}
+
+impl FullBox for TrackFragmentRandomAccessBox {
     #[inline]
     fn version(&self) -> u8 {
-        0
+        u8::from(
+            self.entries
+                .iter()
+                .any(|x| x.time > u32::MAX as u64 || x.moof_offset > u32::MAX as u64),
+        )
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct SampleSizeBox {
-    pub sample_size: SampleSize,
+/// The last box in an `mfra` box. Readers can find the `mfra` box by reading
+/// this from the end of the file.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFragmentRandomAccessOffsetBox {
+    /// The size of the enclosing `mfra` box
+    pub mfra_size: u32,
 }
 
-impl BmffBox for SampleSizeBox {
-    const TYPE: [u8; 4] = *b"stsz";
+impl BmffBox for MovieFragmentRandomAccessOffsetBox {
+    const TYPE: [u8; 4] = *b"mfro";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + self.sample_size.size()
+        12 + 4
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        self.sample_size.write_to(&mut w)?;
+        w.write_all(&self.mfra_size.to_be_bytes())?;
         Ok(())
     }
 }
 
-impl FullBox for SampleSizeBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
+impl ReadBox for MovieFragmentRandomAccessOffsetBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            mfra_size: r.read_u32()?,
+        })
     }
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub enum SampleSize {
-    Same(NonZeroU32),
-    Different(Vec<u32>),
-}
-
-impl SampleSize {
-    fn size(&self) -> u64 {
-        match self {
-            Self::Same(_) => 8,
-            Self::Different(x) => 8 + x.len() as u64 * 4,
-        }
+impl FullBox for MovieFragmentRandomAccessOffsetBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
     }
 }
 
-impl WriteTo for SampleSize {
-    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
-        match self {
-            Self::Same(sample_size) => {
-                w.write_all(&sample_size.get().to_be_bytes())?;
-                w.write_all(&0u32.to_be_bytes())?;
-            }
-            Self::Different(entry_sizes) => {
-                w.write_all(&0u32.to_be_bytes())?;
-                w.write_all(&(entry_sizes.len() as u32).to_be_bytes())?;
-                for entry_size in entry_sizes.iter() {
-                    w.write_all(&entry_size.to_be_bytes())?;
-                }
-            }
-        }
-        Ok(())
-    }
+/// An in-band event, such as a DASH event, placed before the `moof` of the
+/// segment it applies to.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventMessageBox {
+    pub scheme_id_uri: String,
+    pub value: String,
+    pub timescale: u32,
+    pub presentation_time: EventTime,
+    /// `0xFFFF_FFFF` means the duration is unknown.
+    pub event_duration: u32,
+    pub id: u32,
+    pub message_data: Vec<u8>,
 }
 
-#[derive(Debug, Clone)]
-pub struct VisualSampleEntry {
-    pub data_reference_index: u16,
-    pub width: u16,
-    pub height: u16,
-    /// DPI
-    pub horiz_resolution: U16F16,
-    /// DPI
-    pub vert_resolution: U16F16,
-    pub frame_count: u16,
-    // must be less than 32 bytes
-    pub compressor_name: String,
-    pub depth: u16,
+/// When an event starts, in the timescale of the `emsg` box.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTime {
+    /// Relative to the earliest presentation time of the segment. Written as
+    /// version 0.
+    Delta(u32),
+    /// Relative to the start of the track. Written as version 1.
+    Absolute(u64),
 }
 
-impl BmffBox for VisualSampleEntry {
-    const TYPE: [u8; 4] = *b"vide";
+impl BmffBox for EventMessageBox {
+    const TYPE: [u8; 4] = *b"emsg";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + 6 + 2 + 2 + 2 + 4 * 3 + 2 + 2 + 4 + 4 + 4 + 2 + 32 + 2 + 2
+        12 + self.scheme_id_uri.len() as u64
+            + 1
+            + self.value.len() as u64
+            + 1
+            + 4
+            + match self.presentation_time {
+                EventTime::Delta(_) => 4,
+                EventTime::Absolute(_) => 8,
+            }
+            + 4
+            + 4
+            + self.message_data.len() as u64
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&[0u8; 6])?;
-        w.write_all(&self.data_reference_index.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        w.write_all(&[0u32.to_be_bytes(); 3].concat())?;
-        w.write_all(&self.width.to_be_bytes())?;
-        w.write_all(&self.height.to_be_bytes())?;
-        w.write_all(&self.horiz_resolution.to_be_bytes())?;
-        w.write_all(&self.vert_resolution.to_be_bytes())?;
-        w.write_all(&0u32.to_be_bytes())?;
-        w.write_all(&self.frame_count.to_be_bytes())?;
-        assert!(self.compressor_name.len() <= 32);
-        w.write_all(&[self.compressor_name.len() as u8])?;
-        for _ in 0..(32 - 1 - self.compressor_name.len()) {
+        self.validate()?;
+        let write_strings = |w: &mut dyn Write| -> io::Result<()> {
+            w.write_all(self.scheme_id_uri.as_bytes())?;
+            w.write_all(&[0u8])?;
+            w.write_all(self.value.as_bytes())?;
             w.write_all(&[0u8])?;
+            Ok(())
+        };
+        match self.presentation_time {
+            EventTime::Delta(delta) => {
+                write_strings(&mut w)?;
+                w.write_all(&self.timescale.to_be_bytes())?;
+                w.write_all(&delta.to_be_bytes())?;
+                w.write_all(&self.event_duration.to_be_bytes())?;
+                w.write_all(&self.id.to_be_bytes())?;
+            }
+            EventTime::Absolute(time) => {
+                w.write_all(&self.timescale.to_be_bytes())?;
+                w.write_all(&time.to_be_bytes())?;
+                w.write_all(&self.event_duration.to_be_bytes())?;
+                w.write_all(&self.id.to_be_bytes())?;
+                write_strings(&mut w)?;
+            }
         }
-        w.write_all(self.compressor_name.as_bytes())?;
-        w.write_all(&self.depth.to_be_bytes())?;
-        w.write_all(&(-1i16).to_be_bytes())?;
+        w.write_all(&self.message_data)?;
         Ok(())
     }
 }
 
-impl SampleEntry for VisualSampleEntry {
-    fn size(&self) -> u64 {
-        <Self as BmffBox>::size(self)
-    }
-
-    fn write_to(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
-        #[allow(clippy::unwrap_used)] // writing into a `Vec` is infallible
-        write_to(self, &mut buf).unwrap();
-        buf
+impl ReadBox for EventMessageBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let (scheme_id_uri, value, timescale, presentation_time, event_duration, id) =
+            match r.version() {
+                0 => {
+                    let scheme_id_uri = r.read_string()?;
+                    let value = r.read_string()?;
+                    let timescale = r.read_u32()?;
+                    let delta = r.read_u32()?;
+                    let event_duration = r.read_u32()?;
+                    let id = r.read_u32()?;
+                    (
+                        scheme_id_uri,
+                        value,
+                        timescale,
+                        EventTime::Delta(delta),
+                        event_duration,
+                        id,
+                    )
+                }
+                1 => {
+                    let timescale = r.read_u32()?;
+                    let time = r.read_u64()?;
+                    let event_duration = r.read_u32()?;
+                    let id = r.read_u32()?;
+                    let scheme_id_uri = r.read_string()?;
+                    let value = r.read_string()?;
+                    (
+                        scheme_id_uri,
+                        value,
+                        timescale,
+                        EventTime::Absolute(time),
+                        event_duration,
+                        id,
+                    )
+                }
+                version => return Err(invalid_data(format!("unsupported emsg version {version}"))),
+            };
+        Ok(Self {
+            scheme_id_uri,
+            value,
+            timescale,
+            presentation_time,
+            event_duration,
+            id,
+            message_data: r.read_to_end().to_vec(),
+        })
+    }
+}
+
+impl Validate for EventMessageBox {
+    fn validate(&self) -> Result<(), Error> {
+        if self.scheme_id_uri.contains('\0') || self.value.contains('\0') {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "scheme_id_uri and value can't contain null characters",
+            ));
+        }
+        Ok(())
     }
+}
 
-    fn clone_box(&self) -> Box<dyn SampleEntry> {
-        Box::new(self.clone())
+impl FullBox for EventMessageBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        match self.presentation_time {
+            EventTime::Delta(_) => 0,
+            EventTime::Absolute(_) => 1,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct AudioSampleEntry {
-    pub data_reference_index: u16,
-    pub channel_count: u16,
-    /// bits
-    pub sample_size: u16,
-    pub sample_rate: u32,
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// NTP seconds that can be written, as in RFC 4330: from 1968-01-20 in era 0,
+/// when the top bit is set, to 2104-02-26 in era 1, when it isn't.
+const NTP_RANGE: std::ops::Range<i64> = 1 << 31..(1 << 32) + (1 << 31);
+
+/// Relates a wall-clock time to a media time, so that clients can measure
+/// latency. It is placed before the `moof` it applies to.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProducerReferenceTimeBox {
+    pub reference_track_id: u32,
+    pub time_source: ReferenceTimeSource,
+    /// Written as a 64-bit NTP timestamp. Times from 2036-02-07 wrap into the
+    /// next NTP era.
+    pub ntp_timestamp: DateTime<Utc>,
+    /// In the timescale of the reference track.
+    pub media_time: u64,
 }
 
-impl BmffBox for AudioSampleEntry {
-    const TYPE: [u8; 4] = *b"soun";
+/// What `ntp_timestamp` of a `prft` box refers to.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u32)]
+pub enum ReferenceTimeSource {
+    /// When the sample was input to the encoder
+    #[default]
+    EncoderInput = 0,
+    /// When the sample was output from the encoder
+    EncoderOutput = 1,
+    /// When the `moof` was finalized
+    MoofFinalized = 2,
+    /// When the `moof` was written
+    MoofWritten = 4,
+    /// Some time that is consistent between `prft` boxes
+    Arbitrary = 8,
+    /// When the sample was captured
+    Captured = 24,
+}
+
+impl BmffBox for ProducerReferenceTimeBox {
+    const TYPE: [u8; 4] = *b"prft";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + 6 + 2 + 4 * 2 + 2 + 2 + 2 + 2 + 4
+        12 + 4 + 8 + if self.version() == 0 { 4 } else { 8 }
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&[0u8; 6])?;
-        w.write_all(&self.data_reference_index.to_be_bytes())?;
-        w.write_all(&[0u32.to_be_bytes(); 2].concat())?;
-        w.write_all(&self.channel_count.to_be_bytes())?;
-        w.write_all(&self.sample_size.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        w.write_all(&self.sample_rate.to_be_bytes())?;
+        self.validate()?;
+        // the seconds of the current NTP era
+        let secs = (self.ntp_timestamp.timestamp() + NTP_UNIX_OFFSET) as u64 & 0xffff_ffff;
+        let frac = (u64::from(self.ntp_timestamp.timestamp_subsec_nanos()) << 32) / 1_000_000_000;
+        w.write_all(&self.reference_track_id.to_be_bytes())?;
+        w.write_all(&(secs << 32 | frac).to_be_bytes())?;
+        if self.version() == 0 {
+            w.write_all(&(self.media_time as u32).to_be_bytes())?;
+        } else {
+            w.write_all(&self.media_time.to_be_bytes())?;
+        }
         Ok(())
     }
 }
 
-impl SampleEntry for AudioSampleEntry {
-    fn size(&self) -> u64 {
-        <Self as BmffBox>::size(self)
-    }
-    fn write_to(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
-        #[allow(clippy::unwrap_used)] // writing into a `Vec` is infallible
-        write_to(self, &mut buf).unwrap();
-        buf
-    }
-
-    fn clone_box(&self) -> Box<dyn SampleEntry> {
-        Box::new(self.clone())
+impl ReadBox for ProducerReferenceTimeBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let time_source = match r.flags() {
+            0 => ReferenceTimeSource::EncoderInput,
+            1 => ReferenceTimeSource::EncoderOutput,
+            2 => ReferenceTimeSource::MoofFinalized,
+            4 => ReferenceTimeSource::MoofWritten,
+            8 => ReferenceTimeSource::Arbitrary,
+            24 => ReferenceTimeSource::Captured,
+            flags => return Err(invalid_data(format!("unsupported prft flags {flags:#x}"))),
+        };
+        let reference_track_id = r.read_u32()?;
+        let ntp_timestamp = r.read_u64()?;
+        let nanos = ((ntp_timestamp & 0xffff_ffff) * 1_000_000_000) >> 32;
+        let mut secs = (ntp_timestamp >> 32) as i64;
+        if secs < NTP_RANGE.start {
+            secs += 1 << 32;
+        }
+        let ntp_timestamp = DateTime::<Utc>::from_timestamp(secs - NTP_UNIX_OFFSET, nanos as u32)
+            .ok_or_else(|| invalid_data("timestamp out of range"))?;
+        let media_time = if r.version() == 1 {
+            r.read_u64()?
+        } else {
+            r.read_u32()?.into()
+        };
+        Ok(Self {
+            reference_track_id,
+            time_source,
+            ntp_timestamp,
+            media_time,
+        })
+    }
+}
+
+impl Validate for ProducerReferenceTimeBox {
+    fn validate(&self) -> Result<(), Error> {
+        let secs = self.ntp_timestamp.timestamp() + NTP_UNIX_OFFSET;
+        if !NTP_RANGE.contains(&secs) {
+            return Err(Error::Overflow(Self::TYPE, "ntp_timestamp"));
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct HintSampleEntry {
-    pub data_reference_index: u16,
-    pub data: Vec<u8>,
-}
-
-impl BmffBox for HintSampleEntry {
-    const TYPE: [u8; 4] = *b"hint";
-
+impl FullBox for ProducerReferenceTimeBox {
     #[inline]
-    fn size(&self) -> u64 {
-        8 + 6 + 2 + self.data.len() as u64
-    }
-
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&[0u8; 6])?;
-        w.write_all(&self.data_reference_index.to_be_bytes())?;
-        w.write_all(&self.data)?;
-        Ok(())
+    fn version(&self) -> u8 {
+        u8::from(self.media_time > u32::MAX as u64)
     }
-}
 
-impl SampleEntry for HintSampleEntry {
     #[inline]
-    fn size(&self) -> u64 {
-        <Self as BmffBox>::size(self)
+    fn flags(&self) -> [u8; 3] {
+        let flags = (self.time_source as u32).to_be_bytes();
+        [flags[1], flags[2], flags[3]]
     }
+}
 
-    fn write_to(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
-        #[allow(clippy::unwrap_used)] // writing into a `Vec` is infallible
-        write_to(self, &mut buf).unwrap();
-        buf
-    }
+fn read_box_string(r: &mut BoxReader<'_>) -> io::Result<String> {
+    let box_type = r.header().box_type;
+    std::str::from_utf8(r.read_to_end())
+        .map(str::to_string)
+        .map_err(|_| invalid_data(format!("{} box is not valid UTF-8", fourcc(&box_type))))
+}
 
-    fn clone_box(&self) -> Box<dyn SampleEntry> {
-        Box::new(self.clone())
-    }
+/// Writes a box whose payload is a string without a null terminator.
+fn write_box_string(box_type: &[u8; 4], s: &str, mut w: impl Write) -> io::Result<()> {
+    w.write_all(&(8 + s.len() as u32).to_be_bytes())?;
+    w.write_all(box_type)?;
+    w.write_all(s.as_bytes())?;
+    Ok(())
 }
 
+/// A WebVTT text track (ISO 14496-30). Samples are [`VttSample`]s.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone)]
-pub struct AvcSampleEntry {
-    pub data_reference_index: u16,
-    pub width: u16,
-    pub height: u16,
-    pub horiz_resolution: U16F16,
-    pub vert_resolution: U16F16,
-    pub frame_count: u16,
-    pub depth: u16,
-    pub avcc: AvcConfigurationBox,
+pub struct WvttSampleEntry {
+    pub data_reference_index: u16,
+    pub config: WebVttConfigurationBox,
+    pub label: Option<WebVttSourceLabelBox>,
+    pub unknown: Vec<UnknownBox>,
 }
 
-impl BmffBox for AvcSampleEntry {
-    const TYPE: [u8; 4] = *b"avc1";
+impl BmffBox for WvttSampleEntry {
+    const TYPE: [u8; 4] = *b"wvtt";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + 6 + 2 + 2 + 2 + 4 + 4 + 4 + 2 + 2 + 4 + 4 + 4 + 2 + 32 + 2 + 2 + self.avcc.size()
+        8 + 6
+            + 2
+            + self.config.size()
+            + self.label.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let mut children = Children::new(&self.unknown);
         w.write_all(&[0u8; 6])?;
         w.write_all(&self.data_reference_index.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        w.write_all(&0u16.to_be_bytes())?;
-        w.write_all(&0u32.to_be_bytes())?;
-        w.write_all(&0u32.to_be_bytes())?;
-        w.write_all(&0u32.to_be_bytes())?;
-        w.write_all(&self.width.to_be_bytes())?;
-        w.write_all(&self.height.to_be_bytes())?;
-        w.write_all(&self.horiz_resolution.to_be_bytes())?;
-        w.write_all(&self.vert_resolution.to_be_bytes())?;
-        w.write_all(&0u32.to_be_bytes())?;
-        w.write_all(&self.frame_count.to_be_bytes())?;
-        w.write_all(&[0u8; 32])?;
-        w.write_all(&self.depth.to_be_bytes())?;
-        w.write_all(&(-1i16).to_be_bytes())?; // pre_defined
-        write_to(&self.avcc, &mut w)?;
+        children.write(&self.config, &mut w)?;
+        if let Some(label) = &self.label {
+            children.write(label, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for WvttSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.skip(6)?;
+        let data_reference_index = r.read_u16()?;
+        let mut config = None;
+        let mut label = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"vttC" => config = Some(child.parse()?),
+                b"vlab" => label = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            data_reference_index,
+            config: config.ok_or_else(|| missing_box(b"vttC", b"wvtt"))?,
+            label,
+            unknown,
+        })
+    }
+}
+
+impl Validate for WvttSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
         Ok(())
     }
 }
 
-impl SampleEntry for AvcSampleEntry {
+impl SampleEntry for WvttSampleEntry {
     #[inline]
     fn size(&self) -> u64 {
         <Self as BmffBox>::size(self)
     }
 
-    fn write_to(&self) -> Vec<u8> {
+    fn write_to(&self) -> io::Result<Vec<u8>> {
         let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
-        #[allow(clippy::unwrap_used)] // writing into a `Vec` is infallible
-        write_to(self, &mut buf).unwrap();
-        buf
+        write_to(self, &mut buf)?;
+        Ok(buf)
     }
 
     fn clone_box(&self) -> Box<dyn SampleEntry> {
         Box::new(self.clone())
     }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct AvcConfigurationBox {
-    pub configuration: AvcDecoderConfigurationRecord,
+/// The WebVTT file header, such as `WEBVTT`, and any style or region blocks
+/// that apply to the whole track.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebVttConfigurationBox {
+    pub config: String,
 }
 
-impl BmffBox for AvcConfigurationBox {
-    const TYPE: [u8; 4] = *b"avcC";
+impl BmffBox for WebVttConfigurationBox {
+    const TYPE: [u8; 4] = *b"vttC";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + self.configuration.size()
+        8 + self.config.len() as u64
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        self.configuration.write_to(&mut w)?;
-        Ok(())
+        w.write_all(self.config.as_bytes())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct AvcDecoderConfigurationRecord {
-    pub profile_idc: u8,
-    pub constraint_set_flag: u8,
-    pub level_idc: u8,
-    pub sequence_parameter_set: Vec<u8>,
-    pub picture_parameter_set: Vec<u8>,
+impl ReadBox for WebVttConfigurationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            config: read_box_string(r)?,
+        })
+    }
 }
 
-impl AvcDecoderConfigurationRecord {
+/// Identifies the source of a WebVTT track, so that cue IDs from the same
+/// source can be matched across tracks.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebVttSourceLabelBox {
+    pub source_label: String,
+}
+
+impl BmffBox for WebVttSourceLabelBox {
+    const TYPE: [u8; 4] = *b"vlab";
+
+    #[inline]
     fn size(&self) -> u64 {
-        1 + 1
-            + 1
-            + 1
-            + 1
-            + 1
-            + 2
-            + self.sequence_parameter_set.len() as u64
-            + 1
-            + 2
-            + self.picture_parameter_set.len() as u64
+        8 + self.source_label.len() as u64
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(self.source_label.as_bytes())
     }
 }
 
-impl WriteTo for AvcDecoderConfigurationRecord {
+impl ReadBox for WebVttSourceLabelBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            source_label: read_box_string(r)?,
+        })
+    }
+}
+
+/// A WebVTT sample, which holds the cues that are shown for the sample's
+/// whole duration.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VttSample {
+    /// No cues are shown. Written as a `vtte` box.
+    Empty,
+    Cues(Vec<VttCueBox>),
+}
+
+impl VttSample {
+    #[inline]
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Empty => 8,
+            Self::Cues(cues) => cues.iter().map(BmffBox::size).sum(),
+        }
+    }
+
+    /// Parses the data of a sample. Boxes other than cues, such as comments
+    /// in `vtta` boxes, are skipped.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let header = BoxHeader {
+            box_type: *b"mdat",
+            extended_type: None,
+            offset: 0,
+            size: 0,
+            header_size: 0,
+        };
+        let mut r = BoxReader::from_payload(header, bytes);
+        let mut cues = Vec::new();
+        let mut empty = false;
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"vttc" => cues.push(child.parse()?),
+                b"vtte" => empty = true,
+                _ => (),
+            }
+        }
+        if !cues.is_empty() {
+            Ok(Self::Cues(cues))
+        } else if empty {
+            Ok(Self::Empty)
+        } else {
+            Err(invalid_data("WebVTT sample has no vttc or vtte box"))
+        }
+    }
+}
+
+impl WriteTo for VttSample {
     fn write_to(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&1u8.to_be_bytes())?;
-        w.write_all(&self.profile_idc.to_be_bytes())?;
-        w.write_all(&self.constraint_set_flag.to_be_bytes())?;
-        w.write_all(&self.level_idc.to_be_bytes())?;
-        w.write_all(&(0xfau8 | 0x03u8).to_be_bytes())?;
-        w.write_all(&(0xe0u8 | 0x01u8).to_be_bytes())?;
-        w.write_all(&(self.sequence_parameter_set.len() as u16).to_be_bytes())?;
-        w.write_all(&self.sequence_parameter_set)?;
-        w.write_all(&0x01u8.to_be_bytes())?;
-        w.write_all(&(self.picture_parameter_set.len() as u16).to_be_bytes())?;
-        w.write_all(&self.picture_parameter_set)?;
+        match self {
+            Self::Empty => {
+                w.write_all(&8u32.to_be_bytes())?;
+                w.write_all(b"vtte")?;
+            }
+            Self::Cues(cues) => {
+                for cue in cues {
+                    write_to(cue, &mut w)?;
+                }
+            }
+        }
         Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct SampleToChunkBox {
-    /// `(first_chunk, samples_per_chunk, sample_description_index)`
-    pub entries: Vec<(u32, u32, u32)>,
+/// A WebVTT cue, without its timing, which comes from the sample.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VttCueBox {
+    /// `vsid`
+    pub source_id: Option<u32>,
+    /// `ctim`, the time of the start of the sample, for cues with
+    /// timestamps in their payload
+    pub current_time: Option<String>,
+    /// `iden`
+    pub cue_id: Option<String>,
+    /// `sttg`, such as `line:0 position:20%`
+    pub settings: Option<String>,
+    /// `payl`
+    pub payload: String,
 }
 
-impl BmffBox for SampleToChunkBox {
-    const TYPE: [u8; 4] = *b"stsc";
+impl BmffBox for VttCueBox {
+    const TYPE: [u8; 4] = *b"vttc";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + self.entries.len() as u64 * 4 * 3
+        8 + self.source_id.map_or(0, |_| 12)
+            + [&self.current_time, &self.cue_id, &self.settings]
+                .into_iter()
+                .flatten()
+                .map(|s| 8 + s.len() as u64)
+                .sum::<u64>()
+            + 8
+            + self.payload.len() as u64
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&(self.entries.len() as u32).to_be_bytes())?;
-        for (first_chunk, samples_per_chunk, sample_description_index) in &self.entries {
-            w.write_all(&first_chunk.to_be_bytes())?;
-            w.write_all(&samples_per_chunk.to_be_bytes())?;
-            w.write_all(&sample_description_index.to_be_bytes())?;
+        if let Some(source_id) = self.source_id {
+            w.write_all(&12u32.to_be_bytes())?;
+            w.write_all(b"vsid")?;
+            w.write_all(&source_id.to_be_bytes())?;
         }
+        for (box_type, s) in [
+            (b"ctim", &self.current_time),
+            (b"iden", &self.cue_id),
+            (b"sttg", &self.settings),
+        ] {
+            if let Some(s) = s {
+                write_box_string(box_type, s, &mut w)?;
+            }
+        }
+        write_box_string(b"payl", &self.payload, &mut w)?;
         Ok(())
     }
 }
 
-impl FullBox for SampleToChunkBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct ChunkOffsetBox {
-    pub chunk_offsets: Vec<u32>,
+impl ReadBox for VttCueBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut source_id = None;
+        let mut current_time = None;
+        let mut cue_id = None;
+        let mut settings = None;
+        let mut payload = None;
+        while let Some(mut child) = r.next_child()? {
+            match &child.header().box_type {
+                b"vsid" => source_id = Some(child.read_u32()?),
+                b"ctim" => current_time = Some(read_box_string(&mut child)?),
+                b"iden" => cue_id = Some(read_box_string(&mut child)?),
+                b"sttg" => settings = Some(read_box_string(&mut child)?),
+                b"payl" => payload = Some(read_box_string(&mut child)?),
+                _ => (),
+            }
+        }
+        Ok(Self {
+            source_id,
+            current_time,
+            cue_id,
+            settings,
+            payload: payload.ok_or_else(|| missing_box(b"payl", b"vttc"))?,
+        })
+    }
+}
+
+/// Data for a DRM system to acquire the keys of a protected file (ISO
+/// 23001-7). These can be in the `moov` or in a `moof`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectionSystemSpecificHeaderBox {
+    pub system_id: [u8; 16],
+    /// The keys that `data` applies to. A version 1 box is written if this
+    /// isn't empty.
+    pub key_ids: Vec<[u8; 16]>,
+    pub data: Vec<u8>,
 }
 
-impl BmffBox for ChunkOffsetBox {
-    const TYPE: [u8; 4] = *b"stco";
+impl BmffBox for ProtectionSystemSpecificHeaderBox {
+    const TYPE: [u8; 4] = *b"pssh";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + self.chunk_offsets.len() as u64 * 4
+        let key_ids = if self.key_ids.is_empty() {
+            0
+        } else {
+            4 + self.key_ids.len() as u64 * 16
+        };
+        12 + 16 + key_ids + 4 + self.data.len() as u64
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&(self.chunk_offsets.len() as u32).to_be_bytes())?;
-        for chunk_offset in &self.chunk_offsets {
-            w.write_all(&chunk_offset.to_be_bytes())?;
+        self.validate()?;
+        w.write_all(&self.system_id)?;
+        if !self.key_ids.is_empty() {
+            w.write_all(&(self.key_ids.len() as u32).to_be_bytes())?;
+            for key_id in &self.key_ids {
+                w.write_all(key_id)?;
+            }
         }
+        w.write_all(&(self.data.len() as u32).to_be_bytes())?;
+        w.write_all(&self.data)?;
         Ok(())
     }
 }
 
-impl FullBox for ChunkOffsetBox {
+impl ReadBox for ProtectionSystemSpecificHeaderBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let system_id = r.read_array()?;
+        let mut key_ids = Vec::new();
+        if r.version() > 0 {
+            let kid_count = r.read_u32()?;
+            for _ in 0..kid_count {
+                key_ids.push(r.read_array()?);
+            }
+        }
+        let data_size = r.read_u32()? as usize;
+        let data = r.read_bytes(data_size)?.to_vec();
+        Ok(Self {
+            system_id,
+            key_ids,
+            data,
+        })
+    }
+}
+
+impl Validate for ProtectionSystemSpecificHeaderBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(Self::TYPE, "key_ids", self.key_ids.len() as u64, 32)?;
+        check_bits(Self::TYPE, "data", self.data.len() as u64, 32)?;
+        Ok(())
+    }
+}
+
+impl FullBox for ProtectionSystemSpecificHeaderBox {
     #[inline]
     fn version(&self) -> u8 {
-        0
+        u8::from(!self.key_ids.is_empty())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct EditBox {
-    pub elst: EditListBox,
+/// Describes how the samples of a protected track are encrypted. It is added
+/// to the sample entry, such as an [`EncvSampleEntry`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectionSchemeInfoBox {
+    pub frma: OriginalFormatBox,
+    pub schm: Option<SchemeTypeBox>,
+    pub schi: Option<SchemeInformationBox>,
+    pub unknown: Vec<UnknownBox>,
 }
 
-impl BmffBox for EditBox {
-    const TYPE: [u8; 4] = *b"edts";
+impl BmffBox for ProtectionSchemeInfoBox {
+    const TYPE: [u8; 4] = *b"sinf";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + self.elst.size()
+        8 + self.frma.size()
+            + self.schm.as_ref().map_or(0, BmffBox::size)
+            + self.schi.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.elst, &mut w)?;
+        let mut children = Children::new(&self.unknown);
+        children.write(&self.frma, &mut w)?;
+        if let Some(schm) = &self.schm {
+            children.write_full(schm, &mut w)?;
+        }
+        if let Some(schi) = &self.schi {
+            children.write(schi, &mut w)?;
+        }
+        children.finish(w)
+    }
+}
+
+impl ReadBox for ProtectionSchemeInfoBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut frma = None;
+        let mut schm = None;
+        let mut schi = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"frma" => frma = Some(child.parse()?),
+                b"schm" => schm = Some(child.parse_full()?),
+                b"schi" => schi = Some(child.parse()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self {
+            frma: frma.ok_or_else(|| missing_box(b"frma", b"sinf"))?,
+            schm,
+            schi,
+            unknown,
+        })
+    }
+}
+
+impl Validate for ProtectionSchemeInfoBox {
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(schi) = &self.schi {
+            schi.validate()?;
+        }
         Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct EditListBox {
-    /// `(segment_duration, media_time)`
-    pub entries: Vec<(u64, i64)>,
-    pub media_rate_integer: i16,
-    pub media_rate_fraction: i16,
+/// The type of the sample entry before it was replaced with a protected one,
+/// such as `avc1` for an `encv`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalFormatBox {
+    pub data_format: [u8; 4],
 }
 
-impl BmffBox for EditListBox {
-    const TYPE: [u8; 4] = *b"elst";
+impl BmffBox for OriginalFormatBox {
+    const TYPE: [u8; 4] = *b"frma";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + self.entries.len() as u64 * 8 * 2 + 2 + 2
+        8 + 4
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&(self.entries.len() as u32).to_be_bytes())?;
-        for (segment_duration, media_time) in &self.entries {
-            w.write_all(&segment_duration.to_be_bytes())?;
-            w.write_all(&media_time.to_be_bytes())?;
-        }
-        w.write_all(&self.media_rate_integer.to_be_bytes())?;
-        w.write_all(&self.media_rate_fraction.to_be_bytes())?;
+        w.write_all(&self.data_format)?;
         Ok(())
     }
 }
 
-impl FullBox for EditListBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        1
+impl ReadBox for OriginalFormatBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        Ok(Self {
+            data_format: r.read_array()?,
+        })
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct MovieExtendsBox {
-    pub mehd: Option<MovieExtendsHeaderBox>,
-    pub trex: Vec<TrackExtendsBox>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemeTypeBox {
+    /// `cenc` or `cbcs` for Common Encryption
+    pub scheme_type: [u8; 4],
+    /// `0x00010000` for Common Encryption
+    pub scheme_version: u32,
+    pub scheme_uri: Option<String>,
 }
 
-impl BmffBox for MovieExtendsBox {
-    const TYPE: [u8; 4] = *b"mvex";
+impl BmffBox for SchemeTypeBox {
+    const TYPE: [u8; 4] = *b"schm";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + self.mehd.as_ref().map_or(0, BmffBox::size)
-            + self.trex.iter().map(BmffBox::size).sum::<u64>()
+        12 + 4 + 4 + self.scheme_uri.as_ref().map_or(0, |x| x.len() as u64 + 1)
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        if let Some(mehd) = &self.mehd {
-            write_to_full(mehd, &mut w)?;
-        }
-        for trex in &self.trex {
-            write_to_full(trex, &mut w)?;
+        w.write_all(&self.scheme_type)?;
+        w.write_all(&self.scheme_version.to_be_bytes())?;
+        if let Some(scheme_uri) = &self.scheme_uri {
+            w.write_all(scheme_uri.as_bytes())?;
+            w.write_all(&[0])?;
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct MovieExtendsHeaderBox {
-    pub fragment_duration: Duration,
+impl ReadBox for SchemeTypeBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let scheme_type = r.read_array()?;
+        let scheme_version = r.read_u32()?;
+        let scheme_uri = if r.flags() & 0x01 != 0 {
+            Some(r.read_string()?)
+        } else {
+            None
+        };
+        Ok(Self {
+            scheme_type,
+            scheme_version,
+            scheme_uri,
+        })
+    }
 }
 
-impl BmffBox for MovieExtendsHeaderBox {
-    const TYPE: [u8; 4] = *b"mehd";
+impl FullBox for SchemeTypeBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        [0, 0, u8::from(self.scheme_uri.is_some())]
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemeInformationBox {
+    pub tenc: Option<TrackEncryptionBox>,
+    pub unknown: Vec<UnknownBox>,
+}
+
+impl BmffBox for SchemeInformationBox {
+    const TYPE: [u8; 4] = *b"schi";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + if self.fragment_duration.num_seconds() as u64 > u32::MAX as u64 {
-            8
-        } else {
-            4
-        }
+        8 + self.tenc.as_ref().map_or(0, BmffBox::size)
+            + self.unknown.iter().map(UnknownBox::size).sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        let fragment_duration = self.fragment_duration.num_seconds();
-        if fragment_duration as u64 > u32::MAX as u64 {
-            w.write_all(&fragment_duration.to_be_bytes())?;
-        } else {
-            w.write_all(&(fragment_duration as u32).to_be_bytes())?;
+        let mut children = Children::new(&self.unknown);
+        if let Some(tenc) = &self.tenc {
+            children.write_full(tenc, &mut w)?;
         }
-        Ok(())
+        children.finish(w)
     }
 }
 
-impl FullBox for MovieExtendsHeaderBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        (self.fragment_duration.num_seconds() as u64 > u32::MAX as u64) as u8
+impl ReadBox for SchemeInformationBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let mut tenc = None;
+        let mut unknown = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"tenc" => tenc = Some(child.parse_full()?),
+                _ => unknown.push(r.unknown(child)),
+            }
+        }
+        Ok(Self { tenc, unknown })
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TrackExtendsBox {
-    pub track_id: u32,
-    pub default_sample_description_index: u32,
-    pub default_sample_duration: u32,
-    pub default_sample_size: u32,
-    pub default_sample_flags: DefaultSampleFlags,
+impl Validate for SchemeInformationBox {
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(tenc) = &self.tenc {
+            tenc.validate()?;
+        }
+        Ok(())
+    }
 }
 
-bitflags! {
-    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
-    pub struct DefaultSampleFlags: u32 {
-        const SAMPLE_DEPENDS_ON = 0x0300_0000;
-        const SAMPLE_IS_DEPENDED_ON = 0x00C0_0000;
-        const SAMPLE_HAS_REDUNDANCY = 0x0030_0000;
-        const SAMPLE_PADDING_VALUE = 0x000D_0000;
-    }
+/// The default encryption parameters of a track, which can be overridden per
+/// sample with sample groups.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackEncryptionBox {
+    /// The number of encrypted 16-byte blocks in the pattern of `cbcs`, such
+    /// as 1 for video. A version 1 box is written if this or
+    /// `default_skip_byte_block` isn't 0.
+    pub default_crypt_byte_block: u8,
+    /// The number of unencrypted blocks in the pattern, such as 9 for video.
+    pub default_skip_byte_block: u8,
+    pub default_is_protected: bool,
+    /// 0, 8, or 16. This is 0 if `default_constant_iv` is used.
+    pub default_per_sample_iv_size: u8,
+    pub default_kid: [u8; 16],
+    /// An 8 or 16-byte IV for all samples. This is required for protected
+    /// tracks with a per-sample IV size of 0 and not allowed otherwise.
+    pub default_constant_iv: Option<Vec<u8>>,
 }
 
-impl BmffBox for TrackExtendsBox {
-    const TYPE: [u8; 4] = *b"trex";
+impl BmffBox for TrackEncryptionBox {
+    const TYPE: [u8; 4] = *b"tenc";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4 + 4 + 4 + 4 + 4
+        12 + 1
+            + 1
+            + 1
+            + 1
+            + 16
+            + self
+                .default_constant_iv
+                .as_ref()
+                .map_or(0, |x| 1 + x.len() as u64)
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&self.track_id.to_be_bytes())?;
-        w.write_all(&self.default_sample_description_index.to_be_bytes())?;
-        w.write_all(&self.default_sample_duration.to_be_bytes())?;
-        w.write_all(&self.default_sample_size.to_be_bytes())?;
-        w.write_all(&self.default_sample_flags.bits().to_be_bytes())?;
+        self.validate()?;
+        w.write_all(&[0])?;
+        w.write_all(&[self.default_crypt_byte_block << 4 | self.default_skip_byte_block])?;
+        w.write_all(&[u8::from(self.default_is_protected)])?;
+        w.write_all(&[self.default_per_sample_iv_size])?;
+        w.write_all(&self.default_kid)?;
+        if let Some(iv) = &self.default_constant_iv {
+            w.write_all(&[iv.len() as u8])?;
+            w.write_all(iv)?;
+        }
         Ok(())
     }
 }
 
-impl FullBox for TrackExtendsBox {
-    #[inline]
-    fn version(&self) -> u8 {
-        0
+impl ReadBox for TrackEncryptionBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        r.skip(1)?;
+        let pattern = r.read_u8()?;
+        let (default_crypt_byte_block, default_skip_byte_block) = if r.version() > 0 {
+            (pattern >> 4, pattern & 0x0f)
+        } else {
+            (0, 0)
+        };
+        let default_is_protected = r.read_u8()? != 0;
+        let default_per_sample_iv_size = r.read_u8()?;
+        let default_kid = r.read_array()?;
+        let default_constant_iv = if default_is_protected && default_per_sample_iv_size == 0 {
+            let len = r.read_u8()? as usize;
+            Some(r.read_bytes(len)?.to_vec())
+        } else {
+            None
+        };
+        Ok(Self {
+            default_crypt_byte_block,
+            default_skip_byte_block,
+            default_is_protected,
+            default_per_sample_iv_size,
+            default_kid,
+            default_constant_iv,
+        })
+    }
+}
+
+impl Validate for TrackEncryptionBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(
+            Self::TYPE,
+            "default_crypt_byte_block",
+            self.default_crypt_byte_block.into(),
+            4,
+        )?;
+        check_bits(
+            Self::TYPE,
+            "default_skip_byte_block",
+            self.default_skip_byte_block.into(),
+            4,
+        )?;
+        if ![0, 8, 16].contains(&self.default_per_sample_iv_size) {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "default_per_sample_iv_size must be 0, 8, or 16",
+            ));
+        }
+        let needs_constant_iv = self.default_is_protected && self.default_per_sample_iv_size == 0;
+        match &self.default_constant_iv {
+            Some(_) if !needs_constant_iv => {
+                return Err(Error::Invalid(
+                    Self::TYPE,
+                    "a constant IV is only allowed for protected tracks without per-sample IVs",
+                ))
+            }
+            Some(iv) if iv.len() != 8 && iv.len() != 16 => {
+                return Err(Error::Invalid(
+                    Self::TYPE,
+                    "default_constant_iv must be 8 or 16 bytes",
+                ))
+            }
+            None if needs_constant_iv => {
+                return Err(Error::Invalid(
+                    Self::TYPE,
+                    "protected tracks without per-sample IVs need a constant IV",
+                ))
+            }
+            _ => (),
+        }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct MediaDataBox {
-    pub headers: Option<Vec<u8>>,
-    pub data: Arc<Vec<u8>>,
-}
-
-impl BmffBox for MediaDataBox {
-    const TYPE: [u8; 4] = *b"mdat";
-
+impl FullBox for TrackEncryptionBox {
     #[inline]
-    fn size(&self) -> u64 {
-        8 + self.headers.as_ref().map_or(0, Vec::len) as u64 + self.data.len() as u64
+    fn version(&self) -> u8 {
+        u8::from(self.default_crypt_byte_block != 0 || self.default_skip_byte_block != 0)
     }
+}
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        if let Some(headers) = &self.headers {
-            w.write_all(headers)?;
-        }
-        w.write_all(&self.data)?;
-        Ok(())
-    }
+/// The IVs and subsample maps of the samples in a track fragment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SampleEncryptionBox {
+    pub samples: Vec<SampleEncryptionEntry>,
 }
 
-#[derive(Debug, Clone)]
-pub struct MovieFragmentBox {
-    pub mfhd: MovieFragmentHeaderBox,
-    pub traf: Vec<TrackFragmentBox>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SampleEncryptionEntry {
+    /// The per-sample IV, which is empty for tracks with a constant IV
+    pub iv: Vec<u8>,
+    /// Empty if the whole sample is encrypted
+    pub subsamples: Vec<Subsample>,
 }
 
-impl BmffBox for MovieFragmentBox {
-    const TYPE: [u8; 4] = *b"moof";
+/// A range of a sample with some unencrypted bytes followed by some
+/// encrypted ones.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Subsample {
+    pub clear_bytes: u16,
+    pub protected_bytes: u32,
+}
 
-    #[inline]
-    fn size(&self) -> u64 {
-        8 + self.mfhd.size() + self.traf.iter().map(BmffBox::size).sum::<u64>()
+impl SampleEncryptionBox {
+    /// Whether the box has subsample maps, which are written for every sample
+    /// if any sample has one.
+    fn use_subsamples(&self) -> bool {
+        self.samples.iter().any(|x| !x.subsamples.is_empty())
     }
 
-    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.mfhd, &mut w)?;
-        for traf in &self.traf {
-            write_to(traf, &mut w)?;
+    fn sample_info_size(&self, sample: &SampleEncryptionEntry) -> u64 {
+        let subsamples = if self.use_subsamples() {
+            2 + sample.subsamples.len() as u64 * 6
+        } else {
+            0
+        };
+        sample.iv.len() as u64 + subsamples
+    }
+
+    /// Returns a `saiz` box with the size of the data for each sample.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data of a sample is larger than 255 bytes,
+    /// which is the largest size a `saiz` can hold. This happens with more
+    /// than 40 subsamples and 8-byte IVs.
+    pub fn saiz(&self) -> Result<SampleAuxiliaryInformationSizesBox, Error> {
+        let sizes = self
+            .samples
+            .iter()
+            .map(|x| {
+                u8::try_from(self.sample_info_size(x))
+                    .map_err(|_| Error::Overflow(*b"saiz", "sample_info_size"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let same_size = sizes
+            .first()
+            .filter(|&&x| sizes.iter().all(|&y| y == x))
+            .and_then(|&x| NonZeroU8::new(x));
+        let sample_info_size = match same_size {
+            Some(size) => SampleInfoSize::Same {
+                size,
+                sample_count: sizes.len() as u32,
+            },
+            None => SampleInfoSize::Different(sizes),
+        };
+        Ok(SampleAuxiliaryInformationSizesBox {
+            aux_info_type: None,
+            sample_info_size,
+        })
+    }
+
+    /// Reads a `senc` box whose IVs are `per_sample_iv_size` bytes. The box
+    /// doesn't record the size of its IVs, so this is the
+    /// `default_per_sample_iv_size` of the track's `tenc`, or 0 if the track
+    /// uses a constant IV.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the box isn't a `senc` or doesn't match the IV
+    /// size.
+    pub fn read_with_iv_size(mut r: BoxReader<'_>, per_sample_iv_size: u8) -> io::Result<Self> {
+        r.check_type::<Self>()?;
+        let version_flags = r.read_u32()?;
+        let use_subsamples = version_flags & 0x02 != 0;
+        let sample_count = r.read_u32()?;
+        let samples = Self::read_samples(
+            &mut r,
+            sample_count,
+            per_sample_iv_size as usize,
+            use_subsamples,
+        )?;
+        if r.remaining() != 0 {
+            return Err(invalid_data("senc box doesn't match the IV size"));
         }
-        Ok(())
+        Ok(Self { samples })
+    }
+
+    fn read_samples(
+        r: &mut BoxReader<'_>,
+        sample_count: u32,
+        iv_size: usize,
+        use_subsamples: bool,
+    ) -> io::Result<Vec<SampleEncryptionEntry>> {
+        let mut samples = Vec::new();
+        for _ in 0..sample_count {
+            let iv = r.read_bytes(iv_size)?.to_vec();
+            let mut subsamples = Vec::new();
+            if use_subsamples {
+                let subsample_count = r.read_u16()?;
+                for _ in 0..subsample_count {
+                    subsamples.push(Subsample {
+                        clear_bytes: r.read_u16()?,
+                        protected_bytes: r.read_u32()?,
+                    });
+                }
+            }
+            samples.push(SampleEncryptionEntry { iv, subsamples });
+        }
+        Ok(samples)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct MovieFragmentHeaderBox {
-    pub sequence_number: u32,
-}
-
-impl BmffBox for MovieFragmentHeaderBox {
-    const TYPE: [u8; 4] = *b"mfhd";
+impl BmffBox for SampleEncryptionBox {
+    const TYPE: [u8; 4] = *b"senc";
 
     #[inline]
     fn size(&self) -> u64 {
         12 + 4
+            + self
+                .samples
+                .iter()
+                .map(|x| self.sample_info_size(x))
+                .sum::<u64>()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&self.sequence_number.to_be_bytes())?;
+        self.validate()?;
+        let use_subsamples = self.use_subsamples();
+        w.write_all(&(self.samples.len() as u32).to_be_bytes())?;
+        for sample in &self.samples {
+            w.write_all(&sample.iv)?;
+            if use_subsamples {
+                w.write_all(&(sample.subsamples.len() as u16).to_be_bytes())?;
+                for subsample in &sample.subsamples {
+                    w.write_all(&subsample.clear_bytes.to_be_bytes())?;
+                    w.write_all(&subsample.protected_bytes.to_be_bytes())?;
+                }
+            }
+        }
         Ok(())
     }
 }
 
-impl FullBox for MovieFragmentHeaderBox {
+impl Validate for SampleEncryptionBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(Self::TYPE, "samples", self.samples.len() as u64, 32)?;
+        let iv_size = self.samples.first().map_or(0, |x| x.iv.len());
+        if ![0, 8, 16].contains(&iv_size) || self.samples.iter().any(|x| x.iv.len() != iv_size) {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "IVs must all be 0, 8, or 16 bytes",
+            ));
+        }
+        for sample in &self.samples {
+            check_bits(Self::TYPE, "subsamples", sample.subsamples.len() as u64, 16)?;
+            // the size of each sample's data must fit in a `saiz`
+            check_bits(
+                *b"saiz",
+                "sample_info_size",
+                self.sample_info_size(sample),
+                8,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FullBox for SampleEncryptionBox {
     #[inline]
     fn version(&self) -> u8 {
         0
     }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        [0, 0, if self.use_subsamples() { 0x02 } else { 0 }]
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct TrackFragmentBox {
-    pub tfhd: TrackFragmentHeaderBox,
-    pub trun: Vec<TrackFragmentRunBox>,
-    // pub sdtp: (),
-    // pub sbgp: (),
-    // pub subs: (),
+/// The sizes of the auxiliary information of each sample, such as the
+/// entries of a [`SampleEncryptionBox`].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleAuxiliaryInformationSizesBox {
+    /// The `aux_info_type` and `aux_info_type_parameter`. These can be left
+    /// out for the protection scheme of an encrypted track.
+    pub aux_info_type: Option<([u8; 4], u32)>,
+    pub sample_info_size: SampleInfoSize,
 }
 
-impl BmffBox for TrackFragmentBox {
-    const TYPE: [u8; 4] = *b"traf";
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleInfoSize {
+    Same { size: NonZeroU8, sample_count: u32 },
+    Different(Vec<u8>),
+}
+
+impl BmffBox for SampleAuxiliaryInformationSizesBox {
+    const TYPE: [u8; 4] = *b"saiz";
 
     #[inline]
     fn size(&self) -> u64 {
-        8 + self.tfhd.size() + self.trun.iter().map(BmffBox::size).sum::<u64>()
+        let sizes = match &self.sample_info_size {
+            SampleInfoSize::Same { .. } => 0,
+            SampleInfoSize::Different(x) => x.len() as u64,
+        };
+        12 + self.aux_info_type.map_or(0, |_| 8) + 1 + 4 + sizes
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        write_to_full(&self.tfhd, &mut w)?;
-        for trun in &self.trun {
-            write_to_full(trun, &mut w)?;
+        self.validate()?;
+        if let Some((aux_info_type, parameter)) = self.aux_info_type {
+            w.write_all(&aux_info_type)?;
+            w.write_all(&parameter.to_be_bytes())?;
+        }
+        match &self.sample_info_size {
+            SampleInfoSize::Same { size, sample_count } => {
+                w.write_all(&[size.get()])?;
+                w.write_all(&sample_count.to_be_bytes())?;
+            }
+            SampleInfoSize::Different(sizes) => {
+                w.write_all(&[0])?;
+                w.write_all(&(sizes.len() as u32).to_be_bytes())?;
+                w.write_all(sizes)?;
+            }
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TrackFragmentHeaderBox {
-    pub track_id: u32,
-    pub base_data_offset: Option<u64>,
-    pub sample_description_index: Option<u32>,
-    pub default_sample_duration: Option<u32>,
-    pub default_sample_size: Option<u32>,
-    pub default_sample_flags: Option<DefaultSampleFlags>,
-    pub default_base_is_moof: bool,
+impl ReadBox for SampleAuxiliaryInformationSizesBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let aux_info_type = if r.flags() & 0x01 != 0 {
+            Some((r.read_array()?, r.read_u32()?))
+        } else {
+            None
+        };
+        let default_sample_info_size = r.read_u8()?;
+        let sample_count = r.read_u32()?;
+        let sample_info_size = match NonZeroU8::new(default_sample_info_size) {
+            Some(size) => SampleInfoSize::Same { size, sample_count },
+            None => SampleInfoSize::Different(r.read_bytes(sample_count as usize)?.to_vec()),
+        };
+        Ok(Self {
+            aux_info_type,
+            sample_info_size,
+        })
+    }
+}
+
+impl Validate for SampleAuxiliaryInformationSizesBox {
+    fn validate(&self) -> Result<(), Error> {
+        if let SampleInfoSize::Different(sizes) = &self.sample_info_size {
+            check_bits(Self::TYPE, "sample_info_size", sizes.len() as u64, 32)?;
+        }
+        Ok(())
+    }
 }
 
-bitflags! {
-    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
-    struct TrackFragmentHeaderFlags: u32 {
-        const BASE_DATA_OFFSET_PRESENT = 0x00_0001;
-        const SAMPLE_DESCRIPTION_INDEX_PRESENT = 0x00_0002;
-        const DEFAULT_SAMPLE_DURATION_PRESENT = 0x00_0008;
-        const DEFAULT_SAMPLE_SIZE_PRESENT = 0x00_0010;
-        const DEFAULT_SAMPLE_FLAGS_PRESENT = 0x00_0020;
-        const DURATION_IS_EMPTY = 0x01_0000;
-        const DEFAULT_BASE_IS_MOOF = 0x02_0000;
+impl FullBox for SampleAuxiliaryInformationSizesBox {
+    #[inline]
+    fn version(&self) -> u8 {
+        0
+    }
+
+    #[inline]
+    fn flags(&self) -> [u8; 3] {
+        [0, 0, u8::from(self.aux_info_type.is_some())]
     }
 }
 
-impl BmffBox for TrackFragmentHeaderBox {
-    const TYPE: [u8; 4] = *b"tfhd";
+/// The offsets of the auxiliary information of the samples, with one entry
+/// per chunk or track run, or a single entry if it is contiguous.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleAuxiliaryInformationOffsetsBox {
+    /// The same as [`SampleAuxiliaryInformationSizesBox::aux_info_type`]
+    pub aux_info_type: Option<([u8; 4], u32)>,
+    /// Relative to the start of the file in a `stbl`, or the same base as the
+    /// `data_offset` of a `trun` in a `traf`. A version 1 box is written if an
+    /// offset doesn't fit in 32 bits.
+    pub offsets: Vec<u64>,
+}
+
+impl BmffBox for SampleAuxiliaryInformationOffsetsBox {
+    const TYPE: [u8; 4] = *b"saio";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4
-            + self.base_data_offset.as_ref().map_or(0, |_| 8)
-            + self.sample_description_index.as_ref().map_or(0, |_| 4)
-            + self.default_sample_duration.as_ref().map_or(0, |_| 4)
-            + self.default_sample_size.as_ref().map_or(0, |_| 4)
-            + self.default_sample_flags.as_ref().map_or(0, |_| 4)
+        let offset_size = if self.version() == 0 { 4 } else { 8 };
+        12 + self.aux_info_type.map_or(0, |_| 8) + 4 + self.offsets.len() as u64 * offset_size
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        w.write_all(&self.track_id.to_be_bytes())?;
-        if let Some(base_data_offset) = self.base_data_offset {
-            w.write_all(&base_data_offset.to_be_bytes())?;
-        }
-        if let Some(sample_description_index) = self.sample_description_index {
-            w.write_all(&sample_description_index.to_be_bytes())?;
-        }
-        if let Some(default_sample_duration) = self.default_sample_duration {
-            w.write_all(&default_sample_duration.to_be_bytes())?;
-        }
-        if let Some(default_sample_size) = self.default_sample_size {
-            w.write_all(&default_sample_size.to_be_bytes())?;
+        self.validate()?;
+        if let Some((aux_info_type, parameter)) = self.aux_info_type {
+            w.write_all(&aux_info_type)?;
+            w.write_all(&parameter.to_be_bytes())?;
         }
-        if let Some(default_sample_flags) = self.default_sample_flags {
-            w.write_all(&default_sample_flags.bits().to_be_bytes())?;
+        w.write_all(&(self.offsets.len() as u32).to_be_bytes())?;
+        let version = self.version();
+        for &offset in &self.offsets {
+            if version == 0 {
+                w.write_all(&(offset as u32).to_be_bytes())?;
+            } else {
+                w.write_all(&offset.to_be_bytes())?;
+            }
         }
         Ok(())
     }
 }
 
-impl FullBox for TrackFragmentHeaderBox {
+impl ReadBox for SampleAuxiliaryInformationOffsetsBox {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let aux_info_type = if r.flags() & 0x01 != 0 {
+            Some((r.read_array()?, r.read_u32()?))
+        } else {
+            None
+        };
+        let entry_count = r.read_u32()?;
+        let mut offsets = Vec::new();
+        for _ in 0..entry_count {
+            offsets.push(if r.version() == 0 {
+                r.read_u32()?.into()
+            } else {
+                r.read_u64()?
+            });
+        }
+        Ok(Self {
+            aux_info_type,
+            offsets,
+        })
+    }
+}
+
+impl Validate for SampleAuxiliaryInformationOffsetsBox {
+    fn validate(&self) -> Result<(), Error> {
+        check_bits(Self::TYPE, "offsets", self.offsets.len() as u64, 32)
+    }
+}
+
+impl FullBox for SampleAuxiliaryInformationOffsetsBox {
     #[inline]
     fn version(&self) -> u8 {
-        0
+        u8::from(self.offsets.iter().any(|&x| x > u32::MAX as u64))
     }
 
     #[inline]
     fn flags(&self) -> [u8; 3] {
-        let mut flags = TrackFragmentHeaderFlags::empty();
-        if self.base_data_offset.is_some() {
-            flags |= TrackFragmentHeaderFlags::BASE_DATA_OFFSET_PRESENT;
-        }
-        if self.sample_description_index.is_some() {
-            flags |= TrackFragmentHeaderFlags::SAMPLE_DESCRIPTION_INDEX_PRESENT;
-        }
-        if self.default_sample_duration.is_some() {
-            flags |= TrackFragmentHeaderFlags::DEFAULT_SAMPLE_DURATION_PRESENT;
-        }
-        if self.default_sample_size.is_some() {
-            flags |= TrackFragmentHeaderFlags::DEFAULT_SAMPLE_SIZE_PRESENT;
-        }
-        if self.default_sample_flags.is_some() {
-            flags |= TrackFragmentHeaderFlags::DEFAULT_SAMPLE_FLAGS_PRESENT;
-        }
-        if self.default_base_is_moof {
-            flags |= TrackFragmentHeaderFlags::DEFAULT_BASE_IS_MOOF;
-        }
-        let flags = flags.bits().to_be_bytes();
-        [flags[1], flags[2], flags[3]]
+        [0, 0, u8::from(self.aux_info_type.is_some())]
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TrackFragmentRunBox {
-    pub data_offset: Option<i32>,
-    pub first_sample_flags: Option<u32>,
-    pub sample_durations: Option<Vec<u32>>,
-    pub sample_sizes: Option<Vec<u32>>,
-    pub sample_flags: Option<Vec<u32>>,
-    pub sample_composition_time_offsets: Option<Vec<u32>>,
-}
-
-bitflags! {
-    #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
-    struct TrackFragmentRunFlags: u32 {
-        const DATA_OFFSET_PRESENT = 0x00_0001;
-        const FIRST_SAMPLE_FLAGS_PRESENT = 0x00_0004;
-        const SAMPLE_DURATION_PRESENT = 0x00_0100;
-        const SAMPLE_SIZE_PRESENT = 0x00_0200;
-        const SAMPLE_FLAGS_PRESENT = 0x00_0400;
-        const SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT = 0x00_0800;
-    }
+/// A protected video sample entry. This wraps the original sample entry,
+/// whose type is kept in the [`OriginalFormatBox`] of the `sinf`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug)]
+pub struct EncvSampleEntry {
+    #[cfg_attr(feature = "serde", serde(with = "serde_impls::sample_entry"))]
+    pub entry: Box<dyn SampleEntry>,
+    pub sinf: ProtectionSchemeInfoBox,
 }
 
-impl TrackFragmentRunBox {
-    fn len(&self) -> Option<usize> {
-        let mut len = None;
-        if let Some(sample_durations) = &self.sample_durations {
-            len = Some(sample_durations.len());
-        }
-        if let Some(sample_sizes) = &self.sample_sizes {
-            let l = sample_sizes.len();
-            if let Some(len) = len {
-                if len != l {
-                    panic!();
-                }
-            } else {
-                len = Some(l);
-            }
-        }
-        if let Some(sample_flags) = &self.sample_flags {
-            let l = sample_flags.len();
-            if let Some(len) = len {
-                if len != l {
-                    panic!();
-                }
-            } else {
-                len = Some(l);
-            }
-        }
-        if let Some(sample_composition_time_offsets) = &self.sample_composition_time_offsets {
-            let l = sample_composition_time_offsets.len();
-            if let Some(len) = len {
-                if len != l {
-                    panic!();
-                }
-            } else {
-                len = Some(l);
-            }
+impl Clone for EncvSampleEntry {
+    fn clone(&self) -> Self {
+        Self {
+            entry: self.entry.clone_box(),
+            sinf: self.sinf.clone(),
         }
-        len
     }
 }
 
-impl BmffBox for TrackFragmentRunBox {
-    const TYPE: [u8; 4] = *b"trun";
+impl BmffBox for EncvSampleEntry {
+    const TYPE: [u8; 4] = *b"encv";
 
     #[inline]
     fn size(&self) -> u64 {
-        12 + 4
-            + self.data_offset.as_ref().map_or(0, |_| 4)
-            + self.first_sample_flags.as_ref().map_or(0, |_| 4)
-            + self.len().unwrap_or(0) as u64
-                * 4
-                * (self.sample_durations.is_some() as u64
-                    + self.sample_sizes.is_some() as u64
-                    + self.sample_flags.is_some() as u64
-                    + self.sample_composition_time_offsets.is_some() as u64)
+        self.entry.size() + self.sinf.size()
     }
 
     fn write_box(&self, mut w: impl Write) -> io::Result<()> {
-        let len = self.len().unwrap_or(0);
-        w.write_all(&(len as u32).to_be_bytes())?;
-        if let Some(data_offset) = self.data_offset {
-            w.write_all(&data_offset.to_be_bytes())?;
-        }
-        if let Some(first_sample_flags) = self.first_sample_flags {
-            w.write_all(&first_sample_flags.to_be_bytes())?;
-        }
-        for i in 0..len {
-            if let Some(sample_durations) = &self.sample_durations {
-                w.write_all(&sample_durations[i].to_be_bytes())?;
-            }
-            if let Some(sample_sizes) = &self.sample_sizes {
-                w.write_all(&sample_sizes[i].to_be_bytes())?;
-            }
-            if let Some(sample_flags) = &self.sample_flags {
-                w.write_all(&sample_flags[i].to_be_bytes())?;
-            }
-            if let Some(sample_composition_time_offsets) = &self.sample_composition_time_offsets {
-                w.write_all(&sample_composition_time_offsets[i].to_be_bytes())?;
+        self.validate()?;
+        let entry = self.entry.write_to()?;
+        let header = read_header(entry.as_slice())?;
+        w.write_all(&entry[header.header_size as usize..])?;
+        write_to(&self.sinf, &mut w)?;
+        Ok(())
+    }
+}
+
+impl ReadBox for EncvSampleEntry {
+    fn read_box(r: &mut BoxReader<'_>) -> io::Result<Self> {
+        let fields = r.read_bytes(VisualSampleEntryHeader::SIZE as usize)?;
+        let mut sinf = None;
+        let mut children = Vec::new();
+        while let Some(child) = r.next_child()? {
+            match &child.header().box_type {
+                b"sinf" if sinf.is_none() => sinf = Some(child.parse::<ProtectionSchemeInfoBox>()?),
+                _ => WriteTo::write_to(&RawBox::from_reader(child), &mut children)?,
             }
         }
+        let sinf = sinf.ok_or_else(|| missing_box(b"sinf", b"encv"))?;
+
+        // rebuild the original sample entry without the `sinf`
+        let size = 8 + fields.len() + children.len();
+        let mut entry = Vec::with_capacity(size);
+        entry.extend_from_slice(&(size as u32).to_be_bytes());
+        entry.extend_from_slice(&sinf.frma.data_format);
+        entry.extend_from_slice(fields);
+        entry.extend_from_slice(&children);
+        let entry = read_sample_entry(BoxReader::new(&entry)?)?;
+        Ok(Self { entry, sinf })
+    }
+}
+
+impl Validate for EncvSampleEntry {
+    fn validate(&self) -> Result<(), Error> {
+        self.entry.validate()?;
+        self.sinf.validate()?;
+        let entry = self.entry.write_to().ok();
+        if entry.as_ref().and_then(|x| x.get(4..8)) != Some(&self.sinf.frma.data_format[..]) {
+            return Err(Error::Invalid(
+                Self::TYPE,
+                "frma doesn't match the type of the sample entry",
+            ));
+        }
         Ok(())
     }
 }
 
-impl FullBox for TrackFragmentRunBox {
+impl SampleEntry for EncvSampleEntry {
     #[inline]
-    fn version(&self) -> u8 {
-        0
+    fn size(&self) -> u64 {
+        <Self as BmffBox>::size(self)
     }
 
-    #[inline]
-    fn flags(&self) -> [u8; 3] {
-        let mut flags = TrackFragmentRunFlags::empty();
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(<Self as BmffBox>::size(self) as usize);
+        write_to(self, &mut buf)?;
+        Ok(buf)
+    }
 
-        flags.set(
-            TrackFragmentRunFlags::DATA_OFFSET_PRESENT,
-            self.data_offset.is_some(),
-        );
-        flags.set(
-            TrackFragmentRunFlags::FIRST_SAMPLE_FLAGS_PRESENT,
-            self.first_sample_flags.is_some(),
-        );
-        flags.set(
-            TrackFragmentRunFlags::SAMPLE_DURATION_PRESENT,
-            self.sample_durations.is_some(),
-        );
-        flags.set(
-            TrackFragmentRunFlags::SAMPLE_SIZE_PRESENT,
-            self.sample_sizes.is_some(),
-        );
-        flags.set(
-            TrackFragmentRunFlags::SAMPLE_FLAGS_PRESENT,
-            self.sample_flags.is_some(),
-        );
-        flags.set(
-            TrackFragmentRunFlags::SAMPLE_COMPOSITION_TIME_OFFSETS_PRESENT,
-            self.sample_composition_time_offsets.is_some(),
-        );
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
 
-        let flags = flags.bits().to_be_bytes();
-        [flags[1], flags[2], flags[3]]
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
     }
 }
@@ -0,0 +1,225 @@
+use crate::*;
+
+/// Writes a non-fragmented MP4 file.
+///
+/// Samples are written to the sink as they come, inside an `mdat` with a
+/// 64-bit size, and only the sample tables are kept in memory.
+/// [`finish`](Self::finish) fills in the sample tables and durations of the
+/// template `moov`, then moves the `mdat` up to make room for the `moov`
+/// before it, so the file can be played before it's fully downloaded.
+#[derive(Debug)]
+pub struct ProgressiveWriter<W> {
+    w: W,
+    moov: MovieBox,
+    tracks: Vec<TrackSamples>,
+    /// The position of the `mdat` header in the sink
+    mdat_offset: u64,
+    /// The number of bytes written to the `mdat` so far
+    data_len: u64,
+    /// The index of the track the previous sample was written to. Consecutive
+    /// samples from the same track are put in the same chunk.
+    last_track: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrackSamples {
+    durations: Vec<u32>,
+    composition_offsets: Vec<i64>,
+    sizes: Vec<u32>,
+    sync_samples: Vec<u32>,
+    /// `(offset in the sink before the mdat is moved, sample count)`
+    chunks: Vec<(u64, u32)>,
+}
+
+/// The size of an `mdat` header with a 64-bit size
+const MDAT_HEADER_SIZE: u64 = 16;
+
+/// How much of the `mdat` is moved at a time by `finish`
+const MOVE_BUFFER_SIZE: u64 = 64 * 1024;
+
+impl<W: Read + Write + Seek> ProgressiveWriter<W> {
+    /// Creates a writer from a `moov` template, and writes the `ftyp` and the
+    /// header of the `mdat`. The sample tables and durations of the `moov`
+    /// are overwritten, and any `mvex` is removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to `w` fails.
+    pub fn new(ftyp: FileTypeBox, moov: MovieBox, mut w: W) -> io::Result<Self> {
+        write_to(&ftyp, &mut w)?;
+        let mdat_offset = w.stream_position()?;
+        // the size is filled in by `finish`
+        w.write_all(&1u32.to_be_bytes())?;
+        w.write_all(&MediaDataBox::TYPE)?;
+        w.write_all(&0u64.to_be_bytes())?;
+        Ok(Self {
+            w,
+            tracks: vec![TrackSamples::default(); moov.trak.len()],
+            moov,
+            mdat_offset,
+            data_len: 0,
+            last_track: None,
+        })
+    }
+
+    /// Adds a sample to the track with ID `track_id`. `duration` and
+    /// `composition_offset` are in the track's media timescale.
+    pub fn write_sample(
+        &mut self,
+        track_id: u32,
+        data: &[u8],
+        duration: u32,
+        composition_offset: i64,
+        is_sync: bool,
+    ) -> io::Result<()> {
+        let index = self
+            .moov
+            .trak
+            .iter()
+            .position(|trak| trak.tkhd.track_id == track_id)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no track with ID {track_id}"),
+                )
+            })?;
+        let size =
+            u32::try_from(data.len()).map_err(|_| Error::Overflow(*b"stsz", "entry_size"))?;
+        self.w.write_all(data)?;
+        let offset = self.mdat_offset + MDAT_HEADER_SIZE + self.data_len;
+        self.data_len += u64::from(size);
+        let track = &mut self.tracks[index];
+        match track.chunks.last_mut() {
+            Some((_, sample_count)) if self.last_track == Some(index) => *sample_count += 1,
+            _ => track.chunks.push((offset, 1)),
+        }
+        track.durations.push(duration);
+        track.composition_offsets.push(composition_offset);
+        track.sizes.push(size);
+        if is_sync {
+            track.sync_samples.push(track.sizes.len() as u32);
+        }
+        self.last_track = Some(index);
+        Ok(())
+    }
+
+    /// Fills in the size of the `mdat`, moves it to make room for the `moov`,
+    /// writes the `moov` in front of it, and returns the sink.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `moov` is invalid or reading from or writing to
+    /// the sink fails.
+    pub fn finish(self) -> io::Result<W> {
+        let Self {
+            mut w,
+            mut moov,
+            tracks,
+            mdat_offset,
+            data_len,
+            ..
+        } = self;
+
+        let mut movie_duration = Duration::zero();
+        for (trak, samples) in moov.trak.iter_mut().zip(&tracks) {
+            // durations are summed in ticks, and only converted once
+            let ticks = samples.durations.iter().map(|&x| u64::from(x)).sum();
+            let duration = ticks_to_duration(ticks, trak.mdia.mdhd.timescale);
+            movie_duration = movie_duration.max(duration);
+            trak.tkhd.duration = Some(duration);
+            trak.mdia.mdhd.duration = Some(duration);
+
+            let stbl = &mut trak.mdia.minf.stbl;
+            stbl.stts = TimeToSampleBox {
+                samples: run_lengths(&samples.durations),
+            };
+            stbl.ctts = samples
+                .composition_offsets
+                .iter()
+                .any(|&x| x != 0)
+                .then(|| CompositionOffsetBox {
+                    version: composition_offsets_version(
+                        samples.composition_offsets.iter().copied(),
+                    ),
+                    samples: run_lengths(&samples.composition_offsets),
+                });
+            let mut entries = Vec::new();
+            let mut prev = None;
+            for (i, &(_, sample_count)) in samples.chunks.iter().enumerate() {
+                if prev != Some(sample_count) {
+                    entries.push((i as u32 + 1, sample_count, 1));
+                    prev = Some(sample_count);
+                }
+            }
+            stbl.stsc = SampleToChunkBox { entries };
+            stbl.stsz = SampleSizeBox {
+                sample_size: match samples.sizes.first().and_then(|&x| NonZeroU32::new(x)) {
+                    Some(size) if samples.sizes.iter().all(|&x| x == size.get()) => {
+                        SampleSize::Same {
+                            size,
+                            sample_count: samples.sizes.len() as u32,
+                        }
+                    }
+                    _ => SampleSize::Different(samples.sizes.clone()),
+                },
+            };
+            stbl.stss =
+                (samples.sync_samples.len() != samples.sizes.len()).then(|| SyncSampleBox {
+                    sample_numbers: samples.sync_samples.clone(),
+                });
+        }
+        moov.mvhd.duration = Some(movie_duration);
+        moov.mvex = None;
+
+        // The chunk offsets move by the size of the `moov`, which depends on
+        // whether they fit in an `stco` box. Offsets only ever grow, so this
+        // settles after at most a few passes.
+        let mut shift = 0;
+        loop {
+            for (trak, samples) in moov.trak.iter_mut().zip(&tracks) {
+                trak.mdia.minf.stbl.chunk_offset = ChunkOffset::from_offsets(
+                    samples.chunks.iter().map(|&(x, _)| x + shift).collect(),
+                );
+            }
+            if moov.size() == shift {
+                break;
+            }
+            shift = moov.size();
+        }
+        moov.validate()?;
+
+        w.seek(io::SeekFrom::Start(mdat_offset + 8))?;
+        w.write_all(&(MDAT_HEADER_SIZE + data_len).to_be_bytes())?;
+
+        // move the mdat from the end, so nothing is overwritten before it's read
+        let mut buf = vec![0; MOVE_BUFFER_SIZE as usize];
+        let mut end = mdat_offset + MDAT_HEADER_SIZE + data_len;
+        while end > mdat_offset {
+            let len = (end - mdat_offset).min(MOVE_BUFFER_SIZE);
+            let start = end - len;
+            let buf = &mut buf[..len as usize];
+            w.seek(io::SeekFrom::Start(start))?;
+            w.read_exact(buf)?;
+            w.seek(io::SeekFrom::Start(start + shift))?;
+            w.write_all(buf)?;
+            end = start;
+        }
+
+        w.seek(io::SeekFrom::Start(mdat_offset))?;
+        write_to(&moov, &mut w)?;
+        w.seek(io::SeekFrom::End(0))?;
+        Ok(w)
+    }
+}
+
+/// Collapses runs of equal values into `(count, value)` pairs.
+fn run_lengths<T: Copy + PartialEq>(values: &[T]) -> Vec<(u32, T)> {
+    let mut runs: Vec<(u32, T)> = Vec::new();
+    for &value in values {
+        match runs.last_mut() {
+            Some((count, prev)) if *prev == value => *count += 1,
+            _ => runs.push((1, value)),
+        }
+    }
+    runs
+}
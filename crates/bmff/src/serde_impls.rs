@@ -0,0 +1,161 @@
+//! Helpers for fields that don't implement `Serialize` and `Deserialize`
+//! directly.
+
+use crate::*;
+use serde::{ser, Deserializer, Serializer};
+
+/// Serializes a [`Duration`] as a number of nanoseconds, so durations in any
+/// timescale survive a round trip.
+pub mod duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        nanoseconds::<S>(duration)?.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        i64::deserialize(d).map(Duration::nanoseconds)
+    }
+}
+
+/// Serializes an `Option<Duration>` as a number of nanoseconds or null.
+pub mod option_duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        duration
+            .as_ref()
+            .map(nanoseconds::<S>)
+            .transpose()?
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Option::<i64>::deserialize(d).map(|x| x.map(Duration::nanoseconds))
+    }
+}
+
+fn nanoseconds<S: Serializer>(duration: &Duration) -> Result<i64, S::Error> {
+    duration
+        .num_nanoseconds()
+        .ok_or_else(|| ser::Error::custom("duration is too long to count in nanoseconds"))
+}
+
+/// Serializes the entries of an `stsd` box as an enum tagged with the type of
+/// each entry, such as `{ "type": "avc1", "width": 640, ... }`.
+///
+/// Sample entries from outside this crate are serialized as `raw` entries.
+pub mod sample_entries {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "type")]
+    pub(super) enum AnySampleEntry {
+        #[serde(rename = "avc1")]
+        Avc(AvcSampleEntry),
+        #[serde(rename = "hvc1")]
+        Hevc(HevcSampleEntry),
+        #[serde(rename = "hev1")]
+        Hev1(Hev1SampleEntry),
+        #[serde(rename = "av01")]
+        Av1(Av1SampleEntry),
+        #[serde(rename = "mp4a")]
+        Mp4a(Mp4aSampleEntry),
+        #[serde(rename = "Opus")]
+        Opus(OpusSampleEntry),
+        #[serde(rename = "wvtt")]
+        Wvtt(WvttSampleEntry),
+        #[serde(rename = "encv")]
+        Encv(EncvSampleEntry),
+        #[serde(rename = "vide")]
+        Visual(VisualSampleEntry),
+        #[serde(rename = "soun")]
+        Audio(AudioSampleEntry),
+        #[serde(rename = "hint")]
+        Hint(HintSampleEntry),
+        #[serde(rename = "raw")]
+        Raw(RawBox),
+    }
+
+    impl AnySampleEntry {
+        pub(super) fn from_dyn(entry: &dyn SampleEntry) -> io::Result<Self> {
+            let any = entry.as_any();
+            macro_rules! downcast {
+                ($($variant:ident($ty:ty)),* $(,)?) => {
+                    $(if let Some(x) = any.downcast_ref::<$ty>() {
+                        return Ok(Self::$variant(x.clone()));
+                    })*
+                };
+            }
+            downcast!(
+                Avc(AvcSampleEntry),
+                Hevc(HevcSampleEntry),
+                Hev1(Hev1SampleEntry),
+                Av1(Av1SampleEntry),
+                Mp4a(Mp4aSampleEntry),
+                Opus(OpusSampleEntry),
+                Wvtt(WvttSampleEntry),
+                Encv(EncvSampleEntry),
+                Visual(VisualSampleEntry),
+                Audio(AudioSampleEntry),
+                Hint(HintSampleEntry),
+                Raw(RawBox),
+            );
+            let bytes = entry.write_to()?;
+            Ok(Self::Raw(RawBox::from_reader(BoxReader::new(&bytes)?)))
+        }
+
+        pub(super) fn into_dyn(self) -> Box<dyn SampleEntry> {
+            match self {
+                Self::Avc(x) => Box::new(x),
+                Self::Hevc(x) => Box::new(x),
+                Self::Hev1(x) => Box::new(x),
+                Self::Av1(x) => Box::new(x),
+                Self::Mp4a(x) => Box::new(x),
+                Self::Opus(x) => Box::new(x),
+                Self::Wvtt(x) => Box::new(x),
+                Self::Encv(x) => Box::new(x),
+                Self::Visual(x) => Box::new(x),
+                Self::Audio(x) => Box::new(x),
+                Self::Hint(x) => Box::new(x),
+                Self::Raw(x) => Box::new(x),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(
+        entries: &[Box<dyn SampleEntry>],
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        let entries = entries
+            .iter()
+            .map(|x| AnySampleEntry::from_dyn(x.as_ref()))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(ser::Error::custom)?;
+        entries.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Vec<Box<dyn SampleEntry>>, D::Error> {
+        let entries = Vec::<AnySampleEntry>::deserialize(d)?;
+        Ok(entries.into_iter().map(AnySampleEntry::into_dyn).collect())
+    }
+}
+
+/// Serializes a single sample entry in the same way as [`sample_entries`].
+pub mod sample_entry {
+    use super::sample_entries::AnySampleEntry;
+    use super::*;
+
+    #[allow(clippy::borrowed_box)] // `serde(with)` passes a reference to the field
+    pub fn serialize<S: Serializer>(entry: &Box<dyn SampleEntry>, s: S) -> Result<S::Ok, S::Error> {
+        AnySampleEntry::from_dyn(entry.as_ref())
+            .map_err(ser::Error::custom)?
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Box<dyn SampleEntry>, D::Error> {
+        AnySampleEntry::deserialize(d).map(AnySampleEntry::into_dyn)
+    }
+}
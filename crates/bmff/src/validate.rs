@@ -0,0 +1,606 @@
+//! Structural checks of a whole file or stream.
+//!
+//! [`Validate`] checks that a single box can be written. This module checks
+//! the bytes that were actually written: that boxes are nested where ISO
+//! 14496-12 allows them, that containers have their mandatory children, that
+//! each `trun` points into the `mdat` that follows its `moof` and the sample
+//! sizes add up to the size of that `mdat`, and that fragment sequence
+//! numbers increase.
+//!
+//! Boxes that this crate doesn't know about are not checked.
+//!
+//! # Example
+//!
+//! ```rust
+//! use bmff::validate::{self, Violation};
+//!
+//! // a `moof` with only an empty `traf`
+//! let moof = [
+//!     0, 0, 0, 16, b'm', b'o', b'o', b'f', 0, 0, 0, 8, b't', b'r', b'a', b'f',
+//! ];
+//! let issues = validate::check(&moof);
+//! assert_eq!(issues[0].offset, 0);
+//! assert_eq!(issues[0].violation, Violation::MissingChild(*b"moof", "mfhd"));
+//! ```
+
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+
+quick_error! {
+    /// A structural rule that a stream doesn't follow.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum Violation {
+        /// A box is in a parent that can't contain it, or at the top level if
+        /// the parent is `None`.
+        Misplaced(box_type: [u8; 4], parent: Option<[u8; 4]>) {
+            display(
+                "{} box can't be in {}",
+                fourcc(box_type),
+                parent.map_or("the top level".to_string(), |x| format!("a {} box", fourcc(&x))),
+            )
+        }
+        /// A container is missing a mandatory child. Alternatives are
+        /// separated with `|`, such as `stco|co64`.
+        MissingChild(box_type: [u8; 4], child: &'static str) {
+            display("{} box is missing a {} box", fourcc(box_type), child)
+        }
+        /// A container has more than one of a child that must be unique.
+        DuplicateChild(box_type: [u8; 4], child: &'static str) {
+            display("{} box has more than one {} box", fourcc(box_type), child)
+        }
+        /// A box couldn't be parsed.
+        Malformed(box_type: [u8; 4], reason: String) {
+            display("{} box is malformed: {}", fourcc(box_type), reason)
+        }
+        /// A box was parsed, but fails [`Validate`].
+        Invalid(err: Error) {
+            display("{}", err)
+            from()
+        }
+        /// The stream ends partway through a box.
+        Truncated(box_type: [u8; 4]) {
+            display("{} box is truncated", fourcc(box_type))
+        }
+        /// A `moof` isn't followed by an `mdat`.
+        MissingMdat {
+            display("moof box isn't followed by an mdat box")
+        }
+        /// The samples of a `trun` aren't all inside the `mdat` that follows
+        /// its `moof`.
+        DataOffset(track_id: u32) {
+            display("trun box of track {} points outside of the following mdat", track_id)
+        }
+        /// The sample sizes of a `moof` don't add up to the payload size of
+        /// the `mdat` that follows it.
+        SampleSizes(expected: u64, found: u64) {
+            display("sample sizes add up to {} bytes, but the mdat has {}", expected, found)
+        }
+        /// The sequence number of a `mfhd` isn't greater than the one before it.
+        SequenceNumber(previous: u32, found: u32) {
+            display("mfhd sequence number {} follows {}", found, previous)
+        }
+    }
+}
+
+/// A [`Violation`] and the offset of the box it was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Offset of the box from the start of the stream
+    pub offset: u64,
+    pub violation: Violation,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at offset {}: {}", self.offset, self.violation)
+    }
+}
+
+/// Checks a whole file or stream. An empty result means no issues were found.
+pub fn check(data: &[u8]) -> Vec<Issue> {
+    let mut validator = Validator::new();
+    let mut issues = validator.push(data);
+    issues.extend(validator.finish());
+    issues
+}
+
+/// How many of a child box a container must have.
+#[derive(Clone, Copy)]
+enum Count {
+    One,
+    AtLeastOne,
+}
+
+/// Returns the mandatory children of a container, with alternatives
+/// separated by `|`.
+fn mandatory(box_type: &[u8; 4]) -> &'static [(&'static str, Count)] {
+    match box_type {
+        b"moov" => &[("mvhd", Count::One), ("trak", Count::AtLeastOne)],
+        b"trak" => &[("tkhd", Count::One), ("mdia", Count::One)],
+        b"edts" => &[("elst", Count::One)],
+        b"mdia" => &[
+            ("mdhd", Count::One),
+            ("hdlr", Count::One),
+            ("minf", Count::One),
+        ],
+        b"minf" => &[("dinf", Count::One), ("stbl", Count::One)],
+        b"dinf" => &[("dref", Count::One)],
+        b"stbl" => &[
+            ("stsd", Count::One),
+            ("stts", Count::One),
+            ("stsc", Count::One),
+            ("stsz|stz2", Count::One),
+            ("stco|co64", Count::One),
+        ],
+        b"mvex" => &[("trex", Count::AtLeastOne)],
+        b"moof" => &[("mfhd", Count::One)],
+        b"traf" => &[("tfhd", Count::One)],
+        b"mfra" => &[("mfro", Count::One)],
+        b"sinf" => &[("frma", Count::One)],
+        b"meta" => &[("hdlr", Count::One)],
+        b"avc1" | b"avc3" => &[("avcC", Count::One)],
+        b"hvc1" | b"hev1" => &[("hvcC", Count::One)],
+        b"av01" => &[("av1C", Count::One)],
+        b"mp4a" => &[("esds", Count::One)],
+        b"Opus" => &[("dOps", Count::One)],
+        b"encv" | b"enca" => &[("sinf", Count::AtLeastOne)],
+        _ => &[],
+    }
+}
+
+/// Returns the boxes that `box_type` can be in, with `None` for the top
+/// level, or `None` if any parent is allowed.
+fn parents(box_type: &[u8; 4]) -> Option<&'static [Option<&'static [u8; 4]>]> {
+    Some(match box_type {
+        b"ftyp" | b"styp" | b"moov" | b"moof" | b"mdat" | b"mfra" | b"sidx" | b"emsg" | b"prft" => {
+            &[None]
+        }
+        b"mvhd" | b"trak" | b"mvex" => &[Some(b"moov")],
+        b"tkhd" | b"tref" | b"edts" | b"mdia" => &[Some(b"trak")],
+        b"elst" => &[Some(b"edts")],
+        b"mdhd" | b"minf" => &[Some(b"mdia")],
+        b"hdlr" => &[Some(b"mdia"), Some(b"meta")],
+        b"vmhd" | b"smhd" | b"hmhd" | b"nmhd" | b"sthd" | b"stbl" => &[Some(b"minf")],
+        b"dinf" => &[Some(b"minf"), Some(b"meta")],
+        b"dref" => &[Some(b"dinf")],
+        b"stsd" | b"stts" | b"ctts" | b"stsc" | b"stsz" | b"stz2" | b"stco" | b"co64" | b"stss" => {
+            &[Some(b"stbl")]
+        }
+        b"mehd" | b"trex" => &[Some(b"mvex")],
+        b"mfhd" | b"traf" => &[Some(b"moof")],
+        b"tfhd" | b"tfdt" | b"trun" | b"senc" => &[Some(b"traf")],
+        b"saiz" | b"saio" => &[Some(b"stbl"), Some(b"traf")],
+        b"pssh" => &[Some(b"moov"), Some(b"moof")],
+        b"udta" => &[Some(b"moov"), Some(b"trak")],
+        b"tfra" | b"mfro" => &[Some(b"mfra")],
+        b"ilst" | b"keys" => &[Some(b"meta")],
+        b"avcC" => &[Some(b"avc1"), Some(b"avc3"), Some(b"encv")],
+        b"hvcC" => &[Some(b"hvc1"), Some(b"hev1"), Some(b"encv")],
+        b"av1C" => &[Some(b"av01"), Some(b"encv")],
+        b"esds" => &[Some(b"mp4a"), Some(b"enca")],
+        b"dOps" => &[Some(b"Opus"), Some(b"enca")],
+        b"sinf" => &[Some(b"encv"), Some(b"enca"), Some(b"ipro")],
+        b"frma" | b"schm" | b"schi" => &[Some(b"sinf")],
+        b"tenc" => &[Some(b"schi")],
+        _ => return None,
+    })
+}
+
+/// Returns the offset of the first child box in the payload of a box, or
+/// `None` if the box has no children.
+fn children_offset(box_type: &[u8; 4]) -> Option<usize> {
+    Some(match box_type {
+        b"moov" | b"trak" | b"edts" | b"mdia" | b"minf" | b"dinf" | b"stbl" | b"mvex" | b"udta"
+        | b"moof" | b"traf" | b"mfra" | b"sinf" | b"schi" => 0,
+        // version, flags and the entry count
+        b"stsd" => 8,
+        // version and flags
+        b"meta" => 4,
+        // the fields of a visual sample entry
+        b"avc1" | b"avc3" | b"hvc1" | b"hev1" | b"av01" | b"encv" => 78,
+        // the fields of an audio sample entry
+        b"mp4a" | b"Opus" | b"enca" => 28,
+        // the fields of a plain text sample entry
+        b"wvtt" => 8,
+        _ => return None,
+    })
+}
+
+/// The type of a box that might not have a complete header, or zeros if the
+/// type is cut off.
+fn header_type(bytes: &[u8]) -> [u8; 4] {
+    let mut box_type = [0; 4];
+    if let Some(x) = bytes.get(4..8) {
+        box_type.copy_from_slice(x);
+    }
+    box_type
+}
+
+/// The sample data of a `moof`, waiting for the `mdat` that follows it.
+struct PendingMoof {
+    offset: u64,
+    /// The track ID and absolute range of the samples of each `trun`
+    runs: Vec<(u32, Option<(u64, u64)>)>,
+    /// The sum of all sample sizes, if they are all known
+    total_size: Option<u64>,
+}
+
+/// Checks a stream as it is written, such as one that is sent to a client.
+///
+/// Only boxes that are split across calls to [`push`](Self::push) are
+/// buffered, and `mdat` payloads are skipped without being buffered.
+#[derive(Default)]
+pub struct Validator {
+    /// The start of a box that isn't complete yet
+    buf: Vec<u8>,
+    /// Offset of the next byte that isn't buffered or skipped
+    offset: u64,
+    /// Bytes of an `mdat` payload that are still to be skipped
+    skip: u64,
+    /// Whether the rest of the stream is skipped, because the last `mdat`
+    /// extends to the end of it or a box header is malformed
+    skip_to_end: bool,
+    /// Offset of the last `mdat`
+    mdat_offset: u64,
+    /// The `trex` boxes of the `moov`, by track ID
+    trex: HashMap<u32, TrackExtendsBox>,
+    sequence_number: Option<u32>,
+    pending: Option<PendingMoof>,
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Validator")
+            .field("offset", &self.offset)
+            .field("buffered", &self.buf.len())
+            .field("sequence_number", &self.sequence_number)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The number of bytes of a box header. `bytes` must hold at least 8 bytes.
+fn header_size(bytes: &[u8]) -> usize {
+    let mut size = 8;
+    if bytes[..4] == 1u32.to_be_bytes() {
+        size += 8;
+    }
+    if bytes[4..8] == *b"uuid" {
+        size += 16;
+    }
+    size
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks the next bytes of the stream and returns the issues found in
+    /// the boxes that they complete.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let rest = &data[pos..];
+            if self.skip_to_end {
+                self.offset += rest.len() as u64;
+                break;
+            }
+            if self.skip > 0 {
+                let skipped = self.skip.min(rest.len() as u64);
+                self.skip -= skipped;
+                self.offset += skipped;
+                pos += skipped as usize;
+                continue;
+            }
+
+            // check boxes that are complete in `data` without copying them
+            if self.buf.is_empty() {
+                if let Ok(header) = read_header(rest) {
+                    let len = if header.box_type == MediaDataBox::TYPE {
+                        Some(header.header_size as usize)
+                    } else if header.size != 0 && header.size <= rest.len() as u64 {
+                        Some(header.size as usize)
+                    } else {
+                        None
+                    };
+                    if let Some(len) = len {
+                        self.check_box(header, &rest[..len], &mut issues);
+                        pos += len;
+                        continue;
+                    }
+                }
+            }
+
+            let needed = self.needed(&mut issues);
+            if self.skip_to_end {
+                continue;
+            }
+            let len = needed.min(rest.len());
+            self.buf.extend_from_slice(&rest[..len]);
+            pos += len;
+            if self.needed(&mut issues) == 0 {
+                let buf = std::mem::take(&mut self.buf);
+                if let Ok(header) = read_header(buf.as_slice()) {
+                    self.check_box(header, &buf, &mut issues);
+                }
+                // keep the allocation for the next box
+                self.buf = buf;
+                self.buf.clear();
+            }
+        }
+        issues
+    }
+
+    /// Returns the number of bytes that are missing from the box in `buf`,
+    /// counting only the header for `mdat`.
+    fn needed(&mut self, issues: &mut Vec<Issue>) -> usize {
+        if self.buf.len() < 8 {
+            return 8 - self.buf.len();
+        }
+        let header_size = header_size(&self.buf);
+        if self.buf.len() < header_size {
+            return header_size - self.buf.len();
+        }
+        match read_header(self.buf.as_slice()) {
+            Ok(header) if header.box_type == MediaDataBox::TYPE => 0,
+            // boxes that extend to the end of the stream are checked by
+            // `finish`
+            Ok(header) if header.size == 0 => usize::MAX,
+            Ok(header) => (header.size - self.buf.len() as u64)
+                .try_into()
+                .unwrap_or(usize::MAX),
+            Err(err) => {
+                issues.push(Issue {
+                    offset: self.offset,
+                    violation: Violation::Malformed(header_type(&self.buf), err.to_string()),
+                });
+                // the next box can't be found
+                self.buf.clear();
+                self.skip_to_end = true;
+                usize::MAX
+            }
+        }
+    }
+
+    /// Checks a box at the current offset. `bytes` holds the whole box, or
+    /// only the header for `mdat`.
+    fn check_box(&mut self, header: BoxHeader, bytes: &[u8], issues: &mut Vec<Issue>) {
+        if header.box_type == MediaDataBox::TYPE {
+            self.check_mdat(&header, issues);
+            self.offset += header.header_size;
+            self.skip = header.payload_size();
+            self.skip_to_end = header.size == 0;
+        } else {
+            self.check_top_level(header, bytes, issues);
+            self.offset += header.size;
+        }
+    }
+
+    /// Checks the end of the stream and returns any remaining issues.
+    pub fn finish(mut self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        if !self.buf.is_empty() {
+            match read_header(self.buf.as_slice()) {
+                Ok(header) if header.size == 0 => {
+                    let bytes = std::mem::take(&mut self.buf);
+                    self.check_top_level(header, &bytes, &mut issues);
+                }
+                _ => issues.push(Issue {
+                    offset: self.offset,
+                    violation: Violation::Truncated(header_type(&self.buf)),
+                }),
+            }
+        }
+        if self.skip > 0 {
+            issues.push(Issue {
+                offset: self.mdat_offset,
+                violation: Violation::Truncated(MediaDataBox::TYPE),
+            });
+        }
+        if let Some(pending) = self.pending.take() {
+            issues.push(Issue {
+                offset: pending.offset,
+                violation: Violation::MissingMdat,
+            });
+        }
+        issues
+    }
+
+    fn check_top_level(&mut self, mut header: BoxHeader, bytes: &[u8], issues: &mut Vec<Issue>) {
+        header.offset = self.offset;
+        let r = BoxReader::from_payload(header, &bytes[header.header_size as usize..]);
+        check_structure(r.clone(), None, issues);
+
+        let issue = |violation| Issue {
+            offset: header.offset,
+            violation,
+        };
+        let malformed =
+            |err: io::Error| issue(Violation::Malformed(header.box_type, err.to_string()));
+        match &header.box_type {
+            b"moov" => match r.parse::<MovieBox>() {
+                Ok(moov) => {
+                    if let Err(err) = moov.validate() {
+                        issues.push(issue(err.into()));
+                    }
+                    let trex = moov.mvex.into_iter().flat_map(|x| x.trex);
+                    self.trex = trex.map(|x| (x.track_id, x)).collect();
+                }
+                Err(err) => issues.push(malformed(err)),
+            },
+            b"moof" => match r.parse::<MovieFragmentBox>() {
+                Ok(moof) => {
+                    if let Err(err) = moof.validate() {
+                        issues.push(issue(err.into()));
+                    }
+                    self.check_moof(header.offset, &moof, issues);
+                }
+                Err(err) => issues.push(malformed(err)),
+            },
+            _ => (),
+        }
+    }
+
+    fn check_moof(&mut self, offset: u64, moof: &MovieFragmentBox, issues: &mut Vec<Issue>) {
+        let sequence_number = moof.mfhd.sequence_number;
+        if let Some(previous) = self.sequence_number {
+            if sequence_number <= previous {
+                issues.push(Issue {
+                    offset,
+                    violation: Violation::SequenceNumber(previous, sequence_number),
+                });
+            }
+        }
+        self.sequence_number = Some(sequence_number);
+
+        if let Some(pending) = self.pending.take() {
+            issues.push(Issue {
+                offset: pending.offset,
+                violation: Violation::MissingMdat,
+            });
+        }
+
+        let mut runs = Vec::new();
+        let mut total_size = Some(0);
+        // without a base data offset, the first track fragment starts at the
+        // moof and each one after it starts at the end of the previous one
+        let mut next = offset;
+        for traf in &moof.traf {
+            let tfhd = &traf.tfhd;
+            let trex = self.trex.get(&tfhd.track_id);
+            let default_size = tfhd
+                .default_sample_size
+                .or_else(|| trex.map(|x| x.default_sample_size))
+                .filter(|&x| x != 0);
+            let base = match tfhd.base_data_offset {
+                Some(base) => base,
+                None if tfhd.default_base_is_moof => offset,
+                None => next,
+            };
+            next = base;
+            for trun in &traf.trun {
+                let start = trun
+                    .data_offset
+                    .map_or(Some(next), |x| base.checked_add_signed(x.into()));
+                let size = match (&trun.sample_sizes, default_size, trun.len()) {
+                    (Some(sizes), _, _) => Some(sizes.iter().map(|&x| u64::from(x)).sum()),
+                    (None, Some(size), Some(len)) => Some(u64::from(size) * len as u64),
+                    _ => None,
+                };
+                total_size = total_size.zip(size).map(|(x, y)| x + y);
+                let range = start.zip(size).map(|(start, size)| (start, start + size));
+                if let Some((_, end)) = range {
+                    next = end;
+                }
+                runs.push((tfhd.track_id, range.or(start.map(|x| (x, x)))));
+            }
+        }
+        self.pending = Some(PendingMoof {
+            offset,
+            runs,
+            total_size,
+        });
+    }
+
+    fn check_mdat(&mut self, header: &BoxHeader, issues: &mut Vec<Issue>) {
+        self.mdat_offset = self.offset;
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let start = self.offset + header.header_size;
+        let payload_size = header.payload_size();
+        let end = if header.size == 0 {
+            u64::MAX
+        } else {
+            start + payload_size
+        };
+        for &(track_id, range) in &pending.runs {
+            match range {
+                Some((run_start, run_end)) if run_start >= start && run_end <= end => (),
+                _ => issues.push(Issue {
+                    offset: pending.offset,
+                    violation: Violation::DataOffset(track_id),
+                }),
+            }
+        }
+        if let Some(total_size) = pending.total_size {
+            if header.size != 0 && total_size != payload_size {
+                issues.push(Issue {
+                    offset: self.offset,
+                    violation: Violation::SampleSizes(total_size, payload_size),
+                });
+            }
+        }
+    }
+}
+
+/// Checks the nesting and mandatory children of a box and its descendants.
+fn check_structure(mut r: BoxReader<'_>, parent: Option<&[u8; 4]>, issues: &mut Vec<Issue>) {
+    let header = *r.header();
+    let issue = |violation| Issue {
+        offset: header.offset,
+        violation,
+    };
+    if let Some(parents) = parents(&header.box_type) {
+        if !parents.contains(&parent) {
+            issues.push(issue(Violation::Misplaced(
+                header.box_type,
+                parent.copied(),
+            )));
+        }
+    }
+    let Some(offset) = children_offset(&header.box_type) else {
+        return;
+    };
+    if let Err(err) = r.skip(offset) {
+        issues.push(issue(Violation::Malformed(
+            header.box_type,
+            err.to_string(),
+        )));
+        return;
+    }
+
+    let mut children = Vec::new();
+    let mut malformed = false;
+    loop {
+        match r.next_child() {
+            Ok(Some(child)) => children.push(child),
+            Ok(None) => break,
+            Err(err) => {
+                issues.push(issue(Violation::Malformed(
+                    header.box_type,
+                    err.to_string(),
+                )));
+                malformed = true;
+                break;
+            }
+        }
+    }
+
+    // the missing children might be in the part that couldn't be read
+    if !malformed {
+        for &(child, count) in mandatory(&header.box_type) {
+            let found = children
+                .iter()
+                .filter(|x| {
+                    child
+                        .split('|')
+                        .any(|y| y.as_bytes() == x.header().box_type)
+                })
+                .count();
+            match (count, found) {
+                (_, 0) => issues.push(issue(Violation::MissingChild(header.box_type, child))),
+                (Count::One, 2..) => {
+                    issues.push(issue(Violation::DuplicateChild(header.box_type, child)));
+                }
+                _ => (),
+            }
+        }
+    }
+    for child in children {
+        check_structure(child, Some(&header.box_type), issues);
+    }
+}
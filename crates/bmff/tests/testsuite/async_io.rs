@@ -0,0 +1,64 @@
+use crate::common::*;
+use bmff::*;
+
+#[tokio::test]
+async fn matches_sync_writer() {
+    let (moof, mut mdat) = fragment(1);
+    mdat.headers = Some(vec![0xff; 3]);
+    let raw = RawBox {
+        box_type: *b"free",
+        extended_type: None,
+        payload: vec![1, 2, 3],
+    };
+    let mfhd = moof.mfhd.clone();
+
+    let mut expected = serialize(&ftyp());
+    expected.extend(serialize(&moof));
+    expected.extend(serialize(&mdat));
+    write_to_full(&mfhd, &mut expected).unwrap();
+    WriteTo::write_to(&raw, &mut expected).unwrap();
+
+    let mut buf = Vec::new();
+    async_io::write_to(&ftyp(), &mut buf).await.unwrap();
+    async_io::write_to(&moof, &mut buf).await.unwrap();
+    async_io::write_mdat(&mdat, &mut buf).await.unwrap();
+    async_io::write_to_full(&mfhd, &mut buf).await.unwrap();
+    async_io::write_value(&raw, &mut buf).await.unwrap();
+    assert_eq!(buf, expected);
+}
+
+#[tokio::test]
+async fn mdat_is_not_buffered() {
+    let (_, mdat) = fragment(1);
+    let mut buf = Vec::new();
+    let err = async_io::write_to(&mdat, &mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(buf.is_empty());
+}
+
+#[tokio::test]
+async fn chunks_through_pipe() {
+    let (moof, mdat) = fragment(2);
+    let mut chunks = Chunks::new();
+    write_to(&moof, &mut chunks).unwrap();
+    chunks.push_mdat(&mdat).unwrap();
+    let len = chunks.len() as usize;
+
+    // a small buffer forces the writer to wait for the reader
+    let (mut tx, mut rx) = tokio::io::duplex(16);
+    let reader = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut rx, &mut buf)
+            .await
+            .unwrap();
+        buf
+    });
+    async_io::write_chunks(&chunks, &mut tx).await.unwrap();
+    drop(tx);
+
+    let buf = reader.await.unwrap();
+    assert_eq!(buf.len(), len);
+    let mut expected = serialize(&moof);
+    expected.extend(serialize(&mdat));
+    assert_eq!(buf, expected);
+}
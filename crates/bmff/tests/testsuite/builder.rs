@@ -0,0 +1,126 @@
+use crate::common::*;
+use bmff::*;
+use std::io::Cursor;
+
+fn opus() -> OpusSampleEntry {
+    OpusSampleEntry {
+        data_reference_index: 1,
+        channel_count: 1,
+        sample_size: 16,
+        sample_rate: U16F16::from_num(48000),
+        dops: OpusSpecificBox {
+            output_channel_count: 1,
+            pre_skip: 312,
+            input_sample_rate: 48000,
+            output_gain: I8F8::ZERO,
+            channel_mapping_family: 0,
+            channel_mapping_table: None,
+        },
+        unknown: vec![],
+    }
+}
+
+fn wvtt() -> WvttSampleEntry {
+    WvttSampleEntry {
+        data_reference_index: 1,
+        config: WebVttConfigurationBox {
+            config: "WEBVTT".to_string(),
+        },
+        label: None,
+        unknown: vec![],
+    }
+}
+
+fn avc1() -> AvcSampleEntry {
+    moov().trak[0].mdia.minf.stbl.stsd.entries[0]
+        .as_any()
+        .downcast_ref::<AvcSampleEntry>()
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn three_tracks() {
+    let time = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+    let moov = MovieBuilder::new()
+        .creation_time(time)
+        .fragmented(true)
+        .track(
+            TrackBuilder::video(avc1(), 640, 480, 30)
+                .matrix(MATRIX_90)
+                .default_sample_duration(1),
+        )
+        .track(TrackBuilder::audio(opus(), 48000).language(*b"eng"))
+        .track(TrackBuilder::text(wvtt(), 1000).name("Events"))
+        .build()
+        .unwrap();
+
+    assert_eq!(moov.mvhd.next_track_id, 4);
+    assert_eq!(moov.mvhd.creation_time, time);
+    let ids: Vec<_> = moov.trak.iter().map(|x| x.tkhd.track_id).collect();
+    assert_eq!(ids, [1, 2, 3]);
+    let trex = &moov.mvex.as_ref().unwrap().trex;
+    let ids: Vec<_> = trex.iter().map(|x| x.track_id).collect();
+    assert_eq!(ids, [1, 2, 3]);
+    assert_eq!(trex[0].default_sample_duration, 1);
+
+    let [video, audio, text] = &moov.trak[..] else {
+        panic!("expected 3 tracks");
+    };
+    assert_eq!(video.tkhd.matrix, MATRIX_90);
+    assert_eq!(video.tkhd.width, U16F16::from_num(640));
+    assert_eq!(video.mdia.mdhd.timescale, 30);
+    assert_eq!(video.mdia.hdlr.name, "VideoHandler");
+    assert!(matches!(
+        video.mdia.minf.media_header,
+        MediaHeader::Video(_)
+    ));
+
+    assert_eq!(audio.tkhd.volume, I8F8::from_num(1));
+    assert_eq!(audio.tkhd.width, U16F16::ZERO);
+    assert_eq!(audio.mdia.mdhd.language, *b"eng");
+    assert!(matches!(audio.mdia.hdlr.handler_type, HandlerType::Audio));
+    assert!(matches!(
+        audio.mdia.minf.media_header,
+        MediaHeader::Sound(_)
+    ));
+
+    assert_eq!(text.mdia.hdlr.name, "Events");
+    assert!(matches!(text.mdia.hdlr.handler_type, HandlerType::Text));
+    assert!(text.mdia.minf.stbl.stsd.entries[0]
+        .as_any()
+        .downcast_ref::<WvttSampleEntry>()
+        .is_some());
+
+    let parsed = read_from::<MovieBox>(&serialize(&moov)[..]).unwrap();
+    assert_eq!(parsed.trak.len(), 3);
+}
+
+#[test]
+fn progressive_from_builder() {
+    let moov = MovieBuilder::new()
+        .track(TrackBuilder::audio(opus(), 48000))
+        .build()
+        .unwrap();
+    assert!(moov.mvex.is_none());
+
+    let mut writer = ProgressiveWriter::new(ftyp(), moov, Cursor::new(Vec::new())).unwrap();
+    writer.write_sample(1, &[0; 8], 960, 0, true).unwrap();
+    writer.finish().unwrap();
+}
+
+#[test]
+fn track_too_wide() {
+    let res = MovieBuilder::new()
+        .track(TrackBuilder::video(avc1(), 1 << 16, 480, 30))
+        .build();
+    assert_eq!(res.unwrap_err(), Error::Overflow(*b"tkhd", "width"));
+}
+
+#[test]
+fn invalid_language() {
+    let res = MovieBuilder::new()
+        .track(TrackBuilder::audio(opus(), 48000).language(*b"ENG"))
+        .build();
+    assert!(matches!(res, Err(Error::Invalid(box_type, _)) if box_type == *b"mdhd"));
+}
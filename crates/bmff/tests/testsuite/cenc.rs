@@ -0,0 +1,278 @@
+use crate::common::*;
+use bmff::*;
+
+const KID: [u8; 16] = [0x22; 16];
+
+pub fn sinf() -> ProtectionSchemeInfoBox {
+    ProtectionSchemeInfoBox {
+        frma: OriginalFormatBox {
+            data_format: *b"avc1",
+        },
+        schm: Some(SchemeTypeBox {
+            scheme_type: *b"cbcs",
+            scheme_version: 0x0001_0000,
+            scheme_uri: None,
+        }),
+        schi: Some(SchemeInformationBox {
+            tenc: Some(TrackEncryptionBox {
+                default_crypt_byte_block: 1,
+                default_skip_byte_block: 9,
+                default_is_protected: true,
+                default_per_sample_iv_size: 0,
+                default_kid: KID,
+                default_constant_iv: Some(vec![0x33; 16]),
+            }),
+            unknown: vec![],
+        }),
+        unknown: vec![],
+    }
+}
+
+fn encrypted_moof(iv_size: usize) -> MovieFragmentBox {
+    let (mut moof, _) = fragment(1);
+    moof.pssh.push(ProtectionSystemSpecificHeaderBox {
+        system_id: [0x10; 16],
+        key_ids: vec![KID],
+        data: vec![],
+    });
+    moof.traf[0].senc = Some(SampleEncryptionBox {
+        samples: (0..3)
+            .map(|i| SampleEncryptionEntry {
+                iv: vec![i; iv_size],
+                subsamples: vec![Subsample {
+                    clear_bytes: 1,
+                    protected_bytes: 3 + u32::from(i),
+                }],
+            })
+            .collect(),
+    });
+    moof.set_sample_aux_info().unwrap();
+    moof
+}
+
+#[test]
+fn encv_round_trip() {
+    let avc1 = moov().trak[0].mdia.minf.stbl.stsd.entries[0].clone_box();
+    let avc1_bytes = avc1.write_to().unwrap();
+    let encv = EncvSampleEntry {
+        entry: avc1,
+        sinf: sinf(),
+    };
+    encv.validate().unwrap();
+    let stsd = SampleDescriptionBox {
+        entries: vec![Box::new(encv.clone())],
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&stsd, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, stsd.size());
+    assert_eq!(&bytes[20..24], b"encv");
+    // the original entry is written unchanged, followed by the `sinf`
+    assert_eq!(&bytes[24..16 + avc1_bytes.len()], &avc1_bytes[8..]);
+    assert_eq!(&bytes[20 + avc1_bytes.len()..][..4], b"sinf");
+
+    let parsed = read_from_full::<SampleDescriptionBox>(&bytes[..]).unwrap();
+    let parsed = parsed.entries[0]
+        .as_any()
+        .downcast_ref::<EncvSampleEntry>()
+        .unwrap();
+    assert_eq!(parsed.sinf, sinf());
+    let avc1 = parsed
+        .entry
+        .as_any()
+        .downcast_ref::<AvcSampleEntry>()
+        .unwrap();
+    assert_eq!(avc1.width, 640);
+    assert_eq!(parsed.entry.write_to().unwrap(), avc1_bytes);
+}
+
+#[test]
+fn encv_with_wrong_format() {
+    let mut sinf = sinf();
+    sinf.frma.data_format = *b"hvc1";
+    let encv = EncvSampleEntry {
+        entry: moov().trak[0].mdia.minf.stbl.stsd.entries[0].clone_box(),
+        sinf,
+    };
+    assert!(matches!(
+        encv.validate(),
+        Err(Error::Invalid(b, _)) if b == *b"encv"
+    ));
+}
+
+#[test]
+fn tenc_versions() {
+    let tenc = sinf().schi.unwrap().tenc.unwrap();
+    let mut bytes = Vec::new();
+    write_to_full(&tenc, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, tenc.size());
+    assert_eq!(tenc.version(), 1);
+    assert_eq!(bytes[13], 0x19);
+    assert_eq!(
+        read_from_full::<TrackEncryptionBox>(&bytes[..]).unwrap(),
+        tenc
+    );
+
+    let tenc = TrackEncryptionBox {
+        default_crypt_byte_block: 0,
+        default_skip_byte_block: 0,
+        default_is_protected: true,
+        default_per_sample_iv_size: 8,
+        default_kid: KID,
+        default_constant_iv: None,
+    };
+    assert_eq!(tenc.version(), 0);
+    let mut bytes = Vec::new();
+    write_to_full(&tenc, &mut bytes).unwrap();
+    assert_eq!(bytes.len(), 32);
+    assert_eq!(
+        read_from_full::<TrackEncryptionBox>(&bytes[..]).unwrap(),
+        tenc
+    );
+
+    let invalid = TrackEncryptionBox {
+        default_per_sample_iv_size: 0,
+        ..tenc
+    };
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn senc_iv_size_comes_from_tenc() {
+    for iv_size in [0, 8, 16] {
+        let moof = encrypted_moof(iv_size);
+        let traf = &moof.traf[0];
+        let mut bytes = Vec::new();
+        write_to(&moof, &mut bytes).unwrap();
+        let mut parsed = read_from::<MovieFragmentBox>(&bytes[..]).unwrap();
+        assert_eq!(parsed.pssh, moof.pssh);
+        assert_eq!(parsed.traf[0].saiz, traf.saiz);
+        assert_eq!(parsed.traf[0].saio, traf.saio);
+        // the `senc` is kept as is until the IV size is known
+        assert_eq!(parsed.traf[0].senc, None);
+        assert_eq!(parsed.traf[0].unknown[0].raw.box_type, *b"senc");
+
+        let mut tenc = sinf().schi.unwrap().tenc.unwrap();
+        tenc.default_per_sample_iv_size = iv_size as u8;
+        parsed.traf[0].read_senc(&tenc).unwrap();
+        assert_eq!(parsed.traf[0].senc, traf.senc);
+        assert!(parsed.traf[0].unknown.is_empty());
+
+        let mut bytes_again = Vec::new();
+        write_to(&parsed, &mut bytes_again).unwrap();
+        assert_eq!(bytes_again, bytes);
+    }
+}
+
+#[test]
+fn senc_with_wrong_iv_size() {
+    let moof = encrypted_moof(8);
+    let mut bytes = Vec::new();
+    write_to(&moof, &mut bytes).unwrap();
+    let mut parsed = read_from::<MovieFragmentBox>(&bytes[..]).unwrap();
+    let mut tenc = sinf().schi.unwrap().tenc.unwrap();
+    tenc.default_per_sample_iv_size = 16;
+    assert!(parsed.traf[0].read_senc(&tenc).is_err());
+    assert_eq!(parsed.traf[0].senc, None);
+}
+
+#[test]
+fn saio_points_at_senc() {
+    let moof = encrypted_moof(8);
+    let traf = &moof.traf[0];
+    assert_eq!(
+        traf.saiz[0].sample_info_size,
+        SampleInfoSize::Same {
+            size: std::num::NonZeroU8::new(8 + 2 + 6).unwrap(),
+            sample_count: 3,
+        }
+    );
+    let mut bytes = Vec::new();
+    write_to(&moof, &mut bytes).unwrap();
+    let offset = traf.saio[0].offsets[0] as usize;
+    // the first IV, then one subsample of 1 clear and 3 protected bytes
+    assert_eq!(
+        &bytes[offset..offset + 16],
+        &[0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 3]
+    );
+    assert_eq!(&bytes[offset - 16..offset - 8], b"\0\0\0\x40senc");
+}
+
+#[test]
+fn saio_skips_unknown_children() {
+    let mut moof = encrypted_moof(8);
+    let unknown = |index, box_type| UnknownBox {
+        index,
+        raw: RawBox {
+            box_type,
+            extended_type: None,
+            payload: vec![0; 12],
+        },
+    };
+    // after the `mfhd`, and between the `tfhd` and the rest of the `traf`
+    moof.unknown.push(unknown(1, *b"free"));
+    moof.traf[0].unknown.push(unknown(1, *b"sbgp"));
+    moof.set_sample_aux_info().unwrap();
+
+    let mut bytes = Vec::new();
+    write_to(&moof, &mut bytes).unwrap();
+    let offset = moof.traf[0].saio[0].offsets[0] as usize;
+    assert_eq!(&bytes[offset - 16..offset - 8], b"\0\0\0\x40senc");
+    assert_eq!(&bytes[offset..offset + 8], &[0; 8]);
+
+    let parsed = read_from::<MovieFragmentBox>(&bytes[..]).unwrap();
+    assert_eq!(parsed.unknown[0].raw.box_type, *b"free");
+    assert_eq!(parsed.traf[0].unknown[0].raw.box_type, *b"sbgp");
+}
+
+#[test]
+fn saiz_with_different_sizes() {
+    let senc = SampleEncryptionBox {
+        samples: vec![
+            SampleEncryptionEntry {
+                iv: vec![],
+                subsamples: vec![],
+            },
+            SampleEncryptionEntry {
+                iv: vec![],
+                subsamples: vec![Subsample::default(); 2],
+            },
+        ],
+    };
+    let saiz = senc.saiz().unwrap();
+    assert_eq!(
+        saiz.sample_info_size,
+        SampleInfoSize::Different(vec![2, 14])
+    );
+    let mut bytes = Vec::new();
+    write_to_full(&saiz, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, saiz.size());
+    assert_eq!(
+        read_from_full::<SampleAuxiliaryInformationSizesBox>(&bytes[..]).unwrap(),
+        saiz
+    );
+}
+
+#[test]
+fn saiz_overflow() {
+    // 8 + 2 + 41 * 6 bytes doesn't fit in a `saiz`
+    let mut senc = SampleEncryptionBox {
+        samples: vec![SampleEncryptionEntry {
+            iv: vec![0; 8],
+            subsamples: vec![Subsample::default(); 41],
+        }],
+    };
+    assert_eq!(
+        senc.saiz().unwrap_err(),
+        Error::Overflow(*b"saiz", "sample_info_size")
+    );
+    assert!(senc.validate().is_err());
+
+    senc.samples[0].subsamples.pop();
+    assert_eq!(
+        senc.saiz().unwrap().sample_info_size,
+        SampleInfoSize::Same {
+            size: std::num::NonZeroU8::new(8 + 2 + 40 * 6).unwrap(),
+            sample_count: 1,
+        }
+    );
+}
@@ -0,0 +1,83 @@
+use crate::common::*;
+use bmff::*;
+use std::io::{self, IoSlice, Write};
+use std::sync::Arc;
+
+/// Accepts at most `limit` bytes per call, to exercise partial writes.
+struct Limited {
+    buf: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for Limited {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.limit);
+        self.buf.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let mut written = 0;
+        for buf in bufs {
+            written += self.write(&buf[..buf.len().min(self.limit - written)])?;
+            if written == self.limit {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn media_segment_shares_payload() {
+    let (moof, mut mdat) = fragment(1);
+    mdat.headers = Some(vec![0xff; 3]);
+    let mut chunks = Chunks::new();
+    write_to(&moof, &mut chunks).unwrap();
+    chunks.push_mdat(&mdat).unwrap();
+
+    let mut expected = serialize(&moof);
+    expected.extend(serialize(&mdat));
+    assert_eq!(chunks.len(), expected.len() as u64);
+
+    let chunks: Vec<_> = chunks.into_iter().collect();
+    assert_eq!(chunks.len(), 2);
+    let Chunk::Owned(head) = &chunks[0] else {
+        panic!("expected an owned chunk");
+    };
+    assert_eq!(head[..], expected[..expected.len() - 15]);
+    let Chunk::Shared(data) = &chunks[1] else {
+        panic!("expected a shared chunk");
+    };
+    assert!(Arc::ptr_eq(data, &mdat.data));
+}
+
+#[test]
+fn partial_vectored_writes() {
+    let mut chunks = Chunks::new();
+    let mut expected = Vec::new();
+    for i in 1..=3 {
+        let (moof, mdat) = fragment(i);
+        write_to(&moof, &mut chunks).unwrap();
+        chunks.push_mdat(&mdat).unwrap();
+        expected.extend(serialize(&moof));
+        expected.extend(serialize(&mdat));
+    }
+    assert_eq!(chunks.io_slices().len(), 6);
+
+    for limit in [1, 7, 15, 64, 4096] {
+        let mut w = Limited {
+            buf: Vec::new(),
+            limit,
+        };
+        chunks.write_all_to(&mut w).unwrap();
+        assert_eq!(w.buf, expected, "limit {limit}");
+    }
+
+    let mut full = [0u8; 16];
+    assert!(chunks.write_all_to(&mut full[..]).is_err());
+}
@@ -0,0 +1,197 @@
+#![allow(dead_code)]
+
+use bmff::*;
+use std::sync::Arc;
+
+pub const SPS: &[u8] = &[
+    0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x80, 0x50, 0x05, 0xbb, 0x01, 0x6a, 0x02, 0x02, 0x02, 0x80,
+    0x00, 0x00, 0x03, 0x00, 0x80, 0x00, 0x00, 0x1e, 0x07, 0x8c, 0x18, 0xcd,
+];
+pub const PPS: &[u8] = &[0x68, 0xe9, 0x7b, 0x2c, 0x8b];
+
+pub fn serialize<T: BmffBox>(bmff_box: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_to(bmff_box, &mut buf).unwrap();
+    assert_eq!(buf.len() as u64, bmff_box.size());
+    buf
+}
+
+pub fn ftyp() -> FileTypeBox {
+    FileTypeBox {
+        major_brand: *b"isom",
+        minor_version: 0,
+        compatible_brands: vec![*b"isom", *b"iso6", *b"avc1"],
+    }
+}
+
+pub fn moov() -> MovieBox {
+    let time = DateTime::<Utc>::from_timestamp(1_700_000_000, 0).unwrap();
+    let duration = Some(Duration::seconds(10));
+    MovieBox {
+        mvhd: MovieHeaderBox {
+            creation_time: time,
+            modification_time: time,
+            timescale: 30,
+            duration,
+            rate: I16F16::from_num(1),
+            volume: I8F8::from_num(1),
+            matrix: MATRIX_0,
+            next_track_id: 2,
+        },
+        trak: vec![TrackBox {
+            tkhd: TrackHeaderBox {
+                flags: TrackHeaderFlags::TRACK_ENABLED | TrackHeaderFlags::TRACK_IN_MOVIE,
+                creation_time: time,
+                modification_time: time,
+                track_id: 1,
+                timescale: 30,
+                duration,
+                layer: 0,
+                alternate_group: 0,
+                volume: I8F8::from_num(1),
+                matrix: MATRIX_0,
+                width: U16F16::from_num(640),
+                height: U16F16::from_num(480),
+            },
+            tref: None,
+            edts: None,
+            mdia: MediaBox {
+                mdhd: MediaHeaderBox {
+                    creation_time: time,
+                    modification_time: time,
+                    timescale: 30,
+                    duration,
+                    language: *b"und",
+                },
+                hdlr: HandlerBox {
+                    handler_type: HandlerType::Video,
+                    name: "VideoHandler".to_string(),
+                },
+                minf: MediaInformationBox {
+                    media_header: MediaHeader::Video(VideoMediaHeaderBox {
+                        graphics_mode: GraphicsMode::Copy,
+                        opcolor: [0, 0, 0],
+                    }),
+                    dinf: DataInformationBox {
+                        dref: DataReferenceBox {
+                            data_entries: vec![DataEntry::Url(DataEntryUrlBox {
+                                flags: DataEntryFlags::SELF_CONTAINED,
+                                location: String::new(),
+                            })],
+                        },
+                    },
+                    stbl: SampleTableBox {
+                        stsd: SampleDescriptionBox {
+                            entries: vec![Box::new(AvcSampleEntry {
+                                data_reference_index: 1,
+                                width: 640,
+                                height: 480,
+                                horiz_resolution: U16F16::from_num(72),
+                                vert_resolution: U16F16::from_num(72),
+                                frame_count: 1,
+                                depth: 0x0018,
+                                avcc: AvcConfigurationBox {
+                                    configuration: AvcDecoderConfigurationRecord {
+                                        profile_idc: 0x64,
+                                        constraint_set_flag: 0x00,
+                                        level_idc: 0x1f,
+                                        length_size_minus_one: 3,
+                                        sequence_parameter_sets: vec![SPS.to_vec()],
+                                        picture_parameter_sets: vec![PPS.to_vec()],
+                                        extension: vec![],
+                                    },
+                                },
+                                colr: None,
+                                pasp: None,
+                                unknown: vec![],
+                            })],
+                        },
+                        stts: TimeToSampleBox { samples: vec![] },
+                        ctts: None,
+                        stsc: SampleToChunkBox { entries: vec![] },
+                        stsz: SampleSizeBox {
+                            sample_size: SampleSize::Different(vec![]),
+                        },
+                        chunk_offset: ChunkOffset::Compact(ChunkOffsetBox {
+                            chunk_offsets: vec![],
+                        }),
+                        stss: None,
+                        unknown: vec![],
+                    },
+                    unknown: vec![],
+                },
+                unknown: vec![],
+            },
+            udta: None,
+            unknown: vec![],
+        }],
+        mvex: Some(MovieExtendsBox {
+            mehd: None,
+            trex: vec![TrackExtendsBox {
+                track_id: 1,
+                default_sample_description_index: 1,
+                default_sample_duration: 0,
+                default_sample_size: 0,
+                default_sample_flags: SampleFlags::default(),
+            }],
+            unknown: vec![],
+        }),
+        pssh: vec![],
+        udta: None,
+        unknown: vec![],
+    }
+}
+
+/// A `moof` and `mdat` with three samples of 4, 5, and 6 bytes.
+pub fn fragment(sequence_number: u32) -> (MovieFragmentBox, MediaDataBox) {
+    let mut moof = MovieFragmentBox {
+        mfhd: MovieFragmentHeaderBox { sequence_number },
+        pssh: vec![],
+        traf: vec![TrackFragmentBox {
+            tfhd: TrackFragmentHeaderBox {
+                track_id: 1,
+                base_data_offset: None,
+                sample_description_index: None,
+                default_sample_duration: Some(1),
+                default_sample_size: None,
+                default_sample_flags: Some(SampleFlags::from(0x0101_0000)),
+                default_base_is_moof: true,
+            },
+            tfdt: Some(TrackFragmentBaseMediaDecodeTimeBox {
+                base_media_decode_time: 3 * u64::from(sequence_number - 1),
+            }),
+            trun: vec![TrackFragmentRunBox {
+                version: 0,
+                data_offset: Some(0),
+                first_sample_flags: Some(SampleFlags::from(0x0200_0000)),
+                sample_durations: None,
+                sample_sizes: Some(vec![4, 5, 6]),
+                sample_flags: None,
+                sample_composition_time_offsets: None,
+            }],
+            saiz: vec![],
+            saio: vec![],
+            senc: None,
+            unknown: vec![],
+        }],
+        unknown: vec![],
+    };
+    moof.traf[0].trun[0].data_offset = Some(moof.size() as i32 + 8);
+    let mdat = MediaDataBox {
+        headers: None,
+        data: Arc::new((0..15).collect()),
+    };
+    (moof, mdat)
+}
+
+/// An init segment followed by `fragments` media segments.
+pub fn file(fragments: u32) -> Vec<u8> {
+    let mut buf = serialize(&ftyp());
+    buf.extend(serialize(&moov()));
+    for i in 1..=fragments {
+        let (moof, mdat) = fragment(i);
+        buf.extend(serialize(&moof));
+        buf.extend(serialize(&mdat));
+    }
+    buf
+}
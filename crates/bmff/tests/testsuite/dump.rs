@@ -0,0 +1,91 @@
+use crate::common::*;
+use bmff::dump::*;
+use bmff::*;
+use serde_json::json;
+use std::io::Cursor;
+
+#[test]
+fn fragmented_file() {
+    let bytes = file(2);
+    let tree = read_tree(Cursor::new(&bytes)).unwrap();
+    let types: Vec<_> = tree.iter().map(|x| x.header.box_type).collect();
+    assert_eq!(
+        types,
+        [*b"ftyp", *b"moov", *b"moof", *b"mdat", *b"moof", *b"mdat"]
+    );
+    assert!(tree.iter().all(|x| x.error.is_none()));
+    assert_eq!(
+        tree[5].header.offset + tree[5].header.size,
+        bytes.len() as u64
+    );
+
+    let avcc = tree[1].find(b"avcC").unwrap();
+    assert_eq!(avcc.get("level_idc"), Some(&json!(0x1f)));
+    assert_eq!(
+        avcc.get("picture_parameter_sets"),
+        Some(&json!(["68e97b2c8b"]))
+    );
+    assert_eq!(
+        tree[1].find(b"avc1").unwrap().get("width"),
+        Some(&json!(640))
+    );
+
+    let trun = tree[4].find(b"trun").unwrap();
+    assert_eq!(trun.get("version"), Some(&json!(0)));
+    assert_eq!(trun.get("first_sample_flags"), Some(&json!("0x02000000")));
+    assert_eq!(
+        trun.get("samples"),
+        Some(&json!([{ "size": 4 }, { "size": 5 }, { "size": 6 }]))
+    );
+    assert_eq!(
+        tree[4].find(b"mfhd").unwrap().get("sequence_number"),
+        Some(&json!(2))
+    );
+}
+
+#[test]
+fn text_output() {
+    let bytes = file(1);
+    let tree = read_tree(Cursor::new(&bytes)).unwrap();
+    let text = tree[2].to_string();
+    assert!(text.starts_with(&format!("[moof] offset={} size=", tree[2].header.offset)));
+    assert!(text.contains("\n  [mfhd] offset="));
+    assert!(text.contains("\n      samples:\n        [0] size=4\n"));
+}
+
+#[test]
+fn json_output() {
+    let (moof, mdat) = fragment(1);
+    let mut bytes = serialize(&moof);
+    bytes.extend(serialize(&mdat));
+    let tree = read_tree(Cursor::new(&bytes)).unwrap();
+    let mfhd = tree[0].find(b"mfhd").unwrap();
+    assert_eq!(
+        mfhd.to_json(),
+        json!({
+            "type": "mfhd",
+            "offset": 8,
+            "size": 16,
+            "fields": { "version": 0, "flags": "0x000000", "sequence_number": 1 },
+        })
+    );
+    assert_eq!(
+        tree[1].to_json(),
+        json!({ "type": "mdat", "offset": moof.size(), "size": 23 })
+    );
+}
+
+#[test]
+fn malformed_box() {
+    // the `mfhd` is missing its sequence number
+    let mut bytes = b"\0\0\0\x1cmoof\0\0\0\x0cmfhd\0\0\0\0\0\0\0\x08free".to_vec();
+    let tree = read_tree(Cursor::new(&bytes)).unwrap();
+    let moof = &tree[0];
+    assert!(moof.error.is_none());
+    assert!(moof.children[0].error.is_some());
+    assert_eq!(moof.children[1].header.box_type, *b"free");
+    assert!(moof.to_string().contains("\n    error: "));
+
+    bytes.truncate(bytes.len() - 1);
+    assert!(read_tree(Cursor::new(&bytes)).is_err());
+}
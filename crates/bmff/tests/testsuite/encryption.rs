@@ -0,0 +1,259 @@
+use crate::common::*;
+use bmff::encryption::*;
+use bmff::*;
+
+// AES-128 test vectors from NIST SP 800-38A
+const KEY: [u8; 16] = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+const PLAINTEXT: [u8; 32] = [
+    0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+    0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+];
+const KID: [u8; 16] = [0x22; 16];
+
+/// The NAL unit header and slice header of an IDR I slice for [`SPS`] and
+/// [`PPS`], which use CABAC, so the header is padded to a whole byte.
+const IDR_HEADER: [u8; 5] = [0x65, 0x88, 0x84, 0x00, 0xff];
+
+fn config() -> AvcDecoderConfigurationRecord {
+    AvcDecoderConfigurationRecord {
+        profile_idc: 0x64,
+        constraint_set_flag: 0,
+        level_idc: 0x1f,
+        length_size_minus_one: 3,
+        sequence_parameter_sets: vec![SPS.to_vec()],
+        picture_parameter_sets: vec![PPS.to_vec()],
+        extension: vec![],
+    }
+}
+
+/// An SEI NAL unit of 10 bytes followed by an IDR slice of 100 bytes.
+fn avc_sample() -> Vec<u8> {
+    let mut sample = vec![0, 0, 0, 10, 0x06];
+    sample.extend([0x55; 9]);
+    sample.extend([0, 0, 0, 100]);
+    sample.extend(IDR_HEADER);
+    sample.extend((0..95).map(|x| x as u8));
+    sample
+}
+
+#[test]
+fn cbc_test_vector() {
+    let mut encryptor = Encryptor::cbcs(KEY, KID, std::array::from_fn(|i| i as u8)).pattern(0, 0);
+    let mut sample = PLAINTEXT;
+    encryptor.encrypt(&mut sample, vec![]).unwrap();
+    assert_eq!(
+        sample,
+        [
+            0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9,
+            0x19, 0x7d, 0x50, 0x86, 0xcb, 0x9b, 0x50, 0x72, 0x19, 0xee, 0x95, 0xdb, 0x11, 0x3a,
+            0x91, 0x76, 0x78, 0xb2,
+        ]
+    );
+}
+
+#[test]
+fn ctr_test_vector() {
+    let encryptor = Encryptor::cenc(KEY, KID, [0; 8]);
+    let mut sample = [
+        0x87, 0x4d, 0x61, 0x91, 0xb6, 0x20, 0xe3, 0x26, 0x1b, 0xef, 0x68, 0x64, 0x99, 0x0d, 0xb6,
+        0xce, 0x98, 0x06, 0xf6, 0x6b, 0x79, 0x70, 0xfd, 0xff, 0x86, 0x17, 0x18, 0x7b, 0xb9, 0xff,
+        0xfd, 0xff,
+    ];
+    let entry = SampleEncryptionEntry {
+        iv: (0xf0..=0xff).collect(),
+        subsamples: vec![],
+    };
+    encryptor.decrypt(&mut sample, &entry).unwrap();
+    assert_eq!(sample, PLAINTEXT);
+}
+
+#[test]
+fn cenc_subsamples() {
+    let mut encryptor = Encryptor::cenc(KEY, KID, [0, 0, 0, 0, 0, 0, 0, 0xff]);
+    let original = avc_sample();
+    let subsamples = encryptor.avc_subsamples(&config(), &original).unwrap();
+    // the SEI, the slice header and the remainder of the slice after whole
+    // blocks are clear
+    assert_eq!(
+        subsamples,
+        [Subsample {
+            clear_bytes: 14 + 4 + 5 + 15,
+            protected_bytes: 80,
+        }]
+    );
+
+    let mut sample = original.clone();
+    let first = encryptor.encrypt(&mut sample, subsamples.clone()).unwrap();
+    assert_eq!(first.iv, [0, 0, 0, 0, 0, 0, 0, 0xff]);
+    assert_eq!(sample[..38], original[..38]);
+    assert_ne!(sample[38..], original[38..]);
+
+    let mut second_sample = original.clone();
+    let second = encryptor.encrypt(&mut second_sample, subsamples).unwrap();
+    assert_eq!(second.iv, [0, 0, 0, 0, 0, 0, 1, 0]);
+    assert_ne!(second_sample, sample);
+
+    encryptor.decrypt(&mut sample, &first).unwrap();
+    assert_eq!(sample, original);
+}
+
+#[test]
+fn short_nal_unit_lengths() {
+    let encryptor = Encryptor::cenc(KEY, KID, [0; 8]);
+    let config = AvcDecoderConfigurationRecord {
+        length_size_minus_one: 1,
+        ..config()
+    };
+    let mut sample = vec![0, 10, 0x06];
+    sample.extend([0x55; 9]);
+    sample.extend([0, 100]);
+    sample.extend(IDR_HEADER);
+    sample.extend((0..95).map(|x| x as u8));
+    assert_eq!(
+        encryptor.avc_subsamples(&config, &sample).unwrap(),
+        [Subsample {
+            clear_bytes: 12 + 2 + 5 + 15,
+            protected_bytes: 80,
+        }]
+    );
+}
+
+#[test]
+fn cbcs_pattern() {
+    let mut encryptor = Encryptor::cbcs(KEY, KID, [0x33; 16]);
+    let mut original = vec![0, 0, 1, 0];
+    original.extend(IDR_HEADER);
+    original.extend((0..251).map(|x| x as u8));
+    let subsamples = encryptor.avc_subsamples(&config(), &original).unwrap();
+    assert_eq!(
+        subsamples,
+        [Subsample {
+            clear_bytes: 9,
+            protected_bytes: 251,
+        }]
+    );
+
+    let mut sample = original.clone();
+    let entry = encryptor.encrypt(&mut sample, subsamples).unwrap();
+    assert!(entry.iv.is_empty());
+    // 1 encrypted block, 9 skipped, then 1 encrypted and 4 clear blocks and
+    // a partial block
+    let block = |i: usize| 9 + i * 16..9 + (i + 1) * 16;
+    assert_ne!(sample[block(0)], original[block(0)]);
+    assert_eq!(
+        sample[block(1).start..block(10).start],
+        original[block(1).start..block(10).start]
+    );
+    assert_ne!(sample[block(10)], original[block(10)]);
+    assert_eq!(sample[block(11).start..], original[block(11).start..]);
+
+    encryptor.decrypt(&mut sample, &entry).unwrap();
+    assert_eq!(sample, original);
+}
+
+#[test]
+fn subsamples_must_cover_sample() {
+    let mut encryptor = Encryptor::cenc(KEY, KID, [0; 8]);
+    let mut sample = avc_sample();
+    let subsamples = vec![Subsample {
+        clear_bytes: 1,
+        protected_bytes: 16,
+    }];
+    assert!(encryptor.encrypt(&mut sample, subsamples).is_err());
+    assert!(encryptor
+        .avc_subsamples(&config(), &sample[..sample.len() - 1])
+        .is_err());
+}
+
+#[test]
+fn large_clear_nal_units_are_split() {
+    let encryptor = Encryptor::cbcs(KEY, KID, [0x33; 16]);
+    let mut sample = vec![0, 1, 0, 0, 0x06];
+    sample.resize(4 + 0x10000, 0);
+    let subsamples = encryptor.avc_subsamples(&config(), &sample).unwrap();
+    assert_eq!(subsamples.len(), 2);
+    assert_eq!(subsamples[0].clear_bytes, u16::MAX);
+    assert_eq!(subsamples[1].clear_bytes, 5);
+    assert!(subsamples.iter().all(|x| x.protected_bytes == 0));
+}
+
+#[test]
+fn slice_header_with_emulation_prevention() {
+    let encryptor = Encryptor::cbcs(KEY, KID, [0x33; 16]);
+    // an idr_pic_id of 65535 has 16 leading zero bits, so the header needs an
+    // emulation prevention byte
+    let header = [0x65, 0x88, 0x80, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xff];
+    let mut sample = vec![0, 0, 0, 100];
+    sample.extend(header);
+    sample.resize(104, 0xab);
+    let subsamples = encryptor.avc_subsamples(&config(), &sample).unwrap();
+    assert_eq!(
+        subsamples,
+        [Subsample {
+            clear_bytes: 4 + 10,
+            protected_bytes: 90,
+        }]
+    );
+}
+
+#[test]
+fn cavlc_slice_header_with_weights() {
+    let encryptor = Encryptor::cbcs(KEY, KID, [0x33; 16]);
+    // a PPS in the sample that replaces the one in the configuration, with
+    // CAVLC and weighted prediction of P slices
+    let mut sample = vec![0, 0, 0, 4, 0x68, 0xcf, 0x3c, 0x80];
+    // a P slice with a pred_weight_table, whose 44-bit header ends in the
+    // middle of the sixth byte
+    sample.extend([0, 0, 0, 64, 0x41, 0x9a, 0x21, 0x1c, 0x8e, 0xaa, 0xaf]);
+    sample.resize(8 + 68, 0xab);
+    let subsamples = encryptor.avc_subsamples(&config(), &sample).unwrap();
+    assert_eq!(
+        subsamples,
+        [Subsample {
+            clear_bytes: 8 + 4 + 7,
+            protected_bytes: 57,
+        }]
+    );
+
+    // the same slice can't be parsed with the CABAC PPS of the configuration
+    assert_ne!(
+        encryptor.avc_subsamples(&config(), &sample[8..]).ok(),
+        Some(subsamples)
+    );
+}
+
+#[test]
+fn unsupported_slices_are_an_error() {
+    let encryptor = Encryptor::cbcs(KEY, KID, [0x33; 16]);
+    // slice data partition A
+    let mut sample = vec![0, 0, 0, 20, 0x62];
+    sample.resize(24, 0xab);
+    assert!(encryptor.avc_subsamples(&config(), &sample).is_err());
+    // a slice that refers to a PPS that doesn't exist
+    let sample = [0, 0, 0, 4, 0x65, 0x88, 0x40, 0xff];
+    assert!(encryptor.avc_subsamples(&config(), &sample).is_err());
+}
+
+#[test]
+fn encrypted_init_segment() {
+    let encryptor = Encryptor::cbcs(KEY, KID, [0x33; 16]);
+    let mut moov = moov();
+    let stsd = &mut moov.trak[0].mdia.minf.stbl.stsd;
+    let avc1 = stsd.entries[0]
+        .as_any()
+        .downcast_ref::<AvcSampleEntry>()
+        .unwrap()
+        .clone();
+    stsd.entries[0] = Box::new(encryptor.encv(avc1).unwrap());
+    moov.validate().unwrap();
+
+    let parsed = read_from::<MovieBox>(&serialize(&moov)[..]).unwrap();
+    let encv = parsed.trak[0].mdia.minf.stbl.stsd.entries[0]
+        .as_any()
+        .downcast_ref::<EncvSampleEntry>()
+        .unwrap();
+    assert_eq!(encv.sinf, encryptor.sinf(*b"avc1"));
+    assert_eq!(encv.sinf.schm.as_ref().unwrap().scheme_type, *b"cbcs");
+}
@@ -0,0 +1,99 @@
+use crate::common::*;
+use bmff::*;
+use std::io::{self, Write};
+
+/// A box with `len` bytes of zeroes as its payload, so that large boxes can be
+/// written without allocating their payload.
+struct ZeroBox {
+    len: u64,
+}
+
+impl BmffBox for ZeroBox {
+    const TYPE: [u8; 4] = *b"free";
+
+    fn size(&self) -> u64 {
+        large_size(8 + self.len)
+    }
+
+    fn write_box(&self, mut w: impl Write) -> io::Result<()> {
+        let chunk = vec![0u8; 1 << 20];
+        let mut remaining = self.len;
+        while remaining > 0 {
+            let n = remaining.min(chunk.len() as u64) as usize;
+            w.write_all(&chunk[..n])?;
+            remaining -= n as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the first 16 bytes written and counts the rest.
+#[derive(Default)]
+struct HeaderWriter {
+    header: Vec<u8>,
+    len: u64,
+}
+
+impl Write for HeaderWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(16 - self.header.len());
+        self.header.extend_from_slice(&buf[..n]);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn largesize_header() {
+    let zero = ZeroBox {
+        len: u32::MAX as u64,
+    };
+    let mut w = HeaderWriter::default();
+    write_to(&zero, &mut w).unwrap();
+    assert_eq!(w.len, zero.size());
+    assert_eq!(w.len, 16 + u32::MAX as u64);
+    assert_eq!(w.header[..8], [0, 0, 0, 1, b'f', b'r', b'e', b'e']);
+    assert_eq!(w.header[8..], w.len.to_be_bytes());
+
+    let header = read_header(&w.header[..]).unwrap();
+    assert_eq!(header.size, w.len);
+    assert_eq!(header.header_size, 16);
+    assert_eq!(header.payload_size(), u32::MAX as u64);
+}
+
+#[test]
+fn compact_header_up_to_u32_max() {
+    let zero = ZeroBox {
+        len: u32::MAX as u64 - 8,
+    };
+    let mut w = HeaderWriter::default();
+    write_to(&zero, &mut w).unwrap();
+    assert_eq!(w.len, u32::MAX as u64);
+    assert_eq!(
+        w.header[..8],
+        [0xff, 0xff, 0xff, 0xff, b'f', b'r', b'e', b'e']
+    );
+}
+
+#[test]
+fn chunk_offsets() {
+    let small = ChunkOffset::from_offsets(vec![48, 1000]);
+    assert!(matches!(small, ChunkOffset::Compact(_)));
+    let large = ChunkOffset::from_offsets(vec![48, 5_000_000_000]);
+    assert!(matches!(large, ChunkOffset::Large(_)));
+    assert_eq!(large.chunk_offsets(), [48, 5_000_000_000]);
+
+    let mut moov = moov();
+    moov.trak[0].mdia.minf.stbl.chunk_offset = large;
+    let bytes = serialize(&moov);
+    let parsed = read_from::<MovieBox>(&bytes[..]).unwrap();
+    let ChunkOffset::Large(co64) = &parsed.trak[0].mdia.minf.stbl.chunk_offset else {
+        panic!("expected co64");
+    };
+    assert_eq!(co64.chunk_offsets, [48, 5_000_000_000]);
+    assert_eq!(serialize(&parsed), bytes);
+}
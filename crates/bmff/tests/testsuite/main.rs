@@ -0,0 +1,23 @@
+mod common;
+
+#[cfg(feature = "tokio")]
+mod async_io;
+mod builder;
+mod cenc;
+mod chunks;
+#[cfg(feature = "dump")]
+mod dump;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod large;
+mod metadata;
+mod progressive;
+mod raw;
+mod read;
+mod sample_entry;
+mod sample_table;
+mod segment;
+#[cfg(feature = "serde")]
+mod serde;
+mod validate;
+mod webvtt;
@@ -0,0 +1,180 @@
+use crate::common::*;
+use bmff::*;
+
+fn udta(meta: MetaBox) -> Option<UserDataBox> {
+    Some(UserDataBox {
+        meta: Some(meta),
+        unknown: vec![],
+    })
+}
+
+#[test]
+fn itunes_items() {
+    let mut moov = moov();
+    moov.udta = udta(MetaBox::from_items(vec![
+        MetadataItem::new(
+            MetadataItem::TITLE,
+            MetadataValue::Utf8("Living room".to_string()),
+        ),
+        MetadataItem::new(
+            MetadataItem::ENCODER,
+            MetadataValue::Utf8("pet-monitor-app 0.1.0".to_string()),
+        ),
+    ]));
+    let bytes = serialize(&moov);
+    let title = b"\0\0\0\x23\xa9nam\0\0\0\x1bdata\0\0\0\x01\0\0\0\0Living room";
+    assert!(bytes.windows(title.len()).any(|x| x == title));
+
+    let parsed = read_from::<MovieBox>(&bytes[..]).unwrap();
+    let meta = parsed.udta.unwrap().meta.unwrap();
+    assert!(matches!(meta.hdlr.handler_type, HandlerType::Metadata));
+    assert_eq!(
+        meta.item(MetadataItem::TITLE),
+        Some(&MetadataValue::Utf8("Living room".to_string()))
+    );
+    assert_eq!(
+        meta.item(MetadataItem::ENCODER),
+        Some(&MetadataValue::Utf8("pet-monitor-app 0.1.0".to_string()))
+    );
+    assert_eq!(meta.item(MetadataItem::COMMENT), None);
+}
+
+#[test]
+fn keyed_tags() {
+    let tags = [
+        (
+            "com.apple.quicktime.location.name",
+            MetadataValue::Utf8("Kitchen".to_string()),
+        ),
+        ("com.example.small", MetadataValue::integer(-2)),
+        ("com.example.large", MetadataValue::integer(1 << 40)),
+        (
+            "com.example.blob",
+            MetadataValue::Other {
+                type_indicator: 0,
+                data: vec![1, 2, 3],
+            },
+        ),
+    ];
+    let mut moov = moov();
+    moov.trak[0].udta = udta(MetaBox::from_tags(
+        tags.iter().map(|(k, v)| (k.to_string(), v.clone())),
+    ));
+    let bytes = serialize(&moov);
+
+    let parsed = read_from::<MovieBox>(&bytes[..]).unwrap();
+    let meta = parsed.trak[0].udta.as_ref().unwrap().meta.as_ref().unwrap();
+    assert!(matches!(meta.hdlr.handler_type, HandlerType::MetadataKeys));
+    for (key, value) in &tags {
+        assert_eq!(meta.tag(key), Some(value));
+    }
+    assert_eq!(meta.tag("com.example.missing"), None);
+}
+
+#[test]
+fn item_key_out_of_range() {
+    let mut meta = MetaBox::from_tags([("com.example.a".to_string(), MetadataValue::integer(1))]);
+    meta.ilst.as_mut().unwrap().items[0].key = 2u32.to_be_bytes();
+    assert_eq!(
+        meta.validate(),
+        Err(Error::Invalid(
+            *b"ilst",
+            "item key index is not an entry in keys"
+        ))
+    );
+    let mut moov = moov();
+    moov.udta = udta(meta);
+    assert!(moov.validate().is_err());
+}
+
+#[test]
+fn freeform_items() {
+    let mut item = MetadataItem::freeform(
+        "com.apple.iTunes".to_string(),
+        "iTunSMPB".to_string(),
+        MetadataValue::Utf8(" 00000000 00000840".to_string()),
+    );
+    item.values.push(MetadataValue::Utf8("second".to_string()));
+    // an unknown child between the name and the values
+    item.unknown.push(UnknownBox {
+        index: 2,
+        raw: RawBox {
+            box_type: *b"itif",
+            extended_type: None,
+            payload: vec![0, 0, 0, 0, 1, 2, 3, 4],
+        },
+    });
+    let ilst = ItemListBox { items: vec![item] };
+    ilst.validate().unwrap();
+    let mut bytes = Vec::new();
+    write_to(&ilst, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, ilst.size());
+    assert_eq!(&bytes[12..16], b"----");
+    assert_eq!(
+        &bytes[16..52],
+        b"\0\0\0\x1cmean\0\0\0\0com.apple.iTunes\0\0\0\x14name"
+    );
+    assert_eq!(&bytes[68..72], b"itif");
+
+    let parsed = read_from::<ItemListBox>(&bytes[..]).unwrap();
+    assert_eq!(parsed.items, ilst.items);
+
+    let meta = MetaBox::from_items(parsed.items);
+    assert_eq!(
+        meta.freeform("com.apple.iTunes", "iTunSMPB"),
+        Some(&MetadataValue::Utf8(" 00000000 00000840".to_string()))
+    );
+    assert_eq!(meta.freeform("com.apple.iTunes", "other"), None);
+
+    let mut invalid = ilst.clone();
+    invalid.items[0].key = MetadataItem::TITLE;
+    assert!(invalid.validate().is_err());
+}
+
+#[test]
+fn integer_sizes() {
+    let meta = MetaBox::from_items(vec![
+        MetadataItem::new(
+            MetadataItem::TEMPO,
+            MetadataValue::Integer {
+                value: 120,
+                size: 2,
+            },
+        ),
+        MetadataItem::new(
+            MetadataItem::COMPILATION,
+            MetadataValue::Integer { value: 1, size: 1 },
+        ),
+    ]);
+    let mut bytes = Vec::new();
+    write_to_full(&meta, &mut bytes).unwrap();
+    let tmpo = b"\0\0\0\x1atmpo\0\0\0\x12data\0\0\0\x15\0\0\0\0\0\x78";
+    assert!(bytes.windows(tmpo.len()).any(|x| x == tmpo));
+    let cpil = b"\0\0\0\x19cpil\0\0\0\x11data\0\0\0\x15\0\0\0\0\x01";
+    assert!(bytes.windows(cpil.len()).any(|x| x == cpil));
+
+    let parsed = read_from_full::<MetaBox>(&bytes[..]).unwrap();
+    assert_eq!(
+        parsed.item(MetadataItem::TEMPO),
+        Some(&MetadataValue::Integer {
+            value: 120,
+            size: 2
+        })
+    );
+    assert_eq!(
+        parsed.item(MetadataItem::COMPILATION),
+        Some(&MetadataValue::Integer { value: 1, size: 1 })
+    );
+
+    assert_eq!(
+        MetadataValue::Integer {
+            value: 128,
+            size: 1
+        }
+        .validate(),
+        Err(Error::Overflow(*b"data", "value"))
+    );
+    assert!(MetadataValue::Integer { value: 0, size: 5 }
+        .validate()
+        .is_err());
+}
@@ -0,0 +1,173 @@
+use crate::common::*;
+use bmff::*;
+use std::io::{self, Cursor};
+
+fn demux(bytes: &[u8]) -> Vec<TopLevelBox> {
+    Demuxer::new(Cursor::new(bytes))
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap()
+}
+
+#[test]
+fn faststart_layout() {
+    let mut writer = ProgressiveWriter::new(ftyp(), moov(), Cursor::new(Vec::new())).unwrap();
+    for (i, size) in [4, 5, 6, 5].into_iter().enumerate() {
+        writer
+            .write_sample(1, &vec![i as u8; size], 1, 0, i % 3 == 0)
+            .unwrap();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let boxes = demux(&bytes);
+    assert_eq!(boxes.len(), 3);
+    assert!(matches!(boxes[0], TopLevelBox::Ftyp(_)));
+    let TopLevelBox::Moov(moov) = &boxes[1] else {
+        panic!("expected moov, got {:?}", boxes[1]);
+    };
+    assert!(matches!(boxes[2], TopLevelBox::Mdat(_)));
+    assert!(moov.mvex.is_none());
+
+    let stbl = &moov.trak[0].mdia.minf.stbl;
+    assert_eq!(stbl.stts.samples, [(4, 1)]);
+    assert_eq!(stbl.stsc.entries, [(1, 4, 1)]);
+    let SampleSize::Different(sizes) = &stbl.stsz.sample_size else {
+        panic!("expected per-sample sizes");
+    };
+    assert_eq!(sizes, &[4, 5, 6, 5]);
+    assert_eq!(stbl.stss.as_ref().unwrap().sample_numbers, [1, 4]);
+
+    let offsets = stbl.chunk_offset.chunk_offsets();
+    assert_eq!(offsets.len(), 1);
+    let offset = offsets[0] as usize;
+    assert_eq!(bytes[offset..offset + 4], [0; 4]);
+    assert_eq!(bytes[offset + 4..offset + 9], [1; 5]);
+    // the mdat has a 64-bit size that was filled in at the end, and comes
+    // right after the moov
+    let mdat = offset - 16;
+    assert_eq!(&bytes[mdat..mdat + 8], b"\0\0\0\x01mdat");
+    assert_eq!(bytes[mdat + 8..offset], 36u64.to_be_bytes());
+    assert_eq!(offset + 20, bytes.len());
+    let moov_offset = serialize(&ftyp()).len();
+    assert_eq!(&bytes[moov_offset + 4..moov_offset + 8], b"moov");
+    assert_eq!(moov_offset as u64 + moov.size(), mdat as u64);
+}
+
+#[test]
+fn equal_sized_samples() {
+    let mut writer = ProgressiveWriter::new(ftyp(), moov(), Cursor::new(Vec::new())).unwrap();
+    for i in 0..5 {
+        writer.write_sample(1, &[i; 10], 1, 0, true).unwrap();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let TopLevelBox::Moov(moov) = &demux(&bytes)[1] else {
+        panic!("expected moov");
+    };
+    let stbl = &moov.trak[0].mdia.minf.stbl;
+    let SampleSize::Same { size, sample_count } = stbl.stsz.sample_size else {
+        panic!("expected a constant sample size");
+    };
+    assert_eq!(size.get(), 10);
+    assert_eq!(sample_count, 5);
+    assert!(validate::check(&bytes).is_empty());
+}
+
+#[test]
+fn large_mdat_is_moved() {
+    let mut writer = ProgressiveWriter::new(ftyp(), moov(), Cursor::new(Vec::new())).unwrap();
+    // larger than the buffer used to move the mdat, in chunks that don't line
+    // up with it
+    let samples: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i; 30_001]).collect();
+    for sample in &samples {
+        writer.write_sample(1, sample, 1, 0, true).unwrap();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let boxes = demux(&bytes);
+    let TopLevelBox::Moov(moov) = &boxes[1] else {
+        panic!("expected moov, got {:?}", boxes[1]);
+    };
+    let offset = moov.trak[0].mdia.minf.stbl.chunk_offset.chunk_offsets()[0] as usize;
+    assert_eq!(bytes[offset..], samples.concat());
+}
+
+#[test]
+fn durations_are_exact() {
+    let mut moov = moov();
+    moov.trak[0].mdia.mdhd.timescale = 90000;
+    moov.mvhd.timescale = 90000;
+    moov.trak[0].tkhd.timescale = 90000;
+    let mut writer = ProgressiveWriter::new(ftyp(), moov, Cursor::new(Vec::new())).unwrap();
+    // 29.97 fps
+    for _ in 0..7 {
+        writer.write_sample(1, &[0; 4], 3003, 0, true).unwrap();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let TopLevelBox::Moov(moov) = &demux(&bytes)[1] else {
+        panic!("expected moov");
+    };
+    let duration = Duration::nanoseconds(233_566_667);
+    assert_eq!(moov.trak[0].mdia.mdhd.duration, Some(duration));
+    assert_eq!(moov.trak[0].tkhd.duration, Some(duration));
+    assert_eq!(moov.mvhd.duration, Some(duration));
+    // written as 7 * 3003 ticks
+    let ticks = (7u32 * 3003).to_be_bytes();
+    for box_type in [b"mvhd", b"tkhd", b"mdhd"] {
+        let pos = bytes.windows(4).position(|x| x == box_type).unwrap();
+        // version 0: version and flags, creation and modification times,
+        // then the timescale or track ID
+        let pos = pos + 4 + 4 + 8 + 4;
+        let pos = if box_type == b"tkhd" { pos + 4 } else { pos };
+        assert_eq!(bytes[pos..pos + 4], ticks);
+    }
+}
+
+#[test]
+fn interleaved_tracks() {
+    let mut moov = moov();
+    let mut trak = moov.trak[0].clone();
+    trak.tkhd.track_id = 2;
+    trak.mdia.mdhd.timescale = 1000;
+    moov.trak.push(trak);
+    moov.mvhd.next_track_id = 3;
+
+    let mut writer = ProgressiveWriter::new(ftyp(), moov, Cursor::new(Vec::new())).unwrap();
+    writer.write_sample(1, &[1; 3], 1, 0, true).unwrap();
+    writer.write_sample(1, &[1; 3], 1, 0, true).unwrap();
+    writer.write_sample(2, &[2; 3], 500, 0, true).unwrap();
+    writer.write_sample(1, &[1; 3], 1, 0, true).unwrap();
+    writer.write_sample(2, &[2; 3], 500, 0, true).unwrap();
+    writer.write_sample(2, &[2; 3], 500, 0, true).unwrap();
+    assert!(writer.write_sample(3, &[3; 3], 1, 0, true).is_err());
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let boxes = demux(&bytes);
+    let TopLevelBox::Moov(moov) = &boxes[1] else {
+        panic!("expected moov, got {:?}", boxes[1]);
+    };
+    for (trak, value, chunks) in [(&moov.trak[0], 1, [2, 1]), (&moov.trak[1], 2, [1, 2])] {
+        let stbl = &trak.mdia.minf.stbl;
+        assert!(stbl.stss.is_none());
+        assert!(
+            matches!(stbl.stsz.sample_size, SampleSize::Same { size, sample_count: 3 } if size.get() == 3)
+        );
+        assert_eq!(stbl.stsc.entries, [(1, chunks[0], 1), (2, chunks[1], 1)]);
+        for (offset, sample_count) in stbl.chunk_offset.chunk_offsets().into_iter().zip(chunks) {
+            let offset = offset as usize;
+            assert!(bytes[offset..offset + sample_count as usize * 3]
+                .iter()
+                .all(|&x| x == value));
+        }
+    }
+    assert_eq!(
+        moov.trak[0].mdia.mdhd.duration,
+        Some(Duration::milliseconds(100))
+    );
+    assert_eq!(
+        moov.trak[1].mdia.mdhd.duration,
+        Some(Duration::milliseconds(1500))
+    );
+    assert_eq!(moov.mvhd.duration, Some(Duration::milliseconds(1500)));
+}
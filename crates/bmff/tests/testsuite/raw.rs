@@ -0,0 +1,117 @@
+use crate::common::*;
+use bmff::*;
+use std::io::Cursor;
+
+fn free() -> RawBox {
+    RawBox {
+        box_type: *b"free",
+        extended_type: None,
+        payload: vec![0; 7],
+    }
+}
+
+fn uuid() -> RawBox {
+    RawBox {
+        box_type: *b"uuid",
+        extended_type: Some([0xab; 16]),
+        payload: b"vendor data".to_vec(),
+    }
+}
+
+#[test]
+fn raw_box_round_trip() {
+    let mut bytes = Vec::new();
+    WriteTo::write_to(&uuid(), &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, RawBox::size(&uuid()));
+    assert_eq!(&bytes[4..8], b"uuid");
+    let r = BoxReader::new(&bytes).unwrap();
+    assert_eq!(RawBox::from_reader(r), uuid());
+}
+
+#[test]
+fn containers_keep_unknown_children() {
+    let mut moov = moov();
+    moov.unknown.push(uuid().into());
+    moov.trak[0].unknown.push(free().into());
+    moov.trak[0].mdia.minf.stbl.unknown.push(
+        RawBox {
+            box_type: *b"sgpd",
+            extended_type: None,
+            payload: vec![1, 2, 3],
+        }
+        .into(),
+    );
+    moov.trak[0]
+        .mdia
+        .minf
+        .stbl
+        .stsd
+        .entries
+        .push(Box::new(RawBox {
+            box_type: *b"mp4v",
+            extended_type: None,
+            payload: vec![0, 0, 0, 0, 0, 0, 0, 1],
+        }));
+
+    let bytes = serialize(&moov);
+    let parsed = read_from::<MovieBox>(&bytes[..]).unwrap();
+    assert_eq!(serialize(&parsed), bytes);
+    assert_eq!(parsed.unknown[0].raw, uuid());
+    assert_eq!(parsed.trak[0].unknown[0].raw, free());
+    assert_eq!(
+        parsed.trak[0].mdia.minf.stbl.unknown[0].raw.box_type,
+        *b"sgpd"
+    );
+    let mp4v = parsed.trak[0].mdia.minf.stbl.stsd.entries[1]
+        .as_any()
+        .downcast_ref::<RawBox>()
+        .unwrap();
+    assert_eq!(mp4v.box_type, *b"mp4v");
+}
+
+#[test]
+fn unknown_children_keep_their_position() {
+    let moov = moov();
+    let mut free_bytes = Vec::new();
+    WriteTo::write_to(&free(), &mut free_bytes).unwrap();
+
+    // a `free` box between `mvhd` and `trak`
+    let mut bytes = serialize(&moov);
+    let mvhd_end = 8 + moov.mvhd.size() as usize;
+    bytes.splice(mvhd_end..mvhd_end, free_bytes);
+    let size = bytes.len() as u32;
+    bytes[..4].copy_from_slice(&size.to_be_bytes());
+
+    let parsed = read_from::<MovieBox>(&bytes[..]).unwrap();
+    assert_eq!(
+        parsed.unknown,
+        [UnknownBox {
+            index: 1,
+            raw: free()
+        }]
+    );
+    assert_eq!(serialize(&parsed), bytes);
+}
+
+#[test]
+fn file_round_trip() {
+    let mut bytes = file(1);
+    WriteTo::write_to(&uuid(), &mut bytes).unwrap();
+
+    let mut written = Vec::new();
+    for bmff_box in Demuxer::new(Cursor::new(&bytes)).unwrap() {
+        match bmff_box.unwrap() {
+            TopLevelBox::Ftyp(ftyp) => write_to(&ftyp, &mut written).unwrap(),
+            TopLevelBox::Styp(styp) => write_to(&styp, &mut written).unwrap(),
+            TopLevelBox::Sidx(sidx) => write_to_full(&sidx, &mut written).unwrap(),
+            TopLevelBox::Moov(moov) => write_to(&moov, &mut written).unwrap(),
+            TopLevelBox::Moof(moof) => write_to(&moof, &mut written).unwrap(),
+            TopLevelBox::Mdat(mdat) => write_to(&mdat, &mut written).unwrap(),
+            TopLevelBox::Mfra(mfra) => write_to(&mfra, &mut written).unwrap(),
+            TopLevelBox::Emsg(emsg) => write_to_full(&emsg, &mut written).unwrap(),
+            TopLevelBox::Prft(prft) => write_to_full(&prft, &mut written).unwrap(),
+            TopLevelBox::Unknown(raw) => WriteTo::write_to(&raw, &mut written).unwrap(),
+        }
+    }
+    assert_eq!(written, bytes);
+}
@@ -0,0 +1,246 @@
+use crate::common::*;
+use bmff::*;
+use std::io::{self, Cursor, Read, Seek};
+use std::sync::Arc;
+
+#[test]
+fn init_segment_round_trip() {
+    let bytes = serialize(&moov());
+    let moov = read_from::<MovieBox>(&bytes[..]).unwrap();
+    assert_eq!(serialize(&moov), bytes);
+
+    assert_eq!(moov.mvhd.timescale, 30);
+    assert_eq!(moov.mvhd.duration, Some(Duration::seconds(10)));
+    let trak = &moov.trak[0];
+    assert_eq!(trak.tkhd.track_id, 1);
+    assert_eq!(trak.tkhd.duration, Some(Duration::seconds(10)));
+    assert_eq!(trak.mdia.mdhd.language, *b"und");
+    assert_eq!(trak.mdia.hdlr.name, "VideoHandler");
+
+    let avc1 = trak.mdia.minf.stbl.stsd.entries[0]
+        .as_any()
+        .downcast_ref::<AvcSampleEntry>()
+        .unwrap();
+    assert_eq!(avc1.width, 640);
+    assert_eq!(avc1.avcc.configuration.sequence_parameter_sets, [SPS]);
+    assert_eq!(avc1.avcc.configuration.picture_parameter_sets, [PPS]);
+}
+
+#[test]
+fn fragment_round_trip() {
+    let (moof, mdat) = fragment(7);
+    let bytes = serialize(&moof);
+    let parsed = read_from::<MovieFragmentBox>(&bytes[..]).unwrap();
+    assert_eq!(serialize(&parsed), bytes);
+
+    let traf = &parsed.traf[0];
+    assert_eq!(parsed.mfhd.sequence_number, 7);
+    assert!(traf.tfhd.default_base_is_moof);
+    assert_eq!(traf.tfhd.default_sample_duration, Some(1));
+    assert_eq!(traf.tfdt.unwrap().base_media_decode_time, 18);
+    assert_eq!(traf.trun[0].sample_sizes, Some(vec![4, 5, 6]));
+    let first_sample_flags = traf.trun[0].first_sample_flags.unwrap();
+    assert_eq!(first_sample_flags.depends_on, SampleDependency::No);
+    assert!(!first_sample_flags.is_non_sync_sample);
+    let default_sample_flags = traf.tfhd.default_sample_flags.unwrap();
+    assert_eq!(default_sample_flags.depends_on, SampleDependency::Yes);
+    assert!(default_sample_flags.is_non_sync_sample);
+
+    let parsed = read_from::<MediaDataBox>(&serialize(&mdat)[..]).unwrap();
+    assert_eq!(parsed.data, mdat.data);
+}
+
+#[test]
+fn tfdt_version() {
+    for (base_media_decode_time, version, size) in [(u32::MAX as u64, 0, 16), (1 << 32, 1, 20)] {
+        let tfdt = TrackFragmentBaseMediaDecodeTimeBox {
+            base_media_decode_time,
+        };
+        let mut bytes = Vec::new();
+        write_to_full(&tfdt, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        assert_eq!(bytes[8], version);
+        assert_eq!(
+            read_from_full::<TrackFragmentBaseMediaDecodeTimeBox>(&bytes[..]).unwrap(),
+            tfdt
+        );
+    }
+}
+
+#[test]
+fn demux_file() {
+    let bytes = file(2);
+    let boxes = Demuxer::new(Cursor::new(&bytes))
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(boxes.len(), 6);
+    assert!(matches!(boxes[0], TopLevelBox::Ftyp(_)));
+    assert!(matches!(boxes[1], TopLevelBox::Moov(_)));
+    let TopLevelBox::Moof(moof) = &boxes[4] else {
+        panic!("expected moof, got {:?}", boxes[4]);
+    };
+    assert_eq!(moof.mfhd.sequence_number, 2);
+    assert!(matches!(boxes[5], TopLevelBox::Mdat(_)));
+}
+
+#[test]
+fn demux_unknown_boxes() {
+    let mut bytes = file(0);
+    bytes.extend([0, 0, 0, 12, b'f', b'r', b'e', b'e', 1, 2, 3, 4]);
+    bytes.extend(serialize(&ftyp()));
+    let boxes = Demuxer::new(Cursor::new(&bytes))
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    let TopLevelBox::Unknown(free) = &boxes[2] else {
+        panic!("expected unknown box, got {:?}", boxes[2]);
+    };
+    assert_eq!(free.box_type, *b"free");
+    assert_eq!(free.payload, [1, 2, 3, 4]);
+    assert!(matches!(boxes[3], TopLevelBox::Ftyp(_)));
+}
+
+/// Counts the bytes read through it.
+struct CountingReader<R> {
+    inner: R,
+    read: usize,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn demux_without_reading_mdat() {
+    let mdat = MediaDataBox {
+        headers: None,
+        data: Arc::new((0..1 << 20).map(|x| x as u8).collect()),
+    };
+    let mut bytes = serialize(&ftyp());
+    bytes.extend(serialize(&mdat));
+    bytes.extend(serialize(&moov()));
+    let mut demuxer = Demuxer::new(CountingReader {
+        inner: Cursor::new(&bytes),
+        read: 0,
+    })
+    .unwrap();
+
+    let header = demuxer.next_header().unwrap().unwrap();
+    assert_eq!(header.box_type, *b"ftyp");
+    assert!(matches!(demuxer.read_box().unwrap(), TopLevelBox::Ftyp(_)));
+
+    let header = demuxer.next_header().unwrap().unwrap();
+    assert_eq!(header.box_type, *b"mdat");
+    assert_eq!(header.payload_size(), 1 << 20);
+    let mut start = [0; 4];
+    demuxer.payload().read_exact(&mut start).unwrap();
+    assert_eq!(start, [0, 1, 2, 3]);
+    assert!(demuxer.read_box().is_err());
+
+    let header = demuxer.next_header().unwrap().unwrap();
+    assert_eq!(header.box_type, *b"moov");
+    let mut payload = Vec::new();
+    demuxer.payload().read_to_end(&mut payload).unwrap();
+    assert_eq!(payload.len() as u64, header.payload_size());
+    assert!(demuxer.next_header().is_none());
+
+    assert!(demuxer.into_inner().read < bytes.len() - (1 << 20) + 4 + 8192);
+}
+
+#[test]
+fn unknown_handler_round_trip() {
+    let hdlr = HandlerBox {
+        handler_type: HandlerType::Other(*b"tmcd"),
+        name: "TimeCodeHandler".to_string(),
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&hdlr, &mut bytes).unwrap();
+    assert_eq!(bytes[16..20], *b"tmcd");
+    let parsed = read_from_full::<HandlerBox>(&bytes[..]).unwrap();
+    assert_eq!(parsed.handler_type, HandlerType::Other(*b"tmcd"));
+    assert_eq!(parsed.name, hdlr.name);
+    assert_eq!(
+        HandlerType::from(*b"vide"),
+        HandlerType::Video,
+        "known handlers aren't kept as `Other`"
+    );
+}
+
+#[test]
+fn unknown_graphics_mode_round_trip() {
+    // blend, as written by QuickTime
+    let vmhd = VideoMediaHeaderBox {
+        graphics_mode: GraphicsMode::Other(0x20),
+        opcolor: [0x8000, 0x8000, 0x8000],
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&vmhd, &mut bytes).unwrap();
+    assert_eq!(bytes[12..14], [0x00, 0x20]);
+    let parsed = read_from_full::<VideoMediaHeaderBox>(&bytes[..]).unwrap();
+    assert_eq!(parsed.graphics_mode, GraphicsMode::Other(0x20));
+    assert_eq!(parsed.opcolor, vmhd.opcolor);
+    assert_eq!(GraphicsMode::from(0), GraphicsMode::Copy);
+}
+
+#[test]
+fn truncated_box_is_an_error() {
+    let bytes = serialize(&moov());
+    assert!(read_from::<MovieBox>(&bytes[..bytes.len() - 1]).is_err());
+    let mut boxes = Demuxer::new(Cursor::new(&bytes[..bytes.len() - 1])).unwrap();
+    assert!(boxes.next().unwrap().is_err());
+    assert!(boxes.next().is_none());
+}
+
+#[test]
+fn wrong_box_type_is_an_error() {
+    let err = read_from::<MovieBox>(&serialize(&ftyp())[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn sample_flags_bits() {
+    let flags = SampleFlags {
+        is_leading: 3,
+        depends_on: SampleDependency::No,
+        is_depended_on: SampleDependency::Yes,
+        has_redundancy: SampleDependency::Reserved,
+        padding_value: 5,
+        is_non_sync_sample: true,
+        degradation_priority: 0xabcd,
+    };
+    let bits = u32::from(flags);
+    assert_eq!(bits, 0x0e7b_abcd);
+    assert_eq!(SampleFlags::from(bits), flags);
+    for bits in [0, 0x0101_0000, 0x0200_0000, 0x0fff_ffff] {
+        assert_eq!(u32::from(SampleFlags::from(bits)), bits);
+    }
+}
+
+#[test]
+fn mdhd_language_codes_round_trip() {
+    let mdhd = moov().trak[0].mdia.mdhd.clone();
+    let mut bytes = Vec::new();
+    write_to_full(&mdhd, &mut bytes).unwrap();
+    // no language, and a Macintosh language code
+    for code in [0u16, 2] {
+        let len = bytes.len();
+        bytes[len - 4..len - 2].copy_from_slice(&code.to_be_bytes());
+        let parsed = read_from_full::<MediaHeaderBox>(&bytes[..]).unwrap();
+        assert_eq!(parsed.language[0], 0x60);
+        parsed.validate().unwrap();
+        let mut again = Vec::new();
+        write_to_full(&parsed, &mut again).unwrap();
+        assert_eq!(again, bytes);
+    }
+}
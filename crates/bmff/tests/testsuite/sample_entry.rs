@@ -0,0 +1,414 @@
+use bmff::*;
+
+const VPS: &[u8] = &[0x40, 0x01, 0x0c, 0x01, 0xff, 0xff];
+const SPS: &[u8] = &[0x42, 0x01, 0x01, 0x01, 0x60, 0x00];
+const PPS: &[u8] = &[0x44, 0x01, 0xc1, 0x72];
+
+/// An `hvcC` box for 8-bit 4:2:0 Main profile, level 4, as written by FFmpeg.
+fn hvcc_bytes() -> Vec<u8> {
+    let mut payload = vec![
+        0x01, 0x01, 0x60, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x78, 0xf0, 0x00,
+        0xfc, 0xfd, 0xf8, 0xf8, 0x00, 0x00, 0x0f, 0x03,
+    ];
+    for (nal_unit_type, nal_unit) in [(0x20, VPS), (0x21, SPS), (0x22, PPS)] {
+        payload.extend([0x80 | nal_unit_type, 0x00, 0x01, 0x00, nal_unit.len() as u8]);
+        payload.extend(nal_unit);
+    }
+    let mut bytes = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+    bytes.extend(b"hvcC");
+    bytes.extend(payload);
+    bytes
+}
+
+fn hevc_sample_entry(hvcc: HevcConfigurationBox) -> HevcSampleEntry {
+    HevcSampleEntry {
+        data_reference_index: 1,
+        width: 1920,
+        height: 1080,
+        horiz_resolution: U16F16::from_num(72),
+        vert_resolution: U16F16::from_num(72),
+        frame_count: 1,
+        depth: 0x0018,
+        hvcc,
+        colr: None,
+        pasp: None,
+        unknown: vec![],
+    }
+}
+
+#[test]
+fn hvcc_fields() {
+    let bytes = hvcc_bytes();
+    let hvcc = read_from::<HevcConfigurationBox>(&bytes[..]).unwrap();
+    let config = &hvcc.configuration;
+    assert_eq!(config.general_profile_space, 0);
+    assert!(!config.general_tier_flag);
+    assert_eq!(config.general_profile_idc, 1);
+    assert_eq!(config.general_profile_compatibility_flags, 0x6000_0000);
+    assert_eq!(config.general_constraint_indicator_flags, 0x9000_0000_0000);
+    assert_eq!(config.general_level_idc, 120);
+    assert_eq!(config.chroma_format_idc, 1);
+    assert_eq!(config.bit_depth_luma_minus8, 0);
+    assert_eq!(config.num_temporal_layers, 1);
+    assert!(config.temporal_id_nested);
+    assert_eq!(config.length_size_minus_one, 3);
+    assert_eq!(
+        config.arrays,
+        [
+            HevcNalArray {
+                array_completeness: true,
+                nal_unit_type: HevcNalArray::VPS,
+                nal_units: vec![VPS.to_vec()],
+            },
+            HevcNalArray {
+                array_completeness: true,
+                nal_unit_type: HevcNalArray::SPS,
+                nal_units: vec![SPS.to_vec()],
+            },
+            HevcNalArray {
+                array_completeness: true,
+                nal_unit_type: HevcNalArray::PPS,
+                nal_units: vec![PPS.to_vec()],
+            },
+        ]
+    );
+
+    let mut written = Vec::new();
+    write_to(&hvcc, &mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn avcc_fields() {
+    // High profile with 2-byte NAL unit lengths, two SPS, one PPS, and the
+    // chroma format and bit depths at the end
+    let payload = [
+        0x01, 0x64, 0x00, 0x1f, 0xfd, 0xe2, 0x00, 0x02, 0x67, 0x64, 0x00, 0x03, 0x67, 0x64, 0x01,
+        0x01, 0x00, 0x02, 0x68, 0xe9, 0xfd, 0xf8, 0xf8, 0x00,
+    ];
+    let mut bytes = (8 + payload.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(b"avcC");
+    bytes.extend(payload);
+
+    let avcc = read_from::<AvcConfigurationBox>(&bytes[..]).unwrap();
+    let config = &avcc.configuration;
+    assert_eq!(config.profile_idc, 0x64);
+    assert_eq!(config.length_size_minus_one, 1);
+    assert_eq!(
+        config.sequence_parameter_sets,
+        [vec![0x67, 0x64], vec![0x67, 0x64, 0x01]]
+    );
+    assert_eq!(config.picture_parameter_sets, [vec![0x68, 0xe9]]);
+    assert_eq!(config.extension, [0xfd, 0xf8, 0xf8, 0x00]);
+
+    let mut written = Vec::new();
+    write_to(&avcc, &mut written).unwrap();
+    assert_eq!(written.len() as u64, avcc.size());
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn hevc_sample_entries() {
+    let hvcc = read_from::<HevcConfigurationBox>(&hvcc_bytes()[..]).unwrap();
+    let stsd = SampleDescriptionBox {
+        entries: vec![
+            Box::new(hevc_sample_entry(hvcc.clone())),
+            Box::new(Hev1SampleEntry(hevc_sample_entry(hvcc))),
+        ],
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&stsd, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, stsd.size());
+    assert_eq!(&bytes[20..24], b"hvc1");
+
+    let parsed = read_from_full::<SampleDescriptionBox>(&bytes[..]).unwrap();
+    let hvc1 = parsed.entries[0]
+        .as_any()
+        .downcast_ref::<HevcSampleEntry>()
+        .unwrap();
+    assert_eq!((hvc1.width, hvc1.height), (1920, 1080));
+    assert_eq!(hvc1.hvcc.configuration.arrays.len(), 3);
+    let hev1 = parsed.entries[1]
+        .as_any()
+        .downcast_ref::<Hev1SampleEntry>()
+        .unwrap();
+    assert_eq!(hev1.0.hvcc.configuration.general_level_idc, 120);
+
+    let mut written = Vec::new();
+    write_to_full(&parsed, &mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+/// A sequence header OBU for 8-bit 4:2:0 Main profile.
+const SEQUENCE_HEADER: &[u8] = &[
+    0x0a, 0x0b, 0x00, 0x00, 0x00, 0x24, 0xcf, 0x7f, 0x0d, 0xbf, 0xff, 0x30, 0x08,
+];
+
+#[test]
+fn av1c_fields() {
+    let mut bytes = vec![0, 0, 0, 25, b'a', b'v', b'1', b'C', 0x81, 0x08, 0x0c, 0x00];
+    bytes.extend(SEQUENCE_HEADER);
+    let av1c = read_from::<Av1CodecConfigurationBox>(&bytes[..]).unwrap();
+    let config = &av1c.configuration;
+    assert_eq!(config.seq_profile, 0);
+    assert_eq!(config.seq_level_idx_0, 8);
+    assert!(!config.seq_tier_0);
+    assert!(!config.high_bitdepth);
+    assert!(!config.monochrome);
+    assert!(config.chroma_subsampling_x && config.chroma_subsampling_y);
+    assert_eq!(config.chroma_sample_position, 0);
+    assert_eq!(config.initial_presentation_delay_minus_one, None);
+    assert_eq!(config.config_obus, SEQUENCE_HEADER);
+
+    let mut written = Vec::new();
+    write_to(&av1c, &mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn av1_sample_entry() {
+    let entry = Av1SampleEntry {
+        data_reference_index: 1,
+        width: 1280,
+        height: 720,
+        horiz_resolution: U16F16::from_num(72),
+        vert_resolution: U16F16::from_num(72),
+        frame_count: 1,
+        depth: 0x0018,
+        av1c: Av1CodecConfigurationBox {
+            configuration: Av1CodecConfigurationRecord {
+                seq_profile: 1,
+                seq_level_idx_0: 31,
+                seq_tier_0: true,
+                high_bitdepth: true,
+                twelve_bit: false,
+                monochrome: false,
+                chroma_subsampling_x: false,
+                chroma_subsampling_y: false,
+                chroma_sample_position: 0,
+                initial_presentation_delay_minus_one: Some(3),
+                config_obus: SEQUENCE_HEADER.to_vec(),
+            },
+        },
+        colr: None,
+        pasp: None,
+        unknown: vec![],
+    };
+    let mut bytes = Vec::new();
+    write_to(&entry, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, BmffBox::size(&entry));
+    assert_eq!(&bytes[90..94], b"av1C");
+    assert_eq!(bytes[94..98], [0x81, 0x3f, 0xc0, 0x13]);
+
+    let parsed = read_from::<Av1SampleEntry>(&bytes[..]).unwrap();
+    let config = &parsed.av1c.configuration;
+    assert_eq!((config.seq_profile, config.seq_level_idx_0), (1, 31));
+    assert!(config.seq_tier_0 && config.high_bitdepth);
+    assert_eq!(config.initial_presentation_delay_minus_one, Some(3));
+    assert_eq!(SampleEntry::write_to(&parsed).unwrap(), bytes);
+}
+
+/// `AudioSpecificConfig` for AAC-LC, 44.1 kHz, stereo.
+const AUDIO_SPECIFIC_CONFIG: &[u8] = &[0x12, 0x10];
+
+fn esds_payload() -> Vec<u8> {
+    vec![
+        0x03, 0x19, 0x00, 0x01, 0x00, 0x04, 0x11, 0x40, 0x15, 0x00, 0x00, 0x00, 0x00, 0x01, 0xf4,
+        0x00, 0x00, 0x01, 0xf4, 0x00, 0x05, 0x02, 0x12, 0x10, 0x06, 0x01, 0x02,
+    ]
+}
+
+#[test]
+fn esds_fields() {
+    let mut bytes = vec![0, 0, 0, 39, b'e', b's', b'd', b's', 0, 0, 0, 0];
+    bytes.extend(esds_payload());
+    let esds = read_from_full::<ElementaryStreamDescriptorBox>(&bytes[..]).unwrap();
+    let es = &esds.es_descriptor;
+    assert_eq!(es.es_id, 1);
+    assert_eq!(es.decoder_config.object_type_indication, 0x40);
+    assert_eq!(es.decoder_config.stream_type, 0x05);
+    assert_eq!(es.decoder_config.avg_bitrate, 128_000);
+    assert_eq!(
+        es.decoder_config.decoder_specific_info.as_deref(),
+        Some(AUDIO_SPECIFIC_CONFIG)
+    );
+
+    let mut written = Vec::new();
+    write_to_full(&esds, &mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn esds_long_descriptor_sizes() {
+    // Some muxers always write descriptor sizes in four bytes.
+    let payload = [
+        0x03, 0x80, 0x80, 0x80, 0x22, 0x00, 0x02, 0x00, 0x04, 0x80, 0x80, 0x80, 0x14, 0x40, 0x15,
+        0x00, 0x00, 0x00, 0x00, 0x01, 0xf4, 0x00, 0x00, 0x01, 0xf4, 0x00, 0x05, 0x80, 0x80, 0x80,
+        0x02, 0x12, 0x10, 0x06, 0x80, 0x80, 0x80, 0x01, 0x02,
+    ];
+    let mut bytes = vec![
+        0,
+        0,
+        0,
+        12 + payload.len() as u8,
+        b'e',
+        b's',
+        b'd',
+        b's',
+        0,
+        0,
+        0,
+        0,
+    ];
+    bytes.extend(payload);
+    let esds = read_from_full::<ElementaryStreamDescriptorBox>(&bytes[..]).unwrap();
+    assert_eq!(esds.es_descriptor.es_id, 2);
+    assert_eq!(
+        esds.es_descriptor
+            .decoder_config
+            .decoder_specific_info
+            .as_deref(),
+        Some(AUDIO_SPECIFIC_CONFIG)
+    );
+}
+
+#[test]
+fn audio_sample_entries() {
+    let mut esds = vec![0, 0, 0, 39, b'e', b's', b'd', b's', 0, 0, 0, 0];
+    esds.extend(esds_payload());
+    let stsd = SampleDescriptionBox {
+        entries: vec![
+            Box::new(Mp4aSampleEntry {
+                data_reference_index: 1,
+                channel_count: 2,
+                sample_size: 16,
+                sample_rate: U16F16::from_num(44_100),
+                esds: read_from_full(&esds[..]).unwrap(),
+                unknown: vec![],
+            }),
+            Box::new(OpusSampleEntry {
+                data_reference_index: 1,
+                channel_count: 2,
+                sample_size: 16,
+                sample_rate: U16F16::from_num(48_000),
+                dops: OpusSpecificBox {
+                    output_channel_count: 2,
+                    pre_skip: 312,
+                    input_sample_rate: 48_000,
+                    output_gain: I8F8::ZERO,
+                    channel_mapping_family: 0,
+                    channel_mapping_table: None,
+                },
+                unknown: vec![],
+            }),
+        ],
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&stsd, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, stsd.size());
+
+    let parsed = read_from_full::<SampleDescriptionBox>(&bytes[..]).unwrap();
+    let mp4a = parsed.entries[0]
+        .as_any()
+        .downcast_ref::<Mp4aSampleEntry>()
+        .unwrap();
+    assert_eq!(mp4a.sample_rate, 44_100);
+    assert_eq!(mp4a.channel_count, 2);
+    let opus = parsed.entries[1]
+        .as_any()
+        .downcast_ref::<OpusSampleEntry>()
+        .unwrap();
+    assert_eq!(opus.dops.pre_skip, 312);
+    assert_eq!(
+        &bytes[bytes.len() - 19..],
+        [0, 0, 0, 19, b'd', b'O', b'p', b's', 0, 2, 0x01, 0x38, 0, 0, 0xbb, 0x80, 0, 0, 0]
+    );
+
+    let mut written = Vec::new();
+    write_to_full(&parsed, &mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn dops_channel_mapping() {
+    let dops = OpusSpecificBox {
+        output_channel_count: 6,
+        pre_skip: 312,
+        input_sample_rate: 48_000,
+        output_gain: I8F8::from_num(-1.5),
+        channel_mapping_family: 1,
+        channel_mapping_table: Some(ChannelMappingTable {
+            stream_count: 4,
+            coupled_count: 2,
+            channel_mapping: vec![0, 4, 1, 2, 3, 5],
+        }),
+    };
+    let mut bytes = Vec::new();
+    write_to(&dops, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, dops.size());
+    let parsed = read_from::<OpusSpecificBox>(&bytes[..]).unwrap();
+    assert_eq!(parsed.output_gain, I8F8::from_num(-1.5));
+    assert_eq!(parsed.channel_mapping_table, dops.channel_mapping_table);
+}
+
+#[test]
+fn colr_nclx() {
+    let colr = ColourInformationBox::Nclx {
+        colour_primaries: 1,
+        transfer_characteristics: 1,
+        matrix_coefficients: 1,
+        full_range: true,
+    };
+    let mut bytes = Vec::new();
+    write_to(&colr, &mut bytes).unwrap();
+    assert_eq!(
+        bytes,
+        [0, 0, 0, 19, b'c', b'o', b'l', b'r', b'n', b'c', b'l', b'x', 0, 1, 0, 1, 0, 1, 0x80]
+    );
+    assert_eq!(read_from::<ColourInformationBox>(&bytes[..]).unwrap(), colr);
+
+    for colr in [
+        ColourInformationBox::Icc(vec![1, 2, 3]),
+        ColourInformationBox::RestrictedIcc(vec![4, 5]),
+        ColourInformationBox::Other {
+            colour_type: *b"nclc",
+            data: vec![0, 1, 0, 1, 0, 1],
+        },
+    ] {
+        let mut bytes = Vec::new();
+        write_to(&colr, &mut bytes).unwrap();
+        assert_eq!(bytes.len() as u64, colr.size());
+        assert_eq!(read_from::<ColourInformationBox>(&bytes[..]).unwrap(), colr);
+    }
+}
+
+#[test]
+fn visual_entry_colour_and_aspect_ratio() {
+    let hvcc = read_from::<HevcConfigurationBox>(&hvcc_bytes()[..]).unwrap();
+    let mut entry = hevc_sample_entry(hvcc);
+    entry.colr = Some(ColourInformationBox::Nclx {
+        colour_primaries: 6,
+        transfer_characteristics: 6,
+        matrix_coefficients: 6,
+        full_range: true,
+    });
+    entry.pasp = Some(PixelAspectRatioBox {
+        h_spacing: 4,
+        v_spacing: 3,
+    });
+    let stsd = SampleDescriptionBox {
+        entries: vec![Box::new(entry.clone())],
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&stsd, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, stsd.size());
+
+    let parsed = read_from_full::<SampleDescriptionBox>(&bytes[..]).unwrap();
+    let hvc1 = parsed.entries[0]
+        .as_any()
+        .downcast_ref::<HevcSampleEntry>()
+        .unwrap();
+    assert_eq!(hvc1.colr, entry.colr);
+    assert_eq!(hvc1.pasp, entry.pasp);
+    assert!(hvc1.unknown.is_empty());
+}
@@ -0,0 +1,151 @@
+use crate::common::*;
+use bmff::*;
+use std::io::{self, Cursor};
+
+#[test]
+fn ctts_version() {
+    for (offset, version, bytes) in [
+        (u32::MAX as i64, 0, [0xff, 0xff, 0xff, 0xff]),
+        (-2, 1, [0xff, 0xff, 0xff, 0xfe]),
+    ] {
+        let ctts = CompositionOffsetBox {
+            version: composition_offsets_version([2, offset]),
+            samples: vec![(1, 2), (3, offset)],
+        };
+        let mut buf = Vec::new();
+        write_to_full(&ctts, &mut buf).unwrap();
+        assert_eq!(buf.len() as u64, ctts.size());
+        assert_eq!(buf[8], version);
+        assert_eq!(buf[buf.len() - 4..], bytes);
+
+        let parsed = read_from_full::<CompositionOffsetBox>(&buf[..]).unwrap();
+        assert_eq!(parsed.version, version);
+        assert_eq!(parsed.samples, ctts.samples);
+    }
+}
+
+#[test]
+fn stsz_constant_size_keeps_count() {
+    let stsz = SampleSizeBox {
+        sample_size: SampleSize::Same {
+            size: std::num::NonZeroU32::new(10).unwrap(),
+            sample_count: 5,
+        },
+    };
+    let mut buf = Vec::new();
+    write_to_full(&stsz, &mut buf).unwrap();
+    assert_eq!(buf.len() as u64, stsz.size());
+    assert_eq!(buf[12..], [0, 0, 0, 10, 0, 0, 0, 5]);
+    let parsed = read_from_full::<SampleSizeBox>(&buf[..]).unwrap();
+    assert_eq!(parsed.sample_size, stsz.sample_size);
+}
+
+#[test]
+fn ctts_version_is_kept() {
+    // version 1 with offsets that would also fit in version 0
+    let ctts = CompositionOffsetBox {
+        version: 1,
+        samples: vec![(1, 2), (3, 0)],
+    };
+    let mut buf = Vec::new();
+    write_to_full(&ctts, &mut buf).unwrap();
+    assert_eq!(buf[8], 1);
+    let parsed = read_from_full::<CompositionOffsetBox>(&buf[..]).unwrap();
+    assert_eq!(parsed.version, 1);
+    let mut again = Vec::new();
+    write_to_full(&parsed, &mut again).unwrap();
+    assert_eq!(again, buf);
+
+    let ctts = CompositionOffsetBox { version: 2, ..ctts };
+    assert!(matches!(ctts.validate(), Err(Error::Invalid(b, _)) if b == *b"ctts"));
+}
+
+#[test]
+fn ctts_offset_out_of_range() {
+    for (version, offsets) in [
+        (0, vec![u32::MAX as i64 + 1]),
+        (0, vec![-1]),
+        (1, vec![-1, i32::MAX as i64 + 1]),
+    ] {
+        let ctts = CompositionOffsetBox {
+            version,
+            samples: offsets.into_iter().map(|x| (1, x)).collect(),
+        };
+        assert_eq!(
+            ctts.validate(),
+            Err(Error::Overflow(*b"ctts", "sample_offset"))
+        );
+        assert!(write_to_full(&ctts, io::sink()).is_err());
+    }
+}
+
+#[test]
+fn trun_signed_composition_offsets() {
+    let (mut moof, _) = fragment(1);
+    let trun = &mut moof.traf[0].trun[0];
+    trun.sample_composition_time_offsets = Some(vec![2, -1, 0]);
+    assert!(write_to(&moof, io::sink()).is_err());
+    moof.traf[0].trun[0].version = 1;
+    let bytes = serialize(&moof);
+
+    let parsed = read_from::<MovieFragmentBox>(&bytes[..]).unwrap();
+    let parsed = &parsed.traf[0].trun[0];
+    assert_eq!(parsed.version(), 1);
+    assert_eq!(parsed.sample_composition_time_offsets, Some(vec![2, -1, 0]));
+}
+
+#[test]
+fn trun_version_is_kept() {
+    let (mut moof, _) = fragment(1);
+    let trun = &mut moof.traf[0].trun[0];
+    trun.version = 1;
+    trun.sample_composition_time_offsets = Some(vec![2, 1, 0]);
+    let bytes = serialize(&moof);
+
+    let parsed = read_from::<MovieFragmentBox>(&bytes[..]).unwrap();
+    assert_eq!(parsed.traf[0].trun[0].version, 1);
+    assert_eq!(serialize(&parsed), bytes);
+}
+
+#[test]
+fn stbl_round_trip() {
+    let mut moov = moov();
+    let stbl = &mut moov.trak[0].mdia.minf.stbl;
+    stbl.ctts = Some(CompositionOffsetBox {
+        version: 1,
+        samples: vec![(1, 1), (1, -1)],
+    });
+    stbl.stss = Some(SyncSampleBox {
+        sample_numbers: vec![1, 31],
+    });
+    let bytes = serialize(&moov);
+
+    let parsed = read_from::<MovieBox>(&bytes[..]).unwrap();
+    let stbl = &parsed.trak[0].mdia.minf.stbl;
+    assert_eq!(stbl.ctts.as_ref().unwrap().samples, [(1, 1), (1, -1)]);
+    assert_eq!(stbl.stss.as_ref().unwrap().sample_numbers, [1, 31]);
+}
+
+#[test]
+fn progressive_b_frames() {
+    let mut writer = ProgressiveWriter::new(ftyp(), moov(), Cursor::new(Vec::new())).unwrap();
+    // I P B B, in decode order
+    for (offset, is_sync) in [(1, true), (3, false), (0, false), (0, false)] {
+        writer.write_sample(1, &[0; 4], 1, offset, is_sync).unwrap();
+    }
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let moov = Demuxer::new(Cursor::new(&bytes))
+        .unwrap()
+        .find_map(|x| match x.unwrap() {
+            TopLevelBox::Moov(moov) => Some(moov),
+            _ => None,
+        })
+        .unwrap();
+    let stbl = &moov.trak[0].mdia.minf.stbl;
+    assert_eq!(
+        stbl.ctts.as_ref().unwrap().samples,
+        [(1, 1), (1, 3), (2, 0)]
+    );
+    assert_eq!(stbl.stss.as_ref().unwrap().sample_numbers, [1]);
+}
@@ -0,0 +1,305 @@
+use crate::common::*;
+use bmff::*;
+use std::io::{self, Cursor};
+
+fn styp() -> SegmentTypeBox {
+    SegmentTypeBox {
+        major_brand: *b"msdh",
+        minor_version: 0,
+        compatible_brands: vec![*b"msdh", *b"msix"],
+    }
+}
+
+fn reference(referenced_size: u32) -> SegmentReference {
+    SegmentReference {
+        reference_type: SegmentReferenceType::Media,
+        referenced_size,
+        subsegment_duration: 3,
+        starts_with_sap: true,
+        sap_type: 1,
+        sap_delta_time: 0,
+    }
+}
+
+#[test]
+fn sidx_version() {
+    for (first_offset, version, size) in [(0, 0, 56), (u32::MAX as u64 + 1, 1, 64)] {
+        let sidx = SegmentIndexBox {
+            reference_id: 1,
+            timescale: 30,
+            earliest_presentation_time: 90,
+            first_offset,
+            references: vec![reference(1234), reference(5678)],
+        };
+        let mut bytes = Vec::new();
+        write_to_full(&sidx, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        assert_eq!(bytes.len() as u64, sidx.size());
+        assert_eq!(bytes[8], version);
+
+        let parsed = read_from_full::<SegmentIndexBox>(&bytes[..]).unwrap();
+        assert_eq!(parsed.earliest_presentation_time, 90);
+        assert_eq!(parsed.first_offset, first_offset);
+        assert_eq!(parsed.references, sidx.references);
+    }
+}
+
+#[test]
+fn sidx_reference_fields() {
+    let sidx = SegmentIndexBox {
+        reference_id: 1,
+        timescale: 30,
+        earliest_presentation_time: 0,
+        first_offset: 0,
+        references: vec![SegmentReference {
+            reference_type: SegmentReferenceType::Index,
+            referenced_size: 0x7fff_ffff,
+            subsegment_duration: 60,
+            starts_with_sap: false,
+            sap_type: 3,
+            sap_delta_time: 0x0abc_def0,
+        }],
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&sidx, &mut bytes).unwrap();
+    assert_eq!(
+        bytes[32..],
+        [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 60, 0x3a, 0xbc, 0xde, 0xf0]
+    );
+    let parsed = read_from_full::<SegmentIndexBox>(&bytes[..]).unwrap();
+    assert_eq!(parsed.references, sidx.references);
+}
+
+#[test]
+fn demux_media_segment() {
+    let (moof, mdat) = fragment(1);
+    let sidx = SegmentIndexBox {
+        reference_id: 1,
+        timescale: 30,
+        earliest_presentation_time: 0,
+        first_offset: 0,
+        references: vec![reference((moof.size() + mdat.size()) as u32)],
+    };
+    let mut bytes = serialize(&styp());
+    write_to_full(&sidx, &mut bytes).unwrap();
+    bytes.extend(serialize(&moof));
+    bytes.extend(serialize(&mdat));
+
+    let boxes = Demuxer::new(Cursor::new(&bytes))
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(boxes.len(), 4);
+    let TopLevelBox::Styp(styp) = &boxes[0] else {
+        panic!("expected styp, got {:?}", boxes[0]);
+    };
+    assert_eq!(styp.compatible_brands, [*b"msdh", *b"msix"]);
+    let TopLevelBox::Sidx(parsed) = &boxes[1] else {
+        panic!("expected sidx, got {:?}", boxes[1]);
+    };
+    assert_eq!(parsed.references, sidx.references);
+    assert!(matches!(boxes[2], TopLevelBox::Moof(_)));
+    assert!(matches!(boxes[3], TopLevelBox::Mdat(_)));
+}
+
+#[test]
+fn mfra_indexes_fragments() {
+    let init_size = (serialize(&ftyp()).len() + serialize(&moov()).len()) as u64;
+    let (moof, mdat) = fragment(1);
+    let fragment_size = moof.size() + mdat.size();
+    let mut bytes = file(2);
+    let mfra = MovieFragmentRandomAccessBox::new(
+        1,
+        [(0, init_size, 1), (3, init_size + fragment_size, 1)],
+    );
+    bytes.extend(serialize(&mfra));
+
+    // the trailing mfro gives the size of the mfra box
+    let mfra_size = u32::from_be_bytes(bytes[bytes.len() - 4..].try_into().unwrap());
+    assert_eq!(mfra_size as u64, mfra.size());
+    assert_eq!(&bytes[bytes.len() - mfra_size as usize + 4..][..4], b"mfra");
+
+    let boxes = Demuxer::new(Cursor::new(&bytes))
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    let TopLevelBox::Mfra(parsed) = &boxes[6] else {
+        panic!("expected mfra, got {:?}", boxes[6]);
+    };
+    let entries = &parsed.tfra[0].entries;
+    assert_eq!(parsed.tfra[0].track_id, 1);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].time, 3);
+    for entry in entries {
+        let offset = entry.moof_offset as usize;
+        assert_eq!(&bytes[offset + 4..offset + 8], b"moof");
+    }
+}
+
+#[test]
+fn tfra_field_sizes() {
+    for (time, sample_number, version, size) in [
+        (0, 1, 0, 48 + 4 + 4 + 1 + 1 + 1),
+        (0, 0x1_0000, 0, 48 + 4 + 4 + 1 + 1 + 3),
+        (u32::MAX as u64 + 1, 0x100_0000, 1, 48 + 8 + 8 + 1 + 1 + 4),
+    ] {
+        let tfra = TrackFragmentRandomAccessBox {
+            track_id: 2,
+            entries: vec![RandomAccessEntry {
+                time,
+                moof_offset: 1000,
+                traf_number: 1,
+                trun_number: 1,
+                sample_number,
+            }],
+        };
+        let mfra = MovieFragmentRandomAccessBox {
+            tfra: vec![tfra],
+            unknown: vec![],
+        };
+        let bytes = serialize(&mfra);
+        assert_eq!(bytes.len(), size);
+        assert_eq!(bytes[16], version);
+        let parsed = read_from::<MovieFragmentRandomAccessBox>(&bytes[..]).unwrap();
+        assert_eq!(parsed.tfra[0].entries, mfra.tfra[0].entries);
+    }
+}
+
+fn emsg(presentation_time: EventTime) -> EventMessageBox {
+    EventMessageBox {
+        scheme_id_uri: "urn:example:pet-monitor".to_string(),
+        value: "motion".to_string(),
+        timescale: 30,
+        presentation_time,
+        event_duration: 0xffff_ffff,
+        id: 7,
+        message_data: b"{\"zone\":1}".to_vec(),
+    }
+}
+
+#[test]
+fn emsg_versions() {
+    for (presentation_time, version, timescale_offset) in [
+        (EventTime::Delta(15), 0, 12 + 24 + 7),
+        (EventTime::Absolute(u32::MAX as u64 + 1), 1, 12),
+    ] {
+        let emsg = emsg(presentation_time);
+        let mut bytes = Vec::new();
+        write_to_full(&emsg, &mut bytes).unwrap();
+        assert_eq!(bytes.len() as u64, emsg.size());
+        assert_eq!(bytes[8], version);
+        assert_eq!(
+            bytes[timescale_offset..timescale_offset + 4],
+            30u32.to_be_bytes()
+        );
+        assert!(bytes.ends_with(b"{\"zone\":1}"));
+
+        let parsed = read_from_full::<EventMessageBox>(&bytes[..]).unwrap();
+        assert_eq!(parsed, emsg);
+    }
+}
+
+#[test]
+fn emsg_null_in_string() {
+    let mut emsg = emsg(EventTime::Delta(0));
+    emsg.value = "a\0b".to_string();
+    assert!(emsg.validate().is_err());
+    assert!(write_to_full(&emsg, io::sink()).is_err());
+}
+
+#[test]
+fn demux_events_between_fragments() {
+    let mut bytes = file(1);
+    let event = emsg(EventTime::Absolute(3));
+    write_to_full(&event, &mut bytes).unwrap();
+    let (moof, mdat) = fragment(2);
+    bytes.extend(serialize(&moof));
+    bytes.extend(serialize(&mdat));
+
+    let boxes = Demuxer::new(Cursor::new(&bytes))
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(boxes.len(), 7);
+    let TopLevelBox::Emsg(parsed) = &boxes[4] else {
+        panic!("expected emsg, got {:?}", boxes[4]);
+    };
+    assert_eq!(parsed, &event);
+    assert!(matches!(boxes[5], TopLevelBox::Moof(_)));
+}
+
+#[test]
+fn prft_ntp_timestamp() {
+    let ntp_timestamp = DateTime::<Utc>::from_timestamp(1_700_000_000, 500_000_000).unwrap();
+    for (media_time, version, size) in [(90, 0, 28), (u32::MAX as u64 + 1, 1, 32)] {
+        let prft = ProducerReferenceTimeBox {
+            reference_track_id: 1,
+            time_source: ReferenceTimeSource::Captured,
+            ntp_timestamp,
+            media_time,
+        };
+        let mut bytes = Vec::new();
+        write_to_full(&prft, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), size);
+        assert_eq!(bytes[8..12], [version, 0, 0, 24]);
+        // seconds since 1900, then a 32-bit fraction
+        assert_eq!(
+            bytes[16..20],
+            (1_700_000_000u32 + 2_208_988_800).to_be_bytes()
+        );
+        assert_eq!(bytes[20..24], [0x80, 0, 0, 0]);
+
+        let parsed = read_from_full::<ProducerReferenceTimeBox>(&bytes[..]).unwrap();
+        assert_eq!(parsed, prft);
+    }
+}
+
+#[test]
+fn prft_before_ntp_epoch() {
+    let prft = ProducerReferenceTimeBox {
+        reference_track_id: 1,
+        time_source: ReferenceTimeSource::default(),
+        ntp_timestamp: DateTime::<Utc>::from_timestamp(-2_208_988_801, 0).unwrap(),
+        media_time: 0,
+    };
+    assert_eq!(
+        prft.validate(),
+        Err(Error::Overflow(*b"prft", "ntp_timestamp"))
+    );
+}
+
+#[test]
+fn prft_next_ntp_era() {
+    // 2036-02-07T06:28:16Z is the start of NTP era 1
+    let era_1 = (1i64 << 32) - 2_208_988_800;
+    for (unix, ntp) in [
+        (era_1 - 1, u32::MAX),
+        (era_1, 0),
+        (era_1 + 100, 100),
+        // the last second that can be written
+        (era_1 + (1 << 31) - 1, i32::MAX as u32),
+    ] {
+        let prft = ProducerReferenceTimeBox {
+            reference_track_id: 1,
+            time_source: ReferenceTimeSource::default(),
+            ntp_timestamp: DateTime::<Utc>::from_timestamp(unix, 0).unwrap(),
+            media_time: 0,
+        };
+        let mut bytes = Vec::new();
+        write_to_full(&prft, &mut bytes).unwrap();
+        assert_eq!(bytes[16..20], ntp.to_be_bytes());
+        let parsed = read_from_full::<ProducerReferenceTimeBox>(&bytes[..]).unwrap();
+        assert_eq!(parsed, prft);
+    }
+
+    let prft = ProducerReferenceTimeBox {
+        reference_track_id: 1,
+        time_source: ReferenceTimeSource::default(),
+        ntp_timestamp: DateTime::<Utc>::from_timestamp(era_1 + (1 << 31), 0).unwrap(),
+        media_time: 0,
+    };
+    assert_eq!(
+        prft.validate(),
+        Err(Error::Overflow(*b"prft", "ntp_timestamp"))
+    );
+}
@@ -0,0 +1,120 @@
+use crate::common::*;
+use bmff::*;
+use serde_json::json;
+use std::io;
+
+#[test]
+fn init_segment_round_trip() {
+    let moov = moov();
+    let value = serde_json::to_value(&moov).unwrap();
+    assert_eq!(value["mvhd"]["duration"], json!(10_000_000_000u64));
+    let entry = &value["trak"][0]["mdia"]["minf"]["stbl"]["stsd"]["entries"][0];
+    assert_eq!(entry["type"], json!("avc1"));
+    assert_eq!(entry["width"], json!(640));
+
+    let parsed: MovieBox = serde_json::from_value(value).unwrap();
+    assert_eq!(serialize(&parsed), serialize(&moov));
+}
+
+#[test]
+fn durations_keep_ticks() {
+    let mut moov = moov();
+    let mdhd = &mut moov.trak[0].mdia.mdhd;
+    mdhd.timescale = 90_000;
+    // 1001 ticks, which isn't a whole number of milliseconds
+    mdhd.duration = Some(Duration::nanoseconds(11_122_222));
+    let value = serde_json::to_value(&moov).unwrap();
+    assert_eq!(
+        value["trak"][0]["mdia"]["mdhd"]["duration"],
+        json!(11_122_222)
+    );
+
+    let parsed: MovieBox = serde_json::from_value(value).unwrap();
+    assert_eq!(serialize(&parsed), serialize(&moov));
+}
+
+#[test]
+fn fragment_round_trip() {
+    let (moof, mdat) = fragment(1);
+    let json = serde_json::to_string(&(TopLevelBox::Moof(moof.clone()), mdat.clone())).unwrap();
+    let (parsed, parsed_mdat): (TopLevelBox, MediaDataBox) = serde_json::from_str(&json).unwrap();
+    let TopLevelBox::Moof(parsed) = parsed else {
+        panic!("expected moof, got {parsed:?}");
+    };
+    assert_eq!(serialize(&parsed), serialize(&moof));
+    assert_eq!(parsed_mdat.data, mdat.data);
+}
+
+/// A sample entry that this crate doesn't know about.
+#[derive(Debug, Clone)]
+struct Custom;
+
+impl Validate for Custom {
+    fn validate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl SampleEntry for Custom {
+    fn size(&self) -> u64 {
+        12
+    }
+
+    fn write_to(&self) -> io::Result<Vec<u8>> {
+        Ok(b"\0\0\0\x0ccust\0\0\0\x01".to_vec())
+    }
+
+    fn clone_box(&self) -> Box<dyn SampleEntry> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[test]
+fn foreign_sample_entry_is_raw() {
+    let stsd = SampleDescriptionBox {
+        entries: vec![Box::new(Custom)],
+    };
+    let value = serde_json::to_value(&stsd).unwrap();
+    assert_eq!(
+        value["entries"][0],
+        json!({
+            "type": "raw",
+            "box_type": b"cust",
+            "extended_type": null,
+            "payload": [0, 0, 0, 1],
+        })
+    );
+
+    let parsed: SampleDescriptionBox = serde_json::from_value(value).unwrap();
+    let mut bytes = Vec::new();
+    write_to_full(&parsed, &mut bytes).unwrap();
+    assert_eq!(&bytes[16..], Custom.write_to().unwrap());
+}
+
+#[test]
+fn encv_wraps_original_entry() {
+    let mut moov = moov();
+    let stsd = &mut moov.trak[0].mdia.minf.stbl.stsd;
+    stsd.entries[0] = Box::new(EncvSampleEntry {
+        entry: stsd.entries[0].clone_box(),
+        sinf: ProtectionSchemeInfoBox {
+            frma: OriginalFormatBox {
+                data_format: *b"avc1",
+            },
+            schm: None,
+            schi: None,
+            unknown: vec![],
+        },
+    });
+    let value = serde_json::to_value(&moov).unwrap();
+    let entry = &value["trak"][0]["mdia"]["minf"]["stbl"]["stsd"]["entries"][0];
+    assert_eq!(entry["type"], json!("encv"));
+    assert_eq!(entry["entry"]["type"], json!("avc1"));
+
+    let parsed: MovieBox = serde_json::from_value(value).unwrap();
+    assert_eq!(serialize(&parsed), serialize(&moov));
+}
@@ -0,0 +1,375 @@
+use crate::common::*;
+use bmff::*;
+use std::io;
+use std::sync::Arc;
+
+#[test]
+fn fixtures_are_valid() {
+    moov().validate().unwrap();
+    fragment(1).0.validate().unwrap();
+}
+
+#[test]
+fn trun_length_mismatch() {
+    let (mut moof, _) = fragment(1);
+    moof.traf[0].trun[0].sample_durations = Some(vec![1, 1]);
+    assert_eq!(
+        moof.validate(),
+        Err(Error::LengthMismatch(*b"trun", "sample_sizes", 2, 3))
+    );
+
+    // writing returns an error instead of panicking
+    let err = write_to(&moof, &mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn trun_flags_combination() {
+    let (mut moof, _) = fragment(1);
+    moof.traf[0].trun[0].sample_flags = Some(vec![SampleFlags::default(); 3]);
+    assert!(matches!(
+        moof.validate(),
+        Err(Error::Invalid(box_type, _)) if box_type == *b"trun"
+    ));
+}
+
+#[test]
+fn sample_flags_overflow() {
+    let (mut moof, _) = fragment(1);
+    moof.traf[0].tfhd.default_sample_flags = Some(SampleFlags {
+        padding_value: 8,
+        ..Default::default()
+    });
+    assert_eq!(
+        moof.validate(),
+        Err(Error::Overflow(*b"tfhd", "padding_value"))
+    );
+}
+
+#[test]
+fn sample_entries_are_validated() {
+    let mut moov = moov();
+    moov.trak[0]
+        .mdia
+        .minf
+        .stbl
+        .stsd
+        .entries
+        .push(Box::new(VisualSampleEntry {
+            data_reference_index: 1,
+            width: 640,
+            height: 480,
+            horiz_resolution: U16F16::from_num(72),
+            vert_resolution: U16F16::from_num(72),
+            frame_count: 1,
+            compressor_name: "x".repeat(32),
+            depth: 0x0018,
+        }));
+    assert_eq!(
+        moov.validate(),
+        Err(Error::Overflow(*b"vide", "compressor_name"))
+    );
+    let err = write_to(&moov, &mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn invalid_sample_entry_is_not_written() {
+    let entry = VisualSampleEntry {
+        data_reference_index: 1,
+        width: 640,
+        height: 480,
+        horiz_resolution: U16F16::from_num(72),
+        vert_resolution: U16F16::from_num(72),
+        frame_count: 1,
+        compressor_name: "x".repeat(40),
+        depth: 0x0018,
+    };
+    let err = SampleEntry::write_to(&entry).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn opus_channel_mapping() {
+    let mut dops = OpusSpecificBox {
+        output_channel_count: 2,
+        pre_skip: 312,
+        input_sample_rate: 48_000,
+        output_gain: I8F8::ZERO,
+        channel_mapping_family: 1,
+        channel_mapping_table: None,
+    };
+    assert!(matches!(dops.validate(), Err(Error::Invalid(..))));
+    dops.channel_mapping_table = Some(ChannelMappingTable {
+        stream_count: 1,
+        coupled_count: 1,
+        channel_mapping: vec![0],
+    });
+    assert_eq!(
+        dops.validate(),
+        Err(Error::LengthMismatch(*b"dOps", "channel_mapping", 2, 1))
+    );
+    dops.channel_mapping_table = Some(ChannelMappingTable {
+        stream_count: 1,
+        coupled_count: 1,
+        channel_mapping: vec![0, 1],
+    });
+    dops.validate().unwrap();
+}
+
+#[test]
+fn sidx_overflow() {
+    let sidx = SegmentIndexBox {
+        reference_id: 1,
+        timescale: 30,
+        earliest_presentation_time: 0,
+        first_offset: 0,
+        references: vec![SegmentReference {
+            reference_type: SegmentReferenceType::Media,
+            referenced_size: 1 << 31,
+            subsegment_duration: 60,
+            starts_with_sap: true,
+            sap_type: 1,
+            sap_delta_time: 0,
+        }],
+    };
+    assert_eq!(
+        sidx.validate(),
+        Err(Error::Overflow(*b"sidx", "referenced_size"))
+    );
+    assert_eq!(
+        Error::Overflow(*b"sidx", "referenced_size").to_string(),
+        "sidx box: referenced_size is too large"
+    );
+    let err = write_to_full(&sidx, io::sink()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn generated_file_is_valid() {
+    assert_eq!(validate::check(&file(3)), vec![]);
+
+    // the result doesn't depend on how the stream is split
+    let buf = file(3);
+    let mut validator = validate::Validator::new();
+    for chunk in buf.chunks(7) {
+        assert_eq!(validator.push(chunk), vec![]);
+    }
+    assert_eq!(validator.finish(), vec![]);
+}
+
+#[test]
+fn wrong_data_offset() {
+    let mut buf = file(1);
+    let (mut moof, mdat) = fragment(2);
+    let offset = buf.len() as u64;
+    moof.traf[0].trun[0].data_offset = Some(moof.size() as i32 + 4);
+    buf.extend(serialize(&moof));
+    buf.extend(serialize(&mdat));
+    assert_eq!(
+        validate::check(&buf),
+        vec![validate::Issue {
+            offset,
+            violation: validate::Violation::DataOffset(1),
+        }]
+    );
+}
+
+#[test]
+fn wrong_base_data_offset() {
+    let mut buf = file(1);
+    let (mut moof, mdat) = fragment(2);
+    // relative to the start of the segment instead of the stream
+    moof.traf[0].tfhd.default_base_is_moof = false;
+    moof.traf[0].tfhd.base_data_offset = Some(0);
+    buf.extend(serialize(&moof));
+    buf.extend(serialize(&mdat));
+    let issues = validate::check(&buf);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].violation, validate::Violation::DataOffset(1));
+}
+
+#[test]
+fn wrong_sample_sizes() {
+    let mut buf = file(1);
+    let (mut moof, mdat) = fragment(2);
+    moof.traf[0].trun[0].sample_sizes = Some(vec![4, 5, 4]);
+    buf.extend(serialize(&moof));
+    let offset = buf.len() as u64;
+    buf.extend(serialize(&mdat));
+    assert_eq!(
+        validate::check(&buf),
+        vec![validate::Issue {
+            offset,
+            violation: validate::Violation::SampleSizes(13, 15),
+        }]
+    );
+}
+
+#[test]
+fn sequence_numbers_increase() {
+    let mut buf = file(2);
+    let (moof, mdat) = fragment(2);
+    buf.extend(serialize(&moof));
+    buf.extend(serialize(&mdat));
+    let issues = validate::check(&buf);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(
+        issues[0].violation,
+        validate::Violation::SequenceNumber(2, 2)
+    );
+    assert_eq!(
+        issues[0].violation.to_string(),
+        "mfhd sequence number 2 follows 2"
+    );
+}
+
+#[test]
+fn missing_mdat() {
+    let mut buf = file(0);
+    let offset = buf.len() as u64;
+    buf.extend(serialize(&fragment(1).0));
+    assert_eq!(
+        validate::check(&buf),
+        vec![validate::Issue {
+            offset,
+            violation: validate::Violation::MissingMdat,
+        }]
+    );
+}
+
+#[test]
+fn box_nesting() {
+    let mut moov = moov();
+    moov.unknown.push(
+        RawBox {
+            box_type: *b"mfhd",
+            extended_type: None,
+            payload: vec![0; 8],
+        }
+        .into(),
+    );
+    let mut buf = serialize(&ftyp());
+    buf.extend(serialize(&moov));
+    buf.extend(serialize(&ftyp())[..10].iter());
+    let issues = validate::check(&buf);
+    let violations: Vec<_> = issues.into_iter().map(|x| x.violation).collect();
+    assert_eq!(
+        violations,
+        vec![
+            validate::Violation::Misplaced(*b"mfhd", Some(*b"moov")),
+            validate::Violation::Truncated(*b"ftyp"),
+        ]
+    );
+}
+
+#[test]
+fn many_boxes() {
+    // each `mdat` is skipped without buffering or copying the rest of the
+    // stream
+    let free = RawBox {
+        box_type: *b"free",
+        extended_type: None,
+        payload: vec![],
+    };
+    let mut buf = Vec::new();
+    for _ in 0..100_000 {
+        WriteTo::write_to(&free, &mut buf).unwrap();
+        buf.extend(serialize(&MediaDataBox {
+            headers: None,
+            data: Arc::new(vec![]),
+        }));
+    }
+    buf.extend(serialize(&MediaDataBox {
+        headers: None,
+        data: Arc::new(vec![0; 1 << 24]),
+    }));
+    assert_eq!(validate::check(&buf), vec![]);
+
+    let mut validator = validate::Validator::new();
+    for chunk in buf.chunks(1000) {
+        assert_eq!(validator.push(chunk), vec![]);
+    }
+    assert_eq!(validator.finish(), vec![]);
+}
+
+#[test]
+fn sample_entries_are_checked() {
+    let encrypted = |sinf| {
+        let mut moov = moov();
+        let stsd = &mut moov.trak[0].mdia.minf.stbl.stsd;
+        stsd.entries[0] = Box::new(EncvSampleEntry {
+            entry: stsd.entries[0].clone_box(),
+            sinf,
+        });
+        let mut buf = serialize(&ftyp());
+        buf.extend(serialize(&moov));
+        buf
+    };
+    let mut sinf = crate::cenc::sinf();
+    assert_eq!(validate::check(&encrypted(sinf.clone())), vec![]);
+
+    sinf.unknown.push(
+        RawBox {
+            box_type: *b"tenc",
+            extended_type: None,
+            payload: vec![0; 24],
+        }
+        .into(),
+    );
+    let violations: Vec<_> = validate::check(&encrypted(sinf))
+        .into_iter()
+        .map(|x| x.violation)
+        .collect();
+    assert_eq!(
+        violations,
+        vec![validate::Violation::Misplaced(*b"tenc", Some(*b"sinf"))]
+    );
+}
+
+#[test]
+fn header_boxes_are_validated() {
+    let mut moov = moov();
+    moov.trak[0].mdia.mdhd.language = *b"ENG";
+    assert!(matches!(
+        moov.validate(),
+        Err(Error::Invalid(box_type, _)) if box_type == *b"mdhd"
+    ));
+    // writing returns an error instead of panicking
+    let err = write_to(&moov, &mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let mut moov = self::moov();
+    moov.trak[0].tkhd.track_id = 0;
+    assert_eq!(
+        moov.validate(),
+        Err(Error::Invalid(*b"tkhd", "track_id can't be 0"))
+    );
+
+    let mut moov = self::moov();
+    moov.trak[0].mdia.hdlr.name = "a\0b".to_string();
+    assert!(matches!(
+        moov.validate(),
+        Err(Error::Invalid(box_type, _)) if box_type == *b"hdlr"
+    ));
+}
+
+#[test]
+fn sample_table_boxes_are_validated() {
+    let mut moov = moov();
+    moov.trak[0].mdia.minf.stbl.stsc.entries = vec![(2, 1, 1), (1, 1, 1)];
+    assert_eq!(
+        moov.validate(),
+        Err(Error::Invalid(
+            *b"stsc",
+            "first_chunk must start at 1 and increase"
+        ))
+    );
+
+    let mut moov = self::moov();
+    moov.trak[0].mdia.minf.dinf.dref.data_entries.clear();
+    assert!(matches!(
+        moov.validate(),
+        Err(Error::Invalid(box_type, _)) if box_type == *b"dref"
+    ));
+}
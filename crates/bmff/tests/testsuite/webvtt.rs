@@ -0,0 +1,135 @@
+use crate::common::*;
+use bmff::*;
+use std::io::Cursor;
+
+fn wvtt() -> WvttSampleEntry {
+    WvttSampleEntry {
+        data_reference_index: 1,
+        config: WebVttConfigurationBox {
+            config: "WEBVTT".to_string(),
+        },
+        label: Some(WebVttSourceLabelBox {
+            source_label: "urn:example:camera".to_string(),
+        }),
+        unknown: vec![],
+    }
+}
+
+fn serialize_sample(sample: &VttSample) -> Vec<u8> {
+    let mut buf = Vec::new();
+    sample.write_to(&mut buf).unwrap();
+    assert_eq!(buf.len() as u64, sample.size());
+    buf
+}
+
+#[test]
+fn sample_entry_round_trip() {
+    let stsd = SampleDescriptionBox {
+        entries: vec![Box::new(wvtt())],
+    };
+    let mut bytes = Vec::new();
+    write_to_full(&stsd, &mut bytes).unwrap();
+    assert_eq!(bytes.len() as u64, stsd.size());
+    assert_eq!(&bytes[20..24], b"wvtt");
+    assert_eq!(&bytes[32..46], b"\0\0\0\x0evttCWEBVTT");
+
+    let parsed = read_from_full::<SampleDescriptionBox>(&bytes[..]).unwrap();
+    let entry = parsed.entries[0]
+        .as_any()
+        .downcast_ref::<WvttSampleEntry>()
+        .unwrap();
+    assert_eq!(entry.config, wvtt().config);
+    assert_eq!(entry.label, wvtt().label);
+}
+
+#[test]
+fn cue_samples() {
+    let sample = VttSample::Cues(vec![
+        VttCueBox {
+            source_id: None,
+            current_time: None,
+            cue_id: None,
+            settings: None,
+            payload: "Motion detected".to_string(),
+        },
+        VttCueBox {
+            source_id: Some(1),
+            current_time: Some("00:00:01.000".to_string()),
+            cue_id: Some("temp".to_string()),
+            settings: Some("line:0 position:90%".to_string()),
+            payload: "21 °C".to_string(),
+        },
+    ]);
+    let bytes = serialize_sample(&sample);
+    assert_eq!(&bytes[..8], b"\0\0\0\x1fvttc");
+    assert_eq!(&bytes[8..31], b"\0\0\0\x17paylMotion detected");
+    assert_eq!(VttSample::from_bytes(&bytes).unwrap(), sample);
+
+    let empty = serialize_sample(&VttSample::Empty);
+    assert_eq!(empty, b"\0\0\0\x08vtte");
+    assert_eq!(VttSample::from_bytes(&empty).unwrap(), VttSample::Empty);
+
+    assert!(VttSample::from_bytes(b"").is_err());
+    assert!(VttSample::from_bytes(b"\0\0\0\x08vttc").is_err());
+    assert!(VttSample::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn text_track_next_to_video() {
+    let mut moov = moov();
+    let mut trak = moov.trak[0].clone();
+    trak.tkhd.track_id = 2;
+    trak.tkhd.volume = I8F8::from_num(0);
+    trak.tkhd.width = U16F16::from_num(0);
+    trak.tkhd.height = U16F16::from_num(0);
+    trak.mdia.hdlr = HandlerBox {
+        handler_type: HandlerType::Text,
+        name: "TextHandler".to_string(),
+    };
+    trak.mdia.minf.media_header = MediaHeader::Null(NullMediaHeaderBox {
+        flags: NullMediaHeaderFlags::empty(),
+    });
+    trak.mdia.minf.stbl.stsd.entries = vec![Box::new(wvtt())];
+    moov.trak.push(trak);
+    moov.mvhd.next_track_id = 3;
+
+    let cue = VttSample::Cues(vec![VttCueBox {
+        source_id: None,
+        current_time: None,
+        cue_id: None,
+        settings: None,
+        payload: "Motion detected".to_string(),
+    }]);
+    let mut writer = ProgressiveWriter::new(ftyp(), moov, Cursor::new(Vec::new())).unwrap();
+    writer.write_sample(1, &[0; 4], 1, 0, true).unwrap();
+    writer
+        .write_sample(2, &serialize_sample(&cue), 2, 0, true)
+        .unwrap();
+    writer.write_sample(1, &[0; 4], 1, 0, false).unwrap();
+    writer
+        .write_sample(2, &serialize_sample(&VttSample::Empty), 1, 0, true)
+        .unwrap();
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let moov = Demuxer::new(Cursor::new(&bytes))
+        .unwrap()
+        .find_map(|x| match x.unwrap() {
+            TopLevelBox::Moov(moov) => Some(moov),
+            _ => None,
+        })
+        .unwrap();
+    let trak = &moov.trak[1];
+    assert!(matches!(trak.mdia.hdlr.handler_type, HandlerType::Text));
+    let stbl = &trak.mdia.minf.stbl;
+    assert!(stbl.stsd.entries[0]
+        .as_any()
+        .downcast_ref::<WvttSampleEntry>()
+        .is_some());
+    assert_eq!(stbl.stts.samples, [(1, 2), (1, 1)]);
+    let SampleSize::Different(sizes) = &stbl.stsz.sample_size else {
+        panic!("expected per-sample sizes");
+    };
+    let offset = stbl.chunk_offset.chunk_offsets()[0] as usize;
+    let sample = &bytes[offset..offset + sizes[0] as usize];
+    assert_eq!(VttSample::from_bytes(sample).unwrap(), cue);
+}
@@ -22,6 +22,11 @@ pub struct Config {
     pub interval: (u32, u32),
     /// The rotation for the MP4 matrix. This is not supported by some media players.
     pub rotation: Rotation,
+    /// Whether to write a `prft` box before each media segment, so that clients can
+    /// measure how far behind live they are. The time is when the first frame of the
+    /// segment was passed to the encoder, or dequeued from the camera for H264.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub producer_reference_time: bool,
     /// Additional controls to pass to V4L2.
     pub v4l2_controls: HashMap<String, String>,
 }
@@ -34,6 +39,7 @@ impl Default for Config {
             resolution: (640, 480),
             interval: (1, 30),
             rotation: Rotation::R0,
+            producer_reference_time: false,
             v4l2_controls: HashMap::new(),
         }
     }
@@ -53,8 +53,9 @@ pub mod config;
 use config::{Config, Format, Rotation};
 
 use bmff::*;
-use chrono::{Duration, Utc};
-use fixed::types::{I16F16, I8F8, U16F16};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use fixed::types::U16F16;
 use flume::r#async::RecvStream;
 use futures_lite::stream::{self, Stream, StreamExt};
 use quick_error::quick_error;
@@ -88,6 +89,12 @@ quick_error! {
             display("{}", err)
             from()
         }
+        /// A box failed validation. This wraps a [`bmff::Error`].
+        Bmff(err: bmff::Error) {
+            source(err)
+            display("{}", err)
+            from()
+        }
         /// Another unspecified error.
         Other(err: String) {
             display("{}", err)
@@ -129,7 +136,7 @@ impl WriteTo for InitSegment {
 }
 
 impl InitSegment {
-    fn new(config: &Config) -> Self {
+    fn new(config: &Config) -> Result<Self> {
         let sps = vec![
             0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x80, 0x50, 0x05, 0xbb, 0x01, 0x6a, 0x02, 0x02,
             0x02, 0x80, 0x00, 0x00, 0x03, 0x00, 0x80, 0x00, 0x00, 0x1e, 0x07, 0x8c, 0x18, 0xcd,
@@ -137,6 +144,11 @@ impl InitSegment {
         let pps = vec![0x68, 0xe9, 0x7b, 0x2c, 0x8b]; // TODO
         let timescale = config.interval.1;
         let (width, height) = config.resolution;
+        let too_large = |_| format!("resolution {width}x{height} is too large");
+        let (entry_width, entry_height) = (
+            u16::try_from(width).map_err(too_large)?,
+            u16::try_from(height).map_err(too_large)?,
+        );
 
         let ftyp = FileTypeBox {
             major_brand: *b"isom",
@@ -144,110 +156,55 @@ impl InitSegment {
             compatible_brands: vec![*b"isom", *b"iso6", *b"iso2", *b"avc1", *b"mp41"],
         };
 
-        let time = Utc::now();
-        let duration = Some(Duration::zero());
+        let colr = match config.format {
+            // the camera's encoder describes the colours in the SPS
+            Format::H264 => None,
+            // x264 converts RGB to full-range YUV, and webcams capture
+            // full-range YUV, which x264 passes through as is. Without this,
+            // players assume limited range and the picture looks washed out.
+            Format::YUYV | Format::YV12 | Format::RGB3 | Format::BGR3 => {
+                Some(ColourInformationBox::Nclx {
+                    colour_primaries: 1,         // BT.709, the same as sRGB
+                    transfer_characteristics: 1, // BT.709
+                    matrix_coefficients: 6,      // BT.601
+                    full_range: true,
+                })
+            }
+        };
 
-        let moov = MovieBox {
-            mvhd: MovieHeaderBox {
-                creation_time: time,
-                modification_time: time,
-                timescale,
-                duration,
-                rate: I16F16::from_num(1),
-                volume: I8F8::from_num(1),
-                matrix: matrix(config.rotation),
-                next_track_id: 0,
-            },
-            trak: vec![TrackBox {
-                tkhd: TrackHeaderBox {
-                    flags: TrackHeaderFlags::TRACK_ENABLED
-                        | TrackHeaderFlags::TRACK_IN_MOVIE
-                        | TrackHeaderFlags::TRACK_IN_PREVIEW,
-                    creation_time: time,
-                    modification_time: time,
-                    track_id: 1,
-                    timescale,
-                    duration,
-                    layer: 0,
-                    alternate_group: 0,
-                    volume: I8F8::from_num(1),
-                    matrix: matrix(config.rotation),
-                    width: U16F16::from_num(width),
-                    height: U16F16::from_num(height),
-                },
-                tref: None,
-                edts: None,
-                mdia: MediaBox {
-                    mdhd: MediaHeaderBox {
-                        creation_time: time,
-                        modification_time: time,
-                        timescale,
-                        duration,
-                        language: *b"und",
-                    },
-                    hdlr: HandlerBox {
-                        handler_type: HandlerType::Video,
-                        name: "foo".to_string(), // TODO
-                    },
-                    minf: MediaInformationBox {
-                        media_header: MediaHeader::Video(VideoMediaHeaderBox {
-                            graphics_mode: GraphicsMode::Copy,
-                            opcolor: [0, 0, 0],
-                        }),
-                        dinf: DataInformationBox {
-                            dref: DataReferenceBox {
-                                data_entries: vec![DataEntry::Url(DataEntryUrlBox {
-                                    flags: DataEntryFlags::SELF_CONTAINED,
-                                    location: String::new(),
-                                })],
-                            },
-                        },
-                        stbl: SampleTableBox {
-                            stsd: SampleDescriptionBox {
-                                entries: vec![Box::new(AvcSampleEntry {
-                                    data_reference_index: 1,
-                                    width: width as u16,
-                                    height: height as u16,
-                                    horiz_resolution: U16F16::from_num(72),
-                                    vert_resolution: U16F16::from_num(72),
-                                    frame_count: 1,
-                                    depth: 0x0018,
-                                    avcc: AvcConfigurationBox {
-                                        configuration: AvcDecoderConfigurationRecord {
-                                            profile_idc: 0x64, // high
-                                            constraint_set_flag: 0x00,
-                                            level_idc: 0x1f, // 0x2a: 4.2 0b0010_1100
-                                            sequence_parameter_set: sps,
-                                            picture_parameter_set: pps,
-                                        },
-                                    },
-                                })],
-                            },
-                            stts: TimeToSampleBox { samples: vec![] },
-                            stsc: SampleToChunkBox { entries: vec![] },
-                            stsz: SampleSizeBox {
-                                sample_size: SampleSize::Different(vec![]),
-                            },
-                            stco: ChunkOffsetBox {
-                                chunk_offsets: vec![],
-                            },
-                        },
-                    },
+        let avc1 = AvcSampleEntry {
+            data_reference_index: 1,
+            width: entry_width,
+            height: entry_height,
+            horiz_resolution: U16F16::from_num(72),
+            vert_resolution: U16F16::from_num(72),
+            frame_count: 1,
+            depth: 0x0018,
+            avcc: AvcConfigurationBox {
+                configuration: AvcDecoderConfigurationRecord {
+                    profile_idc: 0x64, // high
+                    constraint_set_flag: 0x00,
+                    level_idc: 0x1f, // 0x2a: 4.2 0b0010_1100
+                    length_size_minus_one: 3,
+                    sequence_parameter_sets: vec![sps],
+                    picture_parameter_sets: vec![pps],
+                    extension: vec![],
                 },
-            }],
-            mvex: Some(MovieExtendsBox {
-                mehd: None,
-                trex: vec![TrackExtendsBox {
-                    track_id: 1,
-                    default_sample_description_index: 1,
-                    default_sample_duration: 0,
-                    default_sample_size: 0,
-                    default_sample_flags: DefaultSampleFlags::empty(),
-                }],
-            }),
+            },
+            colr,
+            pasp: None,
+            unknown: vec![],
         };
-
-        Self { ftyp, moov }
+        let moov = MovieBuilder::new()
+            .timescale(timescale)
+            .matrix(matrix(config.rotation))
+            .fragmented(true)
+            .track(
+                TrackBuilder::video(avc1, width, height, timescale).matrix(matrix(config.rotation)),
+            )
+            .build()?;
+
+        Ok(Self { ftyp, moov })
     }
 }
 
@@ -256,15 +213,27 @@ impl InitSegment {
 /// It is passed between the streaming thread and [`VideoStream`]s.
 #[derive(Debug, Clone)]
 pub struct MediaSegment {
+    timescale: u32,
+    events: Vec<EventMessageBox>,
+    prft: Option<ProducerReferenceTimeBox>,
     moof: MovieFragmentBox,
     mdat: MediaDataBox,
 }
 
 impl MediaSegment {
-    fn new(config: &Config, sequence_number: u32, sample_sizes: Vec<u32>, data: Vec<u8>) -> Self {
+    fn new(
+        config: &Config,
+        sequence_number: u32,
+        base_media_decode_time: u64,
+        reference_time: DateTime<Utc>,
+        time_source: ReferenceTimeSource,
+        sample_sizes: Vec<u32>,
+        data: Vec<u8>,
+    ) -> Result<Self> {
         let timescale = config.interval.1;
         let mut moof = MovieFragmentBox {
             mfhd: MovieFragmentHeaderBox { sequence_number },
+            pssh: vec![],
             traf: vec![TrackFragmentBox {
                 tfhd: TrackFragmentHeaderBox {
                     track_id: 1,
@@ -274,46 +243,108 @@ impl MediaSegment {
                         timescale * config.interval.0 / config.interval.1,
                     ),
                     default_sample_size: None,
-                    default_sample_flags: {
-                        #[allow(clippy::unwrap_used)] // infallible
-                        Some(DefaultSampleFlags::from_bits(0x0101_0000).unwrap())
-                    }, // not I-frame
+                    default_sample_flags: Some(SampleFlags {
+                        depends_on: SampleDependency::Yes,
+                        is_non_sync_sample: true,
+                        ..Default::default()
+                    }),
                     default_base_is_moof: false,
                 },
+                tfdt: Some(TrackFragmentBaseMediaDecodeTimeBox {
+                    base_media_decode_time,
+                }),
                 trun: vec![TrackFragmentRunBox {
+                    version: 0,
                     data_offset: Some(0),
-                    first_sample_flags: Some(0x0200_0000), // I-frame
+                    first_sample_flags: Some(SampleFlags {
+                        depends_on: SampleDependency::No,
+                        ..Default::default()
+                    }),
                     sample_durations: None,
                     sample_sizes: Some(sample_sizes),
                     sample_flags: None,
                     sample_composition_time_offsets: None,
                 }],
+                saiz: vec![],
+                saio: vec![],
+                senc: None,
+                unknown: vec![],
             }],
+            unknown: vec![],
         };
 
         moof.traf[0].trun[0].data_offset = Some(moof.size() as i32 + 8);
+        moof.validate()?;
+
+        let prft = config
+            .producer_reference_time
+            .then_some(ProducerReferenceTimeBox {
+                reference_track_id: 1,
+                time_source,
+                ntp_timestamp: reference_time,
+                media_time: base_media_decode_time,
+            });
+        if let Some(prft) = &prft {
+            prft.validate()?;
+        }
 
-        Self {
+        Ok(Self {
+            timescale,
+            events: Vec::new(),
+            prft,
             moof,
             mdat: MediaDataBox {
                 headers: None,
                 data: Arc::new(data),
             },
-        }
+        })
     }
 
     fn size(&self) -> u64 {
-        self.moof.size() + self.mdat.size()
+        self.prefix_size() + self.moof.size() + self.mdat.size()
+    }
+
+    /// The size of the boxes written before the moof.
+    fn prefix_size(&self) -> u64 {
+        self.events.iter().map(BmffBox::size).sum::<u64>()
+            + self.prft.as_ref().map_or(0, BmffBox::size)
     }
 
-    fn base_data_offset(&mut self) -> &mut Option<u64> {
-        &mut self.moof.traf[0].tfhd.base_data_offset
+    /// Sets the offset of the segment from the start of the stream.
+    fn set_offset(&mut self, offset: u64) {
+        // sample data offsets are relative to the moof, which follows any
+        // events and the prft
+        self.moof.traf[0].tfhd.base_data_offset = Some(offset + self.prefix_size());
     }
 
     fn sequence_number(&mut self) -> &mut u32 {
         &mut self.moof.mfhd.sequence_number
     }
 
+    fn base_media_decode_time(&self) -> u64 {
+        self.moof.traf[0]
+            .tfdt
+            .map_or(0, |x| x.base_media_decode_time)
+    }
+
+    /// Moves the segment `offset` ticks earlier, so that each stream's
+    /// timeline starts at 0 rather than when streaming started.
+    fn rebase(&mut self, offset: u64) {
+        if let Some(tfdt) = &mut self.moof.traf[0].tfdt {
+            tfdt.base_media_decode_time -= offset;
+        }
+        if let Some(prft) = &mut self.prft {
+            prft.media_time -= offset;
+        }
+        for event in &mut self.events {
+            if let EventTime::Absolute(time) = &mut event.presentation_time {
+                let offset =
+                    u128::from(offset) * u128::from(event.timescale) / u128::from(self.timescale);
+                *time = time.saturating_sub(offset.try_into().unwrap_or(u64::MAX));
+            }
+        }
+    }
+
     fn add_headers(&mut self, headers: Vec<u8>) {
         // MediaSegments constructed with `new` should always have sample_sizes
         #[allow(clippy::unwrap_used)]
@@ -322,31 +353,50 @@ impl MediaSegment {
         }
         self.mdat.headers = Some(headers);
     }
+
+    fn add_event(&mut self, event: EventMessageBox) {
+        self.events.push(event);
+    }
 }
 
-impl WriteTo for MediaSegment {
-    fn write_to(&self, mut w: impl Write) -> io::Result<()> {
-        write_to(&self.moof, &mut w)?;
-        write_to(&self.mdat, &mut w)?;
+impl MediaSegment {
+    /// Writes the segment without copying the sample data, which is shared
+    /// between all subscribers.
+    fn write_chunks(&self, chunks: &mut Chunks) -> io::Result<()> {
+        for event in &self.events {
+            write_to_full(event, &mut *chunks)?;
+        }
+        if let Some(prft) = &self.prft {
+            write_to_full(prft, &mut *chunks)?;
+        }
+        write_to(&self.moof, &mut *chunks)?;
+        chunks.push_mdat(&self.mdat)?;
         Ok(())
     }
 }
 
 /// Creates a new video stream.
 ///
+/// Each media segment is yielded as several chunks. The sample data is shared
+/// with every other stream instead of being copied for each one.
+///
 /// # Errors
 ///
 /// This function may return an [`Error::Other`] if all receivers on
-/// `stream_sub_tx` have ben dropped.
+/// `stream_sub_tx` have ben dropped or the configured resolution doesn't fit
+/// in an MP4 sample entry, or an [`Error::Bmff`] if the init segment is
+/// invalid.
 #[allow(clippy::missing_panics_doc)]
 pub async fn stream(
     config: &Config,
     stream_sub_tx: flume::Sender<StreamSubscriber>,
-) -> Result<impl Stream<Item = io::Result<Vec<u8>>>> {
+) -> Result<impl Stream<Item = io::Result<Bytes>>> {
     struct StreamState {
         init_segment: Option<InitSegment>,
         size: u64,
         sequence_number: u32,
+        /// The decode time of the first segment
+        base_media_decode_time: Option<u64>,
         segment_stream: RecvStream<'static, MediaSegment>,
         headers: Option<Vec<u8>>,
     }
@@ -360,20 +410,21 @@ pub async fn stream(
     #[allow(clippy::unwrap_used)]
     let (headers, segment_rx) = rx.recv_async().await.unwrap();
 
-    let init_segment = InitSegment::new(config);
+    let init_segment = InitSegment::new(config)?;
     let state = StreamState {
         size: init_segment.size(),
         init_segment: Some(init_segment),
         sequence_number: 1,
+        base_media_decode_time: None,
         segment_stream: segment_rx.into_stream(),
         headers: Some(headers),
     };
 
-    Ok(stream::try_unfold(state, |mut state| async move {
+    let segments = stream::try_unfold(state, |mut state| async move {
+        let mut chunks = Chunks::new();
         if let Some(init_segment) = state.init_segment.take() {
-            let mut buf = Vec::with_capacity(init_segment.size() as usize);
-            init_segment.write_to(&mut buf)?;
-            return Ok(Some((buf, state)));
+            init_segment.write_to(&mut chunks)?;
+            return Ok(Some((chunks, state)));
         }
 
         let Some(mut segment) = state.segment_stream.next().await else {
@@ -385,14 +436,18 @@ pub async fn stream(
         if let Some(headers) = state.headers.take() {
             segment.add_headers(headers);
         }
-        *segment.base_data_offset() = Some(state.size);
+        segment.set_offset(state.size);
         *segment.sequence_number() = state.sequence_number;
         state.sequence_number += 1;
+        let base_media_decode_time = *state
+            .base_media_decode_time
+            .get_or_insert(segment.base_media_decode_time());
+        segment.rebase(base_media_decode_time);
         let size = segment.size();
         state.size += size;
 
-        let mut buf = Vec::with_capacity(size as usize);
-        segment.write_to(&mut buf)?;
+        segment.write_chunks(&mut chunks)?;
+        debug_assert_eq!(chunks.len(), size);
 
         #[cfg(feature = "log")]
         log::trace!(
@@ -400,7 +455,18 @@ pub async fn stream(
             state.sequence_number - 1
         );
 
-        Ok(Some((buf, state)))
+        Ok(Some((chunks, state)))
+    });
+
+    Ok(segments.flat_map(|segment| {
+        let chunks: Vec<io::Result<Bytes>> = match segment {
+            Ok(chunks) => chunks
+                .into_iter()
+                .map(|x| Ok(Bytes::from_owner(x)))
+                .collect(),
+            Err(e) => vec![Err(e)],
+        };
+        stream::iter(chunks)
     }))
 }
 
@@ -462,6 +528,7 @@ enum SegmentIter {
     },
     Hardware {
         config: Config,
+        timestamp: u64,
         frames: FrameIter,
     },
 }
@@ -469,7 +536,11 @@ enum SegmentIter {
 impl SegmentIter {
     fn new(config: Config, frames: FrameIter) -> x264::Result<Self> {
         Ok(match config.format {
-            Format::H264 => Self::Hardware { frames, config },
+            Format::H264 => Self::Hardware {
+                timestamp: 0,
+                frames,
+                config,
+            },
             format => Self::Software {
                 timescale: config.interval.1,
                 encoder: {
@@ -525,9 +596,12 @@ impl Iterator for SegmentIter {
                 timescale,
                 frames,
             } => {
+                let base_media_decode_time = *timestamp as u64;
                 let mut sample_sizes = vec![];
                 let mut buf = vec![];
 
+                // when the first frame is passed to the encoder
+                let mut reference_time = None;
                 for _ in 0..60 {
                     let frame = match frames.next() {
                         Some(Ok(f)) => f,
@@ -538,6 +612,7 @@ impl Iterator for SegmentIter {
                         }
                         None => unreachable!(),
                     };
+                    reference_time.get_or_insert_with(Utc::now);
 
                     let image = x264::Image::new(
                         x264::Colorspace::YUYV,
@@ -564,11 +639,27 @@ impl Iterator for SegmentIter {
                         *timescale as i64 * config.interval.0 as i64 / config.interval.1 as i64;
                 }
 
-                Some(Ok(MediaSegment::new(config, 0, sample_sizes, buf)))
+                Some(MediaSegment::new(
+                    config,
+                    0,
+                    base_media_decode_time,
+                    reference_time.unwrap_or_else(Utc::now),
+                    ReferenceTimeSource::EncoderInput,
+                    sample_sizes,
+                    buf,
+                ))
             }
-            Self::Hardware { frames, config } => {
+            Self::Hardware {
+                frames,
+                timestamp,
+                config,
+            } => {
+                let base_media_decode_time = *timestamp;
                 let mut sample_sizes = Vec::new();
                 let mut buf = Vec::new();
+                // when the first frame, already encoded by the camera, is
+                // dequeued
+                let mut reference_time = None;
                 for _ in 0..60 {
                     let frame = match frames.next() {
                         Some(Ok(f)) => f,
@@ -579,10 +670,20 @@ impl Iterator for SegmentIter {
                         }
                         None => unreachable!(),
                     };
+                    reference_time.get_or_insert_with(Utc::now);
                     sample_sizes.push(frame.len() as u32);
                     buf.extend_from_slice(&frame);
+                    *timestamp += config.interval.0 as u64;
                 }
-                Some(Ok(MediaSegment::new(config, 0, sample_sizes, buf)))
+                Some(MediaSegment::new(
+                    config,
+                    0,
+                    base_media_decode_time,
+                    reference_time.unwrap_or_else(Utc::now),
+                    ReferenceTimeSource::EncoderOutput,
+                    sample_sizes,
+                    buf,
+                ))
             }
         }
     }
@@ -614,11 +715,29 @@ pub type StreamSubscriber = flume::Sender<(Vec<u8>, MediaSegReceiver)>;
 /// This function may return an [`Error::Camera`] if interacting with the provided camera
 /// device fails, an [`Error::Other`] if the device path is invalid UTF-8, or an
 /// [`Error::Encoding`] if constructing an encoder fails.
-#[allow(clippy::missing_panics_doc)]
 pub fn stream_media_segments(
+    rx: flume::Receiver<StreamSubscriber>,
+    config: Config,
+    config_rx: Option<flume::Receiver<Config>>,
+) -> Result<std::convert::Infallible> {
+    stream_media_segments_with_events(rx, config, config_rx, None)
+}
+
+/// Start capturing video, like [`stream_media_segments`], with in-band events.
+///
+/// The optional `event_rx` parameter can be used to send in-band events, such as motion
+/// detection. Each event is written as an `emsg` box before the next media segment sent
+/// to every subscriber.
+///
+/// # Errors
+///
+/// This function returns the same errors as [`stream_media_segments`].
+#[allow(clippy::missing_panics_doc)]
+pub fn stream_media_segments_with_events(
     rx: flume::Receiver<StreamSubscriber>,
     mut config: Config,
     config_rx: Option<flume::Receiver<Config>>,
+    event_rx: Option<flume::Receiver<EventMessageBox>>,
 ) -> Result<std::convert::Infallible> {
     'main: loop {
         #[cfg(feature = "log")]
@@ -646,12 +765,102 @@ pub fn stream_media_segments(
             #[cfg(feature = "log")]
             let time = std::time::Instant::now();
             #[allow(clippy::unwrap_used)] // the iterator never returns `None`
-            let Ok(media_segment) = segments.next().unwrap() else {
+            let Ok(mut media_segment) = segments.next().unwrap() else {
                 break;
             };
+            for event in event_rx.iter().flat_map(flume::Receiver::try_iter) {
+                media_segment.add_event(event);
+            }
             senders.retain(|sender| sender.send(media_segment.clone()).is_ok());
             #[cfg(feature = "log")]
             log::trace!("Sent media segment, took {:?} to capture", time.elapsed());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_lite::future;
+    use std::thread;
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn stream_is_valid() {
+        let config = Config {
+            producer_reference_time: true,
+            ..Config::default()
+        };
+        let segment_config = config.clone();
+        let (stream_sub_tx, stream_sub_rx) = flume::unbounded::<StreamSubscriber>();
+        let worker = thread::spawn(move || {
+            let sender = stream_sub_rx.recv().unwrap();
+            let (tx, rx) = flume::unbounded();
+            sender.send((vec![0; 10], rx)).unwrap();
+            // the stream starts partway through
+            for i in 2..6u32 {
+                let sample_sizes = vec![100 + i, 20, 30];
+                let data = vec![0; sample_sizes.iter().sum::<u32>() as usize];
+                let mut segment = MediaSegment::new(
+                    &segment_config,
+                    // overwritten by the stream
+                    0,
+                    3 * u64::from(i),
+                    Utc::now(),
+                    ReferenceTimeSource::EncoderInput,
+                    sample_sizes,
+                    data,
+                )
+                .unwrap();
+                if i % 2 == 1 {
+                    segment.add_event(EventMessageBox {
+                        scheme_id_uri: "urn:example".to_string(),
+                        value: String::new(),
+                        timescale: 30,
+                        presentation_time: EventTime::Delta(0),
+                        event_duration: 0xFFFF_FFFF,
+                        id: i,
+                        message_data: vec![1, 2, 3],
+                    });
+                }
+                tx.send(segment).unwrap();
+            }
+        });
+
+        let bytes = future::block_on(async {
+            let stream = stream(&config, stream_sub_tx).await.unwrap();
+            let chunks: Vec<_> = stream.try_collect::<_, _, Vec<_>>().await.unwrap();
+            chunks.concat()
+        });
+        worker.join().unwrap();
+
+        let issues = bmff::validate::check(&bytes);
+        assert!(issues.is_empty(), "{issues:#?}");
+
+        let mut colr = None;
+        let times: Vec<_> = bmff::Demuxer::new(io::Cursor::new(&bytes))
+            .unwrap()
+            .filter_map(|x| match x.unwrap() {
+                TopLevelBox::Moov(moov) => {
+                    let entry = &moov.trak[0].mdia.minf.stbl.stsd.entries[0];
+                    let avc1 = entry.as_any().downcast_ref::<AvcSampleEntry>().unwrap();
+                    colr = avc1.colr.clone();
+                    None
+                }
+                TopLevelBox::Moof(moof) => Some(moof.traf[0].tfdt.unwrap().base_media_decode_time),
+                TopLevelBox::Prft(prft) => Some(prft.media_time),
+                _ => None,
+            })
+            .collect();
+        // each prft is followed by the moof it refers to
+        assert_eq!(times, [0, 0, 3, 3, 6, 6, 9, 9]);
+        // YUYV from the camera is full range
+        assert!(matches!(
+            colr,
+            Some(ColourInformationBox::Nclx {
+                full_range: true,
+                ..
+            })
+        ));
+    }
+}
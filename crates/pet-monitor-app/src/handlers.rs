@@ -155,15 +155,16 @@ pub(crate) async fn set_config(
         .decode_utf8()
         .map_err(|e| error!("Percent decoding error: {e}"))?;
     let form: ConfigForm = serde_qs::from_str(&form).map_err(|e| error!("{e}"))?;
+    let ctx_read = ctx.get();
     let config = Config {
         device: form.device,
         format: form.format,
         resolution: form.resolution,
         interval: form.interval,
         rotation: form.rotation,
+        producer_reference_time: ctx_read.config.producer_reference_time,
         v4l2_controls: form.v4l2_controls.unwrap_or_default(),
     };
-    let ctx_read = ctx.get();
 
     if !Token::decode(&form.csrf, &ctx_read.jwt_secret)
         .map_err(|e| error!("{e}"))?
@@ -194,15 +195,26 @@ pub(crate) async fn stream(
     _token: Token,
     State(ctx): State<ContextManager>,
     State(stream_sub_tx): State<Option<flume::Sender<StreamSubscriber>>>,
-) -> Result<Response<StreamBody<impl Stream<Item = std::io::Result<Vec<u8>>>>>, StatusCode> {
+) -> Result<Response<StreamBody<impl Stream<Item = std::io::Result<Bytes>>>>, StatusCode> {
     #[allow(clippy::unwrap_used)] // stream_sub_tx will always be `Some` if this route is mounted
     let stream = mp4_stream::stream(&ctx.get().config, stream_sub_tx.unwrap())
         .await
         .map_err(|e| error!("Error starting stream: {e}"))?;
 
-    let stream = StreamExt::inspect(stream, |it| match it {
+    // checks the stream for muxing bugs, this buffers every segment except
+    // for the sample data
+    let mut validator = std::env::var("VALIDATE_STREAM")
+        .map_or(false, |it| it == "1")
+        .then(bmff::validate::Validator::new);
+    let stream = StreamExt::inspect(stream, move |it| match it {
         Err(e) => log::warn!("Error streaming segment: {e}"),
-        Ok(_) => (),
+        Ok(bytes) => {
+            if let Some(validator) = &mut validator {
+                for issue in validator.push(bytes) {
+                    log::warn!("Invalid stream {issue}");
+                }
+            }
+        }
     });
 
     #[allow(clippy::unwrap_used)]